@@ -0,0 +1,286 @@
+//! `--autosave`: everything that is played written to a Standard MIDI File as it goes, so
+//! nothing is lost for not having pressed record.
+//!
+//! Each run gets its own file, named after when it started, with a number added if a run that
+//! started in the same second has one already. The file is a single track with one tick per
+//! millisecond, and is a complete SMF after every message: the End of Track is written over by
+//! the next message and the track length is updated along with it.
+//!
+//! Control changes and pitch bends are written like everything else, and can be thinned out
+//! with `--autosave-thin` so a controller swept for a while doesn't fill the file. The last of
+//! those left out of each controller is still written, at the end of the track where the next
+//! message written takes its place, so the file always ends up at the value it was left at.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    midi::{self, MidiMsg},
+    output::OutputSink,
+    session_log::report,
+};
+
+/// Ticks per beat, which at the tempo below is one per millisecond.
+const DIVISION: u16 = 500;
+/// 120 BPM, in µs per beat.
+const TEMPO: u32 = 500_000;
+const END_OF_TRACK: [u8; 4] = [0x00, 0xff, 0x2f, 0x00];
+/// Where the length of the track is in the file.
+const TRACK_LENGTH_AT: u64 = 18;
+
+#[derive(Debug)]
+pub struct Autosave {
+    file: File,
+    track_length: u32,
+    /// The length of the messages left out written at the end of the track, before the End of
+    /// Track, which the next message kept is written over.
+    tail_length: u32,
+    /// The time of the first message, in µs, which the file starts at.
+    start: Option<u64>,
+    last_tick: u64,
+    thin: Option<Thin>,
+}
+
+/// The file as an output sink, which gives up after reporting the first error.
+pub struct Sink {
+    autosave: Autosave,
+    path: PathBuf,
+    failed: bool,
+}
+
+impl Sink {
+    pub fn new(autosave: Autosave, path: PathBuf) -> Self {
+        Sink {
+            autosave,
+            path,
+            failed: false,
+        }
+    }
+}
+
+impl OutputSink for Sink {
+    fn name(&self) -> &'static str {
+        "autosave"
+    }
+
+    fn write(&mut self, midi: &MidiMsg, time: jack::Time) {
+        if self.failed {
+            return;
+        }
+        if let Err(err) = self.autosave.write(midi, time) {
+            // Once is enough, this is unlikely to get any better
+            report!("{}: {}", self.path.display(), err);
+            self.failed = true;
+        }
+    }
+}
+
+/// See `--autosave-thin`.
+#[derive(Debug)]
+struct Thin {
+    /// How far apart the messages of a controller are kept, in µs.
+    interval: u64,
+    /// When a message of each controller was last kept, see [`controller`].
+    kept: HashMap<(u8, u8), u64>,
+    /// The latest message of each controller left out since, with its time.
+    left_out: HashMap<(u8, u8), (MidiMsg, u64)>,
+}
+
+impl Autosave {
+    /// Starts a new file in `dir` (made if it doesn't exist), and returns it with its path.
+    /// With `thin`, only one message per that many milliseconds of each controller is kept.
+    pub fn create(dir: &Path, thin: Option<u64>) -> io::Result<(Self, PathBuf)> {
+        fs::create_dir_all(dir)?;
+        let name = timestamp(SystemTime::now());
+        let mut path = dir.join(format!("{}.mid", name));
+        // Never over another run's file
+        let mut n = 1;
+        let mut file = loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    path = dir.join(format!("{}-{}.mid", name, n));
+                }
+                result => break result?,
+            }
+        };
+
+        // Format 0, one track
+        let mut header = b"MThd\0\0\0\x06\0\0\0\x01".to_vec();
+        header.extend_from_slice(&DIVISION.to_be_bytes());
+        header.extend_from_slice(b"MTrk\0\0\0\0");
+        let mut track = vec![0x00, 0xff, 0x51, 0x03];
+        track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+        track.extend_from_slice(&END_OF_TRACK);
+        file.write_all(&header)?;
+
+        let mut autosave = Autosave {
+            file,
+            track_length: 0,
+            tail_length: 0,
+            start: None,
+            last_tick: 0,
+            thin: thin.map(|ms| Thin {
+                interval: ms * 1000,
+                kept: HashMap::new(),
+                left_out: HashMap::new(),
+            }),
+        };
+        autosave.file.write_all(&track)?;
+        autosave.track_length = track.len() as u32;
+        autosave.write_track_length()?;
+
+        Ok((autosave, path))
+    }
+
+    /// Adds `midi`, played at `time` µs.
+    pub fn write(&mut self, midi: &MidiMsg, time: u64) -> io::Result<()> {
+        let mut left_out = Vec::new();
+        if let Some(thin) = &mut self.thin {
+            if let Some(key) = controller(midi) {
+                if thin
+                    .kept
+                    .get(&key)
+                    .is_some_and(|&kept| time.saturating_sub(kept) < thin.interval)
+                {
+                    thin.left_out.insert(key, (*midi, time));
+                    return self.write_left_out();
+                }
+                thin.left_out.remove(&key);
+                thin.kept.insert(key, time);
+            }
+
+            for (key, (midi, time)) in thin.left_out.drain() {
+                thin.kept.insert(key, time);
+                left_out.push((time, midi));
+            }
+        }
+
+        // Whatever was left out was played before this, so it goes first
+        left_out.sort_by_key(|&(time, _)| time);
+        for (time, midi) in left_out {
+            self.event(&midi, time)?;
+        }
+        self.event(midi, time)
+    }
+
+    fn event(&mut self, midi: &MidiMsg, time: u64) -> io::Result<()> {
+        let mut last_tick = self.last_tick;
+        let event = self.encode(midi, time, &mut last_tick);
+        self.last_tick = last_tick;
+        self.replace_end(&event, 0)
+    }
+
+    /// Writes the messages left out so far at the end of the track, over the ones written
+    /// there before, so the file ends at the last value of every controller.
+    fn write_left_out(&mut self) -> io::Result<()> {
+        let mut left_out: Vec<_> = match &self.thin {
+            Some(thin) => thin.left_out.values().copied().collect(),
+            None => return Ok(()),
+        };
+        left_out.sort_by_key(|&(_, time)| time);
+
+        let (mut tail, mut last_tick) = (Vec::new(), self.last_tick);
+        for (midi, time) in left_out {
+            tail.extend(self.encode(&midi, time, &mut last_tick));
+        }
+        let tail_length = tail.len() as u32;
+        self.replace_end(&tail, tail_length)
+    }
+
+    /// `midi` played at `time` µs as an event of the track, following one at `last_tick`.
+    fn encode(&mut self, midi: &MidiMsg, time: u64, last_tick: &mut u64) -> Vec<u8> {
+        let start = *self.start.get_or_insert(time);
+        let tick = time.saturating_sub(start) / 1000;
+        let delta = tick.saturating_sub(*last_tick);
+        *last_tick = (*last_tick).max(tick);
+
+        let mut event = Vec::with_capacity(8);
+        midi::push_variable_length(&mut event, delta.min(u32::MAX as u64) as u32);
+        let (bytes, len) = midi.encode();
+        event.extend_from_slice(&bytes[..len]);
+        event
+    }
+
+    /// Writes `events` and the End of Track over the messages left out at the end of the track
+    /// and the End of Track. The last `tail_length` bytes of `events` are messages left out,
+    /// to be written over in turn.
+    fn replace_end(&mut self, events: &[u8], tail_length: u32) -> io::Result<()> {
+        let over = self.tail_length + END_OF_TRACK.len() as u32;
+        let end = self.file.seek(SeekFrom::End(-(over as i64)))?;
+        // The tail written before can be longer than what replaces it
+        self.file.set_len(end)?;
+        self.file.write_all(events)?;
+        self.file.write_all(&END_OF_TRACK)?;
+
+        self.track_length =
+            self.track_length - over + events.len() as u32 + END_OF_TRACK.len() as u32;
+        self.tail_length = tail_length;
+        self.write_track_length()
+    }
+
+    fn write_track_length(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(TRACK_LENGTH_AT))?;
+        self.file.write_all(&self.track_length.to_be_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+
+        Ok(())
+    }
+}
+
+/// The channel and controller a message moves, with pitch bend as controller 128.
+fn controller(midi: &MidiMsg) -> Option<(u8, u8)> {
+    match *midi {
+        MidiMsg::ControlChange {
+            channel,
+            controller,
+            ..
+        } => Some((channel, controller)),
+        MidiMsg::PitchBend { channel, .. } => Some((channel, 0x80)),
+        _ => None,
+    }
+}
+
+/// The date and time of `time` in UTC, like `2024-05-01T18-30-00`, to name files with.
+fn timestamp(time: SystemTime) -> String {
+    let (year, month, day, seconds) = utc(time);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The year, month and day of `time` in UTC, and the seconds into the day.
+pub fn utc(time: SystemTime) -> (i64, i64, i64, u64) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // From days since 1970-01-01 to the civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month, day, seconds)
+}
@@ -0,0 +1,77 @@
+//! Playing a few keys while another window is focused, configured with `[background]`.
+//!
+//! The window only sees keys while it has the focus, so the keyboard is also read through evdev
+//! like the keyboards in [`devices`](crate::devices). Those keys are only played while the
+//! window doesn't have the focus and after the `background` key has turned this on, so nothing
+//! typed into another window plays notes by surprise.
+//!
+//! The `capture` key is read the same way, as a hotkey that works whichever window has the
+//! focus.
+
+use std::{fs::File, thread};
+
+use winit::event::ScanCode;
+
+use crate::{
+    devices::{self, Matcher},
+    input::InputSource,
+    session_log::report,
+    Proxy, UserEvent,
+};
+
+/// Which keyboard to read, and which of its keys to play.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Background {
+    pub matcher: Matcher,
+    pub keys: Vec<ScanCode>,
+    /// The key turning every key playing anything on and off.
+    pub capture: Option<ScanCode>,
+}
+
+impl InputSource for Background {
+    fn name(&self) -> &'static str {
+        "background"
+    }
+
+    fn start(&self, proxy: Proxy) -> Vec<String> {
+        start(self, proxy)
+    }
+}
+
+/// Starts a thread for every event device of the keyboard, which sends the keys in `keys` and
+/// the `capture` key to the event loop. Returns a message for every device that couldn't be opened.
+fn start(background: &Background, proxy: Proxy) -> Vec<String> {
+    let paths = match background.matcher.paths() {
+        Ok(paths) if !paths.is_empty() => paths,
+        Ok(_) => return vec![format!("no input device matches {:?}", background.matcher)],
+        Err(err) => return vec![format!("/sys/class/input: {}", err)],
+    };
+    let mut errors = Vec::new();
+
+    for path in paths {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                errors.push(format!("{}: {}", path.display(), err));
+                continue;
+            }
+        };
+
+        let proxy = proxy.clone();
+        let (keys, capture) = (background.keys.clone(), background.capture);
+        thread::spawn(move || {
+            let result = devices::read_keys(file, |scancode, pressed| {
+                if !keys.contains(&scancode) && capture != Some(scancode) {
+                    return true;
+                }
+                let key = UserEvent::BackgroundKey { scancode, pressed };
+                proxy.send_event(key).is_ok()
+            });
+            if let Err(err) = result {
+                report!("{}: {}", path.display(), err);
+            }
+        });
+    }
+
+    errors
+}
@@ -0,0 +1,73 @@
+//! Blocking messages on their way out, by kind and channel, with the `block` and `unblock`
+//! commands of the [`protocol`](crate::protocol). Nothing is disconnected, so a synth can be
+//! kept from the mod wheel or a whole channel while its patch is tweaked by hand, and get them
+//! back without repatching. Note offs always go out, so no note is left hanging.
+
+use crate::{midi::MidiMsg, thru::Kind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    /// The kind of message blocked, or `None` for every kind.
+    pub kind: Option<Kind>,
+    /// The channel blocked, zero-based, or `None` for every channel.
+    pub channel: Option<u8>,
+}
+
+impl Block {
+    /// Parses the arguments of `block` and `unblock`: a kind like in `[[thru]]` or `all`, and
+    /// optionally a one-based channel.
+    pub fn parse<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let kind = match words.next()? {
+            "all" => None,
+            name => Some(Kind::from_name(name)?),
+        };
+        let channel = match words.next() {
+            Some(word) => match word.parse::<u8>() {
+                Ok(channel @ 1..=16) => Some(channel - 1),
+                _ => return None,
+            },
+            None => None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(Block { kind, channel }),
+        }
+    }
+
+    fn matches(&self, midi: &MidiMsg) -> bool {
+        self.kind.is_none_or(|kind| kind == Kind::of(midi))
+            && self.channel.is_none_or(|channel| channel == midi.channel())
+    }
+
+    /// Whether everything `other` blocks, this blocks too.
+    fn covers(&self, other: &Block) -> bool {
+        self.kind.is_none_or(|kind| other.kind == Some(kind))
+            && self
+                .channel
+                .is_none_or(|channel| other.channel == Some(channel))
+    }
+
+    /// E.g. `control_change on 3`, `channel 3` or `all`.
+    pub fn name(&self) -> String {
+        match (self.kind, self.channel) {
+            (Some(kind), Some(channel)) => format!("{} on {}", kind.name(), channel + 1),
+            (Some(kind), None) => kind.name().to_string(),
+            (None, Some(channel)) => format!("channel {}", channel + 1),
+            (None, None) => "all".to_string(),
+        }
+    }
+}
+
+/// Whether any of `blocks` keeps `midi` from going out.
+pub fn blocked(blocks: &[Block], midi: &MidiMsg) -> bool {
+    let note_off = matches!(
+        midi,
+        MidiMsg::NoteOff { .. } | MidiMsg::NoteOn { velocity: 0, .. }
+    );
+    !note_off && blocks.iter().any(|block| block.matches(midi))
+}
+
+/// Takes the blocks `unblock` covers out of `blocks`, so `unblock all` lifts every one.
+pub fn unblock(blocks: &mut Vec<Block>, unblock: Block) {
+    blocks.retain(|block| !unblock.covers(block));
+}
@@ -0,0 +1,69 @@
+//! Note probability and conditional triggers, configured with `[chance]`: presses that only
+//! sound some of the time, or only on every so many presses of a key, for generative or
+//! percussive textures.
+
+use std::collections::{HashMap, HashSet};
+
+use winit::event::ScanCode;
+
+use crate::rhythm::Rng;
+
+/// When a press of a key sounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// How likely each press is to sound, in percent.
+    pub percent: u8,
+    /// Only every this many presses can sound, counting the first as 1: 2 for every 2nd press.
+    pub every: u32,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            percent: 100,
+            every: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chance {
+    rule: Rule,
+    /// Keys with a rule of their own instead of `rule`.
+    keys: HashMap<ScanCode, Rule>,
+    /// How many times each key has been pressed.
+    presses: HashMap<ScanCode, u32>,
+    /// Keys held whose press sounded, so only their release does.
+    sounding: HashSet<ScanCode>,
+    rng: Rng,
+}
+
+impl Chance {
+    pub fn new(rule: Rule, keys: HashMap<ScanCode, Rule>, seed: u64) -> Self {
+        Chance {
+            rule,
+            keys,
+            presses: HashMap::new(),
+            sounding: HashSet::new(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Whether a press or release of a key sounds. A release sounds if its press did.
+    pub fn handle(&mut self, scancode: ScanCode, pressed: bool) -> bool {
+        if !pressed {
+            return self.sounding.remove(&scancode);
+        }
+
+        let rule = self.keys.get(&scancode).copied().unwrap_or(self.rule);
+        let presses = self.presses.entry(scancode).or_default();
+        *presses += 1;
+        // Counted whether or not it sounds, so `every` goes by the presses made
+        let sounds =
+            presses.is_multiple_of(rule.every) && self.rng.below(100) < rule.percent as u32;
+        if sounds {
+            self.sounding.insert(scancode);
+        }
+        sounds
+    }
+}
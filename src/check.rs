@@ -0,0 +1,71 @@
+//! `--check`: a health check for session startup scripts, which checks everything jack_keyboard
+//! needs before it is started, prints how each check went and exits with status 0 only if all of
+//! them passed.
+
+use std::{fs, process};
+
+use jack::{Client, ClientOptions, PortFlags};
+
+use crate::{config::Config, keymap, options::Options};
+
+/// The name of the client connecting to JACK, so it doesn't clash with one already running.
+const CLIENT_NAME: &str = "jack_keyboard_check";
+
+/// Runs every check and exits.
+pub fn run(options: &Options) -> ! {
+    let mut failed = false;
+    let mut report = |what: &str, result: Result<String, String>| match result {
+        Ok(detail) => println!("ok      {}: {}", what, detail),
+        Err(err) => {
+            println!("FAILED  {}: {}", what, err);
+            failed = true;
+        }
+    };
+
+    report("config", check_config(options));
+    if let Some(path) = &options.import_keymap {
+        let result = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| keymap::import_vmpk(&source))
+            .map(|notes| format!("{}, {} keys", path.display(), notes.len()))
+            .map_err(|err| format!("{}: {}", path.display(), err));
+        report("keymap", result);
+    }
+    if options.rawmidi.is_none() && options.rtpmidi.is_none() && options.ump.is_none() {
+        report("JACK", check_jack());
+    }
+
+    process::exit(failed as i32);
+}
+
+fn check_config(options: &Options) -> Result<String, String> {
+    let config = Config::load(options.config.as_deref()).map_err(|err| err.to_string())?;
+
+    Ok(match &config.path {
+        Some(path) => path.display().to_string(),
+        None => "no config file, using the defaults".to_string(),
+    })
+}
+
+/// Connects to the JACK server, without starting one, and lists its MIDI ports.
+fn check_jack() -> Result<String, String> {
+    let (client, _client_status) = Client::new(CLIENT_NAME, ClientOptions::NO_START_SERVER)
+        .map_err(|err| format!("no server running ({})", err))?;
+
+    let midi = |flags| client.ports(None, Some("midi"), flags);
+    let (outputs, inputs) = (midi(PortFlags::IS_OUTPUT), midi(PortFlags::IS_INPUT));
+    let mut detail = format!(
+        "running at {} Hz, {} MIDI outputs and {} MIDI inputs",
+        client.sample_rate(),
+        outputs.len(),
+        inputs.len()
+    );
+    for port in &outputs {
+        detail += &format!("\n        MIDI output {}", port);
+    }
+    for port in &inputs {
+        detail += &format!("\n        MIDI input  {}", port);
+    }
+
+    Ok(detail)
+}
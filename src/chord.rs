@@ -0,0 +1,62 @@
+//! Chord learn: after the learn key, the next chord played is recorded, and the key pressed
+//! once it has been released is where it is stored.
+
+#[derive(Debug, Clone, Default)]
+pub enum ChordLearn {
+    #[default]
+    Off,
+    /// Recording the notes played, some of which may still be held.
+    Recording { notes: Vec<u8>, held: Vec<u8> },
+    /// The chord is complete and the next key pressed gets it.
+    Destination(Vec<u8>),
+}
+
+impl ChordLearn {
+    /// Starts learning, or cancels it if it was already started.
+    pub fn toggle(&mut self) {
+        *self = match self {
+            ChordLearn::Off => ChordLearn::Recording {
+                notes: Vec::new(),
+                held: Vec::new(),
+            },
+            _ => ChordLearn::Off,
+        };
+    }
+
+    /// Records a note being played or released.
+    pub fn note(&mut self, note: u8, pressed: bool) {
+        if let ChordLearn::Recording { notes, held } = self {
+            if pressed {
+                if !notes.contains(&note) {
+                    notes.push(note);
+                }
+                held.push(note);
+            } else {
+                held.retain(|&n| n != note);
+                if held.is_empty() && !notes.is_empty() {
+                    *self = ChordLearn::Destination(std::mem::take(notes));
+                }
+            }
+        }
+    }
+
+    /// The learned chord, if the key just pressed is where it should go.
+    pub fn take_destination(&mut self) -> Option<Vec<u8>> {
+        match std::mem::take(self) {
+            ChordLearn::Destination(notes) => Some(notes),
+            other => {
+                *self = other;
+                None
+            }
+        }
+    }
+
+    /// What to show in the window while learning.
+    pub fn status(&self) -> Option<&'static str> {
+        match self {
+            ChordLearn::Off => None,
+            ChordLearn::Recording { .. } => Some("Learn: play a chord"),
+            ChordLearn::Destination(_) => Some("Learn: press a key to store it on"),
+        }
+    }
+}
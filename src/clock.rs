@@ -0,0 +1,146 @@
+//! The internal clock that timed features like note repeat are synced to.
+
+use std::time::{Duration, Instant};
+
+use jack::Frames;
+
+pub const DEFAULT_TEMPO: f64 = 120.0;
+pub const MIN_TEMPO: f64 = 1.0;
+pub const MAX_TEMPO: f64 = 999.0;
+/// Swing in percent, where every other step is played that far into its pair of steps.
+pub const STRAIGHT: f64 = 50.0;
+pub const MAX_SWING: f64 = 75.0;
+
+/// Counts frames from when the client was activated, at a tempo that can change along the way.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    sample_rate: f64,
+    frames_per_beat: f64,
+    /// A frame and the beat it falls on, which the tempo counts on from.
+    anchor: (u64, f64),
+    /// How far into each pair of steps the second one is, from 0.5 (straight) to 0.75.
+    swing: f64,
+    /// The frame at the start of the current cycle.
+    frame: u64,
+}
+
+impl Clock {
+    pub fn new(tempo: f64, sample_rate: usize) -> Self {
+        Clock {
+            sample_rate: sample_rate as f64,
+            frames_per_beat: sample_rate as f64 * 60.0 / tempo,
+            anchor: (0, 0.0),
+            swing: 0.5,
+            frame: 0,
+        }
+    }
+
+    /// Changes the tempo from the current cycle on, carrying on from the beat it is at.
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.anchor = (self.frame, self.beat_at(self.frame));
+        self.frames_per_beat = self.sample_rate * 60.0 / tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+    }
+
+    /// The current tempo in beats per minute.
+    pub fn tempo(&self) -> f64 {
+        self.sample_rate * 60.0 / self.frames_per_beat
+    }
+
+    /// Sets the swing in percent, see [`STRAIGHT`].
+    pub fn set_swing(&mut self, percent: f64) {
+        self.swing = percent.clamp(STRAIGHT, MAX_SWING) / 100.0;
+    }
+
+    /// The frame at the start of the current cycle, counted from activation.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// How many frames `beats` beats last at the current tempo.
+    pub fn frames_for(&self, beats: f64) -> u64 {
+        (beats * self.frames_per_beat).round() as u64
+    }
+
+    /// Moves on to the next cycle.
+    pub fn advance(&mut self, n_frames: Frames) {
+        self.frame += n_frames as u64;
+    }
+
+    fn beat_at(&self, frame: u64) -> f64 {
+        let (anchor_frame, anchor_beat) = self.anchor;
+        anchor_beat + (frame - anchor_frame) as f64 / self.frames_per_beat
+    }
+
+    /// The steps of a grid with `per_beat` steps per beat that fall in the current cycle, as
+    /// the step number and the offset into the cycle. Odd steps are delayed by the swing.
+    pub fn steps(&self, per_beat: f64, n_frames: Frames) -> impl Iterator<Item = (u64, Frames)> {
+        self.grid(per_beat, self.swing, n_frames)
+    }
+
+    /// The beats that fall in the current cycle, like [`Clock::steps`] but never swung.
+    pub fn beats(&self, n_frames: Frames) -> impl Iterator<Item = (u64, Frames)> {
+        self.grid(1.0, 0.5, n_frames)
+    }
+
+    fn grid(
+        &self,
+        per_beat: f64,
+        swing: f64,
+        n_frames: Frames,
+    ) -> impl Iterator<Item = (u64, Frames)> {
+        let frames_per_beat = self.frames_per_beat;
+        let (anchor_frame, anchor_beat) = self.anchor;
+        let start = self.frame;
+        let end = start + n_frames as u64;
+        let position = move |step: u64| {
+            let pair = (step / 2 * 2) as f64;
+            let into_pair = if step % 2 == 1 { swing * 2.0 } else { 0.0 };
+            let beat = (pair + into_pair) / per_beat;
+            (anchor_frame as f64 + (beat - anchor_beat) * frames_per_beat).max(0.0) as u64
+        };
+
+        // One step early, in case it is a swung one that hasn't been played yet
+        let mut step = ((self.beat_at(start) * per_beat) as u64).saturating_sub(1);
+        while position(step) < start {
+            step += 1;
+        }
+
+        (step..)
+            .take_while(move |&step| position(step) < end)
+            .map(move |step| (step, (position(step) - start) as Frames))
+    }
+}
+
+/// Taps longer apart than this start counting again.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many taps the tempo is averaged over.
+const TAPS: usize = 4;
+
+/// Works out a tempo from the times a key is tapped.
+#[derive(Debug, Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    /// Notes a tap at `now`, and returns the tempo once there have been at least two.
+    pub fn tap(&mut self, now: Instant) -> Option<f64> {
+        if self
+            .taps
+            .last()
+            .is_some_and(|&last| now.duration_since(last) > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        if self.taps.len() == TAPS {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let beats = self.taps.len() - 1;
+        let beat = last.duration_since(*first).as_secs_f64() / beats.max(1) as f64;
+
+        (beats > 0 && beat > 0.0).then(|| (60.0 / beat).clamp(MIN_TEMPO, MAX_TEMPO))
+    }
+}
@@ -0,0 +1,2119 @@
+//! The config file, `$XDG_CONFIG_HOME/jack_keyboard/config.toml` unless given with `--config`.
+//!
+//! Every setting is shown and explained in [`TEMPLATE`], which `--print-default-config` prints
+//! with the settings commented out.
+//!
+//! A key can only be given one thing to do. Giving it a note, chord, Euclidean rhythm, macro,
+//! gesture, key-up note, snapshot or a place in the pressure cluster takes it from the action it
+//! is bound to by default, and whatever is configured for a key comes before selecting presets
+//! (F1 to F12) and playing the default notes. Actions come before presets too, so the eleventh
+//! preset can only be selected once `fullscreen` is bound to a key other than F11.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs, io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use winit::event::ScanCode;
+
+use crate::{
+    background::Background,
+    chance::Rule,
+    clock::{self, DEFAULT_TEMPO},
+    devices::{Device, Matcher},
+    euclid::Pattern,
+    feel::MAX_FEEL,
+    generate::{self, Rhythm},
+    gesture::Gesture,
+    gui::canvas::Color,
+    harmonize::Interval,
+    key_up::KeyUp,
+    keymap,
+    keys::{self, Action, Bindings},
+    layout::Layout,
+    midi::{MidiMsg, NoteNames, CC_BANK_SELECT_LSB, CC_BANK_SELECT_MSB, DEFAULT_CHANNEL},
+    mono::Slide,
+    output::Filter,
+    pressure::Target,
+    progression::{Chord, Lock},
+    protocol::{self, Command},
+    range::{Mode, NoteRange},
+    repeat::Rate,
+    scale::Scale,
+    shift,
+    split::Zone,
+    thru::{self, Kind},
+    toml::{self, Entry, Pos, Table, Value},
+};
+
+/// Every setting with an example and what it does.
+pub const TEMPLATE: &str = include_str!("config.toml");
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    /// With the line of the file the error is on, if there is one.
+    Parse(PathBuf, toml::Error, Option<String>),
+}
+
+impl Error {
+    fn parse(path: &Path, source: &str, err: toml::Error) -> Self {
+        let line = source.lines().nth(err.pos.line.saturating_sub(1));
+        Error::Parse(path.to_path_buf(), err, line.map(str::to_string))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse(path, err, None) => write!(f, "{}:{}", path.display(), err),
+            Error::Parse(path, err, Some(line)) => {
+                // Tabs are kept so the caret lines up however wide they are shown
+                let indent: String = line
+                    .chars()
+                    .take(err.pos.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                write!(
+                    f,
+                    "{}:{}\n    {}\n    {}^",
+                    path.display(),
+                    err,
+                    line,
+                    indent
+                )
+            }
+        }
+    }
+}
+
+/// [`TEMPLATE`] with every setting commented out, so as a config file it changes nothing until
+/// some of them are taken in.
+pub fn commented_template() -> String {
+    let mut template = String::from(
+        "# The jack_keyboard config. Every setting is commented out, so everything is left at its\n\
+         # default until one is uncommented.\n\n",
+    );
+    for line in TEMPLATE.lines() {
+        if !line.is_empty() && !line.starts_with('#') {
+            template.push('#');
+        }
+        template.push_str(line);
+        template.push('\n');
+    }
+    template
+}
+
+/// A bank and program to select on a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramSelect {
+    /// The 14-bit bank number, sent as bank select MSB and LSB.
+    pub bank: Option<u16>,
+    pub program: u8,
+}
+
+impl ProgramSelect {
+    pub fn messages(&self, channel: u8) -> Vec<MidiMsg> {
+        let mut messages = Vec::with_capacity(3);
+
+        if let Some(bank) = self.bank {
+            messages.push(MidiMsg::ControlChange {
+                channel,
+                controller: CC_BANK_SELECT_MSB,
+                value: (bank >> 7) as u8,
+            });
+            messages.push(MidiMsg::ControlChange {
+                channel,
+                controller: CC_BANK_SELECT_LSB,
+                value: (bank & 0x7f) as u8,
+            });
+        }
+        messages.push(MidiMsg::ProgramChange {
+            channel,
+            program: self.program,
+        });
+
+        messages
+    }
+}
+
+/// Program selections by zero-based channel.
+pub type ProgramMap = BTreeMap<u8, ProgramSelect>;
+
+/// Controller values by zero-based channel and controller.
+pub type ControllerMap = BTreeMap<(u8, u8), u8>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Preset {
+    pub name: String,
+    /// The background of the window while the preset is active.
+    pub color: Option<Color>,
+    pub programs: ProgramMap,
+    pub controllers: ControllerMap,
+}
+
+/// See [`Progression`](crate::progression::Progression).
+#[derive(Debug, Clone)]
+pub struct ProgressionConfig {
+    pub chords: Vec<Chord>,
+    pub lock: Lock,
+}
+
+/// The control change on the input port that stops every note, see `panic_cc`.
+#[derive(Debug, Clone, Copy)]
+pub struct PanicCc {
+    pub controller: u8,
+    /// The zero-based channel, or `None` for any.
+    pub channel: Option<u8>,
+}
+
+impl PanicCc {
+    /// Whether a control change is a press of the panic button.
+    pub fn matches(&self, (channel, controller, value): (u8, u8, u8)) -> bool {
+        controller == self.controller && self.channel.is_none_or(|own| own == channel) && value > 0
+    }
+}
+
+/// See [`KeyDetect`](crate::key_detect::KeyDetect).
+#[derive(Debug, Clone, Copy)]
+pub struct KeyDetectConfig {
+    /// How many of the last notes played the key is guessed from.
+    pub notes: usize,
+    /// Whether a diatonic harmonizer moves into the key guessed.
+    pub follow: bool,
+}
+
+/// See [`Split`](crate::split::Split).
+#[derive(Debug, Clone)]
+pub struct SplitConfig {
+    /// The lowest note of the upper zone.
+    pub note: u8,
+    pub lower: Zone,
+    pub upper: Zone,
+}
+
+/// See [`Morph`](crate::morph::Morph).
+#[derive(Debug, Clone)]
+pub struct MorphConfig {
+    /// The presets morphed between, by index into [`Config::presets`].
+    pub from: usize,
+    pub to: usize,
+    /// How long morphing all the way takes, in seconds.
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RepeatConfig {
+    /// The rate note repeat starts out with when it is turned on.
+    pub rate: Rate,
+    pub accents: Vec<u8>,
+}
+
+impl Default for RepeatConfig {
+    fn default() -> Self {
+        RepeatConfig {
+            rate: Rate::Sixteenth,
+            accents: vec![100],
+        }
+    }
+}
+
+/// See [`Echo`](crate::echo::Echo).
+#[derive(Debug, Clone)]
+pub struct EchoConfig {
+    pub on: bool,
+    pub repeats: u8,
+    pub rate: Rate,
+    pub decay: u8,
+}
+
+impl Default for EchoConfig {
+    fn default() -> Self {
+        EchoConfig {
+            on: false,
+            repeats: 3,
+            rate: Rate::Eighth,
+            decay: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PortamentoConfig {
+    pub time: Option<u8>,
+    pub on: Option<bool>,
+    pub auto: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SustainConfig {
+    pub inverted: bool,
+    pub wheel: bool,
+    /// How far one step of the mouse wheel moves the pedal.
+    pub wheel_step: u8,
+}
+
+impl Default for SustainConfig {
+    fn default() -> Self {
+        SustainConfig {
+            inverted: false,
+            wheel: false,
+            wheel_step: 8,
+        }
+    }
+}
+
+/// See [`NoteChannels`](crate::note_channels::NoteChannels).
+#[derive(Debug, Clone)]
+pub struct NoteChannelsConfig {
+    /// Zero-based, in the order they are used.
+    pub channels: Vec<u8>,
+    pub round_robin: bool,
+    pub pan_spread: u8,
+}
+
+/// See [`Pressure`](crate::pressure::Pressure).
+#[derive(Debug, Clone)]
+pub struct PressureConfig {
+    pub keys: Vec<ScanCode>,
+    pub target: Target,
+}
+
+/// See `[numpad]` above.
+#[derive(Debug, Clone, Copy)]
+pub struct NumpadConfig {
+    /// The note Numpad0 plays, the lowest.
+    pub lowest: u8,
+    pub channel: u8,
+    pub velocity: u8,
+    /// Milliseconds within which hits of a key stack up, see [`Stack`](crate::stack::Stack).
+    pub stack_window: u64,
+    pub stack_step: u8,
+}
+
+impl NumpadConfig {
+    /// The note `key` plays, if it is on the keypad.
+    pub fn note(&self, key: ScanCode) -> Option<u8> {
+        let index = keymap::NUMPAD
+            .iter()
+            .position(|name| keys::scancode(name) == Some(key))?;
+        Some(self.lowest + index as u8)
+    }
+}
+
+/// See [`Chance`](crate::chance::Chance).
+#[derive(Debug, Clone, Default)]
+pub struct ChanceConfig {
+    pub rule: Rule,
+    pub keys: HashMap<ScanCode, Rule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GenerateConfig {
+    pub scale: Scale,
+    pub rhythm: Rhythm,
+    pub root: u8,
+    pub density: u8,
+    pub velocity: u8,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        GenerateConfig {
+            scale: Scale::MinorPentatonic,
+            rhythm: Rhythm::Euclidean,
+            root: 60,
+            density: 6,
+            velocity: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Where the config was loaded from, and where learned chords are saved.
+    pub path: Option<PathBuf>,
+    /// The keyboard layout to label keys for, detected when `None`.
+    pub layout: Option<Layout>,
+    pub tempo: f64,
+    /// In percent, see [`clock::STRAIGHT`].
+    pub swing: f64,
+    /// Milliseconds to delay note offs by.
+    pub release_delay: f64,
+    /// Milliseconds everything plays behind the beat, see [`Feel`](crate::feel::Feel).
+    pub feel: f64,
+    /// How long a key has to be held before its note plays, in milliseconds.
+    pub dwell: u64,
+    /// The velocity added to notes struck while `accent` is held.
+    pub accent: u8,
+    /// The octaves notes struck while `bass` is held are moved down by.
+    pub bass: u8,
+    /// How long without any keys pressed or released before the notes held are let go of, in
+    /// seconds, or 0 for never.
+    pub idle_release: u64,
+    /// The names of the input sources to start, or `None` for all of them.
+    pub inputs: Option<Vec<String>>,
+    pub mono: bool,
+    pub note_names: NoteNames,
+    pub beat_flash: bool,
+    pub show_input: bool,
+    pub soft_takeover: bool,
+    pub panic_cc: Option<PanicCc>,
+    pub bindings: Bindings,
+    pub repeat: RepeatConfig,
+    pub echo: EchoConfig,
+    pub portamento: PortamentoConfig,
+    pub slide: Option<Slide>,
+    pub sustain: SustainConfig,
+    /// The interval the harmonizer adds a second note at, if it is on.
+    pub harmonize: Option<Interval>,
+    pub key_detect: Option<KeyDetectConfig>,
+    pub progression: Option<ProgressionConfig>,
+    pub range: Option<NoteRange>,
+    pub note_channels: Option<NoteChannelsConfig>,
+    pub pressure: Option<PressureConfig>,
+    pub chance: Option<ChanceConfig>,
+    pub split: Option<SplitConfig>,
+    pub numpad: Option<NumpadConfig>,
+    /// The color of each note's key on the keyboard of the fullscreen view, if it has one.
+    pub key_colors: [Option<Color>; 128],
+    /// The keys that store and recall snapshots of the controllers.
+    pub snapshots: Vec<ScanCode>,
+    /// The sequences of notes of `[practice]`, see [`Practice`](crate::practice::Practice).
+    pub practice: Vec<Vec<u8>>,
+    pub generate: GenerateConfig,
+    /// The keys that toggle Euclidean rhythms, and their patterns.
+    pub euclid: Vec<(ScanCode, Pattern)>,
+    pub gestures: Vec<Gesture>,
+    pub key_ups: Vec<KeyUp>,
+    /// The keys that play macros, and their steps in milliseconds after the press.
+    pub macros: Vec<(ScanCode, Vec<(f64, MidiMsg)>)>,
+    /// The note each key plays, or empty for the keys from A to K.
+    pub notes: HashMap<ScanCode, u8>,
+    /// The notes each chord key plays.
+    pub chords: HashMap<ScanCode, Vec<u8>>,
+    pub devices: Vec<Device>,
+    pub background: Option<Background>,
+    /// The rules for passing on messages from the input port, which is only there with some.
+    pub thru: Vec<thru::Rule>,
+    /// What each output sink gets, by its name.
+    pub sinks: HashMap<String, Filter>,
+    pub programs: ProgramMap,
+    pub presets: Vec<Preset>,
+    pub morph: Option<MorphConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            path: None,
+            layout: None,
+            tempo: DEFAULT_TEMPO,
+            swing: clock::STRAIGHT,
+            release_delay: 0.0,
+            feel: 0.0,
+            dwell: 0,
+            accent: 30,
+            bass: 1,
+            idle_release: 0,
+            inputs: None,
+            mono: false,
+            note_names: NoteNames::default(),
+            beat_flash: false,
+            show_input: false,
+            soft_takeover: false,
+            panic_cc: None,
+            bindings: Bindings::default(),
+            repeat: RepeatConfig::default(),
+            echo: EchoConfig::default(),
+            portamento: PortamentoConfig::default(),
+            slide: None,
+            sustain: SustainConfig::default(),
+            harmonize: None,
+            key_detect: None,
+            progression: None,
+            range: None,
+            note_channels: None,
+            pressure: None,
+            chance: None,
+            split: None,
+            numpad: None,
+            key_colors: [None; 128],
+            snapshots: Vec::new(),
+            practice: Vec::new(),
+            generate: GenerateConfig::default(),
+            euclid: Vec::new(),
+            gestures: Vec::new(),
+            key_ups: Vec::new(),
+            macros: Vec::new(),
+            notes: HashMap::new(),
+            chords: HashMap::new(),
+            devices: Vec::new(),
+            background: None,
+            thru: Vec::new(),
+            sinks: HashMap::new(),
+            programs: ProgramMap::new(),
+            presets: Vec::new(),
+            morph: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+        Some(config_dir.join("jack_keyboard").join("config.toml"))
+    }
+
+    /// Loads the config from `path`, or from the default location if no path is given.
+    ///
+    /// A missing file at the default location isn't an error.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config {
+                    path: Some(path),
+                    ..Config::default()
+                })
+            }
+            Err(err) => return Err(Error::Io(path, err)),
+        };
+
+        let mut config = toml::parse(&source)
+            .and_then(|table| Self::from_table(&table))
+            .map_err(|err| Error::parse(&path, &source, err))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// Stores a learned chord in the config file, leaving the rest of the file as it was.
+    pub fn save_chord(&self, key: ScanCode, notes: &[u8]) -> Result<(), Error> {
+        let notes: Vec<_> = notes
+            .iter()
+            .map(|&note| format!("\"{}\"", self.note_names.name(note)))
+            .collect();
+        self.save("chords", &[(key, format!("[{}]", notes.join(", ")))])
+    }
+
+    /// Stores the note each key plays in the config file, leaving the rest of the file as it
+    /// was.
+    pub fn save_notes(&self, notes: &[(ScanCode, u8)]) -> Result<(), Error> {
+        let entries: Vec<_> = notes
+            .iter()
+            .map(|&(key, note)| (key, format!("\"{}\"", self.note_names.name(note))))
+            .collect();
+        self.save("notes", &entries)
+    }
+
+    /// Takes the chord on a key out of the config file again, leaving the rest of the file as
+    /// it was.
+    pub fn remove_chord(&self, key: ScanCode) -> Result<(), Error> {
+        let key = keys::name(key).unwrap_or_default();
+        self.rewrite(|source| toml::remove_in_section(&source, "chords", key))
+    }
+
+    /// Sets each key to its value in `[section]` of the config file.
+    fn save(&self, section: &str, entries: &[(ScanCode, String)]) -> Result<(), Error> {
+        self.rewrite(|mut source| {
+            for (key, value) in entries {
+                let key = keys::name(*key).unwrap_or_default();
+                source = toml::set_in_section(&source, section, key, value);
+            }
+            source
+        })
+    }
+
+    /// Changes the source of the config file with `change`, if there is a file.
+    fn rewrite(&self, change: impl FnOnce(String) -> String) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(Error::Io(path.clone(), err)),
+        };
+
+        let source = change(source);
+        // Don't write anything that wouldn't load again
+        toml::parse(&source)
+            .and_then(|table| Self::from_table(&table))
+            .map_err(|err| Error::parse(path, &source, err))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::Io(dir.to_path_buf(), err))?;
+        }
+        fs::write(path, source).map_err(|err| Error::Io(path.clone(), err))
+    }
+
+    fn from_table(table: &Table) -> Result<Self, toml::Error> {
+        let mut config = Config::default();
+        let mut claims = Claims::default();
+
+        // Needed for the notes, wherever in the file it is given
+        if let Some(entry) = table.iter().find(|entry| entry.key == "note_names") {
+            let name = string(entry)?;
+            match NoteNames::from_name(name) {
+                Some(names) => config.note_names = names,
+                None => {
+                    return invalid(
+                        entry.pos,
+                        format!(
+                            "unknown note names '{}', expected english, solfege or german",
+                            name
+                        ),
+                    )
+                }
+            }
+        }
+        let names = config.note_names;
+        let mut morph_entry = None;
+
+        for entry in table.iter() {
+            match entry.key.as_str() {
+                "layout" => {
+                    let name = string(entry)?;
+                    match Layout::from_name(name) {
+                        Some(layout) => config.layout = Some(layout),
+                        None => {
+                            return invalid(
+                                entry.pos,
+                                format!(
+                                    "unknown layout '{}', expected qwerty, azerty, qwertz, \
+                                     dvorak or colemak",
+                                    name
+                                ),
+                            )
+                        }
+                    }
+                }
+                "tempo" => config.tempo = number_in(entry, clock::MIN_TEMPO..=clock::MAX_TEMPO)?,
+                "swing" => config.swing = number_in(entry, clock::STRAIGHT..=clock::MAX_SWING)?,
+                "release_delay" => config.release_delay = number_in(entry, 0.0..=10000.0)?,
+                "feel" => config.feel = number_in(entry, -MAX_FEEL..=MAX_FEEL)?,
+                "dwell" => config.dwell = integer_in(entry, 0..=2000)? as u64,
+                "accent" => config.accent = integer_in(entry, 0..=127)? as u8,
+                "bass" => config.bass = integer_in(entry, 1..=shift::MAX_OCTAVES as i64)? as u8,
+                "idle_release" => config.idle_release = integer_in(entry, 0..=86400)? as u64,
+                "inputs" => {
+                    let names = array(entry)?.iter().map(|value| match value {
+                        Value::String(name) => Ok(name.clone()),
+                        _ => invalid(entry.pos, "each input source must be a name"),
+                    });
+                    config.inputs = Some(names.collect::<Result<_, _>>()?);
+                }
+                "mono" => config.mono = boolean(entry)?,
+                "keymap" => {
+                    for (key, note) in keymap(entry)? {
+                        claims.claim(key, format!("note {}", names.name(note)), entry.pos)?;
+                        config.notes.insert(key, note);
+                    }
+                }
+                "note_names" => (),
+                "beat_flash" => config.beat_flash = boolean(entry)?,
+                "show_input" => config.show_input = boolean(entry)?,
+                "soft_takeover" => config.soft_takeover = boolean(entry)?,
+                "panic_cc" => config.panic_cc = Some(panic_cc(entry)?),
+                "keys" => bindings(entry, &mut config.bindings, &mut claims)?,
+                "repeat" => config.repeat = repeat(entry)?,
+                "echo" => config.echo = echo(entry)?,
+                "portamento" => config.portamento = portamento(entry)?,
+                "slide" => config.slide = Some(slide(entry)?),
+                "sustain" => config.sustain = sustain(entry)?,
+                "harmonize" => config.harmonize = Some(harmonize(entry, names)?),
+                "key_detect" => config.key_detect = Some(key_detect(entry)?),
+                "progression" => config.progression = Some(progression(entry)?),
+                "range" => config.range = Some(range(entry, names)?),
+                "note_channels" => config.note_channels = Some(note_channels(entry)?),
+                "pressure" => config.pressure = Some(pressure(entry, &mut claims)?),
+                "chance" => config.chance = Some(chance(entry)?),
+                "split" => config.split = Some(split(entry, names)?),
+                "numpad" => config.numpad = Some(numpad(entry, &mut claims)?),
+                "key_colors" => config.key_colors = key_colors(entry, names)?,
+                "snapshots" => config.snapshots = snapshots(entry, &mut claims)?,
+                "practice" => config.practice = practice(entry, names)?,
+                "generate" => config.generate = generate(entry, names)?,
+                "programs" => config.programs = program_map(entry)?,
+                "notes" => notes(entry, names, &mut claims, &mut config.notes)?,
+                "chords" => config.chords = chords(entry, names, &mut claims)?,
+                "device" => {
+                    for value in array(entry)? {
+                        config.devices.push(device(entry.pos, value, names)?);
+                    }
+                }
+                "background" => config.background = Some(background(entry)?),
+                "sinks" => config.sinks = sinks(entry)?,
+                "thru" => {
+                    for value in array(entry)? {
+                        config.thru.push(thru_rule(entry.pos, value, names)?);
+                    }
+                }
+                "euclid" => {
+                    for value in array(entry)? {
+                        let (key, pattern) = euclid(entry.pos, value, names)?;
+                        claims.claim(key, "a Euclidean rhythm".to_string(), entry.pos)?;
+                        config.euclid.push((key, pattern));
+                    }
+                }
+                "gesture" => {
+                    for value in array(entry)? {
+                        let gesture = gesture(entry.pos, value, names)?;
+                        claims.claim(gesture.key, "a gesture".to_string(), entry.pos)?;
+                        config.gestures.push(gesture);
+                    }
+                }
+                "key_up" => {
+                    for value in array(entry)? {
+                        let key_up = key_up(entry.pos, value, names)?;
+                        claims.claim(key_up.key, "a key-up note".to_string(), entry.pos)?;
+                        config.key_ups.push(key_up);
+                    }
+                }
+                "macro" => {
+                    for value in array(entry)? {
+                        let (key, steps) = key_macro(entry.pos, value)?;
+                        claims.claim(key, "a macro".to_string(), entry.pos)?;
+                        config.macros.push((key, steps));
+                    }
+                }
+                "preset" => {
+                    for value in array(entry)? {
+                        config.presets.push(preset(entry.pos, value)?);
+                    }
+                }
+                "morph" => morph_entry = Some(entry),
+                _ => return unknown_key(entry),
+            }
+        }
+
+        // Only once the presets are known, wherever they are
+        if let Some(entry) = morph_entry {
+            config.morph = Some(morph(entry, config.presets.len())?);
+        }
+
+        let taken = config.chords.keys().chain(config.notes.keys()).copied();
+        let taken = taken.chain(config.euclid.iter().map(|(key, _)| *key));
+        let taken = taken.chain(config.macros.iter().map(|(key, _)| *key));
+        let taken = taken.chain(config.gestures.iter().map(|gesture| gesture.key));
+        let taken = taken.chain(config.key_ups.iter().map(|key_up| key_up.key));
+        let taken = taken.chain(config.snapshots.iter().copied());
+        for key in taken
+            .chain(config.pressure.iter().flat_map(|p| p.keys.iter().copied()))
+            .collect::<Vec<_>>()
+        {
+            config.bindings.free(key);
+        }
+
+        Ok(config)
+    }
+
+    /// The programs to send when `preset` is active: the global ones overridden by the preset's.
+    pub fn programs_for(&self, preset: Option<usize>) -> ProgramMap {
+        let mut programs = self.programs.clone();
+
+        if let Some(preset) = preset.and_then(|index| self.presets.get(index)) {
+            programs.extend(preset.programs.iter().map(|(&k, &v)| (k, v)));
+        }
+
+        programs
+    }
+}
+
+/// The keys given something to do so far, to catch a key given two things.
+#[derive(Debug, Default)]
+struct Claims(Vec<(ScanCode, String, Pos)>);
+
+impl Claims {
+    fn claim(&mut self, key: ScanCode, what: String, pos: Pos) -> Result<(), toml::Error> {
+        if let Some((_, other, other_pos)) = self.0.iter().find(|(claimed, ..)| *claimed == key) {
+            return invalid(
+                pos,
+                format!(
+                    "'{}' is already used for {} on line {}",
+                    keys::name(key).unwrap_or("the key"),
+                    other,
+                    other_pos.line
+                ),
+            );
+        }
+
+        self.0.push((key, what, pos));
+        Ok(())
+    }
+}
+
+fn bindings(
+    entry: &Entry,
+    bindings: &mut Bindings,
+    claims: &mut Claims,
+) -> Result<(), toml::Error> {
+    for key_entry in table(entry)?.iter() {
+        let action = match Action::from_name(&key_entry.key) {
+            Some(action) => action,
+            None => return unknown_key(key_entry),
+        };
+        let name = string(key_entry)?;
+        match keys::scancode(name) {
+            Some(scancode) => {
+                claims.claim(scancode, format!("'{}'", action.name()), key_entry.pos)?;
+                bindings.bind(action, scancode);
+            }
+            None => return unknown_key_name(key_entry.pos, name),
+        }
+    }
+
+    Ok(())
+}
+
+fn repeat(entry: &Entry) -> Result<RepeatConfig, toml::Error> {
+    let mut repeat = RepeatConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "rate" => repeat.rate = rate(field)?,
+            "accents" => {
+                repeat.accents = array(field)?
+                    .iter()
+                    .map(|value| match value {
+                        Value::Integer(n @ 0..=200) => Ok(*n as u8),
+                        _ => invalid(field.pos, "accents must be percentages from 0 to 200"),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(repeat)
+}
+
+fn echo(entry: &Entry) -> Result<EchoConfig, toml::Error> {
+    let mut echo = EchoConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "on" => echo.on = boolean(field)?,
+            "repeats" => echo.repeats = integer_in(field, 1..=16)? as u8,
+            "rate" => echo.rate = rate(field)?,
+            "decay" => echo.decay = integer_in(field, 1..=100)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(echo)
+}
+
+fn portamento(entry: &Entry) -> Result<PortamentoConfig, toml::Error> {
+    let mut portamento = PortamentoConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "time" => portamento.time = Some(integer_in(field, 0..=127)? as u8),
+            "on" => portamento.on = Some(boolean(field)?),
+            "auto" => portamento.auto = boolean(field)?,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(portamento)
+}
+
+fn slide(entry: &Entry) -> Result<Slide, toml::Error> {
+    let mut slide = Slide {
+        time: Duration::from_millis(60),
+        bend_range: 2,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "time" => slide.time = Duration::from_millis(integer_in(field, 0..=2000)? as u64),
+            "bend_range" => slide.bend_range = integer_in(field, 1..=24)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(slide)
+}
+
+fn sustain(entry: &Entry) -> Result<SustainConfig, toml::Error> {
+    let mut sustain = SustainConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "inverted" => sustain.inverted = boolean(field)?,
+            "wheel" => sustain.wheel = boolean(field)?,
+            "wheel_step" => sustain.wheel_step = integer_in(field, 1..=127)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(sustain)
+}
+
+fn harmonize(entry: &Entry, names: NoteNames) -> Result<Interval, toml::Error> {
+    let (mut semitones, mut steps) = (None, None);
+    let (mut scale, mut root) = (Scale::Major, 0);
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "interval" => semitones = Some(integer_in(field, -24..=24)? as i8),
+            "steps" => steps = Some(integer_in(field, -14..=14)? as i8),
+            "scale" => {
+                let name = string(field)?;
+                scale = match Scale::from_name(name) {
+                    Some(scale) => scale,
+                    None => return invalid(field.pos, format!("unknown scale '{}'", name)),
+                };
+            }
+            "root" => root = note(field.pos, &field.value, names)? % 12,
+            _ => return unknown_key(field),
+        }
+    }
+
+    match (semitones, steps) {
+        (Some(semitones), None) => Ok(Interval::Semitones(semitones)),
+        (None, Some(steps)) => Ok(Interval::Diatonic { scale, root, steps }),
+        (Some(_), Some(_)) => invalid(entry.pos, "harmonize takes 'interval' or 'steps', not both"),
+        (None, None) => invalid(entry.pos, "harmonize needs an 'interval' or 'steps'"),
+    }
+}
+
+fn panic_cc(entry: &Entry) -> Result<PanicCc, toml::Error> {
+    let mut controller = None;
+    let mut channel = None;
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "controller" => controller = Some(integer_in(field, 0..=119)? as u8),
+            "channel" => channel = Some(integer_in(field, 1..=16)? as u8 - 1),
+            _ => return unknown_key(field),
+        }
+    }
+
+    match controller {
+        Some(controller) => Ok(PanicCc {
+            controller,
+            channel,
+        }),
+        None => invalid(entry.pos, "missing 'controller' in panic_cc"),
+    }
+}
+
+fn key_detect(entry: &Entry) -> Result<KeyDetectConfig, toml::Error> {
+    let mut key_detect = KeyDetectConfig {
+        notes: 24,
+        follow: false,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "notes" => key_detect.notes = integer_in(field, 8..=256)? as usize,
+            "follow" => key_detect.follow = boolean(field)?,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(key_detect)
+}
+
+fn progression(entry: &Entry) -> Result<ProgressionConfig, toml::Error> {
+    let mut progression = ProgressionConfig {
+        chords: Vec::new(),
+        lock: Lock::Scale,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "chords" => {
+                for value in array(field)? {
+                    let name = match value {
+                        Value::String(name) => name,
+                        _ => return invalid(field.pos, "'chords' takes chord names"),
+                    };
+                    progression.chords.push(chord(field.pos, name)?);
+                }
+            }
+            "file" => {
+                let path = string(field)?;
+                let text = match fs::read_to_string(path) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        return invalid(field.pos, format!("can't read '{}': {}", path, err))
+                    }
+                };
+                for name in text.split(|c: char| c.is_whitespace() || c == '|') {
+                    if !name.is_empty() {
+                        progression.chords.push(chord(field.pos, name)?);
+                    }
+                }
+            }
+            "lock" => {
+                let name = string(field)?;
+                progression.lock = match Lock::from_name(name) {
+                    Some(lock) => lock,
+                    None => return invalid(field.pos, format!("unknown lock '{}'", name)),
+                };
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    if progression.chords.is_empty() {
+        return invalid(entry.pos, "missing 'chords' or 'file' in progression");
+    }
+    Ok(progression)
+}
+
+fn chord(pos: Pos, name: &str) -> Result<Chord, toml::Error> {
+    match Chord::parse(name) {
+        Some(chord) => Ok(chord),
+        None => invalid(pos, format!("unknown chord '{}'", name)),
+    }
+}
+
+fn range(entry: &Entry, names: NoteNames) -> Result<NoteRange, toml::Error> {
+    let mut range = NoteRange {
+        low: 0,
+        high: 127,
+        mode: Mode::Fold,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "low" => range.low = note(field.pos, &field.value, names)?,
+            "high" => range.high = note(field.pos, &field.value, names)?,
+            "mode" => {
+                let name = string(field)?;
+                range.mode = match Mode::from_name(name) {
+                    Some(mode) => mode,
+                    None => {
+                        return invalid(
+                            field.pos,
+                            format!("unknown mode '{}', expected fold or clamp", name),
+                        )
+                    }
+                };
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    if range.high < range.low {
+        return invalid(entry.pos, "'high' must not be below 'low'");
+    }
+    Ok(range)
+}
+
+fn note_channels(entry: &Entry) -> Result<NoteChannelsConfig, toml::Error> {
+    let mut note_channels = NoteChannelsConfig {
+        channels: Vec::new(),
+        round_robin: false,
+        pan_spread: 0,
+    };
+    let (mut first, mut last) = (None, None);
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "first" => first = Some(integer_in(field, 1..=16)? as u8 - 1),
+            "last" => last = Some(integer_in(field, 1..=16)? as u8 - 1),
+            "channels" => {
+                for value in array(field)? {
+                    let channel = match *value {
+                        Value::Integer(n @ 1..=16) => n as u8 - 1,
+                        _ => return invalid(field.pos, "'channels' takes MIDI channels (1-16)"),
+                    };
+                    if note_channels.channels.contains(&channel) {
+                        return invalid(field.pos, format!("channel {} given twice", channel + 1));
+                    }
+                    note_channels.channels.push(channel);
+                }
+                if note_channels.channels.is_empty() {
+                    return invalid(field.pos, "'channels' must not be empty");
+                }
+            }
+            "round_robin" => note_channels.round_robin = boolean(field)?,
+            "pan_spread" => note_channels.pan_spread = integer_in(field, 0..=100)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+
+    if !note_channels.channels.is_empty() {
+        if first.is_some() || last.is_some() {
+            return invalid(
+                entry.pos,
+                "'channels' can't be given with 'first' or 'last'",
+            );
+        }
+        return Ok(note_channels);
+    }
+    let (first, last) = (first.unwrap_or(1), last.unwrap_or(15));
+    if last < first {
+        return invalid(entry.pos, "'last' must not be before 'first'");
+    }
+    note_channels.channels = (first..=last).collect();
+    Ok(note_channels)
+}
+
+fn pressure(entry: &Entry, claims: &mut Claims) -> Result<PressureConfig, toml::Error> {
+    let mut pressure = PressureConfig {
+        keys: Vec::new(),
+        target: Target::Aftertouch,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "keys" => {
+                for value in array(field)? {
+                    let key = match value {
+                        Value::String(name) => match keys::scancode(name) {
+                            Some(scancode) => scancode,
+                            None => return unknown_key_name(field.pos, name),
+                        },
+                        _ => return invalid(field.pos, "keys must be key names like \"KeyA\""),
+                    };
+                    claims.claim(key, "the pressure cluster".to_string(), field.pos)?;
+                    pressure.keys.push(key);
+                }
+            }
+            "target" => {
+                let name = string(field)?;
+                pressure.target = match Target::from_name(name) {
+                    Some(target) => target,
+                    None => {
+                        return invalid(
+                            field.pos,
+                            format!(
+                                "unknown target '{}', expected aftertouch or mod_wheel",
+                                name
+                            ),
+                        )
+                    }
+                };
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    if pressure.keys.is_empty() {
+        return invalid(entry.pos, "missing 'keys' in pressure");
+    }
+    Ok(pressure)
+}
+
+fn split(entry: &Entry, names: NoteNames) -> Result<SplitConfig, toml::Error> {
+    let mut split = SplitConfig {
+        note: 60,
+        lower: Zone {
+            channel: 0,
+            transpose: 0,
+            feel: 0.0,
+            release_delay: None,
+            color: None,
+        },
+        upper: Zone {
+            channel: 0,
+            transpose: 0,
+            feel: 0.0,
+            release_delay: None,
+            color: None,
+        },
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "note" => split.note = note(field.pos, &field.value, names)?,
+            "lower" => split.lower = zone(field)?,
+            "upper" => split.upper = zone(field)?,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(split)
+}
+
+fn zone(entry: &Entry) -> Result<Zone, toml::Error> {
+    let mut zone = Zone {
+        channel: 0,
+        transpose: 0,
+        feel: 0.0,
+        release_delay: None,
+        color: None,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "channel" => zone.channel = integer_in(field, 1..=16)? as u8 - 1,
+            "transpose" => zone.transpose = integer_in(field, -48..=48)? as i8,
+            "feel" => zone.feel = number_in(field, -MAX_FEEL..=MAX_FEEL)?,
+            "release_delay" => zone.release_delay = Some(number_in(field, 0.0..=10000.0)?),
+            "color" => zone.color = Some(color(field)?),
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(zone)
+}
+
+fn numpad(entry: &Entry, claims: &mut Claims) -> Result<NumpadConfig, toml::Error> {
+    let mut numpad = NumpadConfig {
+        lowest: 36,
+        channel: 1,
+        velocity: 100,
+        stack_window: 0,
+        stack_step: 15,
+    };
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "channel" => numpad.channel = integer_in(field, 1..=16)? as u8 - 1,
+            // Up to where NumpadDecimal is still a note
+            "octave" => numpad.lowest = ((integer_in(field, -1..=8)? + 1) * 12) as u8,
+            "velocity" => numpad.velocity = integer_in(field, 1..=127)? as u8,
+            "stack_window" => numpad.stack_window = integer_in(field, 0..=1000)? as u64,
+            "stack_step" => numpad.stack_step = integer_in(field, 0..=127)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+    for name in keymap::NUMPAD {
+        claims.claim(
+            keys::scancode(name).unwrap(),
+            "the numpad".to_string(),
+            entry.pos,
+        )?;
+    }
+
+    Ok(numpad)
+}
+
+fn key_colors(entry: &Entry, names: NoteNames) -> Result<[Option<Color>; 128], toml::Error> {
+    let mut colors = [None; 128];
+    // Notes with their octave go over the ones without, wherever they are in the table
+    let mut notes = Vec::new();
+
+    for field in table(entry)?.iter() {
+        let color = color(field)?;
+        if let Some(note) = names.parse(&field.key) {
+            notes.push((note, color));
+        } else if let Some(note) = names.parse(&format!("{}4", field.key)) {
+            for note in (note % 12..128).step_by(12) {
+                colors[note as usize] = Some(color);
+            }
+        } else {
+            return invalid(field.pos, format!("unknown note '{}'", field.key));
+        }
+    }
+    for (note, color) in notes {
+        colors[note as usize] = Some(color);
+    }
+
+    Ok(colors)
+}
+
+fn snapshots(entry: &Entry, claims: &mut Claims) -> Result<Vec<ScanCode>, toml::Error> {
+    let mut snapshots = Vec::new();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "keys" => {
+                for value in array(field)? {
+                    let key = match value {
+                        Value::String(name) => match keys::scancode(name) {
+                            Some(scancode) => scancode,
+                            None => return unknown_key_name(field.pos, name),
+                        },
+                        _ => return invalid(field.pos, "keys must be key names like \"KeyA\""),
+                    };
+                    claims.claim(key, "a snapshot".to_string(), field.pos)?;
+                    snapshots.push(key);
+                }
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(snapshots)
+}
+
+fn chance(entry: &Entry) -> Result<ChanceConfig, toml::Error> {
+    let mut chance = ChanceConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "keys" => {
+                for key_entry in table(field)?.iter() {
+                    let key = match keys::scancode(&key_entry.key) {
+                        Some(key) => key,
+                        None => return unknown_key_name(key_entry.pos, &key_entry.key),
+                    };
+                    let mut key_rule = Rule::default();
+                    for rule_field in table(key_entry)?.iter() {
+                        rule(rule_field, &mut key_rule)?;
+                    }
+                    chance.keys.insert(key, key_rule);
+                }
+            }
+            _ => rule(field, &mut chance.rule)?,
+        }
+    }
+
+    Ok(chance)
+}
+
+/// Sets a field of a [`Rule`] of `[chance]`.
+fn rule(entry: &Entry, rule: &mut Rule) -> Result<(), toml::Error> {
+    match entry.key.as_str() {
+        "chance" => rule.percent = integer_in(entry, 0..=100)? as u8,
+        "every" => rule.every = integer_in(entry, 1..=64)? as u32,
+        _ => return unknown_key(entry),
+    }
+    Ok(())
+}
+
+fn generate(entry: &Entry, names: NoteNames) -> Result<GenerateConfig, toml::Error> {
+    let mut generate = GenerateConfig::default();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "scale" => {
+                let name = string(field)?;
+                generate.scale = match Scale::from_name(name) {
+                    Some(scale) => scale,
+                    None => return invalid(field.pos, format!("unknown scale '{}'", name)),
+                };
+            }
+            "rhythm" => {
+                let name = string(field)?;
+                generate.rhythm = match Rhythm::from_name(name) {
+                    Some(rhythm) => rhythm,
+                    None => {
+                        return invalid(
+                            field.pos,
+                            format!("unknown rhythm '{}', expected euclidean or random", name),
+                        )
+                    }
+                };
+            }
+            "root" => generate.root = note(field.pos, &field.value, names)?,
+            "density" => generate.density = integer_in(field, 0..=generate::STEPS as i64)? as u8,
+            "velocity" => generate.velocity = integer_in(field, 1..=127)? as u8,
+            _ => return unknown_key(field),
+        }
+    }
+
+    Ok(generate)
+}
+
+/// Adds the notes of the `[notes]` table to `notes`.
+fn notes(
+    entry: &Entry,
+    names: NoteNames,
+    claims: &mut Claims,
+    notes: &mut HashMap<ScanCode, u8>,
+) -> Result<(), toml::Error> {
+    for note_entry in table(entry)?.iter() {
+        let key = match keys::scancode(&note_entry.key) {
+            Some(key) => key,
+            None => return unknown_key_name(note_entry.pos, &note_entry.key),
+        };
+        let note = note(note_entry.pos, &note_entry.value, names)?;
+        claims.claim(key, format!("note {}", names.name(note)), note_entry.pos)?;
+        notes.insert(key, note);
+    }
+
+    Ok(())
+}
+
+fn chords(
+    entry: &Entry,
+    names: NoteNames,
+    claims: &mut Claims,
+) -> Result<HashMap<ScanCode, Vec<u8>>, toml::Error> {
+    let mut chords = HashMap::new();
+
+    for chord_entry in table(entry)?.iter() {
+        let key = match keys::scancode(&chord_entry.key) {
+            Some(key) => key,
+            None => return unknown_key_name(chord_entry.pos, &chord_entry.key),
+        };
+        let notes = array(chord_entry)?
+            .iter()
+            .map(|value| note(chord_entry.pos, value, names))
+            .collect::<Result<_, _>>()?;
+        claims.claim(key, "a chord".to_string(), chord_entry.pos)?;
+        chords.insert(key, notes);
+    }
+
+    Ok(chords)
+}
+
+fn practice(entry: &Entry, names: NoteNames) -> Result<Vec<Vec<u8>>, toml::Error> {
+    let mut sequences = Vec::new();
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "sequences" => {
+                for value in array(field)? {
+                    let notes = match value {
+                        Value::Array(notes) if !notes.is_empty() => notes,
+                        _ => return invalid(field.pos, "each sequence must be an array of notes"),
+                    };
+                    let notes = notes.iter().map(|note| self::note(field.pos, note, names));
+                    sequences.push(notes.collect::<Result<_, _>>()?);
+                }
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    if sequences.is_empty() {
+        return invalid(entry.pos, "missing 'sequences' in practice");
+    }
+    Ok(sequences)
+}
+
+/// The kinds of message in `types`, for thru rules and sinks.
+fn kinds(entry: &Entry) -> Result<Vec<Kind>, toml::Error> {
+    array(entry)?
+        .iter()
+        .map(|value| {
+            let kind = match value {
+                Value::String(name) => Kind::from_name(name),
+                _ => None,
+            };
+            kind.map_or_else(
+                || {
+                    invalid(
+                        entry.pos,
+                        "types must be note, control_change, program, pressure or pitch_bend",
+                    )
+                },
+                Ok,
+            )
+        })
+        .collect()
+}
+
+fn sinks(entry: &Entry) -> Result<HashMap<String, Filter>, toml::Error> {
+    table(entry)?
+        .iter()
+        .map(|sink| Ok((sink.key.clone(), sink_filter(sink)?)))
+        .collect()
+}
+
+fn sink_filter(entry: &Entry) -> Result<Filter, toml::Error> {
+    let mut filter = Filter::default();
+    for entry in table(entry)?.iter() {
+        match entry.key.as_str() {
+            "channels" => {
+                for value in array(entry)? {
+                    match value {
+                        Value::Integer(channel @ 1..=16) => {
+                            filter.channels.push(*channel as u8 - 1)
+                        }
+                        _ => return invalid(entry.pos, "channels must be from 1 to 16"),
+                    }
+                }
+            }
+            "types" => filter.kinds = kinds(entry)?,
+            _ => return unknown_key(entry),
+        }
+    }
+    Ok(filter)
+}
+
+fn thru_rule(pos: Pos, value: &Value, names: NoteNames) -> Result<thru::Rule, toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each thru rule must be a table"),
+    };
+    let mut rule = thru::Rule::default();
+    let (mut scale, mut root) = (None, None);
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "channel" => rule.channel = Some(integer_in(entry, 1..=16)? as u8 - 1),
+            "types" => rule.kinds = kinds(entry)?,
+            "drop" => rule.drop = boolean(entry)?,
+            "to_channel" => rule.to_channel = Some(integer_in(entry, 1..=16)? as u8 - 1),
+            "transpose" => rule.transpose = integer_in(entry, -48..=48)? as i8,
+            "scale" => {
+                let name = string(entry)?;
+                scale = match Scale::from_name(name) {
+                    Some(scale) => Some(scale),
+                    None => return invalid(entry.pos, format!("unknown scale '{}'", name)),
+                };
+            }
+            "root" => root = Some(note(entry.pos, &entry.value, names)? % 12),
+            "velocity" => {
+                rule.velocity = match array(entry)? {
+                    &[Value::Integer(low @ 1..=127), Value::Integer(high @ 1..=127)]
+                        if low <= high =>
+                    {
+                        Some((low as u8, high as u8))
+                    }
+                    _ => {
+                        return invalid(
+                            entry.pos,
+                            "'velocity' must be the lowest and highest velocity, from 1 to 127",
+                        )
+                    }
+                };
+            }
+            _ => return unknown_key(entry),
+        }
+    }
+
+    rule.scale = match (scale, root) {
+        (Some(scale), root) => Some((scale, root.unwrap_or(0))),
+        (None, Some(_)) => return invalid(pos, "'root' in a thru rule needs a 'scale'"),
+        (None, None) => None,
+    };
+    Ok(rule)
+}
+
+/// The notes of the built-in keymap named by `entry`.
+fn keymap(entry: &Entry) -> Result<Vec<(ScanCode, u8)>, toml::Error> {
+    let name = string(entry)?;
+    match keymap::builtin(name) {
+        Some(notes) => Ok(notes),
+        None => invalid(
+            entry.pos,
+            format!(
+                "unknown keymap '{}', expected left_hand or right_hand",
+                name
+            ),
+        ),
+    }
+}
+
+fn device(pos: Pos, value: &Value, names: NoteNames) -> Result<Device, toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each device must be a table"),
+    };
+    let mut matcher = None;
+    let mut device = Device {
+        matcher: Matcher::Name(String::new()),
+        channel: DEFAULT_CHANNEL,
+        transpose: 0,
+        notes: HashMap::new(),
+    };
+    // Its keys are its own, apart from those of the window
+    let mut claims = Claims::default();
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "name" => matcher = Some(Matcher::Name(string(entry)?.to_string())),
+            "path" => matcher = Some(Matcher::Path(PathBuf::from(string(entry)?))),
+            "channel" => device.channel = integer_in(entry, 1..=16)? as u8 - 1,
+            "transpose" => device.transpose = integer_in(entry, -48..=48)? as i8,
+            "keymap" => {
+                for (key, note) in keymap(entry)? {
+                    claims.claim(key, format!("note {}", names.name(note)), entry.pos)?;
+                    device.notes.insert(key, note);
+                }
+            }
+            "notes" => notes(entry, names, &mut claims, &mut device.notes)?,
+            _ => return unknown_key(entry),
+        }
+    }
+
+    match matcher {
+        Some(matcher) => Ok(Device { matcher, ..device }),
+        None => invalid(pos, "each device needs a 'name' or a 'path'"),
+    }
+}
+
+fn background(entry: &Entry) -> Result<Background, toml::Error> {
+    let (mut matcher, mut keys, mut capture) = (None, Vec::new(), None);
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "name" => matcher = Some(Matcher::Name(string(field)?.to_string())),
+            "path" => matcher = Some(Matcher::Path(PathBuf::from(string(field)?))),
+            "keys" => {
+                keys = array(field)?
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(name) => match keys::scancode(name) {
+                            Some(scancode) => Ok(scancode),
+                            None => unknown_key_name(field.pos, name),
+                        },
+                        _ => invalid(field.pos, "keys must be key names like \"KeyA\""),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "capture" => {
+                let name = string(field)?;
+                match keys::scancode(name) {
+                    Some(scancode) => capture = Some(scancode),
+                    None => return unknown_key_name(field.pos, name),
+                }
+            }
+            _ => return unknown_key(field),
+        }
+    }
+
+    match matcher {
+        Some(matcher) => Ok(Background {
+            matcher,
+            keys,
+            capture,
+        }),
+        None => invalid(entry.pos, "background needs a 'name' or a 'path'"),
+    }
+}
+
+fn gesture(pos: Pos, value: &Value, names: NoteNames) -> Result<Gesture, toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each gesture must be a table"),
+    };
+    let (mut key, mut tap) = (None, None);
+    let mut gesture = Gesture {
+        key: 0,
+        tap: 0,
+        double_tap: Vec::new(),
+        latch: false,
+        double_tap_time: Duration::from_millis(250),
+        long_press_time: Duration::from_millis(500),
+    };
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "key" => {
+                let name = string(entry)?;
+                key = match keys::scancode(name) {
+                    Some(scancode) => Some(scancode),
+                    None => return unknown_key_name(entry.pos, name),
+                };
+            }
+            "tap" => tap = Some(note(entry.pos, &entry.value, names)?),
+            "double_tap" => {
+                gesture.double_tap = array(entry)?
+                    .iter()
+                    .map(|value| note(entry.pos, value, names))
+                    .collect::<Result<_, _>>()?;
+            }
+            "latch" => gesture.latch = boolean(entry)?,
+            "double_tap_time" => {
+                gesture.double_tap_time =
+                    Duration::from_millis(integer_in(entry, 50..=2000)? as u64)
+            }
+            "long_press_time" => {
+                gesture.long_press_time =
+                    Duration::from_millis(integer_in(entry, 50..=5000)? as u64)
+            }
+            _ => return unknown_key(entry),
+        }
+    }
+
+    match (key, tap) {
+        (Some(key), Some(tap)) => Ok(Gesture {
+            key,
+            tap,
+            ..gesture
+        }),
+        _ => invalid(pos, "missing 'key' or 'tap' in gesture"),
+    }
+}
+
+fn key_up(pos: Pos, value: &Value, names: NoteNames) -> Result<KeyUp, toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each key_up must be a table"),
+    };
+    let (mut key, mut note) = (None, None);
+    let mut key_up = KeyUp {
+        key: 0,
+        press: None,
+        note: 0,
+        length: Duration::from_millis(100),
+    };
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "key" => {
+                let name = string(entry)?;
+                key = match keys::scancode(name) {
+                    Some(scancode) => Some(scancode),
+                    None => return unknown_key_name(entry.pos, name),
+                };
+            }
+            "press" => key_up.press = Some(self::note(entry.pos, &entry.value, names)?),
+            "note" => note = Some(self::note(entry.pos, &entry.value, names)?),
+            "length" => key_up.length = Duration::from_millis(integer_in(entry, 10..=5000)? as u64),
+            _ => return unknown_key(entry),
+        }
+    }
+
+    match (key, note) {
+        (Some(key), Some(note)) => Ok(KeyUp {
+            key,
+            note,
+            ..key_up
+        }),
+        _ => invalid(pos, "missing 'key' or 'note' in key_up"),
+    }
+}
+
+fn euclid(pos: Pos, value: &Value, names: NoteNames) -> Result<(ScanCode, Pattern), toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each euclid must be a table"),
+    };
+    let (mut key, mut pulses, mut note) = (None, None, None);
+    let mut pattern = Pattern {
+        pulses: 0,
+        steps: 16,
+        channel: DEFAULT_CHANNEL,
+        note: 0,
+        velocity: 100,
+    };
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "key" => {
+                let name = string(entry)?;
+                key = match keys::scancode(name) {
+                    Some(scancode) => Some(scancode),
+                    None => return unknown_key_name(entry.pos, name),
+                };
+            }
+            "pulses" => pulses = Some(integer_in(entry, 0..=64)? as u32),
+            "steps" => pattern.steps = integer_in(entry, 1..=64)? as u32,
+            "note" => note = Some(self::note(entry.pos, &entry.value, names)?),
+            "channel" => pattern.channel = integer_in(entry, 1..=16)? as u8 - 1,
+            "velocity" => pattern.velocity = integer_in(entry, 1..=127)? as u8,
+            _ => return unknown_key(entry),
+        }
+    }
+
+    match (key, pulses, note) {
+        (Some(key), Some(pulses), Some(note)) => {
+            pattern.pulses = pulses.min(pattern.steps);
+            pattern.note = note;
+            Ok((key, pattern))
+        }
+        (None, _, _) => invalid(pos, "missing 'key' in euclid"),
+        (_, None, _) => invalid(pos, "missing 'pulses' in euclid"),
+        (_, _, None) => invalid(pos, "missing 'note' in euclid"),
+    }
+}
+
+fn key_macro(pos: Pos, value: &Value) -> Result<(ScanCode, Vec<(f64, MidiMsg)>), toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each macro must be a table"),
+    };
+    let (mut key, mut steps) = (None, None);
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "key" => {
+                let name = string(entry)?;
+                key = match keys::scancode(name) {
+                    Some(scancode) => Some(scancode),
+                    None => return unknown_key_name(entry.pos, name),
+                };
+            }
+            "steps" => {
+                steps = Some(
+                    array(entry)?
+                        .iter()
+                        .map(|step| macro_step(entry.pos, step))
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
+            }
+            _ => return unknown_key(entry),
+        }
+    }
+
+    match (key, steps) {
+        (Some(key), Some(steps)) => Ok((key, steps)),
+        (None, _) => invalid(pos, "missing 'key' in macro"),
+        (_, None) => invalid(pos, "missing 'steps' in macro"),
+    }
+}
+
+/// A step like `[100, "off 36"]`.
+fn macro_step(pos: Pos, value: &Value) -> Result<(f64, MidiMsg), toml::Error> {
+    let (time, line) = match value {
+        Value::Array(step) => match step.as_slice() {
+            [Value::Integer(time), Value::String(line)] => (*time as f64, line),
+            [Value::Float(time), Value::String(line)] => (*time, line),
+            _ => return invalid(pos, "macro steps must be like [100, \"on 60\"]"),
+        },
+        _ => return invalid(pos, "macro steps must be like [100, \"on 60\"]"),
+    };
+    if !(0.0..=60_000.0).contains(&time) {
+        return invalid(
+            pos,
+            "macro steps must be from 0 to 60000 ms after the press",
+        );
+    }
+
+    match protocol::parse_line(line, DEFAULT_CHANNEL) {
+        Ok(Some(Command::Midi(midi))) => Ok((time, midi)),
+        Ok(_) => invalid(pos, format!("'{}' is not a MIDI message", line)),
+        Err(err) => invalid(pos, format!("'{}': {}", line, err)),
+    }
+}
+
+fn preset(pos: Pos, value: &Value) -> Result<Preset, toml::Error> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return invalid(pos, "each preset must be a table"),
+    };
+    let mut preset = Preset::default();
+
+    for entry in table.iter() {
+        match entry.key.as_str() {
+            "name" => preset.name = string(entry)?.to_string(),
+            "color" => preset.color = Some(color(entry)?),
+            "programs" => preset.programs = program_map(entry)?,
+            "controllers" => preset.controllers = controller_map(entry)?,
+            _ => return unknown_key(entry),
+        }
+    }
+
+    Ok(preset)
+}
+
+fn controller_map(entry: &Entry) -> Result<ControllerMap, toml::Error> {
+    let mut controllers = ControllerMap::new();
+
+    for channel_entry in table(entry)?.iter() {
+        let channel = match channel_entry.key.parse::<u8>() {
+            Ok(channel @ 1..=16) => channel - 1,
+            _ => {
+                return invalid(
+                    channel_entry.pos,
+                    format!("'{}' is not a MIDI channel (1-16)", channel_entry.key),
+                )
+            }
+        };
+
+        for field in table(channel_entry)?.iter() {
+            let controller = match field.key.parse::<u8>() {
+                Ok(controller @ 0..=119) => controller,
+                _ => {
+                    return invalid(
+                        field.pos,
+                        format!("'{}' is not a controller (0-119)", field.key),
+                    )
+                }
+            };
+            controllers.insert((channel, controller), integer_in(field, 0..=127)? as u8);
+        }
+    }
+
+    Ok(controllers)
+}
+
+fn morph(entry: &Entry, presets: usize) -> Result<MorphConfig, toml::Error> {
+    let mut from = None;
+    let mut to = None;
+    let mut seconds = 2.0;
+
+    for field in table(entry)?.iter() {
+        match field.key.as_str() {
+            "from" => from = Some(integer_in(field, 1..=presets.max(1) as i64)? as usize - 1),
+            "to" => to = Some(integer_in(field, 1..=presets.max(1) as i64)? as usize - 1),
+            "seconds" => seconds = number_in(field, 0.1..=60.0)?,
+            _ => return unknown_key(field),
+        }
+    }
+
+    match (from, to) {
+        (Some(from), Some(to)) if presets > 0 => Ok(MorphConfig { from, to, seconds }),
+        (Some(_), Some(_)) => invalid(entry.pos, "morph needs presets to morph between"),
+        _ => invalid(entry.pos, "missing 'from' or 'to' in morph"),
+    }
+}
+
+fn program_map(entry: &Entry) -> Result<ProgramMap, toml::Error> {
+    let mut programs = ProgramMap::new();
+
+    for channel_entry in table(entry)?.iter() {
+        let channel = match channel_entry.key.parse::<u8>() {
+            Ok(channel @ 1..=16) => channel - 1,
+            _ => {
+                return invalid(
+                    channel_entry.pos,
+                    format!("'{}' is not a MIDI channel (1-16)", channel_entry.key),
+                )
+            }
+        };
+
+        let mut bank = None;
+        let mut program = None;
+        for field in table(channel_entry)?.iter() {
+            match field.key.as_str() {
+                "bank" => bank = Some(integer_in(field, 0..=16383)? as u16),
+                "program" => program = Some(integer_in(field, 0..=127)? as u8),
+                _ => return unknown_key(field),
+            }
+        }
+
+        let program = match program {
+            Some(program) => program,
+            None => return invalid(channel_entry.pos, "missing 'program'"),
+        };
+        programs.insert(channel, ProgramSelect { bank, program });
+    }
+
+    Ok(programs)
+}
+
+fn rate(entry: &Entry) -> Result<Rate, toml::Error> {
+    let name = string(entry)?;
+    match Rate::from_name(name) {
+        Some(rate) => Ok(rate),
+        None => invalid(
+            entry.pos,
+            format!("unknown rate '{}', expected 1/8, 1/16, 1/16t or 1/32", name),
+        ),
+    }
+}
+
+fn invalid<T>(pos: Pos, message: impl Into<String>) -> Result<T, toml::Error> {
+    Err(toml::Error {
+        pos,
+        message: message.into(),
+    })
+}
+
+fn unknown_key<T>(entry: &Entry) -> Result<T, toml::Error> {
+    let known = template_keys();
+    let suggestion = did_you_mean(&entry.key, known.iter().map(String::as_str));
+    invalid(
+        entry.pos,
+        format!("unknown key '{}'{}", entry.key, suggestion),
+    )
+}
+
+/// For a name that isn't one of the keys of the keyboard.
+fn unknown_key_name<T>(pos: Pos, name: &str) -> Result<T, toml::Error> {
+    let suggestion = did_you_mean(name, keys::names());
+    invalid(pos, format!("unknown key '{}'{}", name, suggestion))
+}
+
+/// Every key of every table in [`TEMPLATE`], which are all the keys there are but for those
+/// naming keys of the keyboard or notes.
+fn template_keys() -> Vec<String> {
+    fn collect(table: &Table, keys: &mut Vec<String>) {
+        for entry in table.iter() {
+            keys.push(entry.key.clone());
+            match &entry.value {
+                Value::Table(table) => collect(table, keys),
+                Value::Array(values) => {
+                    for value in values {
+                        if let Value::Table(table) = value {
+                            collect(table, keys);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let mut keys = Vec::new();
+    if let Ok(table) = toml::parse(TEMPLATE) {
+        collect(&table, &mut keys);
+    }
+    keys
+}
+
+/// `, did you mean '...'?` with the one of `known` closest to `name`, if one is close enough
+/// to be a typo of it, or else nothing.
+fn did_you_mean<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> String {
+    let lower = name.to_lowercase();
+    // A typo or two, fewer in short names
+    let most = (lower.chars().count() / 4).clamp(1, 3);
+    let closest = known
+        .map(|candidate| (edit_distance(&lower, &candidate.to_lowercase()), candidate))
+        .filter(|&(distance, _)| distance <= most)
+        .min_by_key(|&(distance, _)| distance);
+
+    match closest {
+        Some((_, candidate)) => format!(", did you mean '{}'?", candidate),
+        None => String::new(),
+    }
+}
+
+/// How many characters have to be inserted, removed, replaced or swapped with the next to turn
+/// `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Rows for the prefixes of `a` two back, one back and the current one
+    let mut before: Vec<usize> = Vec::new();
+    let mut last: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (last[j] + 1).min(row[j - 1] + 1).min(last[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut last, row);
+    }
+
+    last[b.len()]
+}
+
+fn expected<T>(entry: &Entry, what: &str) -> Result<T, toml::Error> {
+    invalid(
+        entry.pos,
+        format!(
+            "'{}' should be {}, not {}",
+            entry.key,
+            what,
+            entry.value.type_name()
+        ),
+    )
+}
+
+fn table(entry: &Entry) -> Result<&Table, toml::Error> {
+    match &entry.value {
+        Value::Table(table) => Ok(table),
+        _ => expected(entry, "a table"),
+    }
+}
+
+fn array(entry: &Entry) -> Result<&[Value], toml::Error> {
+    match &entry.value {
+        Value::Array(values) => Ok(values),
+        _ => expected(entry, "an array"),
+    }
+}
+
+fn string(entry: &Entry) -> Result<&str, toml::Error> {
+    match &entry.value {
+        Value::String(string) => Ok(string),
+        _ => expected(entry, "a string"),
+    }
+}
+
+/// A note, as a number or a name in `names`.
+fn note(pos: Pos, value: &Value, names: NoteNames) -> Result<u8, toml::Error> {
+    match value {
+        Value::Integer(n @ 0..=127) => Ok(*n as u8),
+        Value::String(name) => match names.parse(name) {
+            Some(note) => Ok(note),
+            None => invalid(
+                pos,
+                format!("unknown note '{}', expected e.g. {}", name, names.name(60)),
+            ),
+        },
+        _ => invalid(
+            pos,
+            format!(
+                "notes must be numbers from 0 to 127 or names like {}",
+                names.name(60)
+            ),
+        ),
+    }
+}
+
+/// A color written like `"#3a2430"`.
+fn color(entry: &Entry) -> Result<Color, toml::Error> {
+    let hex = string(entry)?
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6);
+    match hex.and_then(|hex| Color::from_str_radix(hex, 16).ok()) {
+        Some(color) => Ok(color),
+        None => invalid(entry.pos, "colors must be written like \"#3a2430\""),
+    }
+}
+
+fn boolean(entry: &Entry) -> Result<bool, toml::Error> {
+    match entry.value {
+        Value::Boolean(b) => Ok(b),
+        _ => expected(entry, "true or false"),
+    }
+}
+
+fn number_in(entry: &Entry, range: RangeInclusive<f64>) -> Result<f64, toml::Error> {
+    let n = match entry.value {
+        Value::Integer(n) => n as f64,
+        Value::Float(n) => n,
+        _ => return expected(entry, "a number"),
+    };
+
+    if range.contains(&n) {
+        Ok(n)
+    } else {
+        invalid(
+            entry.pos,
+            format!(
+                "'{}' must be between {} and {}, not {}",
+                entry.key,
+                range.start(),
+                range.end(),
+                n
+            ),
+        )
+    }
+}
+
+fn integer_in(entry: &Entry, range: RangeInclusive<i64>) -> Result<i64, toml::Error> {
+    match entry.value {
+        Value::Integer(n) if range.contains(&n) => Ok(n),
+        Value::Integer(n) => invalid(
+            entry.pos,
+            format!(
+                "'{}' must be between {} and {}, not {}",
+                entry.key,
+                range.start(),
+                range.end(),
+                n
+            ),
+        ),
+        _ => expected(entry, "an integer"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_is_valid() {
+        let table = toml::parse(TEMPLATE).unwrap();
+        if let Err(err) = Config::from_table(&table) {
+            panic!("{}", err);
+        }
+        assert!(!template_keys().is_empty());
+    }
+
+    #[test]
+    fn commented_template_changes_nothing() {
+        assert_eq!(
+            toml::parse(&commented_template()).unwrap().iter().count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! `--daemon`: running without a window, e.g. as a systemd user service keeping the MIDI port
+//! in a persistent audio session. Everything played comes from the input sources, like
+//! `--stdin` and `--websocket`, and the config. SIGTERM or SIGINT stops every note before
+//! exiting, so the client leaves the session without anything left hanging.
+
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    block::{self, Block},
+    config::Config,
+    engine::Control,
+    panic,
+    protocol::Command,
+    send, send_preset,
+    session_log::{self, report},
+    websocket, KeyboardMsg, UserEvent,
+};
+
+/// How often the loop checks for a signal while nothing arrives.
+const POLL: Duration = Duration::from_millis(100);
+/// How long the note offs are given to be played before the client is closed.
+const DRAIN: Duration = Duration::from_millis(200);
+
+/// Handles the events from the input sources until a signal stops it.
+pub fn run(
+    events: Receiver<UserEvent>,
+    tx: &Sender<KeyboardMsg>,
+    controls: &Sender<Control>,
+    config: &Config,
+    websocket: Option<&websocket::Broadcaster>,
+) {
+    signals::install();
+    let mut blocks: Vec<Block> = Vec::new();
+
+    while !signals::stopped() {
+        let event = match events.recv_timeout(POLL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                // Nothing can play anymore, but the port stays in the session until stopped
+                thread::sleep(POLL);
+                continue;
+            }
+        };
+        match event {
+            UserEvent::Command(Command::Midi(midi)) => send(tx, midi),
+            UserEvent::Command(Command::Panic) => panic(tx),
+            UserEvent::Command(Command::Block(block)) => {
+                if !blocks.contains(&block) {
+                    blocks.push(block);
+                }
+                let _ = controls.send(Control::Block(blocks.clone()));
+            }
+            UserEvent::Command(Command::Unblock(block)) => {
+                block::unblock(&mut blocks, block);
+                let _ = controls.send(Control::Block(blocks.clone()));
+            }
+            UserEvent::Command(Command::Preset(index)) if index < config.presets.len() => {
+                send_preset(tx, config, websocket, Some(index));
+            }
+            // Only the note keys follow these, and without the window there are none
+            UserEvent::Command(Command::Octave(_) | Command::Channel(_)) => {}
+            _ => {}
+        }
+    }
+
+    report!("stopping");
+    panic(tx);
+    thread::sleep(DRAIN);
+    session_log::record("stop", "signal");
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::{
+        os::raw::c_int,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    static STOPPED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn stop(_signum: c_int) {
+        STOPPED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn install() {
+        for signum in [SIGINT, SIGTERM] {
+            unsafe {
+                signal(signum, stop);
+            }
+        }
+    }
+
+    pub fn stopped() -> bool {
+        STOPPED.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() {}
+
+    pub fn stopped() -> bool {
+        false
+    }
+}
@@ -0,0 +1,585 @@
+//! A small D-Bus interface on the session bus, enabled with `--dbus`, so desktop key bindings,
+//! scripts or a stream deck can control the running keyboard:
+//!
+//! ```text
+//! gdbus call --session --dest io.github.jakobrs.JackKeyboard \
+//!     --object-path /io/github/jakobrs/JackKeyboard \
+//!     --method io.github.jakobrs.JackKeyboard.SetOctave 1
+//! ```
+//!
+//! The methods are `SetOctave(i octaves)`, `SetChannel(u channel)` from 1, `SelectPreset(u
+//! number)` from 1 and `Panic()`, which do what the commands of the same names in the
+//! [`protocol`] do.
+//!
+//! Only as much of the D-Bus wire protocol is spoken as that needs, so there's no dependency on
+//! libdbus.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::{fs::MetadataExt, net::UnixStream},
+    thread,
+};
+
+use crate::{input::InputSource, protocol::Command, session_log::report, shift, Proxy, UserEvent};
+
+const NAME: &str = "io.github.jakobrs.JackKeyboard";
+const PATH: &str = "/io/github/jakobrs/JackKeyboard";
+const INTERFACE: &str = NAME;
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.jakobrs.JackKeyboard">
+    <method name="SetOctave"><arg name="octaves" type="i" direction="in"/></method>
+    <method name="SetChannel"><arg name="channel" type="u" direction="in"/></method>
+    <method name="SelectPreset"><arg name="number" type="u" direction="in"/></method>
+    <method name="Panic"/>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect"><arg name="xml" type="s" direction="out"/></method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const NO_REPLY_EXPECTED: u8 = 0x1;
+/// `DBUS_NAME_FLAG_DO_NOT_QUEUE`: fail rather than wait for another instance to go away.
+const DO_NOT_QUEUE: u32 = 0x4;
+const PRIMARY_OWNER: u32 = 1;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// Listening on the session bus, as an input source.
+pub struct DBus;
+
+impl InputSource for DBus {
+    fn name(&self) -> &'static str {
+        "dbus"
+    }
+
+    fn start(&self, proxy: Proxy) -> Vec<String> {
+        let mut connection = match Connection::open() {
+            Ok(connection) => connection,
+            Err(err) => return vec![format!("session bus: {}", err)],
+        };
+        if let Err(err) = connection.request_name() {
+            return vec![format!("{}: {}", NAME, err)];
+        }
+
+        thread::spawn(move || {
+            if let Err(err) = connection.serve(&proxy) {
+                report!("dbus: {}", err);
+            }
+        });
+        Vec::new()
+    }
+}
+
+/// A message, with only the header fields that are used.
+#[derive(Debug, Default)]
+struct Message {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    destination: Option<String>,
+    sender: Option<String>,
+    signature: String,
+    body: Vec<u8>,
+    /// Whether the message is big endian.
+    big_endian: bool,
+}
+
+struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    serial: u32,
+}
+
+impl Connection {
+    /// Connects and authenticates to the session bus, and says hello.
+    fn open() -> io::Result<Self> {
+        let stream = connect()?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            serial: 0,
+        };
+
+        let uid = fs::metadata("/proc/self")?.uid().to_string();
+        let hex: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+        connection
+            .writer
+            .write_all(format!("\0AUTH EXTERNAL {}\r\n", hex).as_bytes())?;
+        let mut line = String::new();
+        connection.reader.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("authentication failed: {}", line.trim()),
+            ));
+        }
+        connection.writer.write_all(b"BEGIN\r\n")?;
+
+        connection.call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", "", &[])?;
+        Ok(connection)
+    }
+
+    fn request_name(&mut self) -> io::Result<()> {
+        let mut body = Writer::default();
+        body.string(NAME);
+        body.u32(DO_NOT_QUEUE);
+        let reply = self.call(BUS_NAME, BUS_PATH, BUS_NAME, "RequestName", "su", &body.buf)?;
+
+        let mut reader = Reader::new(&reply.body, reply.big_endian);
+        match reader.u32() {
+            Some(PRIMARY_OWNER) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "the name is taken, is another instance running?",
+            )),
+        }
+    }
+
+    /// Calls a method and waits for its reply, skipping whatever arrives in the meantime.
+    fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        signature: &str,
+        body: &[u8],
+    ) -> io::Result<Message> {
+        let serial = self.send(&Message {
+            kind: METHOD_CALL,
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            destination: Some(destination.to_string()),
+            signature: signature.to_string(),
+            body: body.to_vec(),
+            ..Message::default()
+        })?;
+
+        loop {
+            let message = read_message(&mut self.reader)?;
+            if message.reply_serial != Some(serial) {
+                continue;
+            }
+            return match message.kind {
+                ERROR => Err(io::Error::other(format!(
+                    "{} failed: {}",
+                    member,
+                    message.error_name.unwrap_or_default()
+                ))),
+                _ => Ok(message),
+            };
+        }
+    }
+
+    /// Answers method calls until the connection closes or the event loop exits.
+    fn serve(&mut self, proxy: &Proxy) -> io::Result<()> {
+        loop {
+            let call = read_message(&mut self.reader)?;
+            if call.kind != METHOD_CALL {
+                continue;
+            }
+
+            let member = call.member.as_deref().unwrap_or_default();
+            let interface = call.interface.as_deref();
+            let mut reply = Writer::default();
+            let mut signature = "";
+            let result = match (interface, member) {
+                (Some(INTROSPECTABLE), "Introspect") => {
+                    reply.string(INTROSPECTION);
+                    signature = "s";
+                    Ok(None)
+                }
+                (Some(PEER), "Ping") => Ok(None),
+                (Some(INTERFACE) | None, _) if call.path.as_deref() == Some(PATH) => {
+                    command(&call, member).map(Some)
+                }
+                _ => Err((
+                    "org.freedesktop.DBus.Error.UnknownMethod",
+                    format!("no method {} here", member),
+                )),
+            };
+
+            if let Ok(Some(command)) = &result {
+                if proxy
+                    .send_event(UserEvent::Command(command.clone()))
+                    .is_err()
+                {
+                    // The event loop has exited
+                    return Ok(());
+                }
+            }
+            if call.flags & NO_REPLY_EXPECTED != 0 {
+                continue;
+            }
+            let answer = match result {
+                Ok(_) => Message {
+                    kind: METHOD_RETURN,
+                    signature: signature.to_string(),
+                    body: reply.buf,
+                    ..Message::default()
+                },
+                Err((name, text)) => {
+                    let mut body = Writer::default();
+                    body.string(&text);
+                    Message {
+                        kind: ERROR,
+                        error_name: Some(name.to_string()),
+                        signature: "s".to_string(),
+                        body: body.buf,
+                        ..Message::default()
+                    }
+                }
+            };
+            self.send(&Message {
+                reply_serial: Some(call.serial),
+                destination: call.sender.clone(),
+                ..answer
+            })?;
+        }
+    }
+
+    /// Sends `message` with the next serial, which is returned.
+    fn send(&mut self, message: &Message) -> io::Result<u32> {
+        self.serial += 1;
+        let mut w = Writer::default();
+        w.buf
+            .extend_from_slice(&[b'l', message.kind, message.flags, 1]);
+        w.u32(message.body.len() as u32);
+        w.u32(self.serial);
+
+        let fields = w.begin_array(8);
+        let strings = [
+            (FIELD_PATH, "o", &message.path),
+            (FIELD_INTERFACE, "s", &message.interface),
+            (FIELD_MEMBER, "s", &message.member),
+            (FIELD_ERROR_NAME, "s", &message.error_name),
+            (FIELD_DESTINATION, "s", &message.destination),
+        ];
+        for (code, kind, value) in strings {
+            if let Some(value) = value {
+                w.pad(8);
+                w.buf.push(code);
+                w.signature(kind);
+                w.string(value);
+            }
+        }
+        if let Some(serial) = message.reply_serial {
+            w.pad(8);
+            w.buf.push(FIELD_REPLY_SERIAL);
+            w.signature("u");
+            w.u32(serial);
+        }
+        if !message.signature.is_empty() {
+            w.pad(8);
+            w.buf.push(FIELD_SIGNATURE);
+            w.signature("g");
+            w.signature(&message.signature);
+        }
+        w.end_array(fields);
+        w.pad(8);
+
+        w.buf.extend_from_slice(&message.body);
+        self.writer.write_all(&w.buf)?;
+        Ok(self.serial)
+    }
+}
+
+/// The command a method call of our own interface stands for.
+fn command(call: &Message, member: &str) -> Result<Command, (&'static str, String)> {
+    let invalid = |text: String| ("org.freedesktop.DBus.Error.InvalidArgs", text);
+    let mut args = Reader::new(&call.body, call.big_endian);
+    let max = shift::MAX_OCTAVES;
+
+    match (member, call.signature.as_str()) {
+        ("SetOctave", "i") => match args.u32().map(|n| n as i32) {
+            Some(octave) if (-max as i32..=max as i32).contains(&octave) => {
+                Ok(Command::Octave(octave as i8))
+            }
+            _ => Err(invalid(format!("octaves must be from -{} to {}", max, max))),
+        },
+        ("SetChannel", "u") => match args.u32() {
+            Some(channel @ 1..=16) => Ok(Command::Channel(channel as u8 - 1)),
+            _ => Err(invalid("channel must be from 1 to 16".to_string())),
+        },
+        ("SelectPreset", "u") => match args.u32() {
+            Some(number @ 1..) => Ok(Command::Preset(number as usize - 1)),
+            _ => Err(invalid("presets are numbered from 1".to_string())),
+        },
+        ("Panic", "") => Ok(Command::Panic),
+        ("SetOctave" | "SetChannel" | "SelectPreset" | "Panic", signature) => Err(invalid(
+            format!("wrong arguments '{}' for {}", signature, member),
+        )),
+        _ => Err((
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            format!("no method {} here", member),
+        )),
+    }
+}
+
+/// Connects to the first address of the session bus that is a Unix socket.
+fn connect() -> io::Result<UnixStream> {
+    let addresses = env::var("DBUS_SESSION_BUS_ADDRESS").unwrap_or_else(|_| {
+        let runtime = env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+        format!("unix:path={}/bus", runtime)
+    });
+
+    for address in addresses.split(';') {
+        let params = match address.strip_prefix("unix:") {
+            Some(params) => params,
+            None => continue,
+        };
+        for param in params.split(',') {
+            match param.split_once('=') {
+                Some(("path", path)) => return UnixStream::connect(unescape(path)),
+                #[cfg(target_os = "linux")]
+                Some(("abstract", name)) => {
+                    use std::os::linux::net::SocketAddrExt;
+
+                    let addr = std::os::unix::net::SocketAddr::from_abstract_name(unescape(name))?;
+                    return UnixStream::connect_addr(&addr);
+                }
+                _ => (),
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no Unix socket address for the session bus",
+    ))
+}
+
+/// Undoes the %XX escapes of a D-Bus address value.
+fn unescape(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut fixed = [0; 16];
+    reader.read_exact(&mut fixed)?;
+    let big_endian = match fixed[0] {
+        b'l' => false,
+        b'B' => true,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a D-Bus message",
+            ))
+        }
+    };
+    let number = |bytes: &[u8]| {
+        let bytes = bytes.try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let body_len = number(&fixed[4..8]) as usize;
+    let fields_len = number(&fixed[12..16]) as usize;
+
+    // The fields are padded to 8 bytes, counting from the start of the message
+    let header_len = (16 + fields_len).next_multiple_of(8);
+    let mut rest = vec![0; header_len - 16 + body_len];
+    reader.read_exact(&mut rest)?;
+    let mut header = fixed.to_vec();
+    header.extend_from_slice(&rest[..header_len - 16]);
+
+    let mut message = Message {
+        kind: fixed[1],
+        flags: fixed[2],
+        serial: number(&fixed[8..12]),
+        body: rest[header_len - 16..].to_vec(),
+        big_endian,
+        ..Message::default()
+    };
+
+    let mut fields = Reader::new(&header[..16 + fields_len], big_endian);
+    fields.pos = 16;
+    while fields.pos < 16 + fields_len {
+        fields.pad(8);
+        let code = fields.u8();
+        let kind = fields.signature();
+        let value = match kind.as_deref() {
+            Some("s" | "o" | "g") => {
+                let value = if kind.as_deref() == Some("g") {
+                    fields.signature()
+                } else {
+                    fields.string()
+                };
+                Field::Text(value.unwrap_or_default())
+            }
+            Some("u") => Field::Number(fields.u32().unwrap_or_default()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected header field",
+                ))
+            }
+        };
+        match (code, value) {
+            (Some(FIELD_PATH), Field::Text(text)) => message.path = Some(text),
+            (Some(FIELD_INTERFACE), Field::Text(text)) => message.interface = Some(text),
+            (Some(FIELD_MEMBER), Field::Text(text)) => message.member = Some(text),
+            (Some(FIELD_ERROR_NAME), Field::Text(text)) => message.error_name = Some(text),
+            (Some(FIELD_DESTINATION), Field::Text(text)) => message.destination = Some(text),
+            (Some(FIELD_SENDER), Field::Text(text)) => message.sender = Some(text),
+            (Some(FIELD_SIGNATURE), Field::Text(text)) => message.signature = text,
+            (Some(FIELD_REPLY_SERIAL), Field::Number(serial)) => {
+                message.reply_serial = Some(serial)
+            }
+            _ => (),
+        }
+    }
+
+    Ok(message)
+}
+
+enum Field {
+    Text(String),
+    Number(u32),
+}
+
+/// Marshals values, always little endian.
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.buf.push(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Starts an array of elements aligned to `align`, returning where its length goes.
+    fn begin_array(&mut self, align: usize) -> usize {
+        self.u32(0);
+        let length_at = self.buf.len() - 4;
+        self.pad(align);
+        length_at
+    }
+
+    /// Fills in the length of the array started at `length_at`, from after the padding.
+    fn end_array(&mut self, length_at: usize) {
+        let start = (length_at + 4).next_multiple_of(8);
+        let length = (self.buf.len() - start.min(self.buf.len())) as u32;
+        self.buf[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+}
+
+/// Unmarshals values from a message, keeping its alignment.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], big_endian: bool) -> Self {
+        Reader {
+            buf,
+            pos: 0,
+            big_endian,
+        }
+    }
+
+    fn pad(&mut self, align: usize) {
+        self.pos = self.pos.next_multiple_of(align);
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let value = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.pad(4);
+        let bytes = self.buf.get(self.pos..self.pos + 4)?.try_into().ok()?;
+        self.pos += 4;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.buf.get(self.pos..self.pos + len)?;
+        self.pos += len + 1;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn signature(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.buf.get(self.pos..self.pos + len)?;
+        self.pos += len + 1;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
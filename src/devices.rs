@@ -0,0 +1,155 @@
+//! Reading keyboards directly through evdev, configured with `[[device]]` in the config.
+//!
+//! The window can't tell keyboards apart (on X11 every key comes from the same virtual core
+//! keyboard), so keyboards that should play on their own channel are read from
+//! `/dev/input/event*` instead, which needs read access to them (usually membership of the
+//! `input` group).
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
+    os::raw::c_long,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use winit::event::ScanCode;
+
+use crate::{input::InputSource, session_log::report, Proxy, UserEvent};
+
+const EV_KEY: u16 = 1;
+
+/// How to find a keyboard and what it plays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub matcher: Matcher,
+    /// Zero-based channel.
+    pub channel: u8,
+    /// Semitones added to every note.
+    pub transpose: i8,
+    /// The note each key plays, or empty for the same notes as in the window.
+    pub notes: HashMap<ScanCode, u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+    /// Every event device whose name contains this, ignoring case.
+    Name(String),
+    /// An event device file, e.g. from `/dev/input/by-path` to tell identical keyboards apart.
+    Path(PathBuf),
+}
+
+impl Matcher {
+    pub fn paths(&self) -> io::Result<Vec<PathBuf>> {
+        match self {
+            Matcher::Path(path) => Ok(vec![path.clone()]),
+            Matcher::Name(name) => {
+                let name = name.to_lowercase();
+                let mut paths = Vec::new();
+
+                for entry in fs::read_dir("/sys/class/input")? {
+                    let entry = entry?;
+                    let file_name = entry.file_name();
+                    if !file_name.to_string_lossy().starts_with("event") {
+                        continue;
+                    }
+
+                    let device_name = fs::read_to_string(entry.path().join("device/name"))
+                        .unwrap_or_default()
+                        .to_lowercase();
+                    if device_name.contains(&name) {
+                        paths.push(Path::new("/dev/input").join(file_name));
+                    }
+                }
+
+                Ok(paths)
+            }
+        }
+    }
+}
+
+/// The configured keyboards, as an input source.
+pub struct Devices(pub Vec<Device>);
+
+impl InputSource for Devices {
+    fn name(&self) -> &'static str {
+        "devices"
+    }
+
+    fn start(&self, proxy: Proxy) -> Vec<String> {
+        start(&self.0, proxy)
+    }
+}
+
+/// Starts a thread for every event device of every configured keyboard, which sends the keys
+/// pressed on it to the event loop. Returns a message for every device that couldn't be opened.
+fn start(devices: &[Device], proxy: Proxy) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    for (index, device) in devices.iter().enumerate() {
+        let paths = match device.matcher.paths() {
+            Ok(paths) if !paths.is_empty() => paths,
+            Ok(_) => {
+                errors.push(format!("no input device matches {:?}", device.matcher));
+                continue;
+            }
+            Err(err) => {
+                errors.push(format!("/sys/class/input: {}", err));
+                continue;
+            }
+        };
+
+        for path in paths {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    errors.push(format!("{}: {}", path.display(), err));
+                    continue;
+                }
+            };
+
+            let proxy = proxy.clone();
+            thread::spawn(move || {
+                let result = read_keys(file, |scancode, pressed| {
+                    let key = UserEvent::DeviceKey {
+                        device: index,
+                        scancode,
+                        pressed,
+                    };
+                    proxy.send_event(key).is_ok()
+                });
+                if let Err(err) = result {
+                    report!("{}: {}", path.display(), err);
+                }
+            });
+        }
+    }
+
+    errors
+}
+
+/// Calls `key` with every key pressed or released on the event device `file`, until it returns
+/// false.
+pub fn read_keys(mut file: File, mut key: impl FnMut(ScanCode, bool) -> bool) -> io::Result<()> {
+    // struct input_event: a struct timeval (two longs), then u16 type and code and an i32 value
+    let time_size = 2 * std::mem::size_of::<c_long>();
+    let mut event = vec![0; time_size + 8];
+
+    loop {
+        file.read_exact(&mut event)?;
+
+        let kind = u16::from_ne_bytes([event[time_size], event[time_size + 1]]);
+        let code = u16::from_ne_bytes([event[time_size + 2], event[time_size + 3]]);
+        let value = i32::from_ne_bytes(event[time_size + 4..].try_into().unwrap());
+
+        // Values are 0 for releases, 1 for presses and 2 for key repeats, which are ignored
+        if kind != EV_KEY || value > 1 {
+            continue;
+        }
+
+        if !key(code as ScanCode, value == 1) {
+            return Ok(());
+        }
+    }
+}
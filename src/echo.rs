@@ -0,0 +1,80 @@
+//! A MIDI delay: every note played from the keys is played again a number of times on the
+//! beat grid, each time quieter than the last.
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg, repeat::Rate, scheduler::Scheduler};
+
+#[derive(Debug, Clone)]
+pub struct Echo {
+    pub on: bool,
+    repeats: u8,
+    rate: Rate,
+    /// Velocity of each repeat in percent of the one before.
+    decay: u8,
+    /// How many times the last note on of each note on each channel was repeated, for its note
+    /// off to be repeated as often.
+    repeated: [[u8; 128]; 16],
+}
+
+impl Echo {
+    pub fn new(on: bool, repeats: u8, rate: Rate, decay: u8) -> Self {
+        Echo {
+            on,
+            repeats,
+            rate,
+            decay,
+            repeated: [[0; 128]; 16],
+        }
+    }
+
+    /// Adds the repeats of the notes among the first `incoming` of `events` to `scheduler`.
+    ///
+    /// Note offs are repeated as well, so every repeat is as long as the note played, and as
+    /// often as its note on was even if echo has been turned off since. Repeats that would be
+    /// too quiet to hear are left out.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        events: &[(Frames, MidiMsg)],
+        incoming: usize,
+        scheduler: &mut Scheduler,
+    ) {
+        let interval = clock.frames_for(1.0 / self.rate.per_beat());
+        for &(time, midi) in &events[..incoming] {
+            let frame = clock.frame() + time as u64;
+
+            match midi {
+                MidiMsg::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } if velocity > 0 => {
+                    let mut velocity = velocity as u32;
+                    let mut repeated = 0;
+                    while self.on && repeated < self.repeats {
+                        velocity = velocity * self.decay as u32 / 100;
+                        if velocity == 0 {
+                            break;
+                        }
+                        repeated += 1;
+                        let midi = MidiMsg::NoteOn {
+                            channel,
+                            note,
+                            velocity: velocity as u8,
+                        };
+                        scheduler.push(frame + repeated as u64 * interval, midi);
+                    }
+                    self.repeated[channel as usize & 0x0f][note as usize & 0x7f] = repeated;
+                }
+                MidiMsg::NoteOn { channel, note, .. } | MidiMsg::NoteOff { channel, note, .. } => {
+                    let repeated = self.repeated[channel as usize & 0x0f][note as usize & 0x7f];
+                    for repeat in 1..=repeated as u64 {
+                        scheduler.push(frame + repeat * interval, midi);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,742 @@
+//! Everything that runs in time with the MIDI output: placing the keyboard's messages in the
+//! cycle, and the notes added by note repeat, echo, release delay, generative mode and Euclidean
+//! rhythms, played with the microtiming of their channel. The output backends call
+//! [`Engine::cycle`] once per period.
+
+use std::{
+    ops::RangeInclusive,
+    sync::mpsc::{Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use jack::Frames;
+
+use crate::{
+    block::{self, Block},
+    clock::Clock,
+    config::Config,
+    echo::Echo,
+    euclid::Euclid,
+    feel::Feel,
+    generate::{self, Generator},
+    level::Target,
+    midi::{
+        MidiMsg, RunningStatus, ACTIVE_SENSING, CC_EXPRESSION, CC_HIGH_RES_VELOCITY,
+        DEFAULT_CHANNEL,
+    },
+    options::Options,
+    range::NoteRange,
+    release::ReleaseDelay,
+    repeat::{NoteRepeat, Rate},
+    scheduler::Scheduler,
+    session_log,
+    stats::Recorder,
+    KeyboardMsg,
+};
+
+/// Seconds of silence after which Active Sensing is sent, leaving some room below the 300 ms
+/// receivers wait for.
+const ACTIVE_SENSING_INTERVAL: f64 = 0.27;
+
+/// The sample rate of an engine driven by [`Engine::run`], which runs one frame per
+/// millisecond.
+pub const REAL_TIME_RATE: usize = 1000;
+
+/// How many messages that didn't fit in a cycle are kept for the next one.
+const MAX_UNSENT: usize = 1024;
+
+/// How many messages from the input port are passed through in a cycle.
+const MAX_THRU: usize = 1024;
+
+/// What happened to a message given to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// There's no room for it in this cycle, but there may be in the next.
+    Full,
+    /// It couldn't be written, and wouldn't be if tried again.
+    Failed,
+}
+
+/// Changes to the state of the engine.
+#[derive(Debug)]
+pub enum Control {
+    /// Turns note repeat on at a rate, or off.
+    Repeat(Option<Rate>),
+    /// Starts or changes generative mode, or stops it.
+    Generate(Option<generate::Params>),
+    /// Starts or stops one of the configured Euclidean rhythms.
+    Euclid(usize),
+    /// Plays one of the configured macros.
+    Macro(usize),
+    /// Sets the swing of everything on the clock, in percent.
+    Swing(f64),
+    /// Sets the tempo of the clock.
+    Tempo(f64),
+    /// Turns echo on or off.
+    Echo(bool),
+    /// Sets what is kept from going out.
+    Block(Vec<Block>),
+}
+
+pub struct Engine {
+    rx: Receiver<KeyboardMsg>,
+    controls: Receiver<Control>,
+    /// Where the number of every beat of the clock is sent as it is played.
+    beats: Option<Sender<u64>>,
+    clock: Clock,
+    repeat: NoteRepeat,
+    echo: Echo,
+    release: ReleaseDelay,
+    generator: Generator,
+    euclid: Euclid,
+    scheduler: Scheduler,
+    feel: Feel,
+    /// The steps of each macro, in frames after it starts.
+    macros: Vec<Vec<(u64, MidiMsg)>>,
+    /// Reused every cycle so the process callback doesn't allocate.
+    events: Vec<(Frames, MidiMsg)>,
+    /// Messages passed through from the input port for the next cycle, see [`Engine::thru`].
+    thru: Vec<(Frames, MidiMsg)>,
+    /// Messages that didn't fit in the last cycle, to go first in the next.
+    unsent: Vec<MidiMsg>,
+    running_status: Option<RunningStatus>,
+    range: Option<NoteRange>,
+    blocks: Vec<Block>,
+    sensing_interval: Option<u64>,
+    /// When something was last written, as counted by the clock.
+    last_written: u64,
+    stats: Option<Recorder>,
+    /// JACK is rendering offline as fast as it can, see [`Engine::set_freewheeling`].
+    freewheeling: bool,
+    /// What the level of the audio input controls, see [`Engine::set_level`].
+    level_target: Option<Target>,
+    level: u16,
+    /// Whether notes scaled by the level get a CC88 before them with the finer part of their
+    /// velocity.
+    high_res_velocity: bool,
+    /// The last expression sent for the level.
+    sent_expression: Option<u8>,
+}
+
+impl Engine {
+    pub fn new(
+        rx: Receiver<KeyboardMsg>,
+        controls: Receiver<Control>,
+        beats: Option<Sender<u64>>,
+        options: &Options,
+        config: &Config,
+        sample_rate: usize,
+    ) -> Self {
+        let frames = |ms: f64| (ms * sample_rate as f64 / 1000.0).round() as u64;
+        let offset = |ms: f64| (ms * sample_rate as f64 / 1000.0).round() as i64;
+        let zones: Vec<_> = config
+            .split
+            .iter()
+            .flat_map(|split| [split.lower, split.upper])
+            .map(|zone| (zone.channel, offset(zone.feel)))
+            .collect();
+        let release_delays: Vec<_> = config
+            .split
+            .iter()
+            .flat_map(|split| [split.lower, split.upper])
+            .filter_map(|zone| Some((zone.channel, frames(zone.release_delay?))))
+            .collect();
+        let mut clock = Clock::new(config.tempo, sample_rate);
+        clock.set_swing(config.swing);
+
+        Engine {
+            rx,
+            controls,
+            beats,
+            clock,
+            repeat: NoteRepeat::new(config.repeat.accents.clone()),
+            echo: Echo::new(
+                config.echo.on,
+                config.echo.repeats,
+                config.echo.rate,
+                config.echo.decay,
+            ),
+            release: ReleaseDelay::new(frames(config.release_delay), &release_delays),
+            generator: Generator::new(
+                config.generate.scale,
+                config.generate.rhythm,
+                DEFAULT_CHANNEL,
+                config.generate.velocity,
+                jack::get_time(),
+            ),
+            euclid: Euclid::new(config.euclid.iter().map(|&(_, p)| p).collect()),
+            scheduler: Scheduler::new(),
+            feel: Feel::new(offset(config.feel), &zones),
+            macros: config
+                .macros
+                .iter()
+                .map(|(_, steps)| steps.iter().map(|&(ms, midi)| (frames(ms), midi)).collect())
+                .collect(),
+            events: Vec::with_capacity(4096),
+            thru: Vec::with_capacity(MAX_THRU),
+            unsent: Vec::with_capacity(MAX_UNSENT),
+            running_status: options.running_status.then(RunningStatus::default),
+            range: config.range,
+            blocks: Vec::new(),
+            sensing_interval: options
+                .active_sensing
+                .then(|| frames(ACTIVE_SENSING_INTERVAL * 1000.0)),
+            last_written: 0,
+            stats: options.stats.then(|| Recorder::new(sample_rate)),
+            freewheeling: false,
+            level_target: options.audio_in,
+            level: 16383,
+            high_res_velocity: options.high_res_velocity,
+            sent_expression: None,
+        }
+    }
+
+    /// The tempo of the clock, which changes with [`Control::Tempo`].
+    pub fn tempo(&self) -> f64 {
+        self.clock.tempo()
+    }
+
+    /// Pauses everything but note offs while JACK is freewheeling, see [`Engine::cycle`].
+    pub fn set_freewheeling(&mut self, freewheeling: bool) {
+        self.freewheeling = freewheeling;
+    }
+
+    /// Passes a message from the input port on to the output in the next cycle, at `time`
+    /// frames into it. Messages beyond what fits without allocating are dropped.
+    pub fn thru(&mut self, time: Frames, midi: MidiMsg) {
+        if self.thru.len() < MAX_THRU {
+            self.thru.push((time, midi));
+        }
+    }
+
+    /// Sets the level of the audio input, from 0 to 16383, for the next cycle.
+    pub fn set_level(&mut self, level: u16) {
+        self.level = level;
+    }
+
+    /// Plays the next `n_frames`.
+    ///
+    /// `place` gives the offset into the cycle for a message generated at a JACK time. `write`
+    /// writes bytes at an offset, after which `played` is called with every message that was
+    /// written.
+    ///
+    /// Once the output is full, the rest of the cycle's messages are kept for the next one,
+    /// notes first, and control changes that a later one replaces are never written at all.
+    pub fn cycle(
+        &mut self,
+        n_frames: Frames,
+        mut place: impl FnMut(jack::Time) -> Frames,
+        mut write: impl FnMut(Frames, &[u8]) -> Outcome,
+        mut played: impl FnMut(Frames, &MidiMsg),
+    ) {
+        let started = Instant::now();
+
+        while let Ok(control) = self.controls.try_recv() {
+            match control {
+                Control::Repeat(rate) => self.repeat.rate = rate,
+                Control::Generate(params) => self.generator.params = params,
+                Control::Euclid(index) => self.euclid.toggle(index),
+                Control::Swing(percent) => self.clock.set_swing(percent),
+                Control::Tempo(tempo) => self.clock.set_tempo(tempo),
+                Control::Echo(on) => self.echo.on = on,
+                Control::Block(blocks) => self.blocks = blocks,
+                Control::Macro(index) => {
+                    for &(offset, midi) in &self.macros[index] {
+                        self.scheduler.push(self.clock.frame() + offset, midi);
+                    }
+                }
+            }
+        }
+
+        let events = &mut self.events;
+        events.clear();
+        let mut last_time = 0;
+        while let Ok(KeyboardMsg { midi, time }) = self.rx.try_recv() {
+            // Writers need the events in order
+            let offset = place(time).max(last_time);
+            last_time = offset;
+            events.push((offset, midi));
+
+            if let Some(stats) = &mut self.stats {
+                stats.received(jack::get_time().saturating_sub(time), offset);
+            }
+        }
+
+        match self.level_target {
+            Some(Target::Velocity) => {
+                let mut index = 0;
+                while index < events.len() {
+                    let (time, midi) = &mut events[index];
+                    let time = *time;
+                    index += 1;
+                    let MidiMsg::NoteOn {
+                        channel,
+                        velocity: velocity @ 1..,
+                        ..
+                    } = midi
+                    else {
+                        continue;
+                    };
+
+                    // The velocity in 14 bits, of which the note on gets the top 7
+                    let fine = *velocity as u32 * self.level as u32 / 127;
+                    *velocity = ((fine >> 7) as u8).max(1);
+                    if self.high_res_velocity && fine >> 7 > 0 {
+                        let midi = MidiMsg::ControlChange {
+                            channel: *channel,
+                            controller: CC_HIGH_RES_VELOCITY,
+                            value: (fine & 0x7f) as u8,
+                        };
+                        events.insert(index - 1, (time, midi));
+                        index += 1;
+                    }
+                }
+            }
+            Some(Target::Expression) if self.sent_expression != Some((self.level >> 7) as u8) => {
+                let value = (self.level >> 7) as u8;
+                let midi = MidiMsg::ControlChange {
+                    channel: DEFAULT_CHANNEL,
+                    controller: CC_EXPRESSION,
+                    value,
+                };
+                events.push((0, midi));
+                self.sent_expression = Some(value);
+            }
+            _ => (),
+        }
+
+        let clock = &self.clock;
+        if self.freewheeling {
+            // Nothing played live belongs in an offline render, but notes still have to be let
+            // go of, and everything on the clock waits until it is over
+            events.retain(|(_, midi)| is_note_off(midi));
+            let incoming = events.len();
+            self.echo
+                .schedule(clock, events, incoming, &mut self.scheduler);
+            self.repeat.follow(events);
+            // Including those held back for later, which would hang through the whole render
+            self.scheduler.flush(events, is_note_off);
+            self.release.flush(events);
+            self.feel.flush(events, is_note_off);
+        } else {
+            if let Some(beats) = &self.beats {
+                for (beat, _) in clock.beats(n_frames) {
+                    // Nothing to be done if the window has gone away
+                    let _ = beats.send(beat);
+                }
+            }
+            let incoming = events.len();
+            // Before release delay, which takes the note offs it needs to repeat
+            self.echo
+                .schedule(clock, events, incoming, &mut self.scheduler);
+            self.repeat.schedule(clock, n_frames, events);
+            // After note repeat, which should stop as soon as the key is released
+            self.release.schedule(clock, n_frames, events, incoming);
+            self.generator.schedule(clock, n_frames, events);
+            self.euclid.schedule(clock, n_frames, events);
+            self.scheduler.schedule(clock, n_frames, events);
+            // Last, so it moves everything played on a channel by the same amount
+            self.feel.schedule(clock, n_frames, events);
+        }
+        // Past everything that plays along with the keys, as thru passes messages on as they are
+        let thru = self.thru.drain(..);
+        if self.freewheeling {
+            events.extend(thru.filter(|(_, midi)| is_note_off(midi)));
+        } else {
+            events.extend(thru);
+        }
+        // Stable, so events at the same time stay in the order they were added
+        events.sort_by_key(|&(time, _)| time);
+        events.splice(0..0, self.unsent.drain(..).map(|midi| (0, midi)));
+        coalesce(events);
+        if let Some(range) = &self.range {
+            // Last, so it covers the notes from everywhere
+            for (_, midi) in events.iter_mut() {
+                *midi = range.apply(*midi);
+            }
+        }
+        if !self.blocks.is_empty() {
+            events.retain(|(_, midi)| !block::blocked(&self.blocks, midi));
+        }
+
+        if let Some(interval) = self.sensing_interval {
+            if clock.frame().saturating_sub(self.last_written) >= interval
+                && write(0, &[ACTIVE_SENSING]) == Outcome::Done
+            {
+                self.last_written = clock.frame();
+            }
+        }
+
+        for (index, &(time, midi)) in events.iter().enumerate() {
+            let (bytes, len) = midi.encode();
+            let bytes = match &mut self.running_status {
+                Some(running_status) => running_status.encode(&bytes[..len]),
+                None => &bytes[..len],
+            };
+
+            let result = write(time, bytes);
+            if result == Outcome::Done {
+                self.last_written = clock.frame() + time as u64;
+                played(time, &midi);
+                if let Some(stats) = &mut self.stats {
+                    stats.written();
+                }
+                continue;
+            }
+
+            if let Some(stats) = &mut self.stats {
+                stats.failed();
+            }
+            if let Some(running_status) = &mut self.running_status {
+                // The next message can't rely on the status of one that wasn't written
+                running_status.reset();
+            }
+            if result == Outcome::Full {
+                // Later messages have to wait as well, or they'd overtake this one
+                let rest = index..events.len();
+                let notes = rest.clone().filter(|&i| is_note(&events[i].1));
+                let others =
+                    rest.filter(|&i| !is_note(&events[i].1) && !is_velocity_prefix(events, i));
+                let (unsent, mut kept) = (&mut self.unsent, 0);
+                for i in notes.chain(others) {
+                    // A note keeps the CC88 before it, which is sent again if it was written
+                    // already, so the finer part of its velocity isn't taken for another note's
+                    let prefix =
+                        (i > 0 && is_velocity_prefix(events, i - 1)).then(|| events[i - 1].1);
+                    // Notes go first, so anything dropped is something else
+                    if unsent.len() + 1 + prefix.is_some() as usize > MAX_UNSENT {
+                        session_log::dropped(events.len() - index - kept);
+                        break;
+                    }
+                    unsent.extend(prefix);
+                    unsent.push(events[i].1);
+                    kept += 1 + (prefix.is_some() && i > index) as usize;
+                }
+                break;
+            }
+            session_log::dropped(1);
+        }
+
+        if let Some(stats) = &mut self.stats {
+            let scheduled = self.scheduler.len() + self.release.len() + self.feel.len();
+            let unsent = self.unsent.len();
+            stats.cycle(started, n_frames, events.len(), scheduled, unsent);
+        }
+        if !self.freewheeling {
+            self.clock.advance(n_frames);
+        }
+    }
+
+    /// Calls `cycle` with the number of frames to play every millisecond, for outputs that
+    /// don't have a clock of their own. The engine has to be made with [`REAL_TIME_RATE`].
+    pub fn run(mut self, mut cycle: impl FnMut(&mut Self, Frames)) -> ! {
+        let start = Instant::now();
+        let mut frame = 0;
+
+        loop {
+            // Catch up on whole milliseconds if the last cycle took longer than one
+            let now = start.elapsed().as_millis() as u64;
+            let n_frames = now.saturating_sub(frame).max(1);
+            frame += n_frames;
+
+            cycle(&mut self, n_frames as Frames);
+
+            let next = start + Duration::from_millis(frame);
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+    }
+}
+
+fn is_note(midi: &MidiMsg) -> bool {
+    matches!(midi, MidiMsg::NoteOn { .. } | MidiMsg::NoteOff { .. })
+}
+
+/// Whether the message at `index` is a CC88 with the finer part of the velocity of the note
+/// on right after it, see `--high-res-velocity`.
+fn is_velocity_prefix(events: &[(Frames, MidiMsg)], index: usize) -> bool {
+    let MidiMsg::ControlChange {
+        channel,
+        controller: CC_HIGH_RES_VELOCITY,
+        ..
+    } = events[index].1
+    else {
+        return false;
+    };
+    matches!(
+        events.get(index + 1),
+        Some(&(_, MidiMsg::NoteOn { channel: c, velocity: 1.., .. })) if c == channel
+    )
+}
+
+fn is_note_off(midi: &MidiMsg) -> bool {
+    matches!(
+        midi,
+        MidiMsg::NoteOff { .. } | MidiMsg::NoteOn { velocity: 0, .. }
+    )
+}
+
+/// Controllers that select or change (N)RPN parameters, which only make sense together.
+const PARAMETER_CONTROLLERS: [u8; 8] = [6, 38, 96, 97, 98, 99, 100, 101];
+
+/// Controllers that are switches, like sustain and portamento, whose every change matters: a
+/// pedal lifted and pressed again lets go of the notes held by it in between.
+const SWITCH_CONTROLLERS: RangeInclusive<u8> = 64..=69;
+
+/// Channel mode messages, like all notes off, which are commands rather than values.
+const MODE_CONTROLLERS: RangeInclusive<u8> = 120..=127;
+
+/// Drops the control changes, channel pressures and pitch bends that a later one for the same
+/// controller replaces before anything else on the channel could have made use of them.
+fn coalesce(events: &mut Vec<(Frames, MidiMsg)>) {
+    // Whether a later message replaces one for each controller, with pitch bend as 128 and
+    // channel pressure as 129
+    let mut replaced = [[false; 130]; 16];
+
+    events.reverse();
+    events.retain(|&(_, midi)| {
+        let (channel, controller) = match midi {
+            MidiMsg::ControlChange {
+                channel,
+                controller,
+                ..
+            } if !PARAMETER_CONTROLLERS.contains(&controller)
+                && !SWITCH_CONTROLLERS.contains(&controller)
+                && !MODE_CONTROLLERS.contains(&controller) =>
+            {
+                (channel, controller as usize)
+            }
+            MidiMsg::PitchBend { channel, .. } => (channel, 128),
+            MidiMsg::ChannelPressure { channel, .. } => (channel, 129),
+            MidiMsg::NoteOn { channel, .. }
+            | MidiMsg::NoteOff { channel, .. }
+            | MidiMsg::ControlChange { channel, .. }
+            | MidiMsg::ProgramChange { channel, .. } => {
+                replaced[channel as usize & 0x0f] = [false; 130];
+                return true;
+            }
+        };
+
+        !std::mem::replace(&mut replaced[channel as usize & 0x0f][controller], true)
+    });
+    events.reverse();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn engine() -> (Engine, Sender<KeyboardMsg>) {
+        engine_with(&Config::default())
+    }
+
+    fn engine_with(config: &Config) -> (Engine, Sender<KeyboardMsg>) {
+        let (tx, rx) = mpsc::channel();
+        let (_, controls) = mpsc::channel();
+        let options = Options::default();
+        let engine = Engine::new(rx, controls, None, &options, config, 48000);
+        (engine, tx)
+    }
+
+    /// Sends `messages` for the next cycle, each at the frame it is paired with.
+    fn send(tx: &Sender<KeyboardMsg>, messages: &[(Frames, MidiMsg)]) {
+        for &(time, midi) in messages {
+            let time = time as jack::Time;
+            tx.send(KeyboardMsg { midi, time }).unwrap();
+        }
+    }
+
+    /// Runs a cycle with an output that has room for `room` messages, and returns the ones
+    /// written.
+    fn cycle(engine: &mut Engine, room: usize) -> Vec<(Frames, MidiMsg)> {
+        let (mut written, mut played) = (0, Vec::new());
+        engine.cycle(
+            256,
+            |time| time as Frames,
+            |_, _| {
+                if written == room {
+                    return Outcome::Full;
+                }
+                written += 1;
+                Outcome::Done
+            },
+            |time, &midi| played.push((time, midi)),
+        );
+        played
+    }
+
+    fn note_on(note: u8) -> MidiMsg {
+        MidiMsg::NoteOn {
+            channel: 0,
+            note,
+            velocity: 100,
+        }
+    }
+
+    fn note_off(note: u8) -> MidiMsg {
+        MidiMsg::NoteOff {
+            channel: 0,
+            note,
+            velocity: 0,
+        }
+    }
+
+    fn cc(controller: u8, value: u8) -> MidiMsg {
+        MidiMsg::ControlChange {
+            channel: 0,
+            controller,
+            value,
+        }
+    }
+
+    #[test]
+    fn full_output_carries_over_notes_first() {
+        let (mut engine, tx) = engine();
+        let bend = MidiMsg::PitchBend {
+            channel: 0,
+            value: 0x2000,
+        };
+        send(
+            &tx,
+            &[
+                (0, cc(7, 100)),
+                (1, note_on(60)),
+                (2, bend),
+                (3, note_on(62)),
+            ],
+        );
+
+        assert_eq!(cycle(&mut engine, 1), [(0, cc(7, 100))]);
+        assert_eq!(engine.unsent, [note_on(60), note_on(62), bend]);
+
+        let played = cycle(&mut engine, usize::MAX);
+        assert_eq!(played, [(0, note_on(60)), (0, note_on(62)), (0, bend)]);
+        assert!(engine.unsent.is_empty());
+    }
+
+    #[test]
+    fn unsent_beyond_the_limit_are_dropped() {
+        let (mut engine, tx) = engine();
+        let controls: Vec<_> = (0..10).map(|controller| (0, cc(controller, 1))).collect();
+        let notes: Vec<_> = (0..MAX_UNSENT)
+            .map(|n| (0, note_on(n as u8 % 128)))
+            .collect();
+        send(&tx, &controls);
+        send(&tx, &notes);
+
+        assert!(cycle(&mut engine, 0).is_empty());
+        // The notes went first, so only the control changes are dropped
+        assert_eq!(engine.unsent.len(), MAX_UNSENT);
+        assert!(engine.unsent.iter().all(is_note));
+    }
+
+    #[test]
+    fn replaced_control_changes_are_coalesced() {
+        let (mut engine, tx) = engine();
+        send(
+            &tx,
+            &[
+                (0, cc(1, 10)),
+                (1, cc(1, 20)),
+                (2, cc(1, 30)),
+                // A sustain pedal pressed again, which has to be lifted in between
+                (3, cc(64, 0)),
+                (4, cc(64, 127)),
+                (5, note_on(60)),
+                (6, cc(1, 40)),
+                (7, cc(1, 50)),
+            ],
+        );
+
+        let played: Vec<_> = cycle(&mut engine, usize::MAX)
+            .into_iter()
+            .map(|(_, midi)| midi)
+            .collect();
+        assert_eq!(
+            played,
+            [cc(1, 30), cc(64, 0), cc(64, 127), note_on(60), cc(1, 50)]
+        );
+    }
+
+    #[test]
+    fn freewheeling_only_lets_go_of_notes() {
+        let (mut engine, tx) = engine();
+        engine.set_freewheeling(true);
+        let released = MidiMsg::NoteOn {
+            channel: 0,
+            note: 64,
+            velocity: 0,
+        };
+        send(
+            &tx,
+            &[
+                (0, note_on(60)),
+                (1, note_off(62)),
+                (2, cc(1, 10)),
+                (3, released),
+            ],
+        );
+
+        assert_eq!(
+            cycle(&mut engine, usize::MAX),
+            [(1, note_off(62)), (3, released)]
+        );
+    }
+
+    #[test]
+    fn playing_a_note_again_only_cancels_its_delayed_release() {
+        let config = Config {
+            release_delay: 100.0,
+            ..Config::default()
+        };
+        let (mut engine, tx) = engine_with(&config);
+        // Like the note off of an echo, which has to stay
+        engine.scheduler.push(48000, note_off(60));
+        send(&tx, &[(0, note_off(60))]);
+        assert!(cycle(&mut engine, usize::MAX).is_empty());
+        assert_eq!(engine.release.len(), 1);
+
+        send(&tx, &[(0, note_on(60))]);
+        assert_eq!(cycle(&mut engine, usize::MAX), [(0, note_on(60))]);
+        assert_eq!(engine.release.len(), 0);
+        assert_eq!(engine.scheduler.len(), 1);
+    }
+
+    #[test]
+    fn freewheeling_lets_go_of_delayed_releases() {
+        let config = Config {
+            release_delay: 100.0,
+            ..Config::default()
+        };
+        let (mut engine, tx) = engine_with(&config);
+        send(&tx, &[(0, note_on(60))]);
+        cycle(&mut engine, usize::MAX);
+        send(&tx, &[(0, note_off(60))]);
+        assert!(cycle(&mut engine, usize::MAX).is_empty());
+
+        engine.set_freewheeling(true);
+        assert_eq!(cycle(&mut engine, usize::MAX), [(0, note_off(60))]);
+        assert_eq!(engine.release.len(), 0);
+    }
+
+    #[test]
+    fn carried_over_notes_keep_their_high_res_velocity() {
+        let (mut waiting, tx) = engine();
+        let fine = cc(CC_HIGH_RES_VELOCITY, 5);
+        send(
+            &tx,
+            &[(0, cc(7, 1)), (0, note_on(60)), (1, fine), (1, note_on(62))],
+        );
+        assert!(cycle(&mut waiting, 0).is_empty());
+        assert_eq!(waiting.unsent, [note_on(60), fine, note_on(62), cc(7, 1)]);
+
+        // Written, but its note has to wait
+        let (mut written, tx) = engine();
+        send(&tx, &[(0, fine), (0, note_on(62))]);
+        assert_eq!(cycle(&mut written, 1), [(0, fine)]);
+        assert_eq!(written.unsent, [fine, note_on(62)]);
+    }
+}
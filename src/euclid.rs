@@ -0,0 +1,90 @@
+//! Keys that start and stop Euclidean rhythms, configured with `[[euclid]]` in the config.
+//!
+//! Each pattern plays one note on a grid of sixteenth notes synced to the [`Clock`]. Patterns
+//! run from the start of the bar rather than from when the key was pressed, so several of them
+//! stay in step with each other.
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg, rhythm};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pattern {
+    pub pulses: u32,
+    pub steps: u32,
+    pub channel: u8,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+/// The running patterns, which live in the process callback.
+#[derive(Debug, Clone)]
+pub struct Euclid {
+    patterns: Vec<Pattern>,
+    running: Vec<bool>,
+    /// Whether each pattern's note is sounding, to be stopped on the next step.
+    sounding: Vec<bool>,
+}
+
+impl Euclid {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        Euclid {
+            running: vec![false; patterns.len()],
+            sounding: vec![false; patterns.len()],
+            patterns,
+        }
+    }
+
+    /// Starts the pattern at `index` if it is stopped and stops it otherwise.
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(running) = self.running.get_mut(index) {
+            *running = !*running;
+        }
+    }
+
+    /// Adds the notes for this cycle to `events`. They are appended, so `events` has to be
+    /// sorted by time afterwards.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+    ) {
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if !self.running[i] && self.sounding[i] {
+                // Stopped since the last cycle
+                self.sounding[i] = false;
+                events.push((0, note_off(pattern)));
+            }
+        }
+
+        for (step, time) in clock.steps(4.0, n_frames) {
+            for (i, pattern) in self.patterns.iter().enumerate() {
+                if self.sounding[i] {
+                    self.sounding[i] = false;
+                    events.push((time, note_off(pattern)));
+                }
+
+                if self.running[i] && rhythm::euclidean(step, pattern.pulses, pattern.steps) {
+                    self.sounding[i] = true;
+                    events.push((
+                        time,
+                        MidiMsg::NoteOn {
+                            channel: pattern.channel,
+                            note: pattern.note,
+                            velocity: pattern.velocity,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn note_off(pattern: &Pattern) -> MidiMsg {
+    MidiMsg::NoteOff {
+        channel: pattern.channel,
+        note: pattern.note,
+        velocity: 0,
+    }
+}
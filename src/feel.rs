@@ -0,0 +1,72 @@
+//! Microtiming, configured with `feel` and the `feel` of each `[split]` zone: everything on a
+//! channel is played some milliseconds behind the beat, or ahead of it, so a drum zone can sit
+//! back while a lead pushes. Nothing can be played before it happens, so pushing a channel
+//! ahead holds every other channel back by as much instead.
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg, scheduler::Scheduler};
+
+/// How many milliseconds anything can be played behind or ahead of the beat.
+pub const MAX_FEEL: f64 = 250.0;
+
+#[derive(Debug, Clone)]
+pub struct Feel {
+    /// Frames the messages of each channel are held back by.
+    delays: [u64; 16],
+    scheduler: Scheduler,
+}
+
+impl Feel {
+    /// `global` applies to every channel, and the offsets in `channels` on top of it, all in
+    /// frames with positive numbers behind the beat.
+    pub fn new(global: i64, channels: &[(u8, i64)]) -> Self {
+        let mut offsets = [global; 16];
+        for &(channel, offset) in channels {
+            offsets[channel as usize] = global + offset;
+        }
+        let earliest = offsets.iter().copied().min().unwrap_or(0).min(0);
+
+        Feel {
+            delays: offsets.map(|offset| (offset - earliest) as u64),
+            scheduler: Scheduler::new(),
+        }
+    }
+
+    /// How many messages are being held back.
+    pub fn len(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Adds the messages held back that `flush` returns true for to the start of this cycle.
+    pub fn flush(
+        &mut self,
+        events: &mut Vec<(Frames, MidiMsg)>,
+        flush: impl FnMut(&MidiMsg) -> bool,
+    ) {
+        self.scheduler.flush(events, flush);
+    }
+
+    /// Holds back the messages in `events` by the delay of their channel, and adds those held
+    /// back earlier that are due in this cycle.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+    ) {
+        if self.delays.iter().all(|&delay| delay == 0) {
+            return;
+        }
+
+        let (delays, scheduler) = (&self.delays, &mut self.scheduler);
+        events.retain(|&(time, midi)| match delays[midi.channel() as usize] {
+            0 => true,
+            delay => {
+                scheduler.push(clock.frame() + time as u64 + delay, midi);
+                false
+            }
+        });
+        scheduler.schedule(clock, n_frames, events);
+    }
+}
@@ -0,0 +1,152 @@
+//! Generative mode, where the keyboard plays by itself.
+//!
+//! Notes are picked from a scale by a Markov chain that mostly moves by small steps, on a grid
+//! of sixteenth notes synced to the [`Clock`]. Which steps play is decided by the density,
+//! either as a Euclidean rhythm over a bar or at random.
+
+use jack::Frames;
+
+use crate::{
+    clock::Clock,
+    midi::MidiMsg,
+    rhythm::{self, Rng},
+    scale::Scale,
+};
+
+/// Steps in a bar, which is also the highest density.
+pub const STEPS: u8 = 16;
+
+/// How likely the next note is to move by -3 to +3 degrees of the scale.
+const MOVES: [(i32, u32); 7] = [(-3, 1), (-2, 2), (-1, 4), (0, 1), (1, 4), (2, 2), (3, 1)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rhythm {
+    /// The pulses are spread as evenly as possible over each bar.
+    Euclidean,
+    /// Each step plays with a probability of density out of 16.
+    Random,
+}
+
+impl Rhythm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "euclidean" => Some(Rhythm::Euclidean),
+            "random" => Some(Rhythm::Random),
+            _ => None,
+        }
+    }
+}
+
+/// What can be changed while generating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub root: u8,
+    /// Notes per bar, from 0 to [`STEPS`].
+    pub density: u8,
+}
+
+/// The generator state, which lives in the process callback.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub params: Option<Params>,
+    scale: Scale,
+    rhythm: Rhythm,
+    channel: u8,
+    velocity: u8,
+    rng: Rng,
+    /// The current degree of the scale, relative to the root.
+    degree: i32,
+    sounding: Option<u8>,
+}
+
+impl Generator {
+    pub fn new(scale: Scale, rhythm: Rhythm, channel: u8, velocity: u8, seed: u64) -> Self {
+        Generator {
+            params: None,
+            scale,
+            rhythm,
+            channel,
+            velocity,
+            rng: Rng::new(seed),
+            degree: 0,
+            sounding: None,
+        }
+    }
+
+    /// Adds the generated notes for this cycle to `events`. They are appended, so `events` has
+    /// to be sorted by time afterwards.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+    ) {
+        let params = match self.params {
+            Some(params) => params,
+            None => {
+                // Stopped since the last cycle
+                self.stop(0, events);
+                return;
+            }
+        };
+
+        for (step, time) in clock.steps(STEPS as f64 / 4.0, n_frames) {
+            // Notes last until the next step
+            self.stop(time, events);
+
+            let play = match self.rhythm {
+                Rhythm::Euclidean => rhythm::euclidean(step, params.density as u32, STEPS as u32),
+                Rhythm::Random => self.rng.below(STEPS as u32) < params.density as u32,
+            };
+            if !play {
+                continue;
+            }
+
+            self.degree = self.next_degree();
+            let note = self.scale.note(params.root, self.degree);
+            events.push((
+                time,
+                MidiMsg::NoteOn {
+                    channel: self.channel,
+                    note,
+                    velocity: self.velocity,
+                },
+            ));
+            self.sounding = Some(note);
+        }
+    }
+
+    fn next_degree(&mut self) -> i32 {
+        let total = MOVES.iter().map(|&(_, weight)| weight).sum();
+        let mut pick = self.rng.below(total);
+        let mut degree = self.degree;
+
+        for &(step, weight) in &MOVES {
+            if pick < weight {
+                degree += step;
+                break;
+            }
+            pick -= weight;
+        }
+
+        // Wander within an octave either side of the root, bouncing off the ends
+        let len = self.scale.intervals().len() as i32;
+        if degree.abs() > len {
+            degree = self.degree - (degree - self.degree);
+        }
+        degree
+    }
+
+    fn stop(&mut self, time: Frames, events: &mut Vec<(Frames, MidiMsg)>) {
+        if let Some(note) = self.sounding.take() {
+            events.push((
+                time,
+                MidiMsg::NoteOff {
+                    channel: self.channel,
+                    note,
+                    velocity: 0,
+                },
+            ));
+        }
+    }
+}
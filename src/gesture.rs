@@ -0,0 +1,157 @@
+//! Gesture keys, configured with `[[gesture]]`: a key that plays a note when tapped, a chord
+//! instead when tapped again quickly, and latches what it plays when held long enough.
+//!
+//! Nothing waits to tell the gestures apart: a tap plays straight away, a double tap is only a
+//! second press soon after the first was released, and a long press is only decided on release.
+
+use std::time::{Duration, Instant};
+
+use winit::event::ScanCode;
+
+use crate::midi::{MidiMsg, DEFAULT_CHANNEL};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gesture {
+    pub key: ScanCode,
+    /// The note a tap plays.
+    pub tap: u8,
+    /// The chord a second tap within `double_tap_time` of releasing the first plays instead, or
+    /// empty to play the note again.
+    pub double_tap: Vec<u8>,
+    /// Whether holding the key for `long_press_time` keeps what it plays sounding once
+    /// released, until the key is pressed again.
+    pub latch: bool,
+    pub double_tap_time: Duration,
+    pub long_press_time: Duration,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Idle,
+    /// Pressed at `since`, playing `notes`.
+    Held {
+        since: Instant,
+        notes: Vec<u8>,
+    },
+    /// Released at `at`, so a press soon after is a double tap.
+    Released {
+        at: Instant,
+    },
+    /// Released after a long press, with `notes` still playing.
+    Latched {
+        notes: Vec<u8>,
+    },
+    /// Pressed to let go of latched notes, which plays nothing until released.
+    Unlatching,
+}
+
+#[derive(Debug, Clone)]
+pub struct Gestures {
+    gestures: Vec<Gesture>,
+    states: Vec<State>,
+}
+
+impl Gestures {
+    pub fn new(gestures: Vec<Gesture>) -> Self {
+        Gestures {
+            states: vec![State::Idle; gestures.len()],
+            gestures,
+        }
+    }
+
+    /// Turns a press or release of a gesture key into the notes it plays or stops, or returns
+    /// `None` if `scancode` isn't a gesture key.
+    pub fn handle(
+        &mut self,
+        scancode: ScanCode,
+        pressed: bool,
+        velocity: u8,
+        now: Instant,
+    ) -> Option<Vec<MidiMsg>> {
+        let index = self.gestures.iter().position(|g| g.key == scancode)?;
+        let (gesture, state) = (&self.gestures[index], &mut self.states[index]);
+        let channel = DEFAULT_CHANNEL;
+        let note_on = |note| MidiMsg::NoteOn {
+            channel,
+            note,
+            velocity,
+        };
+        let note_off = |note| MidiMsg::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        };
+
+        let (next, messages) = match (std::mem::replace(state, State::Idle), pressed) {
+            (State::Latched { notes }, true) => {
+                (State::Unlatching, notes.into_iter().map(note_off).collect())
+            }
+            (State::Released { at }, true)
+                if now.saturating_duration_since(at) <= gesture.double_tap_time
+                    && !gesture.double_tap.is_empty() =>
+            {
+                let notes = gesture.double_tap.clone();
+                let messages = notes.iter().copied().map(note_on).collect();
+                (State::Held { since: now, notes }, messages)
+            }
+            (State::Held { since, notes }, false)
+                if gesture.latch
+                    && now.saturating_duration_since(since) >= gesture.long_press_time =>
+            {
+                (State::Latched { notes }, Vec::new())
+            }
+            (State::Held { notes, .. }, false) => (
+                State::Released { at: now },
+                notes.into_iter().map(note_off).collect(),
+            ),
+            (State::Unlatching, false) => (State::Idle, Vec::new()),
+            (held @ State::Held { .. }, true) => (held, Vec::new()),
+            (_, true) => (
+                State::Held {
+                    since: now,
+                    notes: vec![gesture.tap],
+                },
+                vec![note_on(gesture.tap)],
+            ),
+            (other, false) => (other, Vec::new()),
+        };
+
+        *state = next;
+        Some(messages)
+    }
+
+    /// Whether the gesture on the key with `scancode` has latched its notes.
+    pub fn is_latched(&self, scancode: ScanCode) -> bool {
+        let index = self.gestures.iter().position(|g| g.key == scancode);
+        index.is_some_and(|index| matches!(self.states[index], State::Latched { .. }))
+    }
+
+    /// Lets go of the notes latched by the gesture on the key with `scancode`, returning the
+    /// note offs, or `None` if it hasn't latched anything.
+    pub fn unlatch(&mut self, scancode: ScanCode) -> Option<Vec<MidiMsg>> {
+        let index = self.gestures.iter().position(|g| g.key == scancode)?;
+        match std::mem::replace(&mut self.states[index], State::Idle) {
+            State::Latched { notes } => Some(
+                notes
+                    .into_iter()
+                    .map(|note| MidiMsg::NoteOff {
+                        channel: DEFAULT_CHANNEL,
+                        note,
+                        velocity: 0,
+                    })
+                    .collect(),
+            ),
+            other => {
+                self.states[index] = other;
+                None
+            }
+        }
+    }
+
+    /// Whether any notes are latched.
+    pub fn latched(&self) -> bool {
+        self.states
+            .iter()
+            .any(|state| matches!(state, State::Latched { .. }))
+    }
+}
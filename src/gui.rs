@@ -0,0 +1,77 @@
+//! Software-rendered window contents.
+//!
+//! Everything is drawn into a [`Canvas`] which a platform specific presenter then copies into
+//! the window. Only X11 has a presenter so far; elsewhere the window stays blank but keyboard
+//! input works as before.
+
+pub mod canvas;
+pub mod curve_editor;
+mod font;
+pub mod heat_map;
+pub mod slider;
+pub mod stage;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+mod x11;
+
+use winit::window::Window;
+
+use self::canvas::{Canvas, Color};
+
+pub const BACKGROUND: Color = 0x1e1e24;
+pub const PANEL: Color = 0x2a2a33;
+pub const GRID: Color = 0x3a3a46;
+pub const TEXT: Color = 0xd8d8e0;
+pub const TEXT_DIM: Color = 0x8a8a99;
+pub const ACCENT: Color = 0x4fa3ff;
+pub const HIGHLIGHT: Color = 0xffb347;
+
+pub enum Presenter {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    ))]
+    X11(Box<x11::X11Presenter>),
+    Unsupported,
+}
+
+impl Presenter {
+    pub fn new(window: &Window) -> Self {
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        ))]
+        if let Some(presenter) = x11::X11Presenter::new(window) {
+            return Presenter::X11(Box::new(presenter));
+        }
+
+        let _ = window;
+        eprintln!("Drawing the window contents is only supported on X11");
+        Presenter::Unsupported
+    }
+
+    pub fn present(&mut self, canvas: &Canvas) {
+        match self {
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd"
+            ))]
+            Presenter::X11(presenter) => presenter.present(canvas),
+            Presenter::Unsupported => (),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// An `0x00RRGGBB` colour.
+pub type Color = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Shrinks the rectangle by `amount` pixels on every side.
+    pub fn inset(&self, amount: u32) -> Self {
+        Rect {
+            x: self.x + amount as i32,
+            y: self.y + amount as i32,
+            width: self.width.saturating_sub(2 * amount),
+            height: self.height.saturating_sub(2 * amount),
+        }
+    }
+}
+
+/// A software frame buffer that the GUI renders into before it is presented to the window.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels.resize((width * height) as usize, 0);
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.pixels.fill(color);
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.pixels[(y as u32 * self.width + x as u32) as usize] = color;
+        }
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let x0 = rect.x.clamp(0, self.width as i32) as u32;
+        let x1 = rect.right().clamp(0, self.width as i32) as u32;
+        let y0 = rect.y.clamp(0, self.height as i32) as u32;
+        let y1 = rect.bottom().clamp(0, self.height as i32) as u32;
+
+        for y in y0..y1 {
+            let row = (y * self.width) as usize;
+            self.pixels[row + x0 as usize..row + x1 as usize].fill(color);
+        }
+    }
+
+    /// Draws a one pixel wide line using Bresenham's algorithm.
+    pub fn draw_line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) {
+        let (mut x, mut y) = from;
+        let dx = (to.0 - x).abs();
+        let dy = -(to.1 - y).abs();
+        let sx = if x < to.0 { 1 } else { -1 };
+        let sy = if y < to.1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.set_pixel(x, y, color);
+
+            if (x, y) == to {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draws `text` with its top left corner at `(x, y)`, each font pixel `scale` pixels wide.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, scale: u32, color: Color) {
+        let advance = ((GLYPH_WIDTH + 1) * scale) as i32;
+
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as i32 * advance;
+
+            for (column, bits) in font::glyph(c).iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        self.fill_rect(
+                            Rect::new(
+                                left + (column as u32 * scale) as i32,
+                                y + (row * scale) as i32,
+                                scale,
+                                scale,
+                            ),
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// The size in pixels that [`Canvas::draw_text`] would cover.
+    pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+        let chars = text.chars().count() as u32;
+
+        (
+            (chars * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale,
+            GLYPH_HEIGHT * scale,
+        )
+    }
+}
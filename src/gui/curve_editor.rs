@@ -0,0 +1,157 @@
+use winit::event::MouseButton;
+
+use super::{
+    canvas::{Canvas, Rect},
+    ACCENT, GRID, HIGHLIGHT, PANEL, TEXT, TEXT_DIM,
+};
+use crate::velocity::VelocityCurve;
+
+/// How close to a breakpoint (in pixels) a click has to be to grab it.
+const GRAB_DISTANCE: i32 = 6;
+const POINT_SIZE: u32 = 7;
+
+/// Lets the user draw the velocity curve with the mouse.
+///
+/// Left click on an empty spot adds a breakpoint, dragging moves one and right click removes it.
+#[derive(Debug, Default)]
+pub struct CurveEditor {
+    dragging: Option<usize>,
+    last: Option<(u8, u8)>,
+}
+
+impl CurveEditor {
+    /// Remembers the last velocity that went through the curve so it can be shown.
+    pub fn set_last(&mut self, input: u8, output: u8) {
+        self.last = Some((input, output));
+    }
+
+    fn plot_area(bounds: Rect) -> Rect {
+        Rect::new(
+            bounds.x + 36,
+            bounds.y + 28,
+            bounds.width.saturating_sub(36 + 12),
+            bounds.height.saturating_sub(28 + 24),
+        )
+    }
+
+    fn to_screen(plot: Rect, input: u8, output: u8) -> (i32, i32) {
+        let w = plot.width.saturating_sub(1) as i32;
+        let h = plot.height.saturating_sub(1) as i32;
+
+        (
+            plot.x + input as i32 * w / 127,
+            plot.bottom() - 1 - output as i32 * h / 127,
+        )
+    }
+
+    fn from_screen(plot: Rect, x: i32, y: i32) -> (u8, u8) {
+        let w = plot.width.saturating_sub(1).max(1) as i32;
+        let h = plot.height.saturating_sub(1).max(1) as i32;
+
+        (
+            ((x - plot.x) * 127 / w).clamp(0, 127) as u8,
+            ((plot.bottom() - 1 - y) * 127 / h).clamp(0, 127) as u8,
+        )
+    }
+
+    fn point_at(plot: Rect, curve: &VelocityCurve, x: i32, y: i32) -> Option<usize> {
+        curve.points().iter().position(|&(input, output)| {
+            let (px, py) = Self::to_screen(plot, input, output);
+            (px - x).abs() <= GRAB_DISTANCE && (py - y).abs() <= GRAB_DISTANCE
+        })
+    }
+
+    /// Returns whether the curve changed.
+    pub fn mouse_pressed(
+        &mut self,
+        bounds: Rect,
+        curve: &mut VelocityCurve,
+        button: MouseButton,
+        x: i32,
+        y: i32,
+    ) -> bool {
+        let plot = Self::plot_area(bounds);
+        let hit = Self::point_at(plot, curve, x, y);
+
+        match button {
+            MouseButton::Left => {
+                if let Some(index) = hit {
+                    self.dragging = Some(index);
+                    false
+                } else if plot.contains(x, y) {
+                    let (input, output) = Self::from_screen(plot, x, y);
+                    self.dragging = Some(curve.insert(input, output));
+                    true
+                } else {
+                    false
+                }
+            }
+            MouseButton::Right => match hit {
+                Some(index) if curve.remove(index) => {
+                    // The points after it have moved down one
+                    self.dragging = match self.dragging {
+                        Some(dragging) if dragging > index => Some(dragging - 1),
+                        Some(dragging) if dragging == index => None,
+                        dragging => dragging,
+                    };
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns whether the curve changed.
+    pub fn mouse_moved(&mut self, bounds: Rect, curve: &mut VelocityCurve, x: i32, y: i32) -> bool {
+        match self.dragging {
+            Some(index) => {
+                let (input, output) = Self::from_screen(Self::plot_area(bounds), x, y);
+                curve.move_point(index, input, output);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn mouse_released(&mut self) {
+        self.dragging = None;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, bounds: Rect, curve: &VelocityCurve) {
+        let plot = Self::plot_area(bounds);
+
+        canvas.fill_rect(bounds, PANEL);
+        canvas.draw_text(bounds.x + 8, bounds.y + 8, "Velocity curve", 2, TEXT);
+
+        for step in [0, 32, 64, 96, 127] {
+            let (x, _) = Self::to_screen(plot, step, 0);
+            let (_, y) = Self::to_screen(plot, 0, step);
+            canvas.draw_line((x, plot.y), (x, plot.bottom() - 1), GRID);
+            canvas.draw_line((plot.x, y), (plot.right() - 1, y), GRID);
+
+            let label = step.to_string();
+            let (width, _) = Canvas::text_size(&label, 1);
+            canvas.draw_text(x - width as i32 / 2, plot.bottom() + 6, &label, 1, TEXT_DIM);
+            canvas.draw_text(plot.x - width as i32 - 6, y - 3, &label, 1, TEXT_DIM);
+        }
+
+        if let Some((input, output)) = self.last {
+            let (x, y) = Self::to_screen(plot, input, output);
+            canvas.draw_line((x, plot.bottom() - 1), (x, y), HIGHLIGHT);
+            canvas.draw_line((plot.x, y), (x, y), HIGHLIGHT);
+        }
+
+        for pair in curve.points().windows(2) {
+            let from = Self::to_screen(plot, pair[0].0, pair[0].1);
+            let to = Self::to_screen(plot, pair[1].0, pair[1].1);
+            canvas.draw_line(from, to, ACCENT);
+        }
+
+        for &(input, output) in curve.points() {
+            let (x, y) = Self::to_screen(plot, input, output);
+            let half = (POINT_SIZE / 2) as i32;
+            canvas.fill_rect(Rect::new(x - half, y - half, POINT_SIZE, POINT_SIZE), TEXT);
+        }
+    }
+}
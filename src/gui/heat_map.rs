@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use winit::event::ScanCode;
+
+use super::{
+    canvas::{Canvas, Color, Rect},
+    GRID, HIGHLIGHT, PANEL,
+};
+use crate::{keys, midi::NoteNames};
+
+/// How often each key was pressed and each note played this session, shown as a strip of
+/// all 128 notes from dim to bright with `--heat-map` and written out as CSV with
+/// `--heat-map-csv`.
+#[derive(Debug)]
+pub struct HeatMap {
+    notes: [u32; 128],
+    /// The presses of each key, with the note it played if it did.
+    keys: BTreeMap<ScanCode, (u32, Option<u8>)>,
+}
+
+impl Default for HeatMap {
+    fn default() -> Self {
+        HeatMap {
+            notes: [0; 128],
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl HeatMap {
+    /// Counts a press of the key with `scancode`, which played `note` if it isn't `None`.
+    pub fn press(&mut self, scancode: ScanCode, note: Option<u8>) {
+        let (presses, played) = self.keys.entry(scancode).or_insert((0, note));
+        *presses += 1;
+        *played = note;
+        if let Some(note) = note {
+            self.notes[note as usize & 0x7f] += 1;
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, bounds: Rect) {
+        canvas.fill_rect(bounds, PANEL);
+
+        let most = self.notes.iter().copied().max().unwrap_or(0).max(1);
+        let width = bounds.width as i32;
+        for (note, &count) in self.notes.iter().enumerate() {
+            // Spread over the whole width, however little of a pixel each note gets
+            let left = bounds.x + note as i32 * width / 128;
+            let right = bounds.x + (note as i32 + 1) * width / 128;
+            let color = if count == 0 {
+                GRID
+            } else {
+                blend(PANEL, HIGHLIGHT, count as f64 / most as f64)
+            };
+            let cell = Rect::new(left, bounds.y, (right - left).max(1) as u32, bounds.height);
+            canvas.fill_rect(cell, color);
+        }
+    }
+
+    /// Every key pressed as a line of `key,note,presses`, with a header.
+    pub fn csv(&self, names: NoteNames) -> String {
+        let mut csv = "key,note,presses\n".to_string();
+        for (&scancode, &(presses, note)) in &self.keys {
+            let key = keys::name(scancode).map_or_else(|| scancode.to_string(), str::to_string);
+            let note = note.map_or(String::new(), |note| names.name(note));
+            csv.push_str(&format!("{},{},{}\n", key, note, presses));
+        }
+        csv
+    }
+}
+
+/// The color `amount` of the way from `from` to `to`.
+fn blend(from: Color, to: Color, amount: f64) -> Color {
+    let channel = |shift: u32| {
+        let (a, b) = ((from >> shift) & 0xff, (to >> shift) & 0xff);
+        let mixed = a as f64 + (b as f64 - a as f64) * amount.clamp(0.0, 1.0);
+        (mixed.round() as Color) << shift
+    };
+
+    channel(16) | channel(8) | channel(0)
+}
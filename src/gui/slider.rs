@@ -0,0 +1,120 @@
+use winit::event::MouseButton;
+
+use super::{
+    canvas::{Canvas, Rect},
+    ACCENT, GRID, PANEL, TEXT, TEXT_DIM,
+};
+
+const LABEL_HEIGHT: u32 = 20;
+const HANDLE_HEIGHT: u32 = 8;
+
+/// A vertical slider dragged with the mouse, like a pitch bend or mod wheel.
+///
+/// The value goes from 0 at the bottom to `max` at the top. A slider with a rest value springs
+/// back to it when let go.
+#[derive(Debug)]
+pub struct Slider {
+    label: &'static str,
+    max: u16,
+    value: u16,
+    rest: Option<u16>,
+    dragging: bool,
+}
+
+impl Slider {
+    pub fn new(label: &'static str, max: u16, value: u16, rest: Option<u16>) -> Self {
+        Slider {
+            label,
+            max,
+            value,
+            rest,
+            dragging: false,
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    fn track(bounds: Rect) -> Rect {
+        Rect::new(
+            bounds.x + 8,
+            bounds.y + LABEL_HEIGHT as i32,
+            bounds.width.saturating_sub(16),
+            bounds.height.saturating_sub(LABEL_HEIGHT + 8),
+        )
+    }
+
+    fn value_at(&self, track: Rect, y: i32) -> u16 {
+        let h = track.height.saturating_sub(1).max(1) as i64;
+        let from_bottom = (track.bottom() - 1 - y).clamp(0, h as i32) as i64;
+
+        (from_bottom * self.max as i64 / h) as u16
+    }
+
+    fn set(&mut self, value: u16) -> bool {
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+
+    /// Returns whether the value changed.
+    pub fn mouse_pressed(&mut self, bounds: Rect, button: MouseButton, x: i32, y: i32) -> bool {
+        if button != MouseButton::Left || !bounds.contains(x, y) {
+            return false;
+        }
+
+        self.dragging = true;
+        let value = self.value_at(Self::track(bounds), y);
+        self.set(value)
+    }
+
+    /// Returns whether the value changed.
+    pub fn mouse_moved(&mut self, bounds: Rect, y: i32) -> bool {
+        if !self.dragging {
+            return false;
+        }
+
+        let value = self.value_at(Self::track(bounds), y);
+        self.set(value)
+    }
+
+    /// Returns whether the value changed, which it does if the slider springs back.
+    pub fn mouse_released(&mut self) -> bool {
+        let was_dragging = std::mem::replace(&mut self.dragging, false);
+
+        match self.rest {
+            Some(rest) if was_dragging => self.set(rest),
+            _ => false,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, bounds: Rect) {
+        let track = Self::track(bounds);
+
+        canvas.fill_rect(bounds, PANEL);
+        let (width, _) = Canvas::text_size(self.label, 1);
+        canvas.draw_text(
+            bounds.x + (bounds.width as i32 - width as i32) / 2,
+            bounds.y + 8,
+            self.label,
+            1,
+            TEXT,
+        );
+
+        let center = track.x + track.width as i32 / 2;
+        canvas.fill_rect(Rect::new(center - 1, track.y, 3, track.height), GRID);
+
+        let h = track.height.saturating_sub(HANDLE_HEIGHT) as i64;
+        let y = track.bottom()
+            - HANDLE_HEIGHT as i32
+            - (self.value as i64 * h / self.max as i64) as i32;
+        if let Some(rest) = self.rest {
+            let rest_y = track.bottom()
+                - HANDLE_HEIGHT as i32 / 2
+                - (rest as i64 * h / self.max as i64) as i32;
+            canvas.draw_line((track.x, rest_y), (track.right() - 1, rest_y), TEXT_DIM);
+        }
+        canvas.fill_rect(Rect::new(track.x, y, track.width, HANDLE_HEIGHT), ACCENT);
+    }
+}
@@ -0,0 +1,225 @@
+//! The fullscreen view for playing on stage or in front of a projector: a keyboard of the
+//! notes the keys play, lit while held, with the held notes and the status written large enough
+//! to read from across a room. Notes arriving on the input port are lit in another color, and
+//! the note to play next in practice mode is marked. Hovering a key shows what it plays.
+
+use super::{
+    canvas::{Canvas, Color, Rect},
+    ACCENT, BACKGROUND, GRID, HIGHLIGHT, TEXT, TEXT_DIM,
+};
+use crate::midi::NoteNames;
+
+/// The largest the status is written, if it fits.
+const STATUS_SCALE: u32 = 4;
+/// The largest the held notes are written, if they fit.
+const NOTES_SCALE: u32 = 16;
+const TOOLTIP_SCALE: u32 = 2;
+/// How far the tooltip is from the pointer, and from the edge of its box to its text.
+const TOOLTIP_GAP: i32 = 12;
+const TOOLTIP_PADDING: u32 = 6;
+
+fn is_black(note: u8) -> bool {
+    matches!(note % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+/// Where the keyboard is drawn in the view's `bounds`.
+fn keyboard(bounds: Rect) -> Rect {
+    let height = bounds.height / 2;
+    Rect::new(
+        bounds.x,
+        bounds.bottom() - height as i32,
+        bounds.width,
+        height,
+    )
+}
+
+/// The whole octaves covering the notes from `low` to `high`.
+fn octaves((low, high): (u8, u8)) -> (u8, u8) {
+    (low - low % 12, (high - high % 12 + 11).min(127))
+}
+
+/// What the keys of the keyboard show.
+pub struct Keys<'a> {
+    /// Lit, as they are held here.
+    pub held: &'a [u8],
+    /// Lit in another color, as they are arriving on the input port.
+    pub incoming: &'a [u8],
+    /// Marked, as the note to play next in practice mode.
+    pub prompt: Option<u8>,
+    /// The color of each note's key while it isn't lit, if not the usual one.
+    pub colors: &'a [Option<Color>; 128],
+}
+
+/// Draws the view into `bounds`. The keyboard covers whole octaves from `low` to `high` and
+/// shows `keys`.
+pub fn draw(
+    canvas: &mut Canvas,
+    bounds: Rect,
+    (low, high): (u8, u8),
+    keys: Keys,
+    names: NoteNames,
+    status: &str,
+) {
+    let status_height = draw_centered(canvas, bounds, status, STATUS_SCALE, TEXT_DIM);
+
+    let keyboard = keyboard(bounds);
+    let (low, high) = octaves((low, high));
+    draw_keyboard(canvas, keyboard, low, high, &keys);
+
+    let notes: Vec<_> = keys.held.iter().map(|&note| names.name(note)).collect();
+    let notes = notes.join(" ");
+    let middle = Rect::new(
+        bounds.x,
+        bounds.y + status_height as i32,
+        bounds.width,
+        ((keyboard.y - bounds.y).max(0) as u32).saturating_sub(status_height),
+    );
+    let (_, height) = Canvas::text_size(&notes, fit(&notes, middle, NOTES_SCALE));
+    let centered = Rect::new(
+        middle.x,
+        middle.y + (middle.height.saturating_sub(height) / 2) as i32,
+        middle.width,
+        height,
+    );
+    draw_centered(canvas, centered, &notes, NOTES_SCALE, TEXT);
+}
+
+fn draw_keyboard(canvas: &mut Canvas, bounds: Rect, low: u8, high: u8, keys: &Keys) {
+    let prompt = keys.prompt;
+    // Played here comes first, as that is what the player is looking for
+    let lit = |note: u8| {
+        if keys.held.contains(&note) {
+            Some(HIGHLIGHT)
+        } else if keys.incoming.contains(&note) {
+            Some(ACCENT)
+        } else {
+            None
+        }
+    };
+    let whites = (low..=high).filter(|&note| !is_black(note)).count().max(1) as i32;
+    let width = bounds.width as i32;
+    let left = |white: i32| bounds.x + white * width / whites;
+
+    let mut white = 0;
+    for note in low..=high {
+        if !is_black(note) {
+            let key = Rect::new(
+                left(white),
+                bounds.y,
+                (left(white + 1) - left(white) - 2).max(1) as u32,
+                bounds.height,
+            );
+            let color = lit(note).or(keys.colors[note as usize]).unwrap_or(TEXT);
+            canvas.fill_rect(key, color);
+            if prompt == Some(note) {
+                draw_mark(canvas, key, BACKGROUND);
+            }
+            white += 1;
+        }
+    }
+
+    // On top, straddling the gap between the white keys around them
+    let mut white = 0;
+    let black_width = (width / whites * 3 / 5).max(1);
+    for note in low..=high {
+        if is_black(note) {
+            let key = Rect::new(
+                left(white) - black_width / 2 - 1,
+                bounds.y,
+                black_width as u32,
+                bounds.height * 3 / 5,
+            );
+            let color = lit(note)
+                .or(keys.colors[note as usize])
+                .unwrap_or(BACKGROUND);
+            canvas.fill_rect(key, color);
+            canvas.fill_rect(Rect::new(key.x, key.bottom() - 2, key.width, 2), GRID);
+            if prompt == Some(note) {
+                draw_mark(canvas, key, TEXT);
+            }
+        } else {
+            white += 1;
+        }
+    }
+}
+
+/// The note of the key at `(x, y)` on the keyboard of the view drawn into `bounds` with
+/// `range`, if there is one.
+pub fn key_at(bounds: Rect, range: (u8, u8), (x, y): (i32, i32)) -> Option<u8> {
+    let bounds = keyboard(bounds);
+    if !bounds.contains(x, y) {
+        return None;
+    }
+    let (low, high) = octaves(range);
+    let whites = (low..=high).filter(|&note| !is_black(note)).count().max(1) as i32;
+    let width = bounds.width as i32;
+    let left = |white: i32| bounds.x + white * width / whites;
+
+    // The black keys first, as they are drawn on top
+    let black_width = (width / whites * 3 / 5).max(1);
+    let mut white = 0;
+    for note in low..=high {
+        if !is_black(note) {
+            white += 1;
+            continue;
+        }
+        let key = Rect::new(
+            left(white) - black_width / 2 - 1,
+            bounds.y,
+            black_width as u32,
+            bounds.height * 3 / 5,
+        );
+        if key.contains(x, y) {
+            return Some(note);
+        }
+    }
+
+    let white = (x - bounds.x) * whites / width.max(1);
+    (low..=high)
+        .filter(|&note| !is_black(note))
+        .nth(white as usize)
+}
+
+/// Draws `text` in a box next to the pointer at `(x, y)`, kept inside `bounds`.
+pub fn draw_tooltip(canvas: &mut Canvas, bounds: Rect, (x, y): (i32, i32), text: &str) {
+    let (width, height) = Canvas::text_size(text, TOOLTIP_SCALE);
+    let padding = TOOLTIP_PADDING;
+    let (width, height) = (width + 2 * padding, height + 2 * padding);
+    let x = (x + TOOLTIP_GAP)
+        .min(bounds.right() - width as i32)
+        .max(bounds.x);
+    let y = (y - TOOLTIP_GAP - height as i32).max(bounds.y);
+
+    let tooltip = Rect::new(x, y, width, height);
+    canvas.fill_rect(tooltip, GRID);
+    canvas.fill_rect(tooltip.inset(1), BACKGROUND);
+    let padding = padding as i32;
+    canvas.draw_text(x + padding, y + padding, text, TOOLTIP_SCALE, TEXT);
+}
+
+/// Marks `key` with a square near its bottom.
+fn draw_mark(canvas: &mut Canvas, key: Rect, color: Color) {
+    let size = (key.width / 2).max(1);
+    let x = key.x + ((key.width - size) / 2) as i32;
+    canvas.fill_rect(
+        Rect::new(x, key.bottom() - 2 * size as i32, size, size),
+        color,
+    );
+}
+
+/// Draws `text` centered at the top of `bounds`, as large as fits up to `scale`, and returns
+/// the height it took.
+fn draw_centered(canvas: &mut Canvas, bounds: Rect, text: &str, scale: u32, color: Color) -> u32 {
+    let scale = fit(text, bounds, scale);
+    let (width, height) = Canvas::text_size(text, scale);
+    let x = bounds.x + (bounds.width.saturating_sub(width) / 2) as i32;
+    canvas.draw_text(x, bounds.y, text, scale, color);
+    height
+}
+
+/// The largest scale up to `scale` at which `text` fits in `bounds`.
+fn fit(text: &str, bounds: Rect, scale: u32) -> u32 {
+    let (width, height) = Canvas::text_size(text, 1);
+    let fits = (bounds.width / width.max(1)).min(bounds.height / height.max(1));
+    fits.clamp(1, scale)
+}
@@ -0,0 +1,90 @@
+use std::{
+    os::raw::{c_char, c_int, c_ulong},
+    ptr,
+};
+
+use winit::{platform::unix::WindowExtUnix, window::Window};
+use x11_dl::xlib::{self, Xlib};
+
+use super::canvas::Canvas;
+
+/// Copies the canvas into an X11 window with `XPutImage`.
+pub struct X11Presenter {
+    xlib: Xlib,
+    display: *mut xlib::Display,
+    window: c_ulong,
+    gc: xlib::GC,
+    visual: *mut xlib::Visual,
+    depth: c_int,
+}
+
+impl X11Presenter {
+    /// Returns `None` if the window isn't an X11 window or libX11 can't be loaded.
+    pub fn new(window: &Window) -> Option<Self> {
+        let display = window.xlib_display()? as *mut xlib::Display;
+        let screen = window.xlib_screen_id()?;
+        let window = window.xlib_window()?;
+        let xlib = Xlib::open().ok()?;
+
+        // SAFETY: the display is owned by winit and outlives the event loop.
+        let (gc, visual, depth) = unsafe {
+            (
+                (xlib.XDefaultGC)(display, screen),
+                (xlib.XDefaultVisual)(display, screen),
+                (xlib.XDefaultDepth)(display, screen),
+            )
+        };
+
+        Some(X11Presenter {
+            xlib,
+            display,
+            window,
+            gc,
+            visual,
+            depth,
+        })
+    }
+
+    pub fn present(&mut self, canvas: &Canvas) {
+        if canvas.width() == 0 || canvas.height() == 0 {
+            return;
+        }
+
+        // SAFETY: the image borrows the canvas' pixels only until it is destroyed at the end of
+        // this block, and its data pointer is cleared first so Xlib doesn't try to free it.
+        unsafe {
+            let image = (self.xlib.XCreateImage)(
+                self.display,
+                self.visual,
+                self.depth as u32,
+                xlib::ZPixmap,
+                0,
+                canvas.pixels().as_ptr() as *mut c_char,
+                canvas.width(),
+                canvas.height(),
+                32,
+                0,
+            );
+            if image.is_null() {
+                return;
+            }
+
+            (self.xlib.XPutImage)(
+                self.display,
+                self.window,
+                self.gc,
+                image,
+                0,
+                0,
+                0,
+                0,
+                canvas.width(),
+                canvas.height(),
+            );
+
+            (*image).data = ptr::null_mut();
+            (self.xlib.XDestroyImage)(image);
+            (self.xlib.XFlush)(self.display);
+        }
+    }
+}
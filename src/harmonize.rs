@@ -0,0 +1,110 @@
+//! The harmonizer, which plays a second note at an interval from every note played, either a
+//! fixed number of semitones or a number of steps of a scale.
+
+use crate::{midi::MidiMsg, scale::Scale};
+
+/// How far from the note played the second note is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    /// Semitones above the note, or below it if negative.
+    Semitones(i8),
+    /// Steps of `scale` from `root` (a pitch class from 0 for C to 11), so a third is major or
+    /// minor depending on where it falls in the scale. Notes outside the scale keep their
+    /// distance from the step below them.
+    Diatonic { scale: Scale, root: u8, steps: i8 },
+}
+
+impl Interval {
+    /// The note at this interval from `note`, or `None` if that is outside the MIDI range.
+    fn apply(self, note: u8) -> Option<u8> {
+        let harmony = match self {
+            Interval::Semitones(semitones) => note as i32 + semitones as i32,
+            Interval::Diatonic { scale, root, steps } => {
+                let intervals = scale.intervals();
+                let from_root = note as i32 - root as i32;
+                let (octave, semitone) = (from_root.div_euclid(12), from_root.rem_euclid(12));
+                let index = intervals
+                    .iter()
+                    .rposition(|&interval| interval as i32 <= semitone)
+                    .unwrap_or(0);
+                let outside = semitone - intervals[index] as i32;
+
+                let len = intervals.len() as i32;
+                let degree = octave * len + index as i32 + steps as i32;
+                let interval = intervals[degree.rem_euclid(len) as usize] as i32;
+                root as i32 + 12 * degree.div_euclid(len) + interval + outside
+            }
+        };
+
+        u8::try_from(harmony).ok().filter(|&note| note <= 127)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Harmonizer {
+    interval: Interval,
+    /// Notes held with the second note played with them, so each note off stops the note that
+    /// was actually played even if the interval has changed since.
+    held: Vec<(u8, u8, u8)>,
+}
+
+impl Harmonizer {
+    pub fn new(interval: Interval) -> Self {
+        Harmonizer {
+            interval,
+            held: Vec::new(),
+        }
+    }
+
+    /// Moves a diatonic interval into the key with `root` (a pitch class) and `scale`. Fixed
+    /// intervals stay as they are.
+    pub fn set_key(&mut self, root: u8, scale: Scale) {
+        if let Interval::Diatonic { steps, .. } = self.interval {
+            self.interval = Interval::Diatonic { scale, root, steps };
+        }
+    }
+
+    /// Adds the second note to a note on or off.
+    pub fn handle(&mut self, midi: MidiMsg) -> Vec<MidiMsg> {
+        let mut messages = vec![midi];
+
+        match midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => {
+                if let Some(harmony) = self.interval.apply(note).filter(|&h| h != note) {
+                    self.held.push((channel, note, harmony));
+                    messages.push(MidiMsg::NoteOn {
+                        channel,
+                        note: harmony,
+                        velocity,
+                    });
+                }
+            }
+            MidiMsg::NoteOn { channel, note, .. } | MidiMsg::NoteOff { channel, note, .. } => {
+                if let Some(index) = self
+                    .held
+                    .iter()
+                    .position(|&(c, n, _)| c == channel && n == note)
+                {
+                    let (_, _, harmony) = self.held.remove(index);
+                    messages.push(MidiMsg::NoteOff {
+                        channel,
+                        note: harmony,
+                        velocity: 0,
+                    });
+                }
+            }
+            _ => (),
+        }
+
+        messages
+    }
+
+    /// Forgets the notes held, once they have been stopped some other way.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
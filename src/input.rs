@@ -0,0 +1,91 @@
+//! Where events come from besides the window. Everything that reads keys or commands on a thread
+//! of its own and sends them to the event loop is an [`InputSource`], registered with [`Inputs`]
+//! to be started, so a new one only needs a module implementing the trait and a line
+//! registering it. The `inputs` list in the config picks which of them start.
+
+use std::{
+    io::{self, BufRead},
+    thread,
+};
+
+use crate::{midi::DEFAULT_CHANNEL, protocol, session_log::report, Proxy, UserEvent};
+
+pub trait InputSource {
+    /// What the source is called in the `inputs` list of the config and in errors.
+    fn name(&self) -> &'static str;
+
+    /// Starts reading the source, sending what it reads to the event loop through `proxy`.
+    /// Returns a message for everything that couldn't be started.
+    fn start(&self, proxy: Proxy) -> Vec<String>;
+}
+
+#[derive(Default)]
+pub struct Inputs {
+    sources: Vec<Box<dyn InputSource>>,
+}
+
+impl Inputs {
+    pub fn register(&mut self, source: impl InputSource + 'static) {
+        self.sources.push(Box::new(source));
+    }
+
+    /// Starts the sources named in `enabled`, or all of them if it is `None`. Returns a message
+    /// for everything that couldn't be started, and for names no source has.
+    pub fn start(&self, enabled: Option<&[String]>, proxy: &Proxy) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(enabled) = enabled {
+            for name in enabled {
+                if !self.sources.iter().any(|source| source.name() == name) {
+                    errors.push(format!("no input source '{}' to start", name));
+                }
+            }
+        }
+
+        for source in &self.sources {
+            let name = source.name();
+            if enabled.is_some_and(|enabled| !enabled.iter().any(|n| n == name)) {
+                continue;
+            }
+            for err in source.start(proxy.clone()) {
+                errors.push(format!("{}: {}", name, err));
+            }
+        }
+        errors
+    }
+}
+
+/// The commands read from stdin with `--stdin`, see [`protocol`].
+pub struct Stdin;
+
+impl InputSource for Stdin {
+    fn name(&self) -> &'static str {
+        "stdin"
+    }
+
+    fn start(&self, proxy: Proxy) -> Vec<String> {
+        thread::spawn(move || {
+            for (number, line) in io::stdin().lock().lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        report!("stdin: {}", err);
+                        break;
+                    }
+                };
+
+                match protocol::parse_line(&line, DEFAULT_CHANNEL) {
+                    Ok(Some(command)) => {
+                        if proxy.send_event(UserEvent::Command(command)).is_err() {
+                            // The event loop has exited
+                            break;
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(err) => report!("stdin:{}: {}", number + 1, err),
+                }
+            }
+        });
+
+        Vec::new()
+    }
+}
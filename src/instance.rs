@@ -0,0 +1,117 @@
+//! Single-instance mode with `--single-instance`: the first instance listens on a Unix socket,
+//! and any started after it hand the commands given on their command line (`--panic`,
+//! `--preset` and so on) to it as lines of the [`protocol`] and exit, so the running keyboard
+//! can be controlled from a shell.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::MetadataExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    thread,
+};
+
+use crate::{
+    input::InputSource, midi::DEFAULT_CHANNEL, protocol, session_log::report, Proxy, UserEvent,
+};
+
+/// Where the running instance listens: in `$XDG_RUNTIME_DIR`, or in /tmp by user.
+fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("jack_keyboard.sock"),
+        None => {
+            let uid = fs::metadata("/proc/self").map_or(0, |m| m.uid());
+            PathBuf::from(format!("/tmp/jack_keyboard-{}.sock", uid))
+        }
+    }
+}
+
+/// Sends `lines` to the running instance, returning false if there is none.
+pub fn forward(lines: &[String]) -> io::Result<bool> {
+    let mut stream = match UnixStream::connect(socket_path()) {
+        Ok(stream) => stream,
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(false)
+        }
+        Err(err) => return Err(err),
+    };
+
+    for line in lines {
+        writeln!(stream, "{}", line)?;
+    }
+    Ok(true)
+}
+
+/// The socket of the running instance, as an input source.
+pub struct Instance {
+    listener: UnixListener,
+}
+
+impl Instance {
+    /// Listens for instances started later, taking over the socket of one that didn't exit
+    /// cleanly.
+    pub fn listen() -> io::Result<Self> {
+        let path = socket_path();
+        let listener = match UnixListener::bind(&path) {
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+                // Nothing answered in `forward`, so it's left over
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path)?
+            }
+            listener => listener?,
+        };
+        Ok(Instance { listener })
+    }
+}
+
+impl InputSource for Instance {
+    fn name(&self) -> &'static str {
+        "instance"
+    }
+
+    fn start(&self, proxy: Proxy) -> Vec<String> {
+        let listener = match self.listener.try_clone() {
+            Ok(listener) => listener,
+            Err(err) => return vec![err.to_string()],
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        report!("instance: {}", err);
+                        continue;
+                    }
+                };
+
+                for line in BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    match protocol::parse_line(&line, DEFAULT_CHANNEL) {
+                        Ok(Some(command)) => {
+                            if proxy.send_event(UserEvent::Command(command)).is_err() {
+                                // The event loop has exited
+                                return;
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(err) => report!("instance: {}", err),
+                    }
+                }
+            }
+        });
+
+        Vec::new()
+    }
+}
@@ -0,0 +1,423 @@
+//! Playing on a JACK MIDI output, which is the default. The [`Engine`] runs in the process
+//! callback, along with the built-in synth, the audio input follower, and the monitor and MIDI
+//! thru on the input port if they're enabled.
+//!
+//! With `--extra-client`, the synth and the monitor run in a client of their own instead, so
+//! however long they take the keyboard's output isn't held up. The synth then plays what
+//! arrives on its own input port, which is connected to the keyboard's output.
+
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+};
+
+use jack::{
+    AsyncClient, AudioIn, AudioOut, Client, ClientOptions, ClientStatus, Frames, MidiIn, MidiOut,
+    NotificationHandler, Port, PortId, ProcessHandler, ProcessScope, RawMidi, TransportState,
+    TransportStatePosition, Unowned,
+};
+
+use crate::{
+    config::Config,
+    engine::{Control, Engine, Outcome},
+    latency,
+    level::Follower,
+    metadata,
+    midi::MidiMsg,
+    monitor::Incoming,
+    mtc::Mtc,
+    options::Options,
+    session_log::{self, report},
+    synth::{Synth, Waveform},
+    thru::{self, Rule},
+    timebase::{self, Tempo},
+    KeyboardMsg,
+};
+
+/// The name of the client started with `--extra-client`.
+const EXTRA_CLIENT_NAME: &str = "jack_keyboard_extra";
+
+/// The clients playing, which stop when this is dropped.
+pub struct Clients {
+    _keyboard: AsyncClient<Notifications, Process>,
+    _extra: Option<AsyncClient<(), Extra>>,
+}
+
+/// Registers the ports and starts playing the [`Engine`]. If `written` is given, every message
+/// that was written is also sent there, stamped with the JACK time it is played at, and so is
+/// every beat of the clock to `beats` and every message arriving on the input port to
+/// `monitor`.
+pub fn start(
+    rx: Receiver<KeyboardMsg>,
+    controls: Receiver<Control>,
+    written: Option<Sender<KeyboardMsg>>,
+    beats: Option<Sender<u64>>,
+    monitor: Option<Sender<Incoming>>,
+    options: &Options,
+    config: &Config,
+) -> Clients {
+    let (client, _client_status) =
+        Client::new("jack_keyboard", ClientOptions::NO_START_SERVER).unwrap();
+
+    let (extra, monitor) = match options.extra_client {
+        true => (Some(Extra::new(options.synth, monitor)), None),
+        false => (None, monitor),
+    };
+    let mut process = Process {
+        out: client.register_port("out", MidiOut).unwrap(),
+        synth: options.synth.filter(|_| extra.is_none()).map(|waveform| {
+            (
+                client.register_port("synth_out", AudioOut).unwrap(),
+                Synth::new(waveform, client.sample_rate()),
+            )
+        }),
+        audio_in: options.audio_in.map(|_| {
+            (
+                client.register_port("audio_in", AudioIn).unwrap(),
+                Follower::new(client.sample_rate()),
+            )
+        }),
+        input: (monitor.is_some() || !config.thru.is_empty())
+            .then(|| client.register_port("in", MidiIn).unwrap()),
+        monitor,
+        mtc: options.mtc.map(|rate| {
+            (
+                client.register_port("mtc", MidiOut).unwrap(),
+                Mtc::new(rate),
+            )
+        }),
+        thru: (!config.thru.is_empty()).then(|| config.thru.clone()),
+        latency_offset: options
+            .latency_offset
+            .map(|ms| (ms * client.sample_rate() as f64 / 1000.0).round() as i64),
+        tempo: options
+            .timebase_master
+            .then(|| Tempo::new(AtomicU64::new(config.tempo.to_bits()))),
+        freewheeling: Arc::new(AtomicBool::new(false)),
+        engine: Engine::new(rx, controls, beats, options, config, client.sample_rate()),
+        written,
+    };
+    if let Err(err) = name_ports(&client, &mut process) {
+        // Only the patchbay's names are at stake
+        report!("{}", err);
+    }
+    let notifications = Notifications {
+        report_xruns: options.stats,
+        freewheeling: process.freewheeling.clone(),
+    };
+
+    if options.report_latency {
+        let synth = process.synth.as_ref().map(|(port, _)| port.clone_unowned());
+        let outputs = [process.out.clone_unowned()]
+            .into_iter()
+            .chain(synth)
+            .collect();
+        let mtc = process.mtc.as_ref().map(|(port, _)| port.clone_unowned());
+        let synced = mtc.into_iter().collect();
+        latency::start(&client, outputs, synced, process.latency_offset);
+    }
+
+    let tempo = process.tempo.clone();
+    let out = process.out.name().unwrap();
+    let client = client.activate_async(notifications, process).unwrap();
+    if let Some(tempo) = tempo {
+        if let Err(err) = timebase::start(client.as_client(), tempo) {
+            report!("{}", err);
+        }
+    }
+
+    let extra = extra.and_then(|extra| match extra {
+        Ok((extra_client, extra)) => extra.activate(extra_client, &out),
+        Err(err) => {
+            report!("{}: {}", EXTRA_CLIENT_NAME, err);
+            None
+        }
+    });
+    Clients {
+        _keyboard: client,
+        _extra: extra,
+    }
+}
+
+/// Names the client and its ports for patchbays, see [`metadata`].
+fn name_ports(client: &Client, process: &mut Process) -> Result<(), String> {
+    metadata::name_client(client)?;
+
+    let name = |port: &str| format!("{} {}", metadata::CLIENT_NAME, port);
+    metadata::name_port(client, &mut process.out, &name("Out"), 1)?;
+    if let Some(port) = &mut process.input {
+        metadata::name_port(client, port, &name("In"), 2)?;
+    }
+    if let Some((port, _)) = &mut process.synth {
+        metadata::name_port(client, port, &name("Synth"), 3)?;
+    }
+    if let Some((port, _)) = &mut process.audio_in {
+        metadata::name_port(client, port, &name("Audio In"), 4)?;
+    }
+    if let Some((port, _)) = &mut process.mtc {
+        metadata::name_port(client, port, &name("MTC"), 5)?;
+    }
+
+    Ok(())
+}
+
+pub struct Process {
+    out: Port<MidiOut>,
+    synth: Option<(Port<AudioOut>, Synth)>,
+    audio_in: Option<(Port<AudioIn>, Follower)>,
+    /// The input port, for the monitor and MIDI thru.
+    input: Option<Port<MidiIn>>,
+    monitor: Option<Sender<Incoming>>,
+    /// The port MIDI Time Code is sent on, with `--mtc`.
+    mtc: Option<(Port<MidiOut>, Mtc)>,
+    /// The rules messages from the input port are passed on to the output by.
+    thru: Option<Vec<Rule>>,
+    /// Frames to shift incoming events by, or `None` to play them at the start of the cycle.
+    latency_offset: Option<i64>,
+    /// The tempo published by the timebase callback, with `--timebase-master`.
+    tempo: Option<Tempo>,
+    /// Set by [`Notifications`] while JACK is freewheeling.
+    freewheeling: Arc<AtomicBool>,
+    engine: Engine,
+    written: Option<Sender<KeyboardMsg>>,
+}
+
+impl ProcessHandler for Process {
+    fn process(&mut self, client: &Client, process_scope: &ProcessScope) -> jack::Control {
+        let mut writer = self.out.writer(process_scope);
+        let mut synth_out = self
+            .synth
+            .as_mut()
+            .map(|(port, synth)| (port.as_mut_slice(process_scope), synth, 0));
+
+        self.engine
+            .set_freewheeling(self.freewheeling.load(Ordering::Relaxed));
+        if let Some((port, follower)) = &mut self.audio_in {
+            let level = follower.process(port.as_slice(process_scope));
+            self.engine.set_level(level);
+        }
+        if let Some(port) = &self.input {
+            for midi in port.iter(process_scope) {
+                if let Some(monitor) = &self.monitor {
+                    // Nothing to be done if the window has gone away
+                    let _ = monitor.send(Incoming::new(midi.bytes));
+                }
+                let routed = self.thru.as_ref().and_then(|rules| {
+                    MidiMsg::decode(midi.bytes).and_then(|msg| thru::route(rules, msg))
+                });
+                if let Some(routed) = routed {
+                    self.engine.thru(midi.time, routed);
+                }
+            }
+        }
+
+        let (latency_offset, written) = (self.latency_offset, &self.written);
+        self.engine.cycle(
+            process_scope.n_frames(),
+            |time| match latency_offset {
+                Some(offset) => event_time(client, process_scope, time, offset),
+                None => 0,
+            },
+            |time, bytes| match writer.write(&RawMidi { time, bytes }) {
+                Ok(_) => Outcome::Done,
+                // The only way it fails, apart from events out of order
+                Err(_) => Outcome::Full,
+            },
+            |time, &midi| {
+                if let Some((buffer, synth, rendered)) = &mut synth_out {
+                    // Render up to the event so it starts on the right sample
+                    let time = time as usize;
+                    synth.render(&mut buffer[*rendered..time]);
+                    *rendered = time;
+                    synth.handle(&midi);
+                }
+
+                if let Some(written) = written {
+                    let frame = process_scope.last_frame_time().wrapping_add(time);
+                    let _ = written.send(KeyboardMsg {
+                        midi,
+                        time: client.frames_to_time(frame),
+                    });
+                }
+            },
+        );
+
+        if let Some((buffer, synth, rendered)) = synth_out {
+            synth.render(&mut buffer[rendered..]);
+        }
+        if let Some((port, mtc)) = &mut self.mtc {
+            let mut writer = port.writer(process_scope);
+            if let Ok(TransportStatePosition { pos, state }) = client.transport().query() {
+                let frame_rate = pos.frame_rate().unwrap_or(client.sample_rate() as Frames);
+                mtc.cycle(
+                    state == TransportState::Rolling,
+                    pos.frame(),
+                    frame_rate,
+                    process_scope.n_frames(),
+                    // Only missed if the port is full, and the next ones say the same
+                    |time, bytes| {
+                        let _ = writer.write(&RawMidi { time, bytes });
+                    },
+                );
+            }
+        }
+        if let Some(tempo) = &self.tempo {
+            tempo.store(self.engine.tempo().to_bits(), Ordering::Relaxed);
+        }
+
+        jack::Control::Continue
+    }
+}
+
+/// Places an event that happened at JACK time `time` in the current cycle.
+///
+/// Events are delayed by one period, which keeps the spacing between key presses intact, and
+/// then shifted by `offset` frames. Whatever falls outside the current buffer is clamped to it.
+fn event_time(
+    client: &Client,
+    process_scope: &ProcessScope,
+    time: jack::Time,
+    offset: i64,
+) -> Frames {
+    let n_frames = process_scope.n_frames();
+    let frame = client.time_to_frames(time) as i64 + n_frames as i64 + offset;
+    // Frame times wrap around, so only the (small) difference is meaningful
+    let relative = frame.wrapping_sub(process_scope.last_frame_time() as i64) as i32 as i64;
+
+    relative.clamp(0, n_frames.saturating_sub(1) as i64) as Frames
+}
+
+/// The client started with `--extra-client`, with the built-in synth and the monitor.
+pub struct Extra {
+    /// The synth's own input port, connected to the keyboard's output, and its output.
+    synth: Option<(Port<MidiIn>, Port<AudioOut>, Synth)>,
+    /// The input port, for the monitor.
+    monitor: Option<(Port<MidiIn>, Sender<Incoming>)>,
+}
+
+impl Extra {
+    fn new(
+        waveform: Option<Waveform>,
+        monitor: Option<Sender<Incoming>>,
+    ) -> Result<(Client, Self), jack::Error> {
+        let (client, _client_status) =
+            Client::new(EXTRA_CLIENT_NAME, ClientOptions::NO_START_SERVER)?;
+
+        let synth = match waveform {
+            Some(waveform) => Some((
+                client.register_port("synth_in", MidiIn)?,
+                client.register_port("synth_out", AudioOut)?,
+                Synth::new(waveform, client.sample_rate()),
+            )),
+            None => None,
+        };
+        let monitor = match monitor {
+            Some(monitor) => Some((client.register_port("in", MidiIn)?, monitor)),
+            None => None,
+        };
+
+        Ok((client, Extra { synth, monitor }))
+    }
+
+    /// Starts `client`, with the synth playing what is written to the port named `out`.
+    fn activate(self, client: Client, out: &str) -> Option<AsyncClient<(), Extra>> {
+        let synth_in = self.synth.as_ref().map(|(port, ..)| port.name());
+
+        let client = match client.activate_async((), self) {
+            Ok(client) => client,
+            Err(err) => {
+                report!("{}: {}", EXTRA_CLIENT_NAME, err);
+                return None;
+            }
+        };
+        if let Some(Ok(synth_in)) = synth_in {
+            if let Err(err) = client.as_client().connect_ports_by_name(out, &synth_in) {
+                report!("{}: {}", EXTRA_CLIENT_NAME, err);
+            }
+        }
+
+        Some(client)
+    }
+}
+
+impl ProcessHandler for Extra {
+    fn process(&mut self, _: &Client, process_scope: &ProcessScope) -> jack::Control {
+        if let Some((input, output, synth)) = &mut self.synth {
+            let buffer = output.as_mut_slice(process_scope);
+            let mut rendered = 0;
+            for event in input.iter(process_scope) {
+                // Render up to the event so it starts on the right sample
+                let time = (event.time as usize).clamp(rendered, buffer.len());
+                synth.render(&mut buffer[rendered..time]);
+                rendered = time;
+                if let Some(midi) = MidiMsg::decode(event.bytes) {
+                    synth.handle(&midi);
+                }
+            }
+            synth.render(&mut buffer[rendered..]);
+        }
+
+        if let Some((input, monitor)) = &self.monitor {
+            for midi in input.iter(process_scope) {
+                // Nothing to be done if the window has gone away
+                let _ = monitor.send(Incoming::new(midi.bytes));
+            }
+        }
+
+        jack::Control::Continue
+    }
+}
+
+/// Runs outside the process callback, so it is free to print.
+pub struct Notifications {
+    /// Whether xruns are printed, along with `--stats`.
+    report_xruns: bool,
+    freewheeling: Arc<AtomicBool>,
+}
+
+impl NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, reason: &str) {
+        report!("JACK shut down: {}", reason);
+        session_log::record("stop", "JACK shut down");
+        process::exit(1);
+    }
+
+    fn freewheel(&mut self, _: &Client, is_enabled: bool) {
+        if is_enabled {
+            report!("JACK is freewheeling, only note offs are played");
+        } else {
+            report!("JACK stopped freewheeling");
+        }
+        self.freewheeling.store(is_enabled, Ordering::Relaxed);
+    }
+
+    fn ports_connected(&mut self, client: &Client, a: PortId, b: PortId, are_connected: bool) {
+        // Only our own ports are logged, whatever the rest of the graph does
+        let (Some(a), Some(b)) = (client.port_by_id(a), client.port_by_id(b)) else {
+            return;
+        };
+        if !client.is_mine(&a) && !client.is_mine(&b) {
+            return;
+        }
+        let name = |port: &Port<Unowned>| port.name().unwrap_or_default();
+        let what = if are_connected {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        session_log::record(what, format_args!("{} -> {}", name(&a), name(&b)));
+    }
+
+    fn xrun(&mut self, _: &Client) -> jack::Control {
+        if self.report_xruns {
+            report!("xrun");
+        } else {
+            session_log::record("notice", "xrun");
+        }
+        jack::Control::Continue
+    }
+}
@@ -0,0 +1,284 @@
+//! The JSON line format used by `--emit-json`.
+//!
+//! Each event is one object on its own line, e.g.
+//! `{"type":"note_on","channel":2,"note":60,"velocity":112,"timestamp":1234567}`.
+//! Channels are one-based like in the config file, timestamps are JACK time in microseconds.
+
+use crate::{
+    midi::{MidiMsg, PITCH_BEND_MAX},
+    protocol::Command,
+};
+
+pub fn event_line(midi: &MidiMsg, timestamp: jack::Time) -> String {
+    let fields = match *midi {
+        MidiMsg::NoteOn {
+            channel,
+            note,
+            velocity,
+        } => format!(
+            r#""type":"note_on","channel":{},"note":{},"velocity":{}"#,
+            channel + 1,
+            note,
+            velocity
+        ),
+        MidiMsg::NoteOff {
+            channel,
+            note,
+            velocity,
+        } => format!(
+            r#""type":"note_off","channel":{},"note":{},"velocity":{}"#,
+            channel + 1,
+            note,
+            velocity
+        ),
+        MidiMsg::ControlChange {
+            channel,
+            controller,
+            value,
+        } => format!(
+            r#""type":"control_change","channel":{},"controller":{},"value":{}"#,
+            channel + 1,
+            controller,
+            value
+        ),
+        MidiMsg::ProgramChange { channel, program } => format!(
+            r#""type":"program_change","channel":{},"program":{}"#,
+            channel + 1,
+            program
+        ),
+        MidiMsg::ChannelPressure { channel, value } => format!(
+            r#""type":"channel_pressure","channel":{},"value":{}"#,
+            channel + 1,
+            value
+        ),
+        MidiMsg::PitchBend { channel, value } => format!(
+            r#""type":"pitch_bend","channel":{},"value":{}"#,
+            channel + 1,
+            value
+        ),
+    };
+
+    format!(r#"{{{},"timestamp":{}}}"#, fields, timestamp)
+}
+
+/// The line broadcast when a preset is selected. `preset` is zero-based but written one-based.
+pub fn preset_line(preset: usize, name: &str, color: Option<u32>) -> String {
+    let color = color.map_or(String::new(), |color| {
+        format!(r##","color":"#{:06x}""##, color)
+    });
+    format!(
+        r#"{{"type":"preset","preset":{},"name":{}{}}}"#,
+        preset + 1,
+        string(name),
+        color
+    )
+}
+
+pub fn error_line(message: &str) -> String {
+    format!(r#"{{"type":"error","message":{}}}"#, string(message))
+}
+
+/// `text` as a JSON string, quoted and escaped.
+pub fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses an event line in the format written by [`event_line`] (or by [`preset_line`]). The
+/// timestamp is ignored and the channel defaults to `default_channel` (zero-based) when it is
+/// left out.
+pub fn parse_event(line: &str, default_channel: u8) -> Result<Command, String> {
+    let fields = parse_object(line)?;
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+    let number = |name: &str, max: u8| -> Result<u8, String> {
+        match field(name) {
+            Some(JsonValue::Number(n)) if n.fract() == 0.0 && (0.0..=max as f64).contains(n) => {
+                Ok(*n as u8)
+            }
+            Some(_) => Err(format!("\"{}\" must be a number from 0 to {}", name, max)),
+            None => Err(format!("missing \"{}\"", name)),
+        }
+    };
+
+    let channel = match field("channel") {
+        Some(_) => number("channel", 16)?
+            .checked_sub(1)
+            .ok_or("\"channel\" must be from 1 to 16")?,
+        None => default_channel,
+    };
+
+    let midi = match field("type") {
+        Some(JsonValue::String(kind)) => match kind.as_str() {
+            "preset" => {
+                return match field("preset") {
+                    Some(JsonValue::Number(n)) if n.fract() == 0.0 && *n >= 1.0 => {
+                        Ok(Command::Preset(*n as usize - 1))
+                    }
+                    _ => Err("\"preset\" must be a preset number starting at 1".to_string()),
+                }
+            }
+            "note_on" => MidiMsg::NoteOn {
+                channel,
+                note: number("note", 127)?,
+                velocity: number("velocity", 127)?,
+            },
+            "note_off" => MidiMsg::NoteOff {
+                channel,
+                note: number("note", 127)?,
+                velocity: number("velocity", 127).unwrap_or(0),
+            },
+            "control_change" => MidiMsg::ControlChange {
+                channel,
+                controller: number("controller", 127)?,
+                value: number("value", 127)?,
+            },
+            "program_change" => MidiMsg::ProgramChange {
+                channel,
+                program: number("program", 127)?,
+            },
+            "channel_pressure" => MidiMsg::ChannelPressure {
+                channel,
+                value: number("value", 127)?,
+            },
+            "pitch_bend" => match field("value") {
+                Some(JsonValue::Number(n))
+                    if n.fract() == 0.0 && (0.0..=PITCH_BEND_MAX as f64).contains(n) =>
+                {
+                    MidiMsg::PitchBend {
+                        channel,
+                        value: *n as u16,
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "\"value\" must be a number from 0 to {}",
+                        PITCH_BEND_MAX
+                    ))
+                }
+            },
+            _ => return Err(format!("unknown event type \"{}\"", kind)),
+        },
+        Some(_) => return Err("\"type\" must be a string".to_string()),
+        None => return Err("missing \"type\"".to_string()),
+    };
+
+    Ok(Command::Midi(midi))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    String(String),
+    Number(f64),
+    Other,
+}
+
+/// Parses a flat JSON object. Nested arrays and objects aren't supported.
+fn parse_object(text: &str) -> Result<Vec<(String, JsonValue)>, String> {
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = Vec::new();
+
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    };
+
+    if chars.next() != Some('{') {
+        return Err("expected a JSON object".to_string());
+    }
+
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("expected ':' after \"{}\"", key));
+            }
+            skip_whitespace(&mut chars);
+
+            let value = match chars.peek() {
+                Some('"') => JsonValue::String(parse_string(&mut chars)?),
+                Some(_) => {
+                    let mut word = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c == ',' || c == '}' || c.is_whitespace() {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+
+                    match word.as_str() {
+                        "true" | "false" | "null" => JsonValue::Other,
+                        _ => JsonValue::Number(
+                            word.parse()
+                                .map_err(|_| format!("invalid value for \"{}\"", key))?,
+                        ),
+                    }
+                }
+                None => return Err("unexpected end of line".to_string()),
+            };
+            fields.push((key, value));
+
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("expected ',' or '}'".to_string()),
+            }
+        }
+    }
+
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(fields),
+        Some(_) => Err("trailing characters after the object".to_string()),
+    }
+}
+
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected a string".to_string());
+    }
+
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some('r') => string.push('\r'),
+                Some('b') => string.push('\u{8}'),
+                Some('f') => string.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid escape \\u{}", hex))?;
+                    string.push(c);
+                }
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => string.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
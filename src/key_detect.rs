@@ -0,0 +1,95 @@
+//! Guessing the key of what is being played, configured with `[key_detect]`, by how well the
+//! notes played lately fit the Krumhansl-Kessler profiles of each major and minor key.
+
+use std::collections::VecDeque;
+
+use crate::scale::Scale;
+
+/// How well each degree of the chromatic scale fits a major key, from its root up.
+const MAJOR: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+/// How well each degree of the chromatic scale fits a minor key, from its root up.
+const MINOR: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// How many notes have to be played before there's a guess at all.
+const MIN_NOTES: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct KeyDetect {
+    /// The pitch classes of the notes played lately, oldest first.
+    recent: VecDeque<u8>,
+    /// How many notes are kept in `recent`.
+    notes: usize,
+    key: Option<(u8, Scale)>,
+}
+
+impl KeyDetect {
+    pub fn new(notes: usize) -> Self {
+        KeyDetect {
+            recent: VecDeque::with_capacity(notes),
+            notes,
+            key: None,
+        }
+    }
+
+    /// The key guessed, as the pitch class of its root from 0 for C to 11 and a major or minor
+    /// scale.
+    pub fn key(&self) -> Option<(u8, Scale)> {
+        self.key
+    }
+
+    /// Counts a note played, and returns whether that changed the key guessed.
+    pub fn note(&mut self, note: u8) -> bool {
+        if self.recent.len() == self.notes {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(note % 12);
+
+        let key = self.guess();
+        let changed = key != self.key;
+        self.key = key;
+        changed
+    }
+
+    fn guess(&self) -> Option<(u8, Scale)> {
+        if self.recent.len() < MIN_NOTES {
+            return None;
+        }
+        let mut counts = [0.0; 12];
+        for &pitch_class in &self.recent {
+            counts[pitch_class as usize] += 1.0;
+        }
+
+        let keys = (0..12).flat_map(|root| [(root, Scale::Major), (root, Scale::Minor)]);
+        keys.map(|(root, scale)| {
+            let profile = if scale == Scale::Major {
+                &MAJOR
+            } else {
+                &MINOR
+            };
+            let rotated: Vec<f64> = (0..12).map(|pc| profile[(pc + 12 - root) % 12]).collect();
+            (correlation(&counts, &rotated), root as u8, scale)
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, root, scale)| (root, scale))
+    }
+}
+
+/// The Pearson correlation of two sequences of the same length.
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+
+    let mut covariance = 0.0;
+    let (mut variance_a, mut variance_b) = (0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+
+    covariance / (variance_a * variance_b).sqrt().max(f64::EPSILON)
+}
@@ -0,0 +1,107 @@
+//! Key-up notes, configured with `[[key_up]]`: a key that plays a short note when it is let go
+//! of, either instead of anything on the press or after a note of its own held while the key
+//! is down, for hammer-off like effects and staccato patterns.
+
+use std::time::{Duration, Instant};
+
+use winit::event::ScanCode;
+
+use crate::midi::{MidiMsg, DEFAULT_CHANNEL};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyUp {
+    pub key: ScanCode,
+    /// The note held while the key is down, if any.
+    pub press: Option<u8>,
+    /// The note played when the key is let go of.
+    pub note: u8,
+    /// How long the note played on release sounds.
+    pub length: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyUps {
+    keys: Vec<KeyUp>,
+    /// The notes played on release that are still sounding, with when they stop.
+    sounding: Vec<(u8, Instant)>,
+}
+
+impl KeyUps {
+    pub fn new(keys: Vec<KeyUp>) -> Self {
+        KeyUps {
+            keys,
+            sounding: Vec::new(),
+        }
+    }
+
+    /// Turns a press or release of a key-up key into the notes it plays or stops, or returns
+    /// `None` if `scancode` isn't a key-up key.
+    pub fn handle(
+        &mut self,
+        scancode: ScanCode,
+        pressed: bool,
+        velocity: u8,
+        now: Instant,
+    ) -> Option<Vec<MidiMsg>> {
+        let key = self.keys.iter().find(|key| key.key == scancode)?;
+        let channel = DEFAULT_CHANNEL;
+        let mut messages = Vec::new();
+
+        if pressed {
+            if let Some(note) = key.press {
+                messages.push(MidiMsg::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                });
+            }
+            return Some(messages);
+        }
+
+        if let Some(note) = key.press {
+            messages.push(MidiMsg::NoteOff {
+                channel,
+                note,
+                velocity,
+            });
+        }
+        if let Some(index) = self.sounding.iter().position(|&(n, _)| n == key.note) {
+            // Still sounding from the last release, so it is played again from the start
+            self.sounding.remove(index);
+            messages.push(MidiMsg::NoteOff {
+                channel,
+                note: key.note,
+                velocity,
+            });
+        }
+        messages.push(MidiMsg::NoteOn {
+            channel,
+            note: key.note,
+            velocity,
+        });
+        self.sounding.push((key.note, now + key.length));
+        Some(messages)
+    }
+
+    /// When the next note played on release stops.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.sounding.iter().map(|&(_, due)| due).min()
+    }
+
+    /// The note offs of the notes played on release whose time is up by `now`.
+    pub fn due(&mut self, now: Instant) -> Vec<MidiMsg> {
+        let mut messages = Vec::new();
+        self.sounding.retain(|&(note, due)| {
+            if due > now {
+                return true;
+            }
+            messages.push(MidiMsg::NoteOff {
+                channel: DEFAULT_CHANNEL,
+                note,
+                velocity: 0,
+            });
+            false
+        });
+        messages
+    }
+}
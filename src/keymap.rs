@@ -0,0 +1,309 @@
+//! `--import-keymap`: reads a VMPK keymap and prints it as the `[notes]` table of the config,
+//! for users coming from VMPK with a layout of their own.
+//!
+//! VMPK saves two kinds of keymap, both XML with a `<mapping key="..." note="..."/>` per key.
+//! A `<keyboardmap>` names each key by what it types, which is taken to be on a US layout
+//! (the way the keys are named in the config too). A `<rawkeyboardmap>` gives X11 keycodes,
+//! which are evdev scancodes plus 8. Notes count from VMPK's base octave, taken to be its
+//! default of C3 (48).
+//!
+//! The classic C jack-keyboard has nothing to import: its QWERTY, QWERTZ and AZERTY layouts
+//! are compiled in and put the notes on the same physical keys as here.
+//!
+//! Also here are the built-in keymaps chosen with `keymap` in the config, for playing with one
+//! hand, the keys of the `[numpad]` instrument, and `--export-keymap`, which writes the keymap
+//! in use out for sharing, as config, as JSON or as a chart of the keyboard.
+
+use winit::event::ScanCode;
+
+use crate::{json, keys, layout::Layout, midi::NoteNames, session_log::report};
+
+/// The note VMPK's note 0 is played as.
+const BASE_NOTE: i32 = 48;
+/// How far X11 keycodes are from evdev scancodes.
+const X11_KEYCODE_OFFSET: u32 = 8;
+
+/// Keys VMPK names by what they type rather than after themselves.
+#[rustfmt::skip]
+const SYMBOLS: &[(&str, &str)] = &[
+    ("`", "Backquote"), ("-", "Minus"), ("=", "Equal"), ("[", "BracketLeft"),
+    ("]", "BracketRight"), ("\\", "Backslash"), (";", "Semicolon"), ("'", "Quote"),
+    (",", "Comma"), (".", "Period"), ("/", "Slash"), ("Backtab", "Tab"),
+    ("Return", "Enter"), ("Esc", "Escape"),
+];
+
+/// The main block of keys as [`Format::Chart`] draws it, each row with how far in it starts,
+/// in key widths.
+#[rustfmt::skip]
+const CHART_ROWS: [(f32, &[&str]); 4] = [
+    (0.0, &[
+        "Backquote", "Digit1", "Digit2", "Digit3", "Digit4", "Digit5", "Digit6", "Digit7",
+        "Digit8", "Digit9", "Digit0", "Minus", "Equal",
+    ]),
+    (1.5, &[
+        "KeyQ", "KeyW", "KeyE", "KeyR", "KeyT", "KeyY", "KeyU", "KeyI", "KeyO", "KeyP",
+        "BracketLeft", "BracketRight", "Backslash",
+    ]),
+    (1.75, &[
+        "KeyA", "KeyS", "KeyD", "KeyF", "KeyG", "KeyH", "KeyJ", "KeyK", "KeyL", "Semicolon",
+        "Quote",
+    ]),
+    (2.25, &[
+        "KeyZ", "KeyX", "KeyC", "KeyV", "KeyB", "KeyN", "KeyM", "Comma", "Period", "Slash",
+    ]),
+];
+/// The width of a key in the chart, inside its borders.
+const CHART_KEY_WIDTH: usize = 5;
+
+/// What `--export-keymap` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The `[notes]` table of the config.
+    Toml,
+    /// An object from key names to note names.
+    Json,
+    /// The keyboard drawn in text with each key's note on it.
+    Chart,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "toml" => Some(Format::Toml),
+            "json" => Some(Format::Json),
+            "chart" => Some(Format::Chart),
+            _ => None,
+        }
+    }
+}
+
+/// Writes out the note of each key in `notes` as `format`. The chart labels the keys as they
+/// are on `layout`.
+pub fn export(
+    format: Format,
+    notes: &[(ScanCode, u8)],
+    names: NoteNames,
+    layout: Layout,
+) -> String {
+    let entries = notes
+        .iter()
+        .map(|&(key, note)| (keys::name(key).unwrap_or_default(), names.name(note)));
+
+    match format {
+        Format::Toml => {
+            let lines: Vec<_> = entries
+                .map(|(key, note)| format!("{} = \"{}\"\n", key, note))
+                .collect();
+            format!("[notes]\n{}", lines.concat())
+        }
+        Format::Json => {
+            let lines: Vec<_> = entries
+                .map(|(key, note)| format!("  {}: {}", json::string(key), json::string(&note)))
+                .collect();
+            format!("{{\n{}\n}}\n", lines.join(",\n"))
+        }
+        Format::Chart => chart(notes, names, layout),
+    }
+}
+
+/// The rows of [`CHART_ROWS`], each key with its label and note, followed by the keys elsewhere
+/// that play notes.
+fn chart(notes: &[(ScanCode, u8)], names: NoteNames, layout: Layout) -> String {
+    let note_of = |key: ScanCode| notes.iter().find(|&&(k, _)| k == key).map(|&(_, n)| n);
+    let cell = CHART_KEY_WIDTH + 1;
+    let mut chart = String::new();
+    let mut border = String::new();
+
+    for (offset, row) in CHART_ROWS {
+        let indent = " ".repeat((offset * cell as f32).round() as usize);
+        let keys: Vec<_> = row
+            .iter()
+            .map(|name| keys::scancode(name).unwrap())
+            .collect();
+        border = format!(
+            "{}{}+\n",
+            indent,
+            format!("+{}", "-".repeat(CHART_KEY_WIDTH)).repeat(keys.len())
+        );
+
+        let (mut labels, mut played) = (indent.clone(), indent);
+        for (&key, name) in keys.iter().zip(row) {
+            let note = note_of(key).map_or(String::new(), |note| names.name(note));
+            labels += &format!(
+                "| {:<width$}",
+                label(layout, key, name),
+                width = CHART_KEY_WIDTH - 1
+            );
+            played += &format!("| {:<width$}", note, width = CHART_KEY_WIDTH - 1);
+        }
+        chart += &format!("{}{}|\n{}|\n", border, labels, played);
+    }
+    chart += &border;
+
+    let charted: Vec<_> = CHART_ROWS.iter().flat_map(|(_, row)| row.iter()).collect();
+    let others: Vec<_> = notes
+        .iter()
+        .filter_map(|&(key, note)| {
+            let name = keys::name(key)?;
+            (!charted.contains(&&name)).then(|| format!("{} {}", name, names.name(note)))
+        })
+        .collect();
+    if !others.is_empty() {
+        chart += &format!("\nAlso: {}\n", others.join(", "));
+    }
+
+    chart
+}
+
+/// What is printed on the key called `name`: the letter on `layout` for the letter rows, and
+/// otherwise what it types on a US keyboard.
+fn label(layout: Layout, key: ScanCode, name: &str) -> String {
+    if let Some(label) = layout.label(key) {
+        return label.to_string();
+    }
+    if let Some(digit) = name.strip_prefix("Digit") {
+        return digit.to_string();
+    }
+    match SYMBOLS.iter().find(|&&(_, n)| n == name) {
+        Some((symbol, _)) => symbol.to_string(),
+        None => name.to_string(),
+    }
+}
+
+/// The note the first key of a built-in keymap plays.
+const BUILTIN_FIRST_NOTE: u8 = 60;
+
+/// The built-in keymaps, each four rows of five keys from the bottom up. The notes go up a
+/// semitone a key, so each row starts a fourth above the one below, like the strings of a
+/// bass, and a hand resting on its side of the keyboard reaches twenty notes without moving.
+#[rustfmt::skip]
+const BUILTIN: [(&str, [[&str; 5]; 4]); 2] = [
+    ("left_hand", [
+        ["KeyZ", "KeyX", "KeyC", "KeyV", "KeyB"],
+        ["KeyA", "KeyS", "KeyD", "KeyF", "KeyG"],
+        ["KeyQ", "KeyW", "KeyE", "KeyR", "KeyT"],
+        ["Digit1", "Digit2", "Digit3", "Digit4", "Digit5"],
+    ]),
+    ("right_hand", [
+        ["KeyN", "KeyM", "Comma", "Period", "Slash"],
+        ["KeyH", "KeyJ", "KeyK", "KeyL", "Semicolon"],
+        ["KeyY", "KeyU", "KeyI", "KeyO", "KeyP"],
+        ["Digit6", "Digit7", "Digit8", "Digit9", "Digit0"],
+    ]),
+];
+
+/// The keys of the numeric keypad as `[numpad]` plays them, up a semitone a key from the
+/// lowest note, so the rows go up from the bottom like a phone's.
+pub const NUMPAD: [&str; 11] = [
+    "Numpad0",
+    "Numpad1",
+    "Numpad2",
+    "Numpad3",
+    "Numpad4",
+    "Numpad5",
+    "Numpad6",
+    "Numpad7",
+    "Numpad8",
+    "Numpad9",
+    "NumpadDecimal",
+];
+
+/// The notes of each key in the built-in keymap called `name`.
+pub fn builtin(name: &str) -> Option<Vec<(ScanCode, u8)>> {
+    let (_, rows) = BUILTIN.iter().find(|(n, _)| *n == name)?;
+
+    Some(
+        rows.iter()
+            .flatten()
+            .zip(BUILTIN_FIRST_NOTE..)
+            .map(|(key, note)| (keys::scancode(key).unwrap(), note))
+            .collect(),
+    )
+}
+
+/// The notes of each key in the VMPK keymap `source`, or what is wrong with it.
+pub fn import_vmpk(source: &str) -> Result<Vec<(ScanCode, u8)>, String> {
+    let raw = if source.contains("<rawkeyboardmap") {
+        true
+    } else if source.contains("<keyboardmap") {
+        false
+    } else {
+        return Err("not a VMPK keymap".to_string());
+    };
+
+    let mut notes = Vec::new();
+    for tag in source.split("<mapping").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let (key, note) = match (attribute(tag, "key"), attribute(tag, "note")) {
+            (Some(key), Some(note)) => (key, note),
+            _ => {
+                return Err(format!(
+                    "mapping without a key and a note: <mapping{}>",
+                    tag
+                ))
+            }
+        };
+
+        let scancode = if raw {
+            key.parse::<u32>()
+                .ok()
+                .and_then(|keycode| keycode.checked_sub(X11_KEYCODE_OFFSET))
+        } else {
+            scancode(&key)
+        };
+        let scancode = match scancode {
+            Some(scancode) => scancode,
+            None => {
+                report!("skipping key '{}', which has no name here", key);
+                continue;
+            }
+        };
+        let note = note
+            .parse::<i32>()
+            .ok()
+            .and_then(|note| u8::try_from(BASE_NOTE + note).ok())
+            .filter(|&note| note <= 127)
+            .ok_or_else(|| format!("invalid note '{}' for key '{}'", note, key))?;
+
+        notes.push((scancode, note));
+    }
+
+    Ok(notes)
+}
+
+/// The scancode of a key as VMPK names it: `Q`, `1`, `,`, `Space`, ...
+fn scancode(key: &str) -> Option<ScanCode> {
+    let name = match key.chars().collect::<Vec<_>>()[..] {
+        [c] if c.is_ascii_alphabetic() => format!("Key{}", c.to_ascii_uppercase()),
+        [c] if c.is_ascii_digit() => format!("Digit{}", c),
+        _ => match SYMBOLS.iter().find(|(symbol, _)| *symbol == key) {
+            Some((_, name)) => name.to_string(),
+            None => key.to_string(),
+        },
+    };
+
+    keys::scancode(&name)
+}
+
+/// The value of the attribute `name` in the inside of a tag, with entities replaced.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let (before, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, next) = after[1..].split_once(quote)?;
+
+        if before.trim() == name {
+            return Some(
+                value
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&"),
+            );
+        }
+        rest = next;
+    }
+}
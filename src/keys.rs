@@ -0,0 +1,312 @@
+//! Physical key names and the keys bound to actions.
+//!
+//! Keys are named like the `code` of a browser `KeyboardEvent` (`KeyQ`, `Digit1`, `Tab`, ...),
+//! which names each key after what it says on a US keyboard, whatever its label is on yours.
+//!
+//! Inside, keys are identified by their Linux evdev scancode. winit reports those on both X11
+//! (the X keycode less 8) and Wayland, and the keyboards read directly give them as well, but
+//! Windows and macOS have scancodes of their own, which [`normalize`] turns into evdev ones so
+//! the same config works everywhere.
+
+use std::collections::HashMap;
+
+use winit::event::ScanCode;
+
+/// Linux evdev scancodes, which every key is identified by, see [`normalize`].
+#[rustfmt::skip]
+const NAMES: &[(ScanCode, &str)] = &[
+    (1, "Escape"),
+    (2, "Digit1"), (3, "Digit2"), (4, "Digit3"), (5, "Digit4"), (6, "Digit5"),
+    (7, "Digit6"), (8, "Digit7"), (9, "Digit8"), (10, "Digit9"), (11, "Digit0"),
+    (12, "Minus"), (13, "Equal"), (14, "Backspace"), (15, "Tab"),
+    (16, "KeyQ"), (17, "KeyW"), (18, "KeyE"), (19, "KeyR"), (20, "KeyT"),
+    (21, "KeyY"), (22, "KeyU"), (23, "KeyI"), (24, "KeyO"), (25, "KeyP"),
+    (26, "BracketLeft"), (27, "BracketRight"), (28, "Enter"), (29, "ControlLeft"),
+    (30, "KeyA"), (31, "KeyS"), (32, "KeyD"), (33, "KeyF"), (34, "KeyG"),
+    (35, "KeyH"), (36, "KeyJ"), (37, "KeyK"), (38, "KeyL"),
+    (39, "Semicolon"), (40, "Quote"), (41, "Backquote"), (42, "ShiftLeft"), (43, "Backslash"),
+    (44, "KeyZ"), (45, "KeyX"), (46, "KeyC"), (47, "KeyV"), (48, "KeyB"),
+    (49, "KeyN"), (50, "KeyM"),
+    (51, "Comma"), (52, "Period"), (53, "Slash"), (54, "ShiftRight"),
+    (55, "NumpadMultiply"), (56, "AltLeft"), (57, "Space"), (58, "CapsLock"),
+    (59, "F1"), (60, "F2"), (61, "F3"), (62, "F4"), (63, "F5"),
+    (64, "F6"), (65, "F7"), (66, "F8"), (67, "F9"), (68, "F10"),
+    (69, "NumLock"), (70, "ScrollLock"),
+    (71, "Numpad7"), (72, "Numpad8"), (73, "Numpad9"), (74, "NumpadSubtract"),
+    (75, "Numpad4"), (76, "Numpad5"), (77, "Numpad6"), (78, "NumpadAdd"),
+    (79, "Numpad1"), (80, "Numpad2"), (81, "Numpad3"), (82, "Numpad0"), (83, "NumpadDecimal"),
+    (86, "IntlBackslash"), (87, "F11"), (88, "F12"),
+    (96, "NumpadEnter"), (97, "ControlRight"), (98, "NumpadDivide"), (100, "AltRight"),
+    (102, "Home"), (103, "ArrowUp"), (104, "PageUp"), (105, "ArrowLeft"),
+    (106, "ArrowRight"), (107, "End"), (108, "ArrowDown"), (109, "PageDown"),
+    (110, "Insert"), (111, "Delete"),
+];
+
+/// Windows scancodes (set 1) of the keys that differ from evdev, which are the extended ones
+/// that winit reports with 0xe000 added.
+#[cfg(target_os = "windows")]
+#[rustfmt::skip]
+const PLATFORM: &[(ScanCode, ScanCode)] = &[
+    (0xe01c, 96), (0xe01d, 97), (0xe035, 98), (0xe038, 100),
+    (0xe047, 102), (0xe048, 103), (0xe049, 104), (0xe04b, 105), (0xe04d, 106),
+    (0xe04f, 107), (0xe050, 108), (0xe051, 109), (0xe052, 110), (0xe053, 111),
+];
+
+/// macOS virtual key codes (`kVK_...`), which have nothing to do with evdev, for every key in
+/// [`NAMES`].
+#[cfg(target_os = "macos")]
+#[rustfmt::skip]
+const PLATFORM: &[(ScanCode, ScanCode)] = &[
+    (0x35, 1),
+    (0x12, 2), (0x13, 3), (0x14, 4), (0x15, 5), (0x17, 6),
+    (0x16, 7), (0x1a, 8), (0x1c, 9), (0x19, 10), (0x1d, 11),
+    (0x1b, 12), (0x18, 13), (0x33, 14), (0x30, 15),
+    (0x0c, 16), (0x0d, 17), (0x0e, 18), (0x0f, 19), (0x11, 20),
+    (0x10, 21), (0x20, 22), (0x22, 23), (0x1f, 24), (0x23, 25),
+    (0x21, 26), (0x1e, 27), (0x24, 28), (0x3b, 29),
+    (0x00, 30), (0x01, 31), (0x02, 32), (0x03, 33), (0x05, 34),
+    (0x04, 35), (0x26, 36), (0x28, 37), (0x25, 38),
+    (0x29, 39), (0x27, 40), (0x32, 41), (0x38, 42), (0x2a, 43),
+    (0x06, 44), (0x07, 45), (0x08, 46), (0x09, 47), (0x0b, 48),
+    (0x2d, 49), (0x2e, 50),
+    (0x2b, 51), (0x2f, 52), (0x2c, 53), (0x3c, 54),
+    (0x43, 55), (0x3a, 56), (0x31, 57), (0x39, 58),
+    (0x7a, 59), (0x78, 60), (0x63, 61), (0x76, 62), (0x60, 63),
+    (0x61, 64), (0x62, 65), (0x64, 66), (0x65, 67), (0x6d, 68),
+    // Clear, where the num lock key is on a PC keypad
+    (0x47, 69),
+    (0x59, 71), (0x5b, 72), (0x5c, 73), (0x4e, 74),
+    (0x56, 75), (0x57, 76), (0x58, 77), (0x45, 78),
+    (0x53, 79), (0x54, 80), (0x55, 81), (0x52, 82), (0x41, 83),
+    (0x0a, 86), (0x67, 87), (0x6f, 88),
+    (0x4c, 96), (0x3e, 97), (0x4b, 98), (0x3d, 100),
+    (0x73, 102), (0x7e, 103), (0x74, 104), (0x7b, 105),
+    (0x7c, 106), (0x77, 107), (0x7d, 108), (0x79, 109),
+    // Help, where insert is on a PC keyboard
+    (0x72, 110), (0x75, 111),
+];
+
+/// Turns a scancode from winit into the evdev scancode of the same physical key. Keys this
+/// doesn't know on macOS become 0, which no key has.
+pub fn normalize(scancode: ScanCode) -> ScanCode {
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    {
+        let known = PLATFORM.iter().find(|&&(platform, _)| platform == scancode);
+        if let Some(&(_, evdev)) = known {
+            return evdev;
+        }
+        if cfg!(target_os = "macos") {
+            return 0;
+        }
+    }
+
+    scancode
+}
+
+pub fn scancode(name: &str) -> Option<ScanCode> {
+    NAMES
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|&(scancode, _)| scancode)
+}
+
+/// The names of every key, as the config gives them.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    NAMES.iter().map(|&(_, name)| name)
+}
+
+pub fn name(scancode: ScanCode) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|&&(s, _)| s == scancode)
+        .map(|&(_, name)| name)
+}
+
+/// Things a key can be bound to in the `[keys]` table of the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Turns note repeat on and off.
+    Repeat,
+    /// Switches to the next note repeat rate.
+    RepeatRate,
+    /// Turns portamento (CC65) on and off, unless mono mode does with `auto`.
+    Portamento,
+    /// Makes the portamento time (CC5) shorter.
+    PortamentoDown,
+    /// Makes the portamento time (CC5) longer.
+    PortamentoUp,
+    /// Starts and stops generative mode.
+    Generate,
+    /// Generates fewer notes.
+    DensityDown,
+    /// Generates more notes.
+    DensityUp,
+    /// Moves the root of the generated notes down a semitone.
+    RootDown,
+    /// Moves the root of the generated notes up a semitone.
+    RootUp,
+    /// Records the next chord played, to be stored on the key pressed after it.
+    ChordLearn,
+    /// Holds the sustain pedal (CC64) down while held.
+    Sustain,
+    /// Swings the clock less.
+    SwingDown,
+    /// Swings the clock more.
+    SwingUp,
+    /// Sets the tempo from how fast it is tapped.
+    TapTempo,
+    /// Turns playing the `[background]` keys while another window is focused on and off.
+    Background,
+    /// Turns echo on and off.
+    Echo,
+    /// Makes the `[snapshots]` keys pressed while held store a snapshot instead of recalling it.
+    SnapshotStore,
+    /// Morphs towards the first `[morph]` preset while held.
+    MorphDown,
+    /// Morphs towards the second `[morph]` preset while held.
+    MorphUp,
+    /// Moves the `[split]` to the note of the next note key pressed.
+    SetSplit,
+    /// Switches between the window and the fullscreen view for playing on stage. Bound to F11
+    /// by default, which keeps F11 from selecting the eleventh preset.
+    Fullscreen,
+    /// Makes the notes struck while held louder by `accent`.
+    Accent,
+    /// Moves the notes struck while held down by `bass` octaves.
+    Bass,
+    /// Starts and stops practicing the `[practice]` sequences.
+    Practice,
+    /// Moves on to the next chord of the `[progression]`.
+    ProgressionNext,
+}
+
+impl Action {
+    const ALL: [Action; 26] = [
+        Action::Repeat,
+        Action::RepeatRate,
+        Action::Portamento,
+        Action::PortamentoDown,
+        Action::PortamentoUp,
+        Action::Generate,
+        Action::DensityDown,
+        Action::DensityUp,
+        Action::RootDown,
+        Action::RootUp,
+        Action::ChordLearn,
+        Action::Sustain,
+        Action::SwingDown,
+        Action::SwingUp,
+        Action::TapTempo,
+        Action::Background,
+        Action::Echo,
+        Action::SnapshotStore,
+        Action::MorphDown,
+        Action::MorphUp,
+        Action::SetSplit,
+        Action::Fullscreen,
+        Action::Accent,
+        Action::Bass,
+        Action::Practice,
+        Action::ProgressionNext,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Repeat => "repeat",
+            Action::RepeatRate => "repeat_rate",
+            Action::Portamento => "portamento",
+            Action::PortamentoDown => "portamento_down",
+            Action::PortamentoUp => "portamento_up",
+            Action::Generate => "generate",
+            Action::DensityDown => "density_down",
+            Action::DensityUp => "density_up",
+            Action::RootDown => "root_down",
+            Action::RootUp => "root_up",
+            Action::ChordLearn => "chord_learn",
+            Action::Sustain => "sustain",
+            Action::SwingDown => "swing_down",
+            Action::SwingUp => "swing_up",
+            Action::TapTempo => "tap_tempo",
+            Action::Background => "background",
+            Action::Echo => "echo",
+            Action::SnapshotStore => "snapshot_store",
+            Action::MorphDown => "morph_down",
+            Action::MorphUp => "morph_up",
+            Action::SetSplit => "set_split",
+            Action::Fullscreen => "fullscreen",
+            Action::Accent => "accent",
+            Action::Bass => "bass",
+            Action::Practice => "practice",
+            Action::ProgressionNext => "progression_next",
+        }
+    }
+
+    fn default_key(self) -> &'static str {
+        match self {
+            Action::Repeat => "Tab",
+            Action::RepeatRate => "Backquote",
+            Action::Portamento => "Backslash",
+            Action::PortamentoDown => "BracketLeft",
+            Action::PortamentoUp => "BracketRight",
+            Action::Generate => "NumpadEnter",
+            Action::DensityDown => "NumpadSubtract",
+            Action::DensityUp => "NumpadAdd",
+            Action::RootDown => "NumpadDivide",
+            Action::RootUp => "NumpadMultiply",
+            Action::ChordLearn => "Enter",
+            Action::Sustain => "Space",
+            Action::SwingDown => "Minus",
+            Action::SwingUp => "Equal",
+            Action::TapTempo => "Backspace",
+            Action::Background => "ScrollLock",
+            Action::Echo => "Insert",
+            Action::SnapshotStore => "Home",
+            Action::MorphDown => "PageDown",
+            Action::MorphUp => "PageUp",
+            Action::SetSplit => "End",
+            Action::Fullscreen => "F11",
+            Action::Accent => "ShiftRight",
+            Action::Bass => "ArrowDown",
+            Action::Practice => "Delete",
+            Action::ProgressionNext => "ArrowRight",
+        }
+    }
+}
+
+/// Which action, if any, each key is bound to.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    actions: HashMap<ScanCode, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            actions: Action::ALL
+                .into_iter()
+                .map(|action| (scancode(action.default_key()).unwrap(), action))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, scancode: ScanCode) -> Option<Action> {
+        self.actions.get(&scancode).copied()
+    }
+
+    /// Unbinds whatever action `scancode` is bound to.
+    pub fn free(&mut self, scancode: ScanCode) {
+        self.actions.remove(&scancode);
+    }
+
+    /// Binds `action` to `scancode` instead of the key it was bound to.
+    pub fn bind(&mut self, action: Action, scancode: ScanCode) {
+        self.actions.retain(|_, a| *a != action);
+        self.actions.insert(scancode, action);
+    }
+}
@@ -0,0 +1,101 @@
+//! Reporting the keyboard's own latency with `--report-latency`, so hosts that compensate for
+//! latency can line up what it plays: the capture latency of the output ports is how long after
+//! a key is pressed its event is played. The MIDI Time Code port follows the transport to the
+//! frame, and has none.
+//!
+//! The `jack` crate doesn't wrap latency callbacks, so this registers one with libjack itself.
+
+use std::os::raw::{c_int, c_uint, c_void};
+
+use jack::{Client, Frames, LatencyType, Port, Unowned};
+
+use crate::session_log::report;
+
+/// How long a keyboard can take to tell the computer about a key, as USB keyboards are polled
+/// at 125 Hz.
+const KEYBOARD_SCAN_MS: f64 = 8.0;
+
+/// `JackCaptureLatency` in `jack_latency_callback_mode_t`.
+const CAPTURE_LATENCY: c_uint = 0;
+
+type LatencyCallback = unsafe extern "C" fn(mode: c_uint, arg: *mut c_void);
+
+#[link(name = "jack")]
+extern "C" {
+    fn jack_set_latency_callback(
+        client: *mut c_void,
+        callback: Option<LatencyCallback>,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn jack_get_buffer_size(client: *mut c_void) -> Frames;
+}
+
+/// What the latency callback needs to know.
+struct State {
+    client: *mut c_void,
+    /// The MIDI output and the built-in synth's output, if it has one.
+    outputs: Vec<Port<Unowned>>,
+    /// The outputs that play in time with the transport rather than the keys, like `--mtc`.
+    synced: Vec<Port<Unowned>>,
+    /// Frames events are shifted by with `--latency-offset`, or `None` if they're played at
+    /// the start of the cycle.
+    latency_offset: Option<i64>,
+    keyboard_scan: Frames,
+}
+
+/// Reports the latency of `outputs` and `synced` whenever JACK asks for it. Has to be called
+/// before the client is activated.
+pub fn start(
+    client: &Client,
+    outputs: Vec<Port<Unowned>>,
+    synced: Vec<Port<Unowned>>,
+    latency_offset: Option<i64>,
+) {
+    let keyboard_scan = (KEYBOARD_SCAN_MS * client.sample_rate() as f64 / 1000.0) as Frames;
+    // Stays around for as long as the client, which is as long as the process
+    let state = Box::into_raw(Box::new(State {
+        client: client.raw() as *mut c_void,
+        outputs,
+        synced,
+        latency_offset,
+        keyboard_scan,
+    }));
+
+    // SAFETY: `state` is never freed, and only ever used by the callback.
+    let result = unsafe {
+        jack_set_latency_callback(
+            client.raw() as *mut c_void,
+            Some(latency),
+            state as *mut c_void,
+        )
+    };
+    if result != 0 {
+        report!("couldn't set the JACK latency callback");
+    }
+}
+
+/// Sets the capture latency of the output ports: up to a period for events waiting for the next
+/// cycle, or exactly one shifted by the offset with `--latency-offset` (see
+/// `jack_midi::event_time`), and the keyboard on top.
+unsafe extern "C" fn latency(mode: c_uint, arg: *mut c_void) {
+    if mode != CAPTURE_LATENCY {
+        return;
+    }
+    let state = &*(arg as *const State);
+    let period = jack_get_buffer_size(state.client) as i64;
+
+    let (min, max) = match state.latency_offset {
+        Some(offset) => {
+            let delay = (period + offset).max(0);
+            (delay, delay)
+        }
+        None => (0, period),
+    };
+    let range = (min as Frames, max as Frames + state.keyboard_scan);
+    for port in &state.outputs {
+        port.set_latency_range(LatencyType::Capture, range);
+    }
+    for port in &state.synced {
+        port.set_latency_range(LatencyType::Capture, (0, 0));
+    }
+}
@@ -0,0 +1,131 @@
+//! Keyboard layout detection.
+//!
+//! Notes are mapped by scancode, so the piano keys are the same physical keys whatever the
+//! layout. The layout only decides what those keys are labelled, which is what the window shows
+//! so users know which keys to press.
+
+use std::{env, fs, process::Command};
+
+use winit::event::ScanCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+    Colemak,
+}
+
+/// The three letter rows, by scancode: 16-25, 30-39 and 44-50.
+#[rustfmt::skip]
+const ROWS: [(Layout, [&str; 3]); 5] = [
+    (Layout::Qwerty, ["QWERTYUIOP", "ASDFGHJKL;", "ZXCVBNM"]),
+    (Layout::Azerty, ["AZERTYUIOP", "QSDFGHJKLM", "WXCVBN,"]),
+    (Layout::Qwertz, ["QWERTZUIOP", "ASDFGHJKLÖ", "YXCVBNM"]),
+    (Layout::Dvorak, ["',.PYFGCRL", "AOEUIDHTNS", ";QJKXBM"]),
+    (Layout::Colemak, ["QWFPGJLUY;", "ARSTDHNEIO", "ZXCVBKM"]),
+];
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "qwerty" => Some(Layout::Qwerty),
+            "azerty" => Some(Layout::Azerty),
+            "qwertz" => Some(Layout::Qwertz),
+            "dvorak" => Some(Layout::Dvorak),
+            "colemak" => Some(Layout::Colemak),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Qwerty => "qwerty",
+            Layout::Azerty => "azerty",
+            Layout::Qwertz => "qwertz",
+            Layout::Dvorak => "dvorak",
+            Layout::Colemak => "colemak",
+        }
+    }
+
+    /// Maps an XKB layout and variant (e.g. `fr`, or `us` and `dvorak`) to a layout.
+    fn from_xkb(layout: &str, variant: &str) -> Self {
+        // Only the first group counts when several are configured
+        let layout = layout.split(',').next().unwrap_or_default().trim();
+        let variant = variant.split(',').next().unwrap_or_default().trim();
+
+        if layout == "dvorak" || variant.contains("dvorak") {
+            Layout::Dvorak
+        } else if layout == "colemak" || variant.contains("colemak") {
+            Layout::Colemak
+        } else {
+            match layout {
+                "fr" | "be" => Layout::Azerty,
+                "de" | "at" | "ch" | "cz" | "sk" | "hu" | "si" | "hr" | "rs" => Layout::Qwertz,
+                _ => Layout::Qwerty,
+            }
+        }
+    }
+
+    /// Asks the system for the keyboard layout. Returns the XKB layout name along with it.
+    pub fn detect() -> Option<(Self, String)> {
+        let (layout, variant) = xkb_from_env()
+            .or_else(xkb_from_setxkbmap)
+            .or_else(xkb_from_default_keyboard)?;
+
+        Some((Self::from_xkb(&layout, &variant), layout))
+    }
+
+    /// The label on the key with `scancode`, for the letter rows.
+    pub fn label(self, scancode: ScanCode) -> Option<char> {
+        let (_, rows) = ROWS.iter().find(|(layout, _)| *layout == self)?;
+        let (row, first) = match scancode {
+            16..=25 => (rows[0], 16),
+            30..=39 => (rows[1], 30),
+            44..=50 => (rows[2], 44),
+            _ => return None,
+        };
+
+        row.chars().nth((scancode - first) as usize)
+    }
+}
+
+fn xkb_from_env() -> Option<(String, String)> {
+    let layout = env::var("XKB_DEFAULT_LAYOUT")
+        .ok()
+        .filter(|l| !l.is_empty())?;
+    let variant = env::var("XKB_DEFAULT_VARIANT").unwrap_or_default();
+
+    Some((layout, variant))
+}
+
+fn xkb_from_setxkbmap() -> Option<(String, String)> {
+    let output = Command::new("setxkbmap").arg("-query").output().ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    let field = |name: &str| {
+        output.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim().to_string())
+        })
+    };
+
+    Some((field("layout")?, field("variant").unwrap_or_default()))
+}
+
+fn xkb_from_default_keyboard() -> Option<(String, String)> {
+    let contents = fs::read_to_string("/etc/default/keyboard").ok()?;
+
+    let field = |name: &str| {
+        contents.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim_matches('"').to_string())
+        })
+    };
+
+    Some((
+        field("XKBLAYOUT").filter(|l| !l.is_empty())?,
+        field("XKBVARIANT").unwrap_or_default(),
+    ))
+}
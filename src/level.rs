@@ -0,0 +1,68 @@
+//! Following the level of an audio input, enabled with `--audio-in`, to make playing louder or
+//! softer by singing or playing into a microphone.
+
+const ATTACK: f32 = 0.01;
+const RELEASE: f32 = 0.2;
+/// The quietest level that still counts for something, in dBFS.
+const FLOOR: f32 = -60.0;
+
+/// What the level controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The velocity of each note played, scaled by the level.
+    Velocity,
+    /// Expression (CC11), sent whenever the level changes.
+    Expression,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "velocity" => Some(Target::Velocity),
+            "expression" => Some(Target::Expression),
+            _ => None,
+        }
+    }
+}
+
+/// An RMS envelope follower, quick to rise and slower to fall so it doesn't follow every
+/// period of the waveform.
+#[derive(Debug, Clone)]
+pub struct Follower {
+    attack: f32,
+    release: f32,
+    /// The smoothed mean square of the input.
+    mean_square: f32,
+}
+
+impl Follower {
+    pub fn new(sample_rate: usize) -> Self {
+        let coefficient = |seconds: f32| 1.0 - (-1.0 / (seconds * sample_rate as f32)).exp();
+
+        Follower {
+            attack: coefficient(ATTACK),
+            release: coefficient(RELEASE),
+            mean_square: 0.0,
+        }
+    }
+
+    /// Follows the level through `samples`, and returns it as a 14-bit MIDI value from 0 to
+    /// 16383.
+    pub fn process(&mut self, samples: &[f32]) -> u16 {
+        for &sample in samples {
+            let square = sample * sample;
+            let coefficient = if square > self.mean_square {
+                self.attack
+            } else {
+                self.release
+            };
+            self.mean_square += (square - self.mean_square) * coefficient;
+        }
+
+        // dBFS of the RMS level, from the mean square
+        let db = 10.0 * self.mean_square.max(f32::MIN_POSITIVE).log10();
+        ((db - FLOOR) / -FLOOR * 16383.0)
+            .round()
+            .clamp(0.0, 16383.0) as u16
+    }
+}
@@ -1,77 +1,677 @@
 use std::{
-    any::Any,
-    collections::HashSet,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    process,
     sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
 };
 
-use jack::{Client, ClientOptions, ClosureProcessHandler, ProcessScope, RawMidi};
+use autosave::Autosave;
+use block::Block;
+use chance::Chance;
+use chord::ChordLearn;
+use clock::TapTempo;
+use config::{Config, PanicCc};
+use devices::Devices;
+use engine::Control;
+use gesture::Gestures;
+use gui::{
+    canvas::{Canvas, Color, Rect},
+    curve_editor::CurveEditor,
+    heat_map::HeatMap,
+    slider::Slider,
+    stage, Presenter,
+};
+use harmonize::Harmonizer;
+use input::Inputs;
+use instance::Instance;
+use key_detect::KeyDetect;
+use key_up::KeyUps;
+use keys::Action;
+use layout::Layout;
+use midi::{
+    MidiMsg, NoteNames, CC_ALL_NOTES_OFF, CC_ALL_SOUND_OFF, CC_MOD_WHEEL, CC_PORTAMENTO,
+    CC_PORTAMENTO_TIME, CC_SUSTAIN, DEFAULT_CHANNEL, PITCH_BEND_CENTER, PITCH_BEND_MAX,
+};
+use monitor::Incoming;
+use mono::Mono;
+use morph::Morph;
+use note_channels::NoteChannels;
+use options::{KeySource, Options};
+use output::Outputs;
+use practice::Practice;
+use pressure::Pressure;
+use progression::Progression;
+use protocol::Command;
+use script::Script;
+use session_log::report;
+use shift::Shift;
+use snapshot::Controllers;
+use sounding::Sounding;
+use split::Split;
+use stack::Stack;
+use undo::{Change, Undo};
+use velocity::{VelocityCurve, FIXED_VELOCITY};
 use winit::{
-    event::{ElementState, Event, KeyboardInput, ScanCode, VirtualKeyCode, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    event::{
+        DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseScrollDelta,
+        ScanCode, StartCause, VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop, EventLoopClosed, EventLoopProxy},
+    window::{Fullscreen, Window, WindowBuilder},
 };
+use wizard::Wizard;
+
+mod autosave;
+mod background;
+mod block;
+mod chance;
+mod check;
+mod chord;
+mod clock;
+mod config;
+mod daemon;
+mod dbus;
+mod devices;
+mod echo;
+mod engine;
+mod euclid;
+mod feel;
+mod generate;
+mod gesture;
+mod gui;
+mod harmonize;
+mod input;
+mod instance;
+mod jack_midi;
+mod json;
+mod key_detect;
+mod key_up;
+mod keymap;
+mod keys;
+mod latency;
+mod layout;
+mod level;
+mod mdns;
+mod metadata;
+mod midi;
+mod monitor;
+mod mono;
+mod morph;
+mod mtc;
+mod note_channels;
+mod options;
+mod output;
+mod practice;
+mod pressure;
+mod progression;
+mod protocol;
+mod range;
+mod rawmidi;
+mod release;
+mod repeat;
+mod rhythm;
+mod rtpmidi;
+mod scale;
+mod scheduler;
+mod script;
+mod session_log;
+mod shift;
+mod snapshot;
+mod sounding;
+mod split;
+mod stack;
+mod stats;
+mod stress;
+mod synth;
+mod takeover;
+mod thru;
+mod timebase;
+mod toml;
+mod ump;
+mod undo;
+mod velocity;
+mod websocket;
+mod wizard;
 
 fn main() {
+    let options = Options::from_env();
+    if options.print_default_config {
+        print!("{}", config::commented_template());
+        return;
+    }
+    if let Some(path) = &options.log {
+        if let Err(err) = session_log::open(path) {
+            eprintln!("jack_keyboard: {}: {}", path.display(), err);
+            process::exit(1);
+        }
+        session_log::record(
+            "start",
+            format_args!("jack_keyboard {}", env!("CARGO_PKG_VERSION")),
+        );
+    }
+    let instance = if options.single_instance {
+        match instance::forward(&options.commands) {
+            Ok(true) => return,
+            Ok(false) => match Instance::listen() {
+                Ok(instance) => Some(instance),
+                Err(err) => {
+                    report!("instance: {}", err);
+                    None
+                }
+            },
+            Err(err) => {
+                report!("instance: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if options.check {
+        check::run(&options);
+    }
+    let config = Config::load(options.config.as_deref()).unwrap_or_else(|err| {
+        report!("{}", err);
+        process::exit(1);
+    });
+    if options.check_config {
+        match &config.path {
+            Some(path) => println!("{}: OK", path.display()),
+            None => println!("no config file, using the defaults"),
+        }
+        return;
+    }
+    if let Some(path) = &options.import_keymap {
+        import_keymap(path, &config);
+        return;
+    }
+    if let Some(format) = options.export_keymap {
+        export_keymap(format, &config);
+        return;
+    }
+    if options.learn_keymap {
+        learn_keymap(config);
+    }
+    let (event_loop, proxy) = if options.daemon {
+        let (proxy, events) = mpsc::channel();
+        (Loop::Daemon(events), Proxy::Daemon(proxy))
+    } else {
+        let event_loop = EventLoop::with_user_event();
+        let proxy = Proxy::EventLoop(event_loop.create_proxy());
+        (Loop::Window(Box::new(event_loop)), proxy)
+    };
     let (tx, rx) = mpsc::channel();
+    let (control_tx, control_rx) = mpsc::channel();
+
+    let websocket = options.websocket.as_ref().map(|addr| {
+        websocket::start(addr.as_str(), proxy.clone()).unwrap_or_else(|err| {
+            report!("websocket: {}: {}", addr, err);
+            process::exit(1);
+        })
+    });
 
-    let _async_client = handle_jack(rx);
-    run_gui(tx);
+    let mut outputs = Outputs::default();
+    let filters = &config.sinks;
+    if options.emit_json {
+        outputs.register(output::Json, filters);
+    }
+    if let Some(websocket) = websocket.clone() {
+        outputs.register(websocket, filters);
+    }
+    if let Some(dir) = &options.autosave {
+        let (autosave, path) = Autosave::create(dir, options.autosave_thin).unwrap_or_else(|err| {
+            report!("{}: {}", dir.display(), err);
+            process::exit(1);
+        });
+        report!("saving everything played to {}", path.display());
+        outputs.register(autosave::Sink::new(autosave, path), filters);
+    }
+    let controllers = snapshot::Shared::default();
+    // Soft takeover needs to know where the controllers are as well
+    if !config.snapshots.is_empty() || config.soft_takeover {
+        outputs.register(controllers.clone(), filters);
+    }
+    for name in outputs.unknown(filters) {
+        report!("no output sink '{}' to filter", name);
+    }
+    let written = (!outputs.is_empty()).then(|| outputs.start());
+    // Both only show in the window
+    let beats = (config.beat_flash && !options.daemon).then(|| forward_beats(proxy.clone()));
+    let show = (options.monitor || config.show_input) && !options.daemon;
+    let monitor = (show || config.panic_cc.is_some())
+        .then(|| forward_monitor(proxy.clone(), show, options.monitor, config.panic_cc));
+
+    let mut inputs = Inputs::default();
+    if options.stdin {
+        inputs.register(input::Stdin);
+    }
+    if options.dbus {
+        inputs.register(dbus::DBus);
+    }
+    if let Some(instance) = instance {
+        inputs.register(instance);
+    }
+    // Without the window there are no note keys for them to play
+    if !config.devices.is_empty() && !options.daemon {
+        inputs.register(Devices(config.devices.clone()));
+    }
+    if let (Some(background), false) = (&config.background, options.daemon) {
+        inputs.register(background.clone());
+    }
+    for err in inputs.start(config.inputs.as_deref(), &proxy) {
+        report!("{}", err);
+    }
+    // Given on the command line, for this instance as there's no other
+    for line in &options.commands {
+        if let Ok(Some(command)) = protocol::parse_line(line, DEFAULT_CHANNEL) {
+            let _ = proxy.send_event(UserEvent::Command(command));
+        }
+    }
+
+    if let Some(rate) = options.stress {
+        stress::start(rate, tx.clone());
+    }
+
+    let jack = options.rawmidi.is_none() && options.rtpmidi.is_none() && options.ump.is_none();
+    if !config.thru.is_empty() && !jack {
+        report!("thru needs the JACK input port, ignoring it");
+    }
+    if config.panic_cc.is_some() && !jack {
+        report!("panic_cc needs the JACK input port, ignoring it");
+    }
+    let _async_client = if let Some(path) = &options.rawmidi {
+        rawmidi::start(path, rx, control_rx, written, beats, &options, &config).unwrap_or_else(
+            |err| {
+                report!("{}: {}", path.display(), err);
+                process::exit(1);
+            },
+        );
+        None
+    } else if let Some(path) = &options.ump {
+        ump::start(path, rx, control_rx, written, beats, &options, &config).unwrap_or_else(|err| {
+            report!("{}: {}", path.display(), err);
+            process::exit(1);
+        });
+        None
+    } else if let Some(session) = &options.rtpmidi {
+        rtpmidi::start(session, rx, control_rx, written, beats, &options, &config).unwrap_or_else(
+            |err| {
+                report!("network session: {}", err);
+                process::exit(1);
+            },
+        );
+        None
+    } else {
+        Some(jack_midi::start(
+            rx, control_rx, written, beats, monitor, &options, &config,
+        ))
+    };
+    match event_loop {
+        Loop::Window(event_loop) => run_gui(
+            *event_loop,
+            tx,
+            control_tx,
+            config,
+            websocket,
+            controllers,
+            &options,
+        ),
+        Loop::Daemon(events) => daemon::run(events, &tx, &control_tx, &config, websocket.as_ref()),
+    }
+}
+
+/// Events sent to the event loop from other threads.
+#[derive(Debug)]
+enum UserEvent {
+    /// A command from stdin or a remote client.
+    Command(Command),
+    /// A key on one of the keyboards read directly, by index into [`Config::devices`].
+    DeviceKey {
+        device: usize,
+        scancode: ScanCode,
+        pressed: bool,
+    },
+    /// A beat of the clock was played, by its number counted from the start.
+    Beat(u64),
+    /// One of the `[background]` keys, read directly whether or not the window has the focus.
+    BackgroundKey { scancode: ScanCode, pressed: bool },
+    /// A message arrived on the input port, for the monitor or `show_input`.
+    Monitor(Incoming),
 }
 
-fn handle_jack(rx: Receiver<KeyboardMsg>) -> impl Any {
-    let (client, _client_status) =
-        Client::new("jack_keyboard", ClientOptions::NO_START_SERVER).unwrap();
+/// What runs once everything is started, handling the [`UserEvent`]s.
+enum Loop {
+    /// The window's event loop.
+    Window(Box<EventLoop<UserEvent>>),
+    /// The loop of `--daemon`, without a window.
+    Daemon(Receiver<UserEvent>),
+}
 
-    let mut out = client
-        .register_port("out", jack::MidiOut::default())
-        .unwrap();
+/// Where other threads send [`UserEvent`]s, whichever [`Loop`] handles them.
+#[derive(Debug, Clone)]
+enum Proxy {
+    EventLoop(EventLoopProxy<UserEvent>),
+    Daemon(Sender<UserEvent>),
+}
 
-    let process = move |_client: &Client, process_scope: &ProcessScope| -> jack::Control {
-        let mut writer = out.writer(process_scope);
+impl Proxy {
+    /// Fails once the loop has exited.
+    fn send_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+        match self {
+            Proxy::EventLoop(proxy) => proxy.send_event(event),
+            Proxy::Daemon(proxy) => proxy.send(event).map_err(|err| EventLoopClosed(err.0)),
+        }
+    }
+}
 
-        while let Ok(msg) = rx.try_recv() {
-            let KeyboardMsg { note, pressed } = msg;
+/// Passes the beats played by the engine on to the event loop, to flash them in the window.
+fn forward_beats(proxy: Proxy) -> Sender<u64> {
+    let (tx, rx) = mpsc::channel();
 
-            match writer.write(&RawMidi {
-                time: 0,
-                bytes: &[
-                    if pressed { 0x91 } else { 0x81 }, // Command
-                    note.to_midi_value(),              // Note
-                    0x70,                              // Velocity
-                ],
-            }) {
-                Ok(_) => (),
-                Err(err) => eprintln!("{:?}", err),
+    thread::spawn(move || {
+        for beat in rx {
+            if proxy.send_event(UserEvent::Beat(beat)).is_err() {
+                // The event loop has exited
+                break;
             }
         }
+    });
 
-        jack::Control::Continue
-    };
+    tx
+}
 
-    client
-        .activate_async((), ClosureProcessHandler::new(process))
-        .unwrap()
+/// Passes the messages arriving on the input port on to the event loop, to list them in the
+/// window if `show`, or only the notes unless `all`, and turns the `panic_cc` into a panic.
+fn forward_monitor(
+    proxy: Proxy,
+    show: bool,
+    all: bool,
+    panic_cc: Option<PanicCc>,
+) -> Sender<Incoming> {
+    let (tx, rx) = mpsc::channel::<Incoming>();
+
+    thread::spawn(move || {
+        for incoming in rx {
+            let pressed = incoming
+                .control_change()
+                .is_some_and(|cc| panic_cc.is_some_and(|panic_cc| panic_cc.matches(cc)));
+            let event = if pressed {
+                UserEvent::Command(Command::Panic)
+            } else if show
+                && (all || incoming.note().is_some() || incoming.all_notes_off().is_some())
+            {
+                UserEvent::Monitor(incoming)
+            } else {
+                continue;
+            };
+            if proxy.send_event(event).is_err() {
+                // The event loop has exited
+                break;
+            }
+        }
+    });
+
+    tx
 }
 
-fn run_gui(tx: Sender<KeyboardMsg>) {
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().with_title("JACK keyboard").build(&event_loop).unwrap();
+fn run_gui(
+    event_loop: EventLoop<UserEvent>,
+    tx: Sender<KeyboardMsg>,
+    controls: Sender<Control>,
+    mut config: Config,
+    websocket: Option<websocket::Broadcaster>,
+    controllers: snapshot::Shared,
+    options: &Options,
+) {
+    let window = WindowBuilder::new()
+        .with_title("JACK keyboard")
+        .build(&event_loop)
+        .unwrap();
 
     #[cfg(unix)]
     {
         use winit::platform::unix::EventLoopWindowTargetExtUnix;
 
         if event_loop.is_wayland() {
-            println!("Running on Wayland");
+            eprintln!("Running on Wayland");
         } else if event_loop.is_x11() {
-            println!("Running on X11");
+            eprintln!("Running on X11");
         }
     }
 
+    let layout = config.layout.unwrap_or_else(|| match Layout::detect() {
+        Some((layout, xkb_layout)) => {
+            eprintln!("Keyboard layout: {} ({})", layout.name(), xkb_layout);
+            layout
+        }
+        None => Layout::Qwerty,
+    });
+    let mut key_hint = key_hint(layout, &config);
+    // Without a config file yet, ask which keys play which notes first
+    let mut wizard = config
+        .path
+        .as_ref()
+        .is_some_and(|path| !path.exists())
+        .then(Wizard::new);
+
+    let mut presenter = Presenter::new(&window);
+    let size = window.inner_size();
+    let mut canvas = Canvas::new(size.width, size.height);
+    let mut cursor = (0, 0);
+    // Whether the pointer is in the window, for the tooltip of the fullscreen view
+    let mut cursor_in = false;
+
     let mut active_keys = HashSet::new();
+    // When a key was last pressed or released, for `idle_release`
+    let mut last_input = Instant::now();
+    let mut velocity_curve = VelocityCurve::default();
+    let mut curve_editor = CurveEditor::default();
+    let mut repeat_on = false;
+    let mut repeat_rate = config.repeat.rate;
+    let mut echo_on = config.echo.on;
+    let mut mono = config
+        .mono
+        .then(|| Mono::new(config.portamento.auto, config.slide));
+    let mut harmonizer = config.harmonize.map(Harmonizer::new);
+    let mut gestures = Gestures::new(config.gestures.clone());
+    let mut key_ups = KeyUps::new(config.key_ups.clone());
+    let mut practice: Option<Practice> = None;
+    // What the `block` command keeps from going out
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut sounding = Sounding::default();
+    let mut progression = config
+        .progression
+        .as_ref()
+        .map(|p| Progression::new(p.chords.clone(), p.lock));
+    if let (Some(progression), Some(harmonizer)) = (&progression, &mut harmonizer) {
+        let chord = progression.chord();
+        harmonizer.set_key(chord.root, chord.quality.scale());
+    }
+    let mut key_detect = config.key_detect.map(|k| KeyDetect::new(k.notes));
+    let mut note_channels = config
+        .note_channels
+        .as_ref()
+        .map(|c| NoteChannels::new(c.channels.clone(), c.round_robin, c.pan_spread));
+    let mut chance = config
+        .chance
+        .as_ref()
+        .map(|c| Chance::new(c.rule, c.keys.clone(), jack::get_time()));
+    let mut split = config
+        .split
+        .as_ref()
+        .map(|s| Split::new(s.note, s.lower, s.upper));
+    // Whether `set_split` was pressed and the next note key moves the split, and the note key
+    // that moved it until it is released
+    let mut setting_split = false;
+    let mut split_key = None;
+    let mut pressure = config
+        .pressure
+        .as_ref()
+        .map(|p| Pressure::new(p.keys.clone(), p.target, DEFAULT_CHANNEL));
+    let mut portamento_time = config.portamento.time.unwrap_or(0);
+    let mut portamento_on = config.portamento.on.unwrap_or(false);
+    // Mono mode turns it on and off by itself, which the key would only get in the way of
+    let auto_portamento = config.mono && config.portamento.auto;
+    let mut swing = config.swing;
+    let mut tempo = config.tempo;
+    let mut tap_tempo = TapTempo::default();
+    // How far down the sustain pedal is, from 0 to 127
+    let mut sustain = 0;
+    let mut generating = false;
+    let mut focused = true;
+    let device_keys = options.key_source == KeySource::Device;
+    // The last beat flashed and when the flash ends
+    let mut flash: Option<(u64, Instant)> = None;
+    // Keys held with `dwell` that haven't played yet, with their note and when it plays
+    let mut dwelling: Vec<(ScanCode, u8, Instant)> = Vec::new();
+    let mut morph = config.morph.as_ref().map(|m| {
+        let (from, to) = (&config.presets[m.from], &config.presets[m.to]);
+        let duration = Duration::from_secs_f64(m.seconds);
+        Morph::new(&from.controllers, &to.controllers, duration)
+    });
+    // Which way the morph keys held are morphing, and when it last moved
+    let mut morphing: Option<(bool, Instant)> = None;
+    let mut snapshots: Vec<Option<Controllers>> = vec![None; config.snapshots.len()];
+    // Whether `snapshot_store` is held
+    let mut storing = false;
+    // The velocity added while `accent` is held
+    let mut accent = 0;
+    // The octaves down notes play while `bass` is held, and the notes the keys struck with it
+    // play until they are let go of
+    let mut bass = 0;
+    let mut bass_notes: HashMap<ScanCode, u8> = HashMap::new();
+    let mut stack = config
+        .numpad
+        .filter(|numpad| numpad.stack_window > 0)
+        .map(|numpad| {
+            Stack::new(
+                Duration::from_millis(numpad.stack_window),
+                numpad.stack_step,
+            )
+        });
+    // The messages from the input port, as text, newest last
+    let mut monitored: VecDeque<String> = VecDeque::new();
+    // The channels and notes held on the input port, with `show_input`
+    let mut incoming_notes: Vec<(u8, u8)> = Vec::new();
+    let monitor = options.monitor;
+    let mut heat_map = (options.heat_map || options.heat_map_csv.is_some()).then(HeatMap::default);
+    let (show_heat_map, heat_map_csv) = (options.heat_map, options.heat_map_csv.clone());
+    // Only ever turned on with its key, never by the config
+    let mut background_on = false;
+    // Whether the keys play anything, turned off with the `capture` key of `[background]`
+    let mut capturing = true;
+    let capture_key = config
+        .background
+        .as_ref()
+        .and_then(|background| background.capture);
+    // Background keys played and not released yet, which are released even once they are off
+    let mut background_held = HashSet::new();
+    // Note keys held on the keyboards of `[[device]]`, which the window gets too as they are
+    // also keyboards of the X server, and those of them pressed in the window and not played
+    let mut device_held = HashSet::new();
+    let mut device_muted = HashSet::new();
+    let mut chords = config.chords.clone();
+    let mut chord_learn = ChordLearn::default();
+    let mut undo = Undo::default();
+    let mut shift = Shift::default();
+    let script = options
+        .script
+        .clone()
+        .map(|path| Script::start(path, Proxy::EventLoop(event_loop.create_proxy())));
+    let mut fullscreen = false;
+    let mut modifiers = ModifiersState::empty();
+    // The key pressed with Ctrl to undo, whose release is ignored
+    let mut undo_key = None;
+    let mut bend = Slider::new(
+        "Bend",
+        PITCH_BEND_MAX,
+        PITCH_BEND_CENTER,
+        Some(PITCH_BEND_CENTER),
+    );
+    let mut mod_wheel = Slider::new("Mod", 127, 0, None);
+    let mut generate = generate::Params {
+        root: config.generate.root,
+        density: config.generate.density,
+    };
+
+    if let Some(time) = config.portamento.time {
+        send(&tx, portamento_time_msg(time));
+    }
+    if let Some(on) = config.portamento.on {
+        send(&tx, portamento_msg(on));
+    }
+    if config.sustain.inverted {
+        // Otherwise the pedal would start out as held
+        send(&tx, sustain_msg(0, true));
+    }
+
+    // The first preset, if there are any, is active on startup
+    let mut preset = if config.presets.is_empty() {
+        None
+    } else {
+        Some(0)
+    };
+    select_preset(&tx, &window, &config, websocket.as_ref(), preset);
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+        // Wake up for the end of the beat flash, for keys held long enough to play, for the
+        // next step of morphing or sliding and for letting go of notes held too long without
+        // any input
+        let dwelled = dwelling.iter().map(|&(_, _, due)| due);
+        let morphed = morphing.map(|(_, moved)| moved + MORPH_STEP);
+        let slid = mono.as_ref().and_then(Mono::next_step);
+        let key_up = key_ups.next_due();
+        let idle = (config.idle_release > 0
+            && (active_keys
+                .iter()
+                .any(|key| plays_notes(&config, &chords, *key))
+                || !background_held.is_empty()))
+        .then(|| last_input + Duration::from_secs(config.idle_release));
+        *control_flow = match flash
+            .map(|(_, until)| until)
+            .into_iter()
+            .chain(dwelled)
+            .chain(morphed)
+            .chain(slid)
+            .chain(key_up)
+            .chain(idle)
+            .min()
+        {
+            Some(deadline) => ControlFlow::WaitUntil(deadline),
+            None => ControlFlow::Wait,
+        };
+
+        // With --key-source device the keys come from the raw events instead, pressed while
+        // the window has the focus and released if they were pressed then
+        let event = match event {
+            Event::DeviceEvent {
+                device_id,
+                event: DeviceEvent::Key(input),
+            } if device_keys => {
+                let held = active_keys.contains(&keys::normalize(input.scancode));
+                match input.state {
+                    ElementState::Pressed if !focused => return,
+                    ElementState::Released if !held => return,
+                    _ => {}
+                }
+                Event::WindowEvent {
+                    window_id: window.id(),
+                    event: WindowEvent::KeyboardInput {
+                        device_id,
+                        input,
+                        is_synthetic: false,
+                    },
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { .. },
+                ..
+            } if device_keys => return,
+            event => event,
+        };
 
         match event {
             Event::WindowEvent {
@@ -89,11 +689,44 @@ fn run_gui(tx: Sender<KeyboardMsg>) {
                 window_id,
                 ..
             } if window_id == window.id() => {
+                let scancode = keys::normalize(scancode);
+                if let Some(setup) = &mut wizard {
+                    if state == ElementState::Pressed {
+                        if virtual_keycode == Some(VirtualKeyCode::Escape) {
+                            wizard = None;
+                        } else if keys::name(scancode).is_some() {
+                            if let Some(notes) = setup.key(scancode) {
+                                let saved = config
+                                    .save_notes(&notes)
+                                    .and_then(|()| Config::load(config.path.as_deref()));
+                                match saved {
+                                    Ok(saved) => {
+                                        config = saved;
+                                        key_hint = crate::key_hint(layout, &config);
+                                    }
+                                    Err(err) => report!("{}", err),
+                                }
+                                wizard = None;
+                            }
+                        }
+                        window.request_redraw();
+                    }
+                    return;
+                }
+
                 if virtual_keycode == Some(VirtualKeyCode::Escape) {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
 
+                // Read through `[background]` instead, whichever window has the focus
+                if Some(scancode) == capture_key {
+                    return;
+                }
+                // With capture off only the keys pressed before go on, to be let go of
+                if !capturing && !active_keys.contains(&scancode) {
+                    return;
+                }
                 if state == ElementState::Pressed && active_keys.contains(&scancode) {
                     // Ignore repeated keys
                     return;
@@ -103,29 +736,1632 @@ fn run_gui(tx: Sender<KeyboardMsg>) {
                     ElementState::Pressed => active_keys.insert(scancode),
                     ElementState::Released => active_keys.remove(&scancode),
                 };
+                last_input = Instant::now();
+                if fullscreen {
+                    // The keys held light up
+                    window.request_redraw();
+                }
+
+                if state == ElementState::Pressed && undoes(modifiers.ctrl(), bass, scancode) {
+                    undo_key = Some(scancode);
+                    // Changes undone some other way since, like unlatching by pressing the key
+                    // again, are skipped
+                    while let Some(change) = undo.pop() {
+                        let undone = match change {
+                            Change::Latched(key) => match gestures.unlatch(key) {
+                                Some(messages) => {
+                                    for midi in messages {
+                                        play_note(
+                                            &tx,
+                                            &mut shift,
+                                            &mut split,
+                                            &mut mono,
+                                            &mut harmonizer,
+                                            &mut note_channels,
+                                            midi,
+                                        );
+                                    }
+                                    true
+                                }
+                                None => false,
+                            },
+                            Change::Chord { key, previous } => {
+                                let saved = match &previous {
+                                    Some(notes) => config.save_chord(key, notes),
+                                    None => config.remove_chord(key),
+                                };
+                                if let Err(err) = saved {
+                                    report!("{}", err);
+                                }
+                                match previous {
+                                    Some(notes) => chords.insert(key, notes),
+                                    None => chords.remove(&key),
+                                };
+                                true
+                            }
+                        };
+                        if undone {
+                            break;
+                        }
+                    }
+                    window.request_redraw();
+                    return;
+                }
+                if undo_key == Some(scancode) {
+                    if state == ElementState::Released {
+                        undo_key = None;
+                    }
+                    return;
+                }
+
+                if let (ElementState::Pressed, Some(heat_map)) = (state, &mut heat_map) {
+                    // Chords and actions come before the notes of keys
+                    let note = key_note(&config, scancode).filter(|_| {
+                        !device_held.contains(&scancode)
+                            && !chords.contains_key(&scancode)
+                            && !config
+                                .gestures
+                                .iter()
+                                .any(|gesture| gesture.key == scancode)
+                            && !config.key_ups.iter().any(|key_up| key_up.key == scancode)
+                            && config.bindings.action(scancode).is_none()
+                    });
+                    heat_map.press(scancode, note);
+                    if show_heat_map {
+                        window.request_redraw();
+                    }
+                }
+
+                let learn_key = config.bindings.action(scancode) == Some(Action::ChordLearn);
+                if state == ElementState::Pressed && !learn_key {
+                    if let Some(notes) = chord_learn.take_destination() {
+                        match config.save_chord(scancode, &notes) {
+                            Ok(()) => {
+                                let previous = chords.insert(scancode, notes);
+                                undo.push(Change::Chord {
+                                    key: scancode,
+                                    previous,
+                                });
+                            }
+                            Err(err) => report!("{}", err),
+                        }
+                        window.request_redraw();
+                        return;
+                    }
+                }
+
+                let pressed = state == ElementState::Pressed;
+                if let Some(notes) = chords.get(&scancode) {
+                    if let Some(chance) = &mut chance {
+                        if !chance.handle(scancode, pressed) {
+                            return;
+                        }
+                    }
+
+                    let velocity = accented(velocity_curve.apply(FIXED_VELOCITY), accent);
+                    let channel = DEFAULT_CHANNEL;
+                    if pressed {
+                        let sent: Vec<_> = notes
+                            .iter()
+                            .map(|&note| MidiMsg::NoteOn {
+                                channel,
+                                note,
+                                velocity,
+                            })
+                            .collect();
+                        for &midi in &sent {
+                            send(&tx, midi);
+                        }
+                        sounding.pressed(scancode, &sent);
+                    } else {
+                        // The notes it played, even if the chord was learned again since
+                        for midi in sounding.released(scancode, &[]) {
+                            send(&tx, midi);
+                        }
+                    }
+                    return;
+                }
+
+                if let Some(numpad) = &config.numpad {
+                    // A second instrument, so nothing the note keys go through applies
+                    if let Some(note) = numpad.note(scancode) {
+                        if pressed {
+                            let velocity = match &mut stack {
+                                Some(stack) => stack.hit(scancode, numpad.velocity, Instant::now()),
+                                None => numpad.velocity,
+                            };
+                            let sent = [MidiMsg::NoteOn {
+                                channel: numpad.channel,
+                                note,
+                                velocity,
+                            }];
+                            send(&tx, sent[0]);
+                            sounding.pressed(scancode, &sent);
+                        } else {
+                            for midi in sounding.released(scancode, &[]) {
+                                send(&tx, midi);
+                            }
+                        }
+                        return;
+                    }
+                }
+
+                let velocity = accented(velocity_curve.apply(FIXED_VELOCITY), accent);
+                let latched = gestures.is_latched(scancode);
+                if let Some(messages) = gestures.handle(scancode, pressed, velocity, Instant::now())
+                {
+                    if !latched && gestures.is_latched(scancode) {
+                        undo.push(Change::Latched(scancode));
+                    }
+                    for midi in messages {
+                        play_note(
+                            &tx,
+                            &mut shift,
+                            &mut split,
+                            &mut mono,
+                            &mut harmonizer,
+                            &mut note_channels,
+                            midi,
+                        );
+                    }
+                    window.request_redraw();
+                    return;
+                }
+
+                if let Some(messages) = key_ups.handle(scancode, pressed, velocity, Instant::now())
+                {
+                    for midi in messages {
+                        play_note(
+                            &tx,
+                            &mut shift,
+                            &mut split,
+                            &mut mono,
+                            &mut harmonizer,
+                            &mut note_channels,
+                            midi,
+                        );
+                    }
+                    return;
+                }
+
+                if let Some(action) = config.bindings.action(scancode) {
+                    if action == Action::Sustain {
+                        sustain = match state {
+                            ElementState::Pressed => 127,
+                            ElementState::Released => 0,
+                        };
+                        send(&tx, sustain_msg(sustain, config.sustain.inverted));
+                        window.request_redraw();
+                    } else if action == Action::SnapshotStore {
+                        storing = state == ElementState::Pressed;
+                    } else if action == Action::Accent {
+                        accent = match state {
+                            ElementState::Pressed => config.accent,
+                            ElementState::Released => 0,
+                        };
+                    } else if action == Action::Bass {
+                        bass = match state {
+                            ElementState::Pressed => config.bass,
+                            ElementState::Released => 0,
+                        };
+                    } else if matches!(action, Action::MorphDown | Action::MorphUp) {
+                        let up = action == Action::MorphUp;
+                        morphing = match state {
+                            ElementState::Pressed if morph.is_some() => Some((up, Instant::now())),
+                            // Letting go of the other key leaves this one morphing
+                            ElementState::Released if morphing.is_some_and(|(m, _)| m != up) => {
+                                morphing
+                            }
+                            _ => None,
+                        };
+                    } else if state == ElementState::Pressed {
+                        match action {
+                            Action::Repeat | Action::RepeatRate => {
+                                if action == Action::Repeat {
+                                    repeat_on = !repeat_on;
+                                } else {
+                                    repeat_rate = repeat_rate.next();
+                                }
+                                let rate = repeat_on.then_some(repeat_rate);
+                                controls.send(Control::Repeat(rate)).unwrap();
+                            }
+                            Action::Portamento if auto_portamento => (),
+                            Action::Portamento => {
+                                portamento_on = !portamento_on;
+                                send(&tx, portamento_msg(portamento_on));
+                            }
+                            Action::PortamentoDown | Action::PortamentoUp => {
+                                let from = portamento_time;
+                                portamento_time = if action == Action::PortamentoUp {
+                                    portamento_time.saturating_add(PORTAMENTO_STEP).min(127)
+                                } else {
+                                    portamento_time.saturating_sub(PORTAMENTO_STEP)
+                                };
+                                let midi = portamento_time_msg(portamento_time);
+                                send_control(&tx, &config, &controllers, from, midi);
+                            }
+                            Action::Generate
+                            | Action::DensityDown
+                            | Action::DensityUp
+                            | Action::RootDown
+                            | Action::RootUp => {
+                                match action {
+                                    Action::Generate => generating = !generating,
+                                    Action::DensityDown => {
+                                        generate.density = generate.density.saturating_sub(1)
+                                    }
+                                    Action::DensityUp => {
+                                        generate.density =
+                                            (generate.density + 1).min(generate::STEPS)
+                                    }
+                                    Action::RootDown => {
+                                        generate.root = generate.root.saturating_sub(1)
+                                    }
+                                    _ => generate.root = (generate.root + 1).min(127),
+                                }
+                                let params = generating.then_some(generate);
+                                controls.send(Control::Generate(params)).unwrap();
+                            }
+                            Action::ChordLearn => chord_learn.toggle(),
+                            Action::SwingDown | Action::SwingUp => {
+                                swing = if action == Action::SwingUp {
+                                    swing + SWING_STEP
+                                } else {
+                                    swing - SWING_STEP
+                                }
+                                .clamp(clock::STRAIGHT, clock::MAX_SWING);
+                                controls.send(Control::Swing(swing)).unwrap();
+                            }
+                            Action::TapTempo => {
+                                if let Some(tapped) = tap_tempo.tap(Instant::now()) {
+                                    tempo = tapped;
+                                    controls.send(Control::Tempo(tempo)).unwrap();
+                                }
+                            }
+                            Action::Background => background_on = !background_on,
+                            Action::Practice if !config.practice.is_empty() => {
+                                practice = match practice {
+                                    Some(_) => None,
+                                    None => {
+                                        Some(Practice::new(config.practice.clone(), Instant::now()))
+                                    }
+                                };
+                                window.request_redraw();
+                            }
+                            Action::Practice => (),
+                            Action::ProgressionNext => {
+                                if let Some(progression) = &mut progression {
+                                    let chord = progression.next();
+                                    if let Some(harmonizer) = &mut harmonizer {
+                                        harmonizer.set_key(chord.root, chord.quality.scale());
+                                    }
+                                    // In the octave it was in
+                                    generate.root =
+                                        (generate.root - generate.root % 12 + chord.root).min(127);
+                                    let params = generating.then_some(generate);
+                                    controls.send(Control::Generate(params)).unwrap();
+                                    window.request_redraw();
+                                }
+                            }
+                            Action::Fullscreen => {
+                                fullscreen = !fullscreen;
+                                window.set_fullscreen(
+                                    fullscreen.then_some(Fullscreen::Borderless(None)),
+                                );
+                                window.set_decorations(!fullscreen);
+                            }
+                            Action::SetSplit => setting_split = split.is_some() && !setting_split,
+                            Action::Echo => {
+                                echo_on = !echo_on;
+                                controls.send(Control::Echo(echo_on)).unwrap();
+                            }
+                            Action::Sustain
+                            | Action::SnapshotStore
+                            | Action::Accent
+                            | Action::Bass
+                            | Action::MorphDown
+                            | Action::MorphUp => unreachable!(),
+                        }
+                        window.request_redraw();
+                    }
+                    return;
+                }
+
+                if let Some(index) = config.snapshots.iter().position(|&key| key == scancode) {
+                    if state == ElementState::Pressed && storing {
+                        snapshots[index] = Some(controllers.lock().unwrap().clone());
+                    } else if let (ElementState::Pressed, Some(snapshot)) =
+                        (state, &snapshots[index])
+                    {
+                        for midi in snapshot.messages() {
+                            send(&tx, midi);
+                        }
+                    }
+                    return;
+                }
+
+                if let Some(index) = config.euclid.iter().position(|&(key, _)| key == scancode) {
+                    if state == ElementState::Pressed {
+                        controls.send(Control::Euclid(index)).unwrap();
+                    }
+                    return;
+                }
+
+                if let Some(index) = config.macros.iter().position(|(key, _)| *key == scancode) {
+                    if state == ElementState::Pressed {
+                        controls.send(Control::Macro(index)).unwrap();
+                    }
+                    return;
+                }
+
+                if let Some(pressure) = pressure.as_mut().filter(|p| p.contains(scancode)) {
+                    if let Some(midi) = pressure.handle(scancode, state == ElementState::Pressed) {
+                        send(&tx, midi);
+                    }
+                    return;
+                }
+
+                if state == ElementState::Pressed {
+                    if let Some(index) = virtual_keycode.and_then(preset_index) {
+                        if index < config.presets.len() {
+                            preset = Some(index);
+                            select_preset(&tx, &window, &config, websocket.as_ref(), preset);
+                        }
+                        return;
+                    }
+                }
+
+                // Pressed on a keyboard read directly, which plays its own note for it. Those
+                // are read there before the X server has read them and passed them on here.
+                let muted = match state {
+                    ElementState::Pressed => {
+                        device_held.contains(&scancode) && device_muted.insert(scancode)
+                    }
+                    ElementState::Released => device_muted.remove(&scancode),
+                };
+                let note = key_note(&config, scancode).filter(|_| !muted);
+                if let Some(note) = note {
+                    if let (true, ElementState::Pressed, Some(split)) =
+                        (setting_split, state, &mut split)
+                    {
+                        split.note = note;
+                        setting_split = false;
+                        split_key = Some(scancode);
+                        window.request_redraw();
+                        return;
+                    }
+                    if split_key == Some(scancode) {
+                        // The key didn't play anything, so its release doesn't either
+                        if state == ElementState::Released {
+                            split_key = None;
+                        }
+                        return;
+                    }
+                    if let Some(script) = &script {
+                        script.key(scancode, pressed, note);
+                        return;
+                    }
+
+                    if let Some(chance) = &mut chance {
+                        if !chance.handle(scancode, pressed) {
+                            return;
+                        }
+                    }
+
+                    let note = match state {
+                        ElementState::Pressed if bass > 0 => {
+                            // As many octaves down as there are below the note
+                            let low = note - 12 * bass.min(note / 12);
+                            bass_notes.insert(scancode, low);
+                            low
+                        }
+                        ElementState::Pressed => note,
+                        ElementState::Released => bass_notes.remove(&scancode).unwrap_or(note),
+                    };
+
+                    if config.dwell > 0 {
+                        let dwelled = dwelling.iter().position(|&(key, ..)| key == scancode);
+                        match (state, dwelled) {
+                            (ElementState::Pressed, _) => {
+                                let due = Instant::now() + Duration::from_millis(config.dwell);
+                                dwelling.push((scancode, note, due));
+                                return;
+                            }
+                            (ElementState::Released, Some(index)) => {
+                                // Released before it played
+                                dwelling.remove(index);
+                                return;
+                            }
+                            (ElementState::Released, None) => (),
+                        }
+                    }
+
+                    let velocity = accented(velocity_curve.apply(FIXED_VELOCITY), accent);
+                    let channel = DEFAULT_CHANNEL;
+
+                    chord_learn.note(note, state == ElementState::Pressed);
+                    if state == ElementState::Pressed {
+                        detect_key(&config, &mut key_detect, &mut harmonizer, &window, note);
+                        if let Some(practice) = &mut practice {
+                            practice.note(note, Instant::now());
+                        }
+                    }
+                    if chord_learn.status().is_some() {
+                        window.request_redraw();
+                    }
+
+                    let midi = match state {
+                        ElementState::Pressed => {
+                            curve_editor.set_last(FIXED_VELOCITY, velocity);
+                            window.request_redraw();
+                            MidiMsg::NoteOn {
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        }
+                        ElementState::Released => MidiMsg::NoteOff {
+                            channel,
+                            note,
+                            velocity,
+                        },
+                    };
+
+                    let midi = locked(&mut progression, midi);
+                    let sent = play_note(
+                        &tx,
+                        &mut shift,
+                        &mut split,
+                        &mut mono,
+                        &mut harmonizer,
+                        &mut note_channels,
+                        midi,
+                    );
+                    match state {
+                        ElementState::Pressed => sounding.pressed(scancode, &sent),
+                        ElementState::Released => {
+                            for midi in sounding.released(scancode, &sent) {
+                                send(&tx, midi);
+                            }
+                        }
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(has_focus),
+                window_id,
+                ..
+            } if window_id == window.id() => focused = has_focus,
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(state),
+                window_id,
+                ..
+            } if window_id == window.id() => modifiers = state,
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                window_id,
+                ..
+            } if window_id == window.id() && fullscreen => {
+                let (bounds, range) = stage_layout(&canvas, &config, &incoming_notes);
+                let before = stage::key_at(bounds, range, cursor);
+                cursor = (position.x as i32, position.y as i32);
+                cursor_in = true;
+                // The tooltip follows the pointer while it is on a key
+                if before.is_some() || stage::key_at(bounds, range, cursor).is_some() {
+                    window.request_redraw();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorLeft { .. },
+                window_id,
+                ..
+            } if window_id == window.id() => {
+                cursor_in = false;
+                if fullscreen {
+                    window.request_redraw();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                window_id,
+                ..
+            } if window_id == window.id() => {
+                cursor = (position.x as i32, position.y as i32);
+                cursor_in = true;
+
+                let areas = Areas::new(&canvas, monitor, show_heat_map);
+                let from = mod_wheel.value() as u8;
+                if curve_editor.mouse_moved(areas.curve, &mut velocity_curve, cursor.0, cursor.1) {
+                    window.request_redraw();
+                }
+                if bend.mouse_moved(areas.bend, cursor.1) {
+                    send(&tx, pitch_bend_msg(bend.value()));
+                    window.request_redraw();
+                }
+                if mod_wheel.mouse_moved(areas.mod_wheel, cursor.1) {
+                    let midi = mod_wheel_msg(mod_wheel.value());
+                    send_control(&tx, &config, &controllers, from, midi);
+                    window.request_redraw();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { state, button, .. },
+                window_id,
+                ..
+            } if window_id == window.id() => match state {
+                // Nothing to click on in the fullscreen view
+                ElementState::Pressed if fullscreen => (),
+                ElementState::Pressed => {
+                    let areas = Areas::new(&canvas, monitor, show_heat_map);
+                    let (x, y) = cursor;
+                    let from = mod_wheel.value() as u8;
+
+                    if curve_editor.mouse_pressed(areas.curve, &mut velocity_curve, button, x, y) {
+                        window.request_redraw();
+                    }
+                    if bend.mouse_pressed(areas.bend, button, x, y) {
+                        send(&tx, pitch_bend_msg(bend.value()));
+                        window.request_redraw();
+                    }
+                    if mod_wheel.mouse_pressed(areas.mod_wheel, button, x, y) {
+                        let midi = mod_wheel_msg(mod_wheel.value());
+                        send_control(&tx, &config, &controllers, from, midi);
+                        window.request_redraw();
+                    }
+                }
+                ElementState::Released => {
+                    curve_editor.mouse_released();
+                    mod_wheel.mouse_released();
+                    if bend.mouse_released() {
+                        send(&tx, pitch_bend_msg(bend.value()));
+                        window.request_redraw();
+                    }
+                }
+            },
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                window_id,
+                ..
+            } if window_id == window.id() && config.sustain.wheel => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines,
+                    MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_LINE) as f32,
+                };
+                let value = sustain as f32 + steps * config.sustain.wheel_step as f32;
+                let value = value.round().clamp(0.0, 127.0) as u8;
+
+                if value != sustain {
+                    sustain = value;
+                    send(&tx, sustain_msg(sustain, config.sustain.inverted));
+                    window.request_redraw();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                window_id,
+                ..
+            } if window_id == window.id() => {
+                canvas.resize(size.width, size.height);
+                window.request_redraw();
+            }
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
+                let active = preset.and_then(|index| config.presets.get(index));
+                let background = active.and_then(|preset| preset.color);
+                canvas.clear(background.unwrap_or(gui::BACKGROUND));
+                let status = format!(
+                    "{}{}{}{}{}{}{}{}{}{}{}{}{}{}{:.0} BPM   Gen {}   Glide {} {}   Repeat {}",
+                    chord_learn
+                        .status()
+                        .map_or(String::new(), |status| format!("{}   ", status)),
+                    match blocks.is_empty() {
+                        true => String::new(),
+                        false => {
+                            let names: Vec<_> = blocks.iter().map(Block::name).collect();
+                            format!("Blocked {}   ", names.join(", "))
+                        }
+                    },
+                    match &practice {
+                        Some(practice) => format!(
+                            "Practice {}   ",
+                            practice.status(|note| config.note_names.name(note))
+                        ),
+                        None => String::new(),
+                    },
+                    match &split {
+                        Some(_) if setting_split => "Split at the next note   ".to_string(),
+                        Some(split) => {
+                            format!("Split {}   ", config.note_names.name(split.note))
+                        }
+                        None => String::new(),
+                    },
+                    match &progression {
+                        Some(progression) => {
+                            format!("Chord {}   ", progression.chord().name(config.note_names))
+                        }
+                        None => String::new(),
+                    },
+                    match key_detect.as_ref().and_then(KeyDetect::key) {
+                        Some((root, scale)) => format!(
+                            "Key {} {}   ",
+                            config.note_names.pitch_class(root),
+                            scale.name()
+                        ),
+                        None => String::new(),
+                    },
+                    match (shift.octave, shift.channel) {
+                        (0, DEFAULT_CHANNEL) => String::new(),
+                        (octave, channel) => format!("Octave {:+} Ch {}   ", octave, channel + 1),
+                    },
+                    if gestures.latched() { "Latched   " } else { "" },
+                    if background_on { "Background   " } else { "" },
+                    if capturing { "" } else { "Capture off   " },
+                    if echo_on { "Echo   " } else { "" },
+                    match &morph {
+                        Some(morph) if morph.position() > 0.0 => {
+                            format!("Morph {:.0}%   ", morph.position() * 100.0)
+                        }
+                        _ => String::new(),
+                    },
+                    match sustain {
+                        0 => String::new(),
+                        sustain => format!("Sustain {}   ", sustain),
+                    },
+                    if swing > clock::STRAIGHT {
+                        format!("Swing {}%   ", swing)
+                    } else {
+                        String::new()
+                    },
+                    tempo,
+                    if generating {
+                        format!(
+                            "{} {}/{}",
+                            config.note_names.name(generate.root),
+                            generate.density,
+                            generate::STEPS
+                        )
+                    } else {
+                        "off".to_string()
+                    },
+                    match (auto_portamento, portamento_on) {
+                        (true, _) => "auto",
+                        (false, true) => "on",
+                        (false, false) => "off",
+                    },
+                    portamento_time,
+                    if repeat_on { repeat_rate.name() } else { "off" }
+                );
+                if fullscreen {
+                    let held = held_notes(&config, &chords, &active_keys);
+                    let incoming: Vec<_> = incoming_notes.iter().map(|&(_, note)| note).collect();
+                    let (bounds, range) = stage_layout(&canvas, &config, &incoming_notes);
+                    let colors = key_colors(&config, split.as_ref());
+                    let keys = stage::Keys {
+                        held: &held,
+                        incoming: &incoming,
+                        prompt: practice.as_ref().map(Practice::prompt),
+                        colors: &colors,
+                    };
+                    stage::draw(&mut canvas, bounds, range, keys, config.note_names, &status);
+                    let hovered = cursor_in
+                        .then(|| stage::key_at(bounds, range, cursor))
+                        .flatten();
+                    if let Some(note) = hovered {
+                        let text = key_tooltip(note, shift.octave, config.note_names);
+                        stage::draw_tooltip(&mut canvas, bounds, cursor, &text);
+                    }
+                    presenter.present(&canvas);
+                    return;
+                }
+
+                let areas = Areas::new(&canvas, monitor, show_heat_map);
+                curve_editor.draw(&mut canvas, areas.curve, &velocity_curve);
+                bend.draw(&mut canvas, areas.bend);
+                mod_wheel.draw(&mut canvas, areas.mod_wheel);
+                if let Some(area) = areas.monitor {
+                    draw_monitor(&mut canvas, area, &monitored);
+                }
+                if let (Some(area), Some(heat_map)) = (areas.heat_map, &heat_map) {
+                    heat_map.draw(&mut canvas, area);
+                }
+                let footer = areas.footer;
+                let mut hint_x = footer.x;
+                if config.beat_flash {
+                    let color = match flash {
+                        Some((beat, _)) if beat % BEATS_PER_BAR == 0 => gui::HIGHLIGHT,
+                        Some(_) => gui::ACCENT,
+                        None => gui::PANEL,
+                    };
+                    let size = footer.height;
+                    canvas.fill_rect(Rect::new(footer.x, footer.y, size, size), color);
+                    hint_x += size as i32 + 8;
+                }
+                let hint = match &wizard {
+                    Some(setup) => setup.status(config.note_names),
+                    None => key_hint.clone(),
+                };
+                canvas.draw_text(hint_x, footer.y, &hint, HINT_SCALE, gui::TEXT_DIM);
+
+                let (width, _) = Canvas::text_size(&status, HINT_SCALE);
+                canvas.draw_text(
+                    footer.right() - width as i32,
+                    footer.y,
+                    &status,
+                    HINT_SCALE,
+                    gui::TEXT_DIM,
+                );
+                presenter.present(&canvas);
+            }
+            Event::UserEvent(UserEvent::DeviceKey {
+                device,
+                scancode,
+                pressed,
+            }) => {
+                let device = &config.devices[device];
+                if pressed && !capturing {
+                    return;
+                }
+                let note = if device.notes.is_empty() {
+                    key_note(&config, scancode)
+                } else {
+                    device.notes.get(&scancode).copied()
+                };
+                if let Some(note) = note {
+                    if pressed {
+                        device_held.insert(scancode);
+                    } else {
+                        device_held.remove(&scancode);
+                    }
+                    let note = note as i32 + device.transpose as i32;
+                    let (channel, note) = (device.channel, note.clamp(0, 127) as u8);
+                    let velocity = velocity_curve.apply(FIXED_VELOCITY);
+
+                    send(
+                        &tx,
+                        if pressed {
+                            MidiMsg::NoteOn {
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        } else {
+                            MidiMsg::NoteOff {
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        },
+                    );
+                }
+            }
+            Event::UserEvent(UserEvent::Monitor(incoming)) => {
+                if config.show_input {
+                    let before = incoming_notes.clone();
+                    if let Some((channel, note, on)) = incoming.note() {
+                        incoming_notes.retain(|&held| held != (channel, note));
+                        if on {
+                            incoming_notes.push((channel, note));
+                        }
+                    }
+                    if let Some(channel) = incoming.all_notes_off() {
+                        incoming_notes.retain(|&(held, _)| held != channel);
+                    }
+                    if fullscreen && incoming_notes != before {
+                        window.request_redraw();
+                    }
+                }
+
+                if monitor {
+                    if monitored.len() == monitor::LINES {
+                        monitored.pop_front();
+                    }
+                    monitored.push_back(incoming.describe(config.note_names));
+                    window.request_redraw();
+                }
+            }
+            Event::UserEvent(UserEvent::Beat(beat)) => {
+                flash = Some((beat, Instant::now() + BEAT_FLASH));
+                window.request_redraw();
+            }
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                let now = Instant::now();
+                if flash.is_some_and(|(_, until)| until <= now) {
+                    flash = None;
+                    window.request_redraw();
+                }
+
+                if let (Some((up, moved)), Some(morph)) = (morphing, &mut morph) {
+                    if moved + MORPH_STEP <= now {
+                        for midi in morph.ramp(up, now - moved) {
+                            send(&tx, midi);
+                        }
+                        morphing = Some((up, now));
+                        window.request_redraw();
+                    }
+                }
 
-                if let Some(note) = Note::from_scancode(scancode) {
-                    tx.send(KeyboardMsg {
+                if let Some(midi) = mono.as_mut().and_then(|mono| mono.step(now)) {
+                    send(&tx, midi);
+                }
+
+                for midi in key_ups.due(now) {
+                    play_note(
+                        &tx,
+                        &mut shift,
+                        &mut split,
+                        &mut mono,
+                        &mut harmonizer,
+                        &mut note_channels,
+                        midi,
+                    );
+                }
+
+                let idle = now.saturating_duration_since(last_input);
+                if config.idle_release > 0 && idle >= Duration::from_secs(config.idle_release) {
+                    let held: Vec<_> = active_keys
+                        .iter()
+                        .copied()
+                        .filter(|&key| plays_notes(&config, &chords, key))
+                        .collect();
+                    if !held.is_empty() || !background_held.is_empty() {
+                        report!(
+                            "no input for {} s with {} note keys held, letting go \
+                             of them",
+                            config.idle_release,
+                            held.len() + background_held.len()
+                        );
+                    }
+                    for key in held {
+                        active_keys.remove(&key);
+                        dwelling.retain(|&(dwelled, ..)| dwelled != key);
+
+                        let (channel, velocity) = (DEFAULT_CHANNEL, 0);
+                        let mut sent = Vec::new();
+                        if let (false, Some(note)) =
+                            (chords.contains_key(&key), key_note(&config, key))
+                        {
+                            chord_learn.note(note, false);
+                            let midi = MidiMsg::NoteOff {
+                                channel,
+                                note,
+                                velocity,
+                            };
+                            let midi = locked(&mut progression, midi);
+                            sent = play_note(
+                                &tx,
+                                &mut shift,
+                                &mut split,
+                                &mut mono,
+                                &mut harmonizer,
+                                &mut note_channels,
+                                midi,
+                            );
+                        }
+                        for midi in sounding.released(key, &sent) {
+                            send(&tx, midi);
+                        }
+                    }
+                    for key in background_held.drain() {
+                        let mut sent = Vec::new();
+                        if let Some(note) = key_note(&config, key) {
+                            let midi = MidiMsg::NoteOff {
+                                channel: DEFAULT_CHANNEL,
+                                note,
+                                velocity: 0,
+                            };
+                            let midi = locked(&mut progression, midi);
+                            sent = play_note(
+                                &tx,
+                                &mut shift,
+                                &mut split,
+                                &mut mono,
+                                &mut harmonizer,
+                                &mut note_channels,
+                                midi,
+                            );
+                        }
+                        for midi in sounding.released(key, &sent) {
+                            send(&tx, midi);
+                        }
+                    }
+                }
+
+                while let Some(index) = dwelling.iter().position(|&(_, _, due)| due <= now) {
+                    let (key, note, _) = dwelling.remove(index);
+                    let velocity = accented(velocity_curve.apply(FIXED_VELOCITY), accent);
+                    chord_learn.note(note, true);
+                    detect_key(&config, &mut key_detect, &mut harmonizer, &window, note);
+                    if let Some(practice) = &mut practice {
+                        practice.note(note, now);
+                    }
+                    curve_editor.set_last(FIXED_VELOCITY, velocity);
+                    window.request_redraw();
+
+                    let midi = MidiMsg::NoteOn {
+                        channel: DEFAULT_CHANNEL,
                         note,
-                        pressed: state == ElementState::Pressed,
-                    })
-                    .unwrap();
+                        velocity,
+                    };
+                    let midi = locked(&mut progression, midi);
+                    let sent = play_note(
+                        &tx,
+                        &mut shift,
+                        &mut split,
+                        &mut mono,
+                        &mut harmonizer,
+                        &mut note_channels,
+                        midi,
+                    );
+                    sounding.pressed(key, &sent);
+                }
+            }
+            Event::UserEvent(UserEvent::BackgroundKey { scancode, pressed }) => {
+                if Some(scancode) == capture_key {
+                    if pressed {
+                        capturing = !capturing;
+                        window.request_redraw();
+                    }
+                    return;
+                }
+                last_input = Instant::now();
+                let play = if pressed {
+                    background_on && capturing && !focused && background_held.insert(scancode)
+                } else {
+                    background_held.remove(&scancode)
+                };
+                let play = play
+                    && match (&mut chance, key_note(&config, scancode)) {
+                        (Some(chance), Some(_)) => chance.handle(scancode, pressed),
+                        _ => true,
+                    };
+                if let Some(note) = key_note(&config, scancode).filter(|_| play) {
+                    let (channel, velocity) =
+                        (DEFAULT_CHANNEL, velocity_curve.apply(FIXED_VELOCITY));
+                    let midi = if pressed {
+                        MidiMsg::NoteOn {
+                            channel,
+                            note,
+                            velocity,
+                        }
+                    } else {
+                        MidiMsg::NoteOff {
+                            channel,
+                            note,
+                            velocity,
+                        }
+                    };
+                    let midi = locked(&mut progression, midi);
+                    let sent = play_note(
+                        &tx,
+                        &mut shift,
+                        &mut split,
+                        &mut mono,
+                        &mut harmonizer,
+                        &mut note_channels,
+                        midi,
+                    );
+                    if pressed {
+                        sounding.pressed(scancode, &sent);
+                    } else {
+                        for midi in sounding.released(scancode, &sent) {
+                            send(&tx, midi);
+                        }
+                    }
                 }
             }
+            Event::UserEvent(UserEvent::Command(command)) => match command {
+                Command::Midi(midi) => send(&tx, midi),
+                Command::Octave(octave) => {
+                    shift.octave = octave;
+                    window.request_redraw();
+                }
+                Command::Channel(channel) => {
+                    shift.channel = channel;
+                    window.request_redraw();
+                }
+                Command::Block(block) => {
+                    if !blocks.contains(&block) {
+                        blocks.push(block);
+                    }
+                    controls.send(Control::Block(blocks.clone())).unwrap();
+                    window.request_redraw();
+                }
+                Command::Unblock(block) => {
+                    block::unblock(&mut blocks, block);
+                    controls.send(Control::Block(blocks.clone())).unwrap();
+                    window.request_redraw();
+                }
+                Command::Panic => {
+                    panic(&tx);
+                    shift.clear();
+                    sounding.clear();
+                    split.iter_mut().for_each(Split::clear);
+                    mono.iter_mut().for_each(Mono::clear);
+                    harmonizer.iter_mut().for_each(Harmonizer::clear);
+                    note_channels.iter_mut().for_each(NoteChannels::clear);
+                    sustain = 0;
+                    window.request_redraw();
+                }
+                Command::Preset(index) => {
+                    if index < config.presets.len() {
+                        preset = Some(index);
+                        select_preset(&tx, &window, &config, websocket.as_ref(), preset);
+                    }
+                }
+            },
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 window_id,
                 ..
             } if window_id == window.id() => *control_flow = ControlFlow::Exit,
+            Event::LoopDestroyed => {
+                if let (Some(path), Some(heat_map)) = (&heat_map_csv, &heat_map) {
+                    if let Err(err) = fs::write(path, heat_map.csv(config.note_names)) {
+                        report!("{}: {}", path.display(), err);
+                    }
+                }
+                session_log::record("stop", "window closed");
+            }
             _ => (),
         }
     });
 }
 
+const HINT_SCALE: u32 = 2;
+/// The space around the fullscreen view.
+const STAGE_MARGIN: u32 = 24;
+/// How long the window flashes for on every beat, with `beat_flash`.
+const BEAT_FLASH: Duration = Duration::from_millis(100);
+/// How often the controllers are sent while morphing.
+const MORPH_STEP: Duration = Duration::from_millis(20);
+/// Beats to a bar, of which the first flashes brighter.
+const BEATS_PER_BAR: u64 = 4;
+const SLIDER_WIDTH: u32 = 48;
+const MONITOR_WIDTH: u32 = 240;
+const MONITOR_SCALE: u32 = 1;
+const HEAT_MAP_HEIGHT: u32 = 24;
+
+/// Where everything goes in the window: the velocity curve editor with the pitch bend and mod
+/// wheel sliders to its right and the monitor to its left, the heat map below them, and the key
+/// hint and status line below that.
+struct Areas {
+    curve: Rect,
+    bend: Rect,
+    mod_wheel: Rect,
+    /// The list of messages from the input port, with `--monitor`.
+    monitor: Option<Rect>,
+    /// How often each note was played, with `--heat-map`.
+    heat_map: Option<Rect>,
+    footer: Rect,
+}
+
+impl Areas {
+    fn new(canvas: &Canvas, monitor: bool, heat_map: bool) -> Self {
+        let (_, footer_height) = Canvas::text_size("", HINT_SCALE);
+        let bounds = canvas.bounds().inset(8);
+        let mut height = bounds.height.saturating_sub(footer_height + 8);
+        let heat_map = heat_map.then(|| {
+            height = height.saturating_sub(HEAT_MAP_HEIGHT + 8);
+            Rect::new(
+                bounds.x,
+                bounds.y + (height + 8) as i32,
+                bounds.width,
+                HEAT_MAP_HEIGHT,
+            )
+        });
+        let sliders = 2 * (SLIDER_WIDTH + 8);
+        let monitor = monitor.then(|| Rect::new(bounds.x, bounds.y, MONITOR_WIDTH, height));
+        let curve_x = monitor.map_or(bounds.x, |monitor| monitor.right() + 8);
+
+        let mod_wheel = Rect::new(
+            bounds.right() - SLIDER_WIDTH as i32,
+            bounds.y,
+            SLIDER_WIDTH,
+            height,
+        );
+        let bend = Rect {
+            x: mod_wheel.x - 8 - SLIDER_WIDTH as i32,
+            ..mod_wheel
+        };
+
+        Areas {
+            curve: Rect::new(
+                curve_x,
+                bounds.y,
+                (bounds.right() - sliders as i32 - curve_x).max(0) as u32,
+                height,
+            ),
+            bend,
+            mod_wheel,
+            monitor,
+            heat_map,
+            footer: Rect::new(
+                bounds.x,
+                bounds.bottom() - footer_height as i32,
+                bounds.width,
+                footer_height,
+            ),
+        }
+    }
+}
+
+/// Lists the newest of the messages from the input port that fit in `area`, newest at the
+/// bottom.
+fn draw_monitor(canvas: &mut Canvas, area: Rect, monitored: &VecDeque<String>) {
+    canvas.fill_rect(area, gui::PANEL);
+
+    let (_, line_height) = Canvas::text_size("", MONITOR_SCALE);
+    let line_height = line_height + 4;
+    let fits = (area.height.saturating_sub(8) / line_height) as usize;
+    let mut y = area.bottom() - 4 - line_height as i32;
+    for line in monitored.iter().rev().take(fits) {
+        canvas.draw_text(area.x + 4, y, line, MONITOR_SCALE, gui::TEXT);
+        y -= line_height as i32;
+    }
+}
+
+/// The notes played by the keys held, for the fullscreen view.
+fn held_notes(
+    config: &Config,
+    chords: &HashMap<ScanCode, Vec<u8>>,
+    active_keys: &HashSet<ScanCode>,
+) -> Vec<u8> {
+    let mut held: Vec<u8> = active_keys
+        .iter()
+        .flat_map(|scancode| match chords.get(scancode) {
+            Some(notes) => notes.clone(),
+            None => key_note(config, *scancode).into_iter().collect(),
+        })
+        .collect();
+    held.sort_unstable();
+    held.dedup();
+    held
+}
+
+/// Where the fullscreen view is drawn, and the notes its keyboard covers: those the keys play
+/// and those arriving on the input port.
+fn stage_layout(canvas: &Canvas, config: &Config, incoming: &[(u8, u8)]) -> (Rect, (u8, u8)) {
+    let range = incoming
+        .iter()
+        .fold(key_range(config), |(low, high), &(_, note)| {
+            (low.min(note), high.max(note))
+        });
+    (canvas.bounds().inset(STAGE_MARGIN), range)
+}
+
+/// The colors of the keys of the fullscreen view: those of `[key_colors]`, over those of the
+/// zones of the split wherever it is now.
+fn key_colors(config: &Config, split: Option<&Split>) -> [Option<Color>; 128] {
+    let mut colors = config.key_colors;
+    if let Some(split) = split {
+        for (note, color) in (0..128).zip(&mut colors) {
+            *color = color.or(split.zone(note).color);
+        }
+    }
+    colors
+}
+
+/// What the key of `note` on the fullscreen view plays with the note keys moved `octave`
+/// octaves: its name, number and frequency.
+fn key_tooltip(note: u8, octave: i8, names: NoteNames) -> String {
+    let played = (note as i32 + octave as i32 * 12).clamp(0, 127) as u8;
+    let text = format!(
+        "{} {} {:.2} Hz",
+        names.name(played),
+        played,
+        midi::frequency(played)
+    );
+    match played == note {
+        true => text,
+        false => format!("{} plays {}", names.name(note), text),
+    }
+}
+
+/// The lowest and highest notes the keys play.
+fn key_range(config: &Config) -> (u8, u8) {
+    let notes = (0..128).filter_map(|scancode| key_note(config, scancode));
+    notes.fold((127, 0), |(low, high), note| {
+        (low.min(note), high.max(note))
+    })
+}
+
+/// The line at the bottom of the window listing the keys that play notes, as labelled in
+/// `layout`.
+fn key_hint(layout: Layout, config: &Config) -> String {
+    let mut keys: Vec<_> = (0..128)
+        .filter_map(|scancode| {
+            let note = key_note(config, scancode)?;
+            Some((note, layout.label(scancode)?))
+        })
+        .collect();
+    keys.sort();
+
+    let row = |black: bool| {
+        keys.iter()
+            .filter(|(note, _)| matches!(note % 12, 1 | 3 | 6 | 8 | 10) == black)
+            .map(|(_, label)| label.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    format!("White keys: {}   Black keys: {}", row(false), row(true))
+}
+
+/// Counts a note played towards guessing the key, and follows the key guessed with the
+/// harmonizer if `[key_detect]` says to.
+fn detect_key(
+    config: &Config,
+    key_detect: &mut Option<KeyDetect>,
+    harmonizer: &mut Option<Harmonizer>,
+    window: &Window,
+    note: u8,
+) {
+    let Some(key_detect) = key_detect else {
+        return;
+    };
+    if !key_detect.note(note) {
+        return;
+    }
+    if let (Some((root, scale)), Some(harmonizer)) = (key_detect.key(), harmonizer) {
+        if config.key_detect.is_some_and(|k| k.follow) {
+            harmonizer.set_key(root, scale);
+        }
+    }
+    window.request_redraw();
+}
+
+/// Whether a key pressed in the window plays notes, as a chord or a note key.
+fn plays_notes(config: &Config, chords: &HashMap<ScanCode, Vec<u8>>, scancode: ScanCode) -> bool {
+    chords.contains_key(&scancode) || key_note(config, scancode).is_some()
+}
+
+/// The note the key with `scancode` plays, from the `[notes]` of the config if it has any.
+fn key_note(config: &Config, scancode: ScanCode) -> Option<u8> {
+    if config.notes.is_empty() {
+        Note::from_scancode(scancode).map(Note::to_midi_value)
+    } else {
+        config.notes.get(&scancode).copied()
+    }
+}
+
+/// Whether pressing `scancode` with Ctrl held or not undoes the last change: Ctrl+Z does,
+/// unless Ctrl is the bass key and held as such, in which case Z plays its note lower.
+fn undoes(ctrl: bool, bass: u8, scancode: ScanCode) -> bool {
+    ctrl && bass == 0 && keys::name(scancode) == Some("KeyZ")
+}
+
+/// Stops every note on every channel, whatever is holding it.
+fn panic(tx: &Sender<KeyboardMsg>) {
+    for channel in 0..16 {
+        for controller in [CC_SUSTAIN, CC_ALL_NOTES_OFF, CC_ALL_SOUND_OFF] {
+            let value = 0;
+            send(
+                tx,
+                MidiMsg::ControlChange {
+                    channel,
+                    controller,
+                    value,
+                },
+            );
+        }
+    }
+}
+
+/// A note from a note key locked to the current chord of the progression, if there is one.
+fn locked(progression: &mut Option<Progression>, midi: MidiMsg) -> MidiMsg {
+    match progression {
+        Some(progression) => progression.handle(midi),
+        None => midi,
+    }
+}
+
+/// Sends a note played on the keyboard, moved to the octave and channel set, through the split,
+/// mono mode, the harmonizer and note channels if they are on. Returns the messages sent.
+fn play_note(
+    tx: &Sender<KeyboardMsg>,
+    shift: &mut Shift,
+    split: &mut Option<Split>,
+    mono: &mut Option<Mono>,
+    harmonizer: &mut Option<Harmonizer>,
+    note_channels: &mut Option<NoteChannels>,
+    midi: MidiMsg,
+) -> Vec<MidiMsg> {
+    let midi = shift.handle(midi);
+    let midi = match split {
+        Some(split) => split.handle(midi),
+        None => midi,
+    };
+    let messages = match mono {
+        Some(mono) => mono.handle(midi),
+        None => vec![midi],
+    };
+    let messages = match harmonizer {
+        Some(harmonizer) => messages
+            .into_iter()
+            .flat_map(|midi| harmonizer.handle(midi))
+            .collect(),
+        None => messages,
+    };
+    let messages: Vec<_> = match note_channels {
+        Some(note_channels) => messages
+            .into_iter()
+            .flat_map(|midi| note_channels.handle(midi))
+            .collect(),
+        None => messages,
+    };
+    for &midi in &messages {
+        send(tx, midi);
+    }
+    messages
+}
+
+/// Prints the VMPK keymap at `path` as a `[notes]` table, see [`keymap`].
+fn import_keymap(path: &Path, config: &Config) {
+    let notes = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| keymap::import_vmpk(&source))
+        .unwrap_or_else(|err| {
+            report!("{}: {}", path.display(), err);
+            process::exit(1);
+        });
+
+    let layout = config.layout.unwrap_or(Layout::Qwerty);
+    let toml = keymap::export(keymap::Format::Toml, &notes, config.note_names, layout);
+    print!("{}", toml);
+}
+
+/// Prints the note of every key that plays one, lowest first.
+fn export_keymap(format: keymap::Format, config: &Config) {
+    let mut notes: Vec<_> = keys::names()
+        .filter_map(|name| {
+            let scancode = keys::scancode(name)?;
+            Some((scancode, key_note(config, scancode)?))
+        })
+        .collect();
+    notes.sort_by_key(|&(scancode, note)| (note, scancode));
+
+    let layout = config
+        .layout
+        .or_else(|| Layout::detect().map(|(layout, _)| layout))
+        .unwrap_or(Layout::Qwerty);
+    print!(
+        "{}",
+        keymap::export(format, &notes, config.note_names, layout)
+    );
+}
+
+/// The note the first key pressed with `--learn-keymap` plays, with each key after it a
+/// semitone higher.
+const LEARN_FIRST_NOTE: u8 = 60;
+
+/// Prints every key pressed in a window of its own, as comments, and the `[notes]` table of
+/// the keys pressed once the window is closed.
+fn learn_keymap(config: Config) -> ! {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("JACK keyboard - press the keys in order, then close")
+        .build(&event_loop)
+        .unwrap();
+    let mut learned: Vec<ScanCode> = Vec::new();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        let done = match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                scancode,
+                                state: ElementState::Pressed,
+                                virtual_keycode,
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+                ..
+            } if window_id == window.id() => {
+                let scancode = keys::normalize(scancode);
+                let name = keys::name(scancode);
+                println!(
+                    "# {}: scancode {}, {}",
+                    name.unwrap_or("(no name)"),
+                    scancode,
+                    virtual_keycode.map_or("no keycode".to_string(), |key| format!("{:?}", key))
+                );
+
+                if virtual_keycode == Some(VirtualKeyCode::Escape) {
+                    true
+                } else {
+                    // Keys without a name can't go in the config
+                    let new = name.is_some() && !learned.contains(&scancode);
+                    if new && LEARN_FIRST_NOTE as usize + learned.len() <= 127 {
+                        learned.push(scancode);
+                    }
+                    false
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+                ..
+            } => window_id == window.id(),
+            _ => false,
+        };
+
+        if done {
+            if !learned.is_empty() {
+                println!("[notes]");
+            }
+            for (note, &scancode) in (LEARN_FIRST_NOTE..).zip(&learned) {
+                let key = keys::name(scancode).unwrap_or_default();
+                println!("{} = \"{}\"", key, config.note_names.name(note));
+            }
+            *control_flow = ControlFlow::Exit;
+        }
+    })
+}
+
+/// How much the portamento keys change the portamento time by.
+const PORTAMENTO_STEP: u8 = 8;
+
+fn portamento_time_msg(time: u8) -> MidiMsg {
+    MidiMsg::ControlChange {
+        channel: DEFAULT_CHANNEL,
+        controller: CC_PORTAMENTO_TIME,
+        value: time,
+    }
+}
+
+fn pitch_bend_msg(value: u16) -> MidiMsg {
+    MidiMsg::PitchBend {
+        channel: DEFAULT_CHANNEL,
+        value,
+    }
+}
+
+/// Sends `midi`, a control change from the mod wheel slider or the portamento time keys moved
+/// from `from`, unless [soft takeover](takeover) holds it back.
+fn send_control(
+    tx: &Sender<KeyboardMsg>,
+    config: &Config,
+    controllers: &snapshot::Shared,
+    from: u8,
+    midi: MidiMsg,
+) {
+    if let (
+        true,
+        MidiMsg::ControlChange {
+            channel,
+            controller,
+            value,
+        },
+    ) = (config.soft_takeover, midi)
+    {
+        let written = controllers.lock().unwrap().get(channel, controller);
+        if !takeover::takes_over(written, from, value) {
+            return;
+        }
+    }
+    send(tx, midi);
+}
+
+fn mod_wheel_msg(value: u16) -> MidiMsg {
+    MidiMsg::ControlChange {
+        channel: DEFAULT_CHANNEL,
+        controller: CC_MOD_WHEEL,
+        value: value as u8,
+    }
+}
+
+/// How much the swing keys change the swing by, in percent.
+const SWING_STEP: f64 = 2.0;
+
+/// Roughly how many pixels touchpads scroll for one line of a mouse wheel.
+const PIXELS_PER_LINE: f64 = 20.0;
+
+/// `velocity` made louder by `accent`, up to the loudest there is.
+fn accented(velocity: u8, accent: u8) -> u8 {
+    velocity.saturating_add(accent).min(127)
+}
+
+/// The sustain pedal at `value`, or the other way round if `inverted`.
+fn sustain_msg(value: u8, inverted: bool) -> MidiMsg {
+    MidiMsg::ControlChange {
+        channel: DEFAULT_CHANNEL,
+        controller: CC_SUSTAIN,
+        value: if inverted { 127 - value } else { value },
+    }
+}
+
+fn portamento_msg(on: bool) -> MidiMsg {
+    MidiMsg::ControlChange {
+        channel: DEFAULT_CHANNEL,
+        controller: CC_PORTAMENTO,
+        value: if on { 127 } else { 0 },
+    }
+}
+
+fn send(tx: &Sender<KeyboardMsg>, midi: MidiMsg) {
+    tx.send(KeyboardMsg {
+        midi,
+        time: jack::get_time(),
+    })
+    .unwrap();
+}
+
+/// Sends the programs and controllers for `preset` and shows its name in the title bar. The
+/// window is redrawn in its color.
+fn select_preset(
+    tx: &Sender<KeyboardMsg>,
+    window: &Window,
+    config: &Config,
+    websocket: Option<&websocket::Broadcaster>,
+    preset: Option<usize>,
+) {
+    let name = send_preset(tx, config, websocket, preset);
+    window.set_title(&if name.is_empty() {
+        "JACK keyboard".to_string()
+    } else {
+        format!("JACK keyboard - {}", name)
+    });
+    window.request_redraw();
+}
+
+/// Sends the programs and controllers for `preset` and tells the remote clients about it.
+/// Returns its name.
+fn send_preset<'a>(
+    tx: &Sender<KeyboardMsg>,
+    config: &'a Config,
+    websocket: Option<&websocket::Broadcaster>,
+    preset: Option<usize>,
+) -> &'a str {
+    for (channel, program) in config.programs_for(preset) {
+        for midi in program.messages(channel) {
+            send(tx, midi);
+        }
+    }
+
+    let active = preset.and_then(|index| config.presets.get(index));
+    for (&(channel, controller), &value) in active.iter().flat_map(|preset| &preset.controllers) {
+        send(
+            tx,
+            MidiMsg::ControlChange {
+                channel,
+                controller,
+                value,
+            },
+        );
+    }
+    let name = active.map_or("", |preset| preset.name.as_str());
+    match preset {
+        Some(index) => session_log::record("preset", format_args!("{} {}", index + 1, name)),
+        None => session_log::record("preset", "none"),
+    }
+
+    if let (Some(websocket), Some(preset)) = (websocket, preset) {
+        let color = active.and_then(|preset| preset.color);
+        websocket.set_state("preset", json::preset_line(preset, name, color));
+    }
+    name
+}
+
+/// F1 to F12 select the first twelve presets, but for those bound to an action, which comes
+/// first: by default F11 is `fullscreen`, so preset 11 needs that bound to another key.
+fn preset_index(key: VirtualKeyCode) -> Option<usize> {
+    use VirtualKeyCode::*;
+
+    [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12]
+        .iter()
+        .position(|&f| f == key)
+}
+
 #[derive(Debug)]
 struct KeyboardMsg {
-    note: Note,
-    pressed: bool,
+    midi: MidiMsg,
+    /// JACK time (in microseconds) at which the message was generated.
+    time: jack::Time,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -185,3 +2421,21 @@ impl Note {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctrl_z_undoes_with_the_default_keys() {
+        let config = Config::default();
+        for ctrl in ["ControlLeft", "ControlRight"] {
+            let ctrl = keys::scancode(ctrl).unwrap();
+            let bass = match config.bindings.action(ctrl) {
+                Some(Action::Bass) => config.bass,
+                _ => 0,
+            };
+            assert!(undoes(true, bass, keys::scancode("KeyZ").unwrap()));
+        }
+    }
+}
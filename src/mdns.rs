@@ -0,0 +1,179 @@
+//! Just enough multicast DNS (RFC 6762) and DNS-SD to find a service on the local network by
+//! its instance name.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+const MDNS_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// How often queries that haven't been answered are sent again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Looks for the instance of `service` (like `_apple-midi._udp.local`) called `instance` for
+/// up to `timeout`, and returns its address.
+///
+/// The queries are sent from an ordinary port, which makes responders answer with unicast
+/// ("legacy unicast"), so nothing has to listen on port 5353.
+pub fn resolve(service: &str, instance: &str, timeout: Duration) -> io::Result<Option<SocketAddr>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    let full_name = format!("{}.{}", instance, service).to_lowercase();
+    let mut records = Records::default();
+    let deadline = Instant::now() + timeout;
+    let mut last_query: Option<Instant> = None;
+    let mut buffer = [0; 9000];
+
+    while Instant::now() < deadline {
+        if let Some(addr) = records.address(&full_name) {
+            return Ok(Some(addr));
+        }
+
+        if last_query.is_none_or(|last| last.elapsed() >= RETRY_INTERVAL) {
+            // Ask for whatever is still missing, in case it wasn't in the additional records
+            let query = match records.srv.get(&full_name) {
+                Some((_, target)) => query(target, TYPE_A),
+                None if records.instances.contains(&full_name) => query(&full_name, TYPE_SRV),
+                None => query(service, TYPE_PTR),
+            };
+            socket.send_to(&query, MDNS_ADDR)?;
+            last_query = Some(Instant::now());
+        }
+
+        match socket.recv_from(&mut buffer) {
+            Ok((len, _)) => records.parse(&buffer[..len]),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(records.address(&full_name))
+}
+
+/// The records seen so far, with names in lowercase.
+#[derive(Debug, Default)]
+struct Records {
+    instances: Vec<String>,
+    /// The port and target host of each instance.
+    srv: HashMap<String, (u16, String)>,
+    a: HashMap<String, Ipv4Addr>,
+}
+
+impl Records {
+    fn address(&self, instance: &str) -> Option<SocketAddr> {
+        let (port, target) = self.srv.get(instance)?;
+        let ip = self.a.get(target)?;
+
+        Some(SocketAddr::from((*ip, *port)))
+    }
+
+    /// Takes in the records of a response. Anything malformed is ignored from there on.
+    fn parse(&mut self, packet: &[u8]) {
+        let _ = self.try_parse(packet);
+    }
+
+    fn try_parse(&mut self, packet: &[u8]) -> Option<()> {
+        let count = |at: usize| Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]));
+
+        let is_response = packet.get(2)? & 0x80 != 0;
+        if !is_response {
+            return None;
+        }
+        let questions = count(4)?;
+        let records = count(6)? as usize + count(8)? as usize + count(10)? as usize;
+
+        let mut pos = 12;
+        for _ in 0..questions {
+            let (_, end) = read_name(packet, pos)?;
+            pos = end + 4;
+        }
+
+        for _ in 0..records {
+            let (name, end) = read_name(packet, pos)?;
+            let kind = count(end)?;
+            let len = count(end + 8)? as usize;
+            let data = end + 10;
+            let rdata = packet.get(data..data + len)?;
+            pos = data + len;
+
+            match kind {
+                TYPE_PTR => {
+                    let (instance, _) = read_name(packet, data)?;
+                    if !self.instances.contains(&instance) {
+                        self.instances.push(instance);
+                    }
+                }
+                TYPE_SRV if len >= 6 => {
+                    let port = u16::from_be_bytes([rdata[4], rdata[5]]);
+                    let (target, _) = read_name(packet, data + 6)?;
+                    self.srv.insert(name, (port, target));
+                }
+                TYPE_A if len == 4 => {
+                    let ip = Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]);
+                    self.a.insert(name, ip);
+                }
+                _ => (),
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// A query for one record of `name`.
+fn query(name: &str, kind: u16) -> Vec<u8> {
+    // No ID, no flags, one question
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&kind.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    packet
+}
+
+/// Reads the (possibly compressed) name at `pos`, in lowercase, and returns it with the
+/// position after it.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+
+    // Pointers can only go back, but a bad packet could still loop
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".").to_lowercase();
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let target = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            len => {
+                let label = packet.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,104 @@
+//! Names for patchbays: JACK metadata with a pretty name, order and icon for the client and its
+//! ports, and aliases for the ports, so e.g. qjackctl and Carla show "Virtual Keyboard Out"
+//! rather than `jack_keyboard:out`.
+//!
+//! The `jack` crate only wraps metadata behind a feature and doesn't give ports' UUIDs, so
+//! this calls libjack itself.
+
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+};
+
+use jack::{Client, Port, PortSpec};
+
+const PRETTY_NAME: &str = "http://jackaudio.org/metadata/pretty-name";
+const ORDER: &str = "http://jackaudio.org/metadata/order";
+const ICON_NAME: &str = "http://jackaudio.org/metadata/icon-name";
+const INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+
+/// The pretty name of the client.
+pub const CLIENT_NAME: &str = "Virtual Keyboard";
+/// The freedesktop.org icon the client is shown with.
+const ICON: &str = "input-keyboard";
+
+#[link(name = "jack")]
+extern "C" {
+    fn jack_client_get_uuid(client: *mut c_void) -> *mut c_char;
+    fn jack_uuid_parse(buf: *const c_char, uuid: *mut u64) -> c_int;
+    fn jack_free(ptr: *mut c_void);
+    fn jack_port_uuid(port: *const c_void) -> u64;
+    fn jack_set_property(
+        client: *mut c_void,
+        subject: u64,
+        key: *const c_char,
+        value: *const c_char,
+        kind: *const c_char,
+    ) -> c_int;
+}
+
+/// Gives the client its pretty name and icon.
+pub fn name_client(client: &Client) -> Result<(), String> {
+    let mut uuid = 0;
+    // SAFETY: the string JACK gives is only read before it is freed.
+    let parsed = unsafe {
+        let string = jack_client_get_uuid(client.raw() as *mut c_void);
+        if string.is_null() {
+            -1
+        } else {
+            let parsed = jack_uuid_parse(string, &mut uuid);
+            jack_free(string as *mut c_void);
+            parsed
+        }
+    };
+    if parsed != 0 {
+        return Err("couldn't get the UUID of the JACK client".to_string());
+    }
+
+    set(client, uuid, PRETTY_NAME, CLIENT_NAME, None)?;
+    set(client, uuid, ICON_NAME, ICON, None)
+}
+
+/// Gives `port` a pretty name and alias of `name`, and its place among the client's ports.
+pub fn name_port<PS: PortSpec>(
+    client: &Client,
+    port: &mut Port<PS>,
+    name: &str,
+    order: usize,
+) -> Result<(), String> {
+    port.set_alias(name)
+        .map_err(|err| format!("couldn't give port alias {}: {}", name, err))?;
+
+    // SAFETY: the port belongs to `client`, which is still around.
+    let uuid = unsafe { jack_port_uuid(port.raw() as *const c_void) };
+    set(client, uuid, PRETTY_NAME, name, None)?;
+    set(client, uuid, ORDER, &order.to_string(), Some(INTEGER))
+}
+
+fn set(
+    client: &Client,
+    subject: u64,
+    key: &str,
+    value: &str,
+    kind: Option<&str>,
+) -> Result<(), String> {
+    let cstring = |s: &str| CString::new(s).unwrap();
+    let (c_key, c_value, c_kind) = (cstring(key), cstring(value), kind.map(cstring));
+
+    // SAFETY: the strings outlive the call, which copies them.
+    let result = unsafe {
+        jack_set_property(
+            client.raw() as *mut c_void,
+            subject,
+            c_key.as_ptr(),
+            c_value.as_ptr(),
+            c_kind.as_ref().map_or(ptr::null(), |kind| kind.as_ptr()),
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err(format!("couldn't set JACK metadata {} to {}", key, value)),
+    }
+}
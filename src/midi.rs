@@ -0,0 +1,284 @@
+/// The channel notes are sent on unless configured otherwise (MIDI channel 2).
+pub const DEFAULT_CHANNEL: u8 = 1;
+
+pub const CC_BANK_SELECT_MSB: u8 = 0;
+pub const CC_MOD_WHEEL: u8 = 1;
+pub const CC_PAN: u8 = 10;
+pub const CC_EXPRESSION: u8 = 11;
+pub const CC_BANK_SELECT_LSB: u8 = 32;
+pub const CC_PORTAMENTO_TIME: u8 = 5;
+pub const CC_SUSTAIN: u8 = 64;
+pub const CC_PORTAMENTO: u8 = 65;
+pub const CC_ALL_SOUND_OFF: u8 = 120;
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+/// The low 7 bits of the velocity of the next note on, for 14-bit velocity.
+pub const CC_HIGH_RES_VELOCITY: u8 = 88;
+
+/// How notes are named. Octaves are numbered the same way in each, with middle C (60) in
+/// octave 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteNames {
+    /// C D E F G A B, e.g. `C4` for 60 or `F#2` for 42.
+    #[default]
+    English,
+    /// Fixed do, e.g. `Do4` for 60 or `Fa#2` for 42.
+    Solfege,
+    /// C D E F G A H, with B for B flat, e.g. `H3` for 59 and `B3` for 58.
+    German,
+}
+
+impl NoteNames {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "english" => Some(NoteNames::English),
+            "solfege" => Some(NoteNames::Solfege),
+            "german" => Some(NoteNames::German),
+            _ => None,
+        }
+    }
+
+    /// The names of the notes from C up, with the semitone each one is above C.
+    fn naturals(self) -> &'static [(&'static str, i32)] {
+        match self {
+            NoteNames::English => &[
+                ("C", 0),
+                ("D", 2),
+                ("E", 4),
+                ("F", 5),
+                ("G", 7),
+                ("A", 9),
+                ("B", 11),
+            ],
+            NoteNames::Solfege => &[
+                ("Do", 0),
+                ("Re", 2),
+                ("Mi", 4),
+                ("Fa", 5),
+                ("Sol", 7),
+                ("La", 9),
+                ("Si", 11),
+            ],
+            NoteNames::German => &[
+                ("C", 0),
+                ("D", 2),
+                ("E", 4),
+                ("F", 5),
+                ("G", 7),
+                ("A", 9),
+                ("B", 10),
+                ("H", 11),
+            ],
+        }
+    }
+
+    /// The name of a note number, using sharps for the black keys (except B in German).
+    pub fn name(self, note: u8) -> String {
+        format!("{}{}", self.pitch_class(note), note as i32 / 12 - 1)
+    }
+
+    /// The name of a note without its octave, like [`name`](Self::name) otherwise.
+    pub fn pitch_class(self, note: u8) -> String {
+        let semitone = note as i32 % 12;
+        let naturals = self.naturals();
+        match naturals.iter().find(|(_, s)| *s == semitone) {
+            Some((name, _)) => name.to_string(),
+            None => {
+                let (below, _) = naturals.iter().find(|(_, s)| *s == semitone - 1).unwrap();
+                format!("{}#", below)
+            }
+        }
+    }
+
+    /// Reads a note name like [`name`](Self::name) writes, ignoring case, also with `b` for a
+    /// flat.
+    pub fn parse(self, name: &str) -> Option<u8> {
+        let lower = name.to_lowercase();
+        // Longest first, so "sol" isn't taken for something shorter
+        let (rest, semitone) = self
+            .naturals()
+            .iter()
+            .filter_map(|(natural, semitone)| {
+                let rest = lower.strip_prefix(&natural.to_lowercase())?;
+                Some((rest, *semitone, natural.len()))
+            })
+            .max_by_key(|&(_, _, len)| len)
+            .map(|(rest, semitone, _)| (rest, semitone))?;
+
+        let (rest, semitone) = match rest.as_bytes().first() {
+            Some(b'#') => (&rest[1..], semitone + 1),
+            Some(b'b') => (&rest[1..], semitone - 1),
+            _ => (rest, semitone),
+        };
+        let octave: i32 = rest.parse().ok()?;
+
+        u8::try_from((octave + 1) * 12 + semitone)
+            .ok()
+            .filter(|note| *note <= 127)
+    }
+}
+
+/// A MIDI channel message. Channels are zero-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMsg {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// Aftertouch for the whole channel rather than a single note.
+    ChannelPressure {
+        channel: u8,
+        value: u8,
+    },
+    /// `value` is 14 bits, with no bend at [`PITCH_BEND_CENTER`].
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+}
+
+/// A system real-time message which, once sent, has to be repeated at least every 300 ms or the
+/// receiver assumes the connection was lost.
+pub const ACTIVE_SENSING: u8 = 0xfe;
+
+pub const PITCH_BEND_CENTER: u16 = 0x2000;
+pub const PITCH_BEND_MAX: u16 = 0x3fff;
+
+impl MidiMsg {
+    /// The zero-based channel of the message.
+    pub fn channel(&self) -> u8 {
+        match *self {
+            MidiMsg::NoteOn { channel, .. }
+            | MidiMsg::NoteOff { channel, .. }
+            | MidiMsg::ControlChange { channel, .. }
+            | MidiMsg::ProgramChange { channel, .. }
+            | MidiMsg::ChannelPressure { channel, .. }
+            | MidiMsg::PitchBend { channel, .. } => channel,
+        }
+    }
+
+    /// Returns the message's bytes and how many of them are used.
+    pub fn encode(&self) -> ([u8; 3], usize) {
+        match *self {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => ([0x90 | channel, note, velocity], 3),
+            MidiMsg::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => ([0x80 | channel, note, velocity], 3),
+            MidiMsg::ControlChange {
+                channel,
+                controller,
+                value,
+            } => ([0xb0 | channel, controller, value], 3),
+            MidiMsg::ProgramChange { channel, program } => ([0xc0 | channel, program, 0], 2),
+            MidiMsg::ChannelPressure { channel, value } => ([0xd0 | channel, value, 0], 2),
+            MidiMsg::PitchBend { channel, value } => (
+                [0xe0 | channel, (value & 0x7f) as u8, (value >> 7) as u8],
+                3,
+            ),
+        }
+    }
+
+    /// Reads a message from its bytes, the reverse of [`encode`](Self::encode). Anything this
+    /// enum has no variant for gives `None`.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0f;
+        let data = |index: usize| data.get(index).copied().filter(|byte| *byte < 0x80);
+
+        Some(match status & 0xf0 {
+            0x80 => MidiMsg::NoteOff {
+                channel,
+                note: data(0)?,
+                velocity: data(1)?,
+            },
+            0x90 => MidiMsg::NoteOn {
+                channel,
+                note: data(0)?,
+                velocity: data(1)?,
+            },
+            0xb0 => MidiMsg::ControlChange {
+                channel,
+                controller: data(0)?,
+                value: data(1)?,
+            },
+            0xc0 => MidiMsg::ProgramChange {
+                channel,
+                program: data(0)?,
+            },
+            0xd0 => MidiMsg::ChannelPressure {
+                channel,
+                value: data(0)?,
+            },
+            0xe0 => MidiMsg::PitchBend {
+                channel,
+                value: (data(1)? as u16) << 7 | data(0)? as u16,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Leaves out status bytes that are the same as the previous message's, which receivers on a
+/// byte stream fill in themselves. Real-time messages don't count as they don't change the
+/// running status.
+#[derive(Debug, Default)]
+pub struct RunningStatus {
+    last: Option<u8>,
+}
+
+impl RunningStatus {
+    /// Forgets the running status, so the next message is sent in full.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    pub fn encode<'a>(&mut self, bytes: &'a [u8]) -> &'a [u8] {
+        match bytes.first() {
+            Some(&status) if status >= 0xf8 => bytes,
+            Some(&status) if self.last == Some(status) => &bytes[1..],
+            Some(&status) => {
+                // System common messages cancel the running status
+                self.last = (status < 0xf0).then_some(status);
+                bytes
+            }
+            None => bytes,
+        }
+    }
+}
+
+/// The frequency of `note` in Hz, in equal temperament with A4 at 440 Hz.
+pub fn frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+/// Appends `value` as a variable length number, as in delta times, capped at the largest one
+/// that fits in four bytes.
+pub fn push_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let value = value.min(0x0fff_ffff);
+    for shift in [21, 14, 7] {
+        if value >> shift != 0 {
+            bytes.push(0x80 | (value >> shift) as u8 & 0x7f);
+        }
+    }
+    bytes.push(value as u8 & 0x7f);
+}
@@ -0,0 +1,91 @@
+//! The MIDI monitor, enabled with `--monitor`: messages arriving on the MIDI input port are
+//! listed in the window as text. With `show_input`, the notes among them are also lit on the
+//! keyboard of the fullscreen view.
+
+use crate::midi::{NoteNames, CC_ALL_NOTES_OFF};
+
+/// How many messages the window keeps to show.
+pub const LINES: usize = 64;
+
+/// A message from the input port, cut to its first bytes so the process callback can pass
+/// it on without allocating.
+#[derive(Debug, Clone, Copy)]
+pub struct Incoming {
+    bytes: [u8; 3],
+    /// The length of the whole message, which is longer than `bytes` for system exclusive.
+    len: usize,
+}
+
+impl Incoming {
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut incoming = Incoming {
+            bytes: [0; 3],
+            len: bytes.len(),
+        };
+        let kept = bytes.len().min(3);
+        incoming.bytes[..kept].copy_from_slice(&bytes[..kept]);
+        incoming
+    }
+
+    /// The channel and note of a note on or off, and whether it is a note on.
+    pub fn note(&self) -> Option<(u8, u8, bool)> {
+        let [status, note, velocity] = self.bytes;
+        let channel = status & 0x0f;
+        match status & 0xf0 {
+            0x80 => Some((channel, note & 0x7f, false)),
+            0x90 => Some((channel, note & 0x7f, velocity > 0)),
+            _ => None,
+        }
+    }
+
+    /// The channel of an All Notes Off.
+    pub fn all_notes_off(&self) -> Option<u8> {
+        let [status, controller, _] = self.bytes;
+        (status & 0xf0 == 0xb0 && controller == CC_ALL_NOTES_OFF).then_some(status & 0x0f)
+    }
+
+    /// The channel, controller and value of a control change.
+    pub fn control_change(&self) -> Option<(u8, u8, u8)> {
+        let [status, controller, value] = self.bytes;
+        (status & 0xf0 == 0xb0).then_some((status & 0x0f, controller & 0x7f, value & 0x7f))
+    }
+
+    /// The message as text, e.g. `Ch 2  Note on  C4  100`.
+    pub fn describe(&self, names: NoteNames) -> String {
+        let [status, data1, data2] = self.bytes;
+        let channel = (status & 0x0f) + 1;
+        let note = |note: u8| names.name(note & 0x7f);
+
+        let text = match status & 0xf0 {
+            0x80 => format!("Note off  {}  {}", note(data1), data2),
+            0x90 if data2 == 0 => format!("Note off  {}", note(data1)),
+            0x90 => format!("Note on  {}  {}", note(data1), data2),
+            0xa0 => format!("Aftertouch  {}  {}", note(data1), data2),
+            0xb0 => format!("CC {}  {}", data1, data2),
+            0xc0 => format!("Program  {}", data1),
+            0xd0 => format!("Pressure  {}", data1),
+            0xe0 => {
+                let bend = ((data2 as i32) << 7 | data1 as i32) - 0x2000;
+                format!("Pitch bend  {:+}", bend)
+            }
+            _ => {
+                return match status {
+                    0xf0 => format!("SysEx  {} bytes", self.len),
+                    0xf1 => format!("MTC quarter frame  {}", data1),
+                    0xf2 => format!("Song position  {}", (data2 as u16) << 7 | data1 as u16),
+                    0xf3 => format!("Song select  {}", data1),
+                    0xf6 => "Tune request".to_string(),
+                    0xf8 => "Clock".to_string(),
+                    0xfa => "Start".to_string(),
+                    0xfb => "Continue".to_string(),
+                    0xfc => "Stop".to_string(),
+                    0xfe => "Active sensing".to_string(),
+                    0xff => "Reset".to_string(),
+                    _ => format!("Unknown  {:02x}", status),
+                }
+            }
+        };
+
+        format!("Ch {}  {}", channel, text)
+    }
+}
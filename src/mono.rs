@@ -0,0 +1,213 @@
+//! Mono mode, where only the last of the held keys sounds.
+//!
+//! Playing a key while another is held is a legato transition: the new note starts before the
+//! old one stops, and releasing it goes back to the note that is still held. With `[slide]`,
+//! legato transitions within the bend range don't start a new note at all, but bend the one
+//! sounding over to the new pitch.
+
+use std::time::{Duration, Instant};
+
+use crate::midi::{MidiMsg, CC_PORTAMENTO, PITCH_BEND_CENTER, PITCH_BEND_MAX};
+
+/// How often the pitch bend is sent while sliding.
+const SLIDE_STEP: Duration = Duration::from_millis(5);
+
+/// Slides between legato notes with pitch bend, see [`Mono`].
+#[derive(Debug, Clone, Copy)]
+pub struct Slide {
+    /// How long a slide takes.
+    pub time: Duration,
+    /// How far the synth bends at full pitch bend, in semitones.
+    pub bend_range: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mono {
+    /// Held notes with their velocity, most recent last. Only the last one sounds.
+    held: Vec<(u8, u8)>,
+    /// Turn portamento on for legato transitions and off for other notes.
+    auto_portamento: bool,
+    slide: Option<Slide>,
+    /// The note actually playing and its channel, which slides bend towards the last held one.
+    sounding: Option<(u8, u8)>,
+    /// The bend the last slide started from and goes to, in semitones.
+    bend: (f64, f64),
+    /// When the last slide started.
+    started: Instant,
+    /// When its pitch bend was last sent.
+    stepped: Instant,
+}
+
+impl Mono {
+    pub fn new(auto_portamento: bool, slide: Option<Slide>) -> Self {
+        let now = Instant::now();
+        Mono {
+            held: Vec::new(),
+            auto_portamento,
+            slide,
+            sounding: None,
+            bend: (0.0, 0.0),
+            started: now,
+            stepped: now,
+        }
+    }
+
+    /// Turns a note on or off from the keyboard into what should actually be sent.
+    pub fn handle(&mut self, midi: MidiMsg) -> Vec<MidiMsg> {
+        let mut messages = Vec::new();
+
+        match midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => {
+                let legato = !self.held.is_empty();
+                self.held.retain(|&(n, _)| n != note);
+                self.held.push((note, velocity));
+
+                if legato && self.slide_to(&mut messages, note) {
+                    return messages;
+                }
+                self.portamento(&mut messages, channel, legato);
+                self.unbend(&mut messages);
+                messages.push(midi);
+                if let Some((sounding, channel)) = self.sounding.filter(|&(n, _)| n != note) {
+                    messages.push(MidiMsg::NoteOff {
+                        channel,
+                        note: sounding,
+                        velocity: 0,
+                    });
+                }
+                self.sounding = Some((note, channel));
+            }
+            MidiMsg::NoteOff { note, velocity, .. } => {
+                let last = self.held.last().is_some_and(|&(n, _)| n == note);
+                self.held.retain(|&(n, _)| n != note);
+                let Some((sounding, channel)) = self.sounding.filter(|_| last) else {
+                    return messages;
+                };
+
+                let note_off = MidiMsg::NoteOff {
+                    channel,
+                    note: sounding,
+                    velocity,
+                };
+                if let Some(&(previous, velocity)) = self.held.last() {
+                    if self.slide_to(&mut messages, previous) {
+                        return messages;
+                    }
+                    self.portamento(&mut messages, channel, true);
+                    self.unbend(&mut messages);
+                    messages.push(MidiMsg::NoteOn {
+                        channel,
+                        note: previous,
+                        velocity,
+                    });
+                    messages.push(note_off);
+                    self.sounding = Some((previous, channel));
+                } else {
+                    // Bent back only once it is let go of, so its release stays where it was
+                    messages.push(note_off);
+                    self.unbend(&mut messages);
+                    self.sounding = None;
+                }
+            }
+            _ => messages.push(midi),
+        }
+
+        messages
+    }
+
+    /// When the pitch bend of the slide going on should be sent next, if one is.
+    pub fn next_step(&self) -> Option<Instant> {
+        (self.bend.0 != self.bend.1).then_some(self.stepped + SLIDE_STEP)
+    }
+
+    /// The pitch bend of the slide going on, if it is time to send it again.
+    pub fn step(&mut self, now: Instant) -> Option<MidiMsg> {
+        let step = self.next_step()?;
+        if now < step {
+            return None;
+        }
+        self.stepped = now;
+        let pitch_bend = self.pitch_bend();
+        if self
+            .slide
+            .is_some_and(|slide| self.started.elapsed() >= slide.time)
+        {
+            // Done, with the last step right where it was going
+            self.bend.0 = self.bend.1;
+        }
+        pitch_bend
+    }
+
+    /// Starts bending the note sounding over to `note`, if sliding is on and it is in range.
+    fn slide_to(&mut self, messages: &mut Vec<MidiMsg>, note: u8) -> bool {
+        let (Some(slide), Some((sounding, _))) = (self.slide, self.sounding) else {
+            return false;
+        };
+        let to = note as f64 - sounding as f64;
+        if to.abs() > slide.bend_range as f64 {
+            return false;
+        }
+
+        let now = Instant::now();
+        self.bend = (self.bent(), to);
+        self.started = now;
+        self.stepped = now;
+        if slide.time.is_zero() {
+            self.bend.0 = to;
+            messages.extend(self.pitch_bend());
+        }
+        true
+    }
+
+    /// Takes the bend of the note sounding back to none, before another one starts.
+    fn unbend(&mut self, messages: &mut Vec<MidiMsg>) {
+        let bent = self.bent() != 0.0;
+        self.bend = (0.0, 0.0);
+        if bent {
+            messages.extend(self.pitch_bend());
+        }
+    }
+
+    /// How far the note sounding is bent now, in semitones.
+    fn bent(&self) -> f64 {
+        let (from, to) = self.bend;
+        let time = self.slide.map_or(Duration::ZERO, |slide| slide.time);
+        if time.is_zero() {
+            return to;
+        }
+        let along = (self.started.elapsed().as_secs_f64() / time.as_secs_f64()).min(1.0);
+        from + (to - from) * along
+    }
+
+    fn pitch_bend(&self) -> Option<MidiMsg> {
+        let (slide, (_, channel)) = (self.slide?, self.sounding?);
+        let bend = self.bent() / slide.bend_range.max(1) as f64;
+        let value = PITCH_BEND_CENTER as f64 + bend * PITCH_BEND_CENTER as f64;
+        Some(MidiMsg::PitchBend {
+            channel,
+            value: (value.round() as u16).min(PITCH_BEND_MAX),
+        })
+    }
+
+    fn portamento(&self, messages: &mut Vec<MidiMsg>, channel: u8, legato: bool) {
+        if self.auto_portamento {
+            messages.push(MidiMsg::ControlChange {
+                channel,
+                controller: CC_PORTAMENTO,
+                value: if legato { 127 } else { 0 },
+            });
+        }
+    }
+
+    /// Forgets the notes held and the slide going on, once the notes have been stopped some
+    /// other way.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.sounding = None;
+        self.bend = (0.0, 0.0);
+    }
+}
@@ -0,0 +1,66 @@
+//! Morphing between the controllers of two presets, configured with `[morph]`: holding
+//! `morph_up` or `morph_down` moves from one preset's controller values to the other's,
+//! sending every value in between as it goes.
+
+use std::time::Duration;
+
+use crate::{config::ControllerMap, midi::MidiMsg};
+
+#[derive(Debug, Clone)]
+pub struct Morph {
+    /// The controllers set by both presets, with their value in each.
+    controllers: Vec<((u8, u8), u8, u8)>,
+    /// How long going all the way from one preset to the other takes.
+    duration: Duration,
+    /// How far along from the first preset to the second, from 0 to 1.
+    position: f64,
+    /// The last value sent for each of `controllers`.
+    sent: Vec<Option<u8>>,
+}
+
+impl Morph {
+    pub fn new(from: &ControllerMap, to: &ControllerMap, duration: Duration) -> Self {
+        let controllers: Vec<_> = from
+            .iter()
+            .filter_map(|(&key, &from)| Some((key, from, *to.get(&key)?)))
+            .collect();
+
+        Morph {
+            sent: vec![None; controllers.len()],
+            controllers,
+            duration,
+            position: 0.0,
+        }
+    }
+
+    /// How far along from the first preset to the second, from 0 to 1.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Moves towards the second preset (or the first if `up` is false) for `elapsed`, and
+    /// returns the control changes for the values that changed.
+    pub fn ramp(&mut self, up: bool, elapsed: Duration) -> Vec<MidiMsg> {
+        let step = elapsed.as_secs_f64() / self.duration.as_secs_f64().max(f64::EPSILON);
+        let step = if up { step } else { -step };
+        self.position = (self.position + step).clamp(0.0, 1.0);
+
+        let mut messages = Vec::new();
+        for (&((channel, controller), from, to), sent) in
+            self.controllers.iter().zip(&mut self.sent)
+        {
+            let value = from as f64 + (to as f64 - from as f64) * self.position;
+            let value = value.round() as u8;
+            if *sent != Some(value) {
+                *sent = Some(value);
+                messages.push(MidiMsg::ControlChange {
+                    channel,
+                    controller,
+                    value,
+                });
+            }
+        }
+
+        messages
+    }
+}
@@ -0,0 +1,162 @@
+//! MIDI Time Code with `--mtc`, following the JACK transport on a port of its own, for hardware
+//! recorders and lighting consoles that sync to MTC rather than MIDI clock. Quarter frames are
+//! sent while the transport rolls, and a full frame message whenever it is moved.
+
+use jack::Frames;
+
+/// The status byte of a quarter frame message.
+const QUARTER_FRAME: u8 = 0xf1;
+
+/// The frame rates of MIDI Time Code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rate {
+    Fps24,
+    Fps25,
+    /// 29.97 frames a second, counted as 30 with frame numbers dropped to keep up with the clock.
+    Fps30Drop,
+    Fps30,
+}
+
+impl Rate {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "24" => Some(Rate::Fps24),
+            "25" => Some(Rate::Fps25),
+            "29.97" => Some(Rate::Fps30Drop),
+            "30" => Some(Rate::Fps30),
+            _ => None,
+        }
+    }
+
+    /// Frames a second, as they pass.
+    fn fps(self) -> f64 {
+        match self {
+            Rate::Fps24 => 24.0,
+            Rate::Fps25 => 25.0,
+            Rate::Fps30Drop => 30000.0 / 1001.0,
+            Rate::Fps30 => 30.0,
+        }
+    }
+
+    /// Frames a second, as they are numbered.
+    fn nominal(self) -> u64 {
+        match self {
+            Rate::Fps24 => 24,
+            Rate::Fps25 => 25,
+            Rate::Fps30Drop | Rate::Fps30 => 30,
+        }
+    }
+
+    /// How the rate is given in the hours of a time code.
+    fn code(self) -> u8 {
+        match self {
+            Rate::Fps24 => 0,
+            Rate::Fps25 => 1,
+            Rate::Fps30Drop => 2,
+            Rate::Fps30 => 3,
+        }
+    }
+
+    /// The hours, minutes, seconds and frame of the `frame`th frame from zero, wrapping around
+    /// after 24 hours.
+    fn timecode(self, frame: u64) -> (u8, u8, u8, u8) {
+        let frame = match self {
+            // Frames 0 and 1 are skipped every minute, except every tenth
+            Rate::Fps30Drop => {
+                let (tens, rest) = (frame / 17982, frame % 17982);
+                frame + 18 * tens + 2 * (rest.saturating_sub(2) / 1798)
+            }
+            _ => frame,
+        };
+        let fps = self.nominal();
+
+        (
+            (frame / (fps * 3600) % 24) as u8,
+            (frame / (fps * 60) % 60) as u8,
+            (frame / fps % 60) as u8,
+            (frame % fps) as u8,
+        )
+    }
+
+    /// The data byte of the `quarter`th quarter frame from zero. Every eight of them spell out
+    /// the time code of the frame the first was sent on, two frames back by the time the last is.
+    fn quarter_frame(self, quarter: u64) -> u8 {
+        let piece = (quarter % 8) as u8;
+        let (hours, minutes, seconds, frame) = self.timecode(quarter / 8 * 2);
+        let nibble = match piece {
+            0 => frame & 0xf,
+            1 => frame >> 4,
+            2 => seconds & 0xf,
+            3 => seconds >> 4,
+            4 => minutes & 0xf,
+            5 => minutes >> 4,
+            6 => hours & 0xf,
+            _ => hours >> 4 | self.code() << 1,
+        };
+        piece << 4 | nibble
+    }
+}
+
+pub struct Mtc {
+    rate: Rate,
+    /// Where the transport will be at the start of the next cycle unless it is moved.
+    expected: Option<Frames>,
+}
+
+impl Mtc {
+    pub fn new(rate: Rate) -> Self {
+        Mtc {
+            rate,
+            expected: None,
+        }
+    }
+
+    /// Writes the time code for a cycle of `n_frames` starting at transport frame `frame`, with
+    /// `frame_rate` transport frames a second.
+    pub fn cycle(
+        &mut self,
+        rolling: bool,
+        frame: Frames,
+        frame_rate: Frames,
+        n_frames: Frames,
+        mut write: impl FnMut(Frames, &[u8]),
+    ) {
+        let moved = self.expected != Some(frame);
+        self.expected = Some(if rolling {
+            frame.wrapping_add(n_frames)
+        } else {
+            frame
+        });
+        let per_quarter = frame_rate as f64 / (self.rate.fps() * 4.0);
+
+        if moved {
+            // Receivers locate to it straight away, rather than wait for eight quarter frames
+            let at = (frame as f64 / per_quarter / 4.0) as u64;
+            let (hours, minutes, seconds, frame) = self.rate.timecode(at);
+            let hours = self.rate.code() << 5 | hours;
+            write(
+                0,
+                &[
+                    0xf0, 0x7f, 0x7f, 0x01, 0x01, hours, minutes, seconds, frame, 0xf7,
+                ],
+            );
+        }
+        if !rolling {
+            return;
+        }
+
+        let end = frame as u64 + n_frames as u64;
+        let mut quarter = (frame as f64 / per_quarter).ceil() as u64;
+        loop {
+            let at = (quarter as f64 * per_quarter).round() as u64;
+            if at >= end {
+                break;
+            }
+            write(
+                (at - frame as u64) as Frames,
+                &[QUARTER_FRAME, self.rate.quarter_frame(quarter)],
+            );
+            quarter += 1;
+        }
+    }
+}
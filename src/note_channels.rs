@@ -0,0 +1,126 @@
+//! Note channels, where each held note is played on a channel of its own so that whatever is
+//! sent on that channel only changes that note, like a simple form of MPE.
+//!
+//! Each note can also be panned (CC10) by its pitch, spreading the keyboard across the stereo
+//! image on a multitimbral synth. In round robin, notes go to the channels in turn instead,
+//! for layering several mono synths or a sampler's round robins.
+
+use crate::midi::{MidiMsg, CC_PAN};
+
+/// Pitches this far either side of middle C are panned all the way at full spread.
+const PAN_SEMITONES: i32 = 24;
+
+#[derive(Debug, Clone)]
+pub struct NoteChannels {
+    numbers: Vec<u8>,
+    /// The note held on each channel of `numbers`, with when the channel was last given a note.
+    channels: Vec<(Option<u8>, u64)>,
+    /// Whether new notes go to the channels in turn, see [`NoteChannels::handle`].
+    round_robin: bool,
+    /// How far notes are panned from the centre, in percent, or 0 to leave the pan alone.
+    pan_spread: u8,
+    /// Counts the notes played, to tell which one is the oldest.
+    played: u64,
+}
+
+impl NoteChannels {
+    /// Plays notes on `numbers`, the zero-based channels in the order they are used.
+    pub fn new(numbers: Vec<u8>, round_robin: bool, pan_spread: u8) -> Self {
+        NoteChannels {
+            channels: vec![(None, 0); numbers.len()],
+            numbers,
+            round_robin,
+            pan_spread,
+            played: 0,
+        }
+    }
+
+    /// Moves a note on or off to its channel. New notes go to the channel that has been free
+    /// the longest, so a note's release isn't cut short, and with every channel in use take
+    /// the channel of the oldest note, which is stopped first. In round robin, they go to the
+    /// channel after the one given the last note, stopping whatever it holds.
+    pub fn handle(&mut self, midi: MidiMsg) -> Vec<MidiMsg> {
+        let mut messages = Vec::new();
+
+        match midi {
+            MidiMsg::NoteOn { note, velocity, .. } => {
+                self.played += 1;
+                let index = match self.index_of(note) {
+                    Some(index) => index,
+                    None => self.free_index(),
+                };
+                let channel = self.numbers[index];
+                if let (Some(previous), _) = self.channels[index] {
+                    messages.push(MidiMsg::NoteOff {
+                        channel,
+                        note: previous,
+                        velocity: 0,
+                    });
+                }
+                self.channels[index] = (Some(note), self.played);
+
+                if self.pan_spread > 0 {
+                    messages.push(MidiMsg::ControlChange {
+                        channel,
+                        controller: CC_PAN,
+                        value: self.pan(note),
+                    });
+                }
+                messages.push(MidiMsg::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                });
+            }
+            MidiMsg::NoteOff { note, velocity, .. } => {
+                if let Some(index) = self.index_of(note) {
+                    self.channels[index].0 = None;
+                    messages.push(MidiMsg::NoteOff {
+                        channel: self.numbers[index],
+                        note,
+                        velocity,
+                    });
+                }
+            }
+            _ => messages.push(midi),
+        }
+
+        messages
+    }
+
+    fn index_of(&self, note: u8) -> Option<usize> {
+        self.channels
+            .iter()
+            .position(|(held, _)| *held == Some(note))
+    }
+
+    fn free_index(&self) -> usize {
+        if self.round_robin {
+            let last = self
+                .channels
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, played))| *played);
+            return last.map_or(0, |(index, _)| (index + 1) % self.channels.len());
+        }
+
+        self.channels
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (held, played))| (held.is_some(), *played))
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn pan(&self, note: u8) -> u8 {
+        let offset = (note as i32 - 60) * 63 * self.pan_spread as i32 / (PAN_SEMITONES * 100);
+        (64 + offset).clamp(0, 127) as u8
+    }
+
+    /// Forgets the notes held on every channel, once they have been stopped some other way.
+    /// Round robin goes on from where it was.
+    pub fn clear(&mut self) {
+        for (note, _) in &mut self.channels {
+            *note = None;
+        }
+    }
+}
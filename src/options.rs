@@ -0,0 +1,396 @@
+use std::{env, path::PathBuf, process};
+
+use crate::{
+    keymap, level::Target, midi::DEFAULT_CHANNEL, mtc, protocol, rawmidi, synth::Waveform, ump,
+};
+
+const USAGE: &str = "\
+Usage: jack_keyboard [OPTIONS]
+
+Options:
+    --active-sensing        Send Active Sensing whenever nothing else was sent for 270 ms,
+                            for hardware that silences itself when its input goes quiet
+    --audio-in <TARGET>     Follow the level of an extra audio input port with note velocity
+                            or expression (TARGET is velocity or expression)
+    --autosave <DIR>        Write everything played to a new MIDI file in DIR, kept
+                            complete after every message so nothing is lost
+    --autosave-thin <MS>    Keep at most one control change or pitch bend every MS
+                            milliseconds of each controller in the --autosave file
+    --channel <N>           Play the note keys on channel N (1-16)
+    --check                 Check the config, the --import-keymap file if given and that
+                            the JACK server is running, list its MIDI ports and exit with
+                            status 1 if anything failed, for session startup scripts
+    --check-config          Check the config for errors, like a key given two things to
+                            do, and exit without starting
+    --config <FILE>         Read the config from FILE instead of
+                            $XDG_CONFIG_HOME/jack_keyboard/config.toml
+    --daemon                Run without a window, played only by --stdin, --websocket,
+                            --dbus and --single-instance, until SIGTERM or SIGINT stops
+                            every note and leaves the JACK session
+    --dbus                  Take commands like SetOctave and Panic over D-Bus on the session
+                            bus, as io.github.jakobrs.JackKeyboard
+    --emit-json             Print every outgoing event as a line of JSON on stdout
+    --extra-client          Run --synth and --monitor in a second JACK client,
+                            jack_keyboard_extra, so they don't share the keyboard's process
+                            callback
+    --export-keymap <FORMAT>
+                            Print the keymap in use and exit, as toml (the [notes] table of
+                            the config), json or chart (a drawing of the keyboard)
+    --heat-map              Show how often each note was played in the window, from dim to
+                            bright
+    --heat-map-csv <FILE>   Write how often each key was pressed to FILE as CSV on exit
+    --high-res-velocity     Send the finer part of the velocity from --audio-in velocity as
+                            a CC88 before every note on, for synths with 14-bit velocity
+    --import-keymap <FILE>  Print a VMPK keymap as the [notes] table of the config and exit
+    --learn-keymap          Open a window that prints every key pressed, and on closing it
+                            a [notes] table with the keys in the order pressed playing
+                            ascending notes from C4. Nothing is played.
+    --key-source <SOURCE>   Where the keys are read from: window (the default) or device,
+                            the raw key events, which skip the compositor and never repeat
+                            but are only read while the window has the focus
+    --latency-offset <MS>   Shift outgoing events by MS milliseconds (may be negative)
+                            to line up with latency further down the chain
+    --log <FILE>            Add a timestamped line to FILE for every start and stop, preset
+                            change, port connected or disconnected, error and dropped event
+    --monitor               List the messages arriving on the MIDI input port in the
+                            window
+    --mtc <FPS>             Send MIDI Time Code following the JACK transport on an mtc
+                            port, at FPS 24, 25, 29.97 (drop frame) or 30
+    --octave <N>            Move the note keys N octaves up or down, from -4 to 4
+    --panic                 Stop every note on every channel, for --single-instance
+    --preset <N>            Select preset N, counted from 1
+    --print-default-config  Print a config with every setting explained and commented out,
+                            to start one from, and exit
+    --rawmidi <DEVICE>      Write to an ALSA rawmidi device (e.g. hw:1,0) instead of JACK,
+                            so no JACK server is needed
+    --report-latency        Tell JACK how late played events are after the key, for hosts
+                            that compensate for latency
+    --rtpmidi <SESSION>     Play on a network MIDI session instead of JACK, either one
+                            found by name or the HOST:PORT of its control port
+    --running-status        Leave out status bytes that repeat the previous one. Only for
+                            outputs that pass bytes on as they are, like a raw MIDI bridge
+    --script <FILE>         Hand the note keys to the executable FILE, which plays the lines
+                            it prints like --stdin. Started again whenever FILE changes.
+    --single-instance       Hand --channel, --octave, --panic and --preset to the instance
+                            already running, if there is one, and exit
+    --stats                 Print how long each cycle takes, how many events go out and
+                            how long they wait, once a second on stderr
+    --stress <N>            Play N random note ons and offs a second along with the keys,
+                            with --stats, to check everything keeps up under load
+    --stdin                 Play events read from stdin, one per line, either as JSON
+                            (like --emit-json) or as e.g. \"on 60 100\" or \"off 60\"
+    --synth <WAVE>          Play the notes on a built-in synth (WAVE is sine or square),
+                            on an extra audio output port
+    --timebase-master       Become the JACK timebase master, so other clients can follow
+                            the tempo in bars and beats
+    --ump <DEVICE>          Write MIDI 2.0 to an ALSA UMP device (e.g. hw:1,0) instead of
+                            JACK, with 16-bit velocity and the pan of note channels per note
+    --websocket <ADDR>      Accept remote control connections on ADDR (e.g. 0.0.0.0:8080);
+                            open it in a browser for a remote keyboard
+    -h, --help              Print this help and exit
+";
+
+/// The most random note events `--stress` plays a second.
+const MAX_STRESS: u32 = 100_000;
+
+/// Where the keys played are read from, see `--key-source`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeySource {
+    /// The key events of the window.
+    #[default]
+    Window,
+    /// The raw key events of the devices, while the window has the focus.
+    Device,
+}
+
+impl KeySource {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "window" => Some(KeySource::Window),
+            "device" => Some(KeySource::Device),
+            _ => None,
+        }
+    }
+}
+
+/// Command line options.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub active_sensing: bool,
+    /// What follows the level of the audio input, see `--audio-in`.
+    pub audio_in: Option<Target>,
+    /// Where to write a MIDI file of everything played, see `--autosave`.
+    pub autosave: Option<PathBuf>,
+    /// Milliseconds between the controller messages kept in the file, see `--autosave-thin`.
+    pub autosave_thin: Option<u64>,
+    pub check: bool,
+    pub check_config: bool,
+    /// Lines of the protocol for `--channel`, `--octave`, `--panic` and `--preset`, in order.
+    pub commands: Vec<String>,
+    pub config: Option<PathBuf>,
+    pub daemon: bool,
+    pub dbus: bool,
+    pub emit_json: bool,
+    pub extra_client: bool,
+    pub heat_map: bool,
+    /// Where to write the presses of each key on exit, see `--heat-map-csv`.
+    pub heat_map_csv: Option<PathBuf>,
+    pub high_res_velocity: bool,
+    /// How to print the keymap in use, see `--export-keymap`.
+    pub export_keymap: Option<keymap::Format>,
+    /// A VMPK keymap to print as config, see `--import-keymap`.
+    pub import_keymap: Option<PathBuf>,
+    pub key_source: KeySource,
+    pub learn_keymap: bool,
+    pub monitor: bool,
+    /// The frame rate of the MIDI Time Code to send, see `--mtc`.
+    pub mtc: Option<mtc::Rate>,
+    /// Milliseconds to shift every outgoing event by, see `--latency-offset`.
+    pub latency_offset: Option<f64>,
+    /// The session log to append to, see `--log`.
+    pub log: Option<PathBuf>,
+    pub print_default_config: bool,
+    /// The rawmidi device file to write to instead of JACK, see `--rawmidi`.
+    pub rawmidi: Option<PathBuf>,
+    pub report_latency: bool,
+    /// The network MIDI session to play on instead of JACK, see `--rtpmidi`.
+    pub rtpmidi: Option<String>,
+    pub running_status: bool,
+    /// The executable the note keys are handed to, see `--script`.
+    pub script: Option<PathBuf>,
+    pub single_instance: bool,
+    pub stats: bool,
+    /// How many random note events to play a second, see `--stress`.
+    pub stress: Option<u32>,
+    pub stdin: bool,
+    pub synth: Option<Waveform>,
+    pub timebase_master: bool,
+    /// The UMP device file to write MIDI 2.0 to instead of JACK, see `--ump`.
+    pub ump: Option<PathBuf>,
+    pub websocket: Option<String>,
+}
+
+impl Options {
+    /// Parses the process' arguments, printing usage and exiting on errors or `--help`.
+    pub fn from_env() -> Self {
+        match Self::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("jack_keyboard: {}\n\n{}", err, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Accept both `--option value` and `--option=value`
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => {
+                    (name.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match name.as_str() {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                }
+                "--active-sensing" => options.active_sensing = true,
+                "--audio-in" => {
+                    let value = value()?;
+                    let target = Target::from_name(&value)
+                        .ok_or_else(|| format!("unknown audio input target: {}", value))?;
+                    options.audio_in = Some(target);
+                }
+                "--autosave" => options.autosave = Some(PathBuf::from(value()?)),
+                "--autosave-thin" => {
+                    let value = value()?;
+                    let interval = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&interval| interval > 0)
+                        .ok_or_else(|| format!("invalid autosave thinning: {}", value))?;
+                    options.autosave_thin = Some(interval);
+                }
+                "--channel" | "--octave" | "--panic" | "--preset" => {
+                    let command = name.trim_start_matches("--");
+                    let line = match name.as_str() {
+                        "--panic" => command.to_string(),
+                        _ => format!("{} {}", command, value()?),
+                    };
+                    protocol::parse_line(&line, DEFAULT_CHANNEL)?;
+                    options.commands.push(line);
+                }
+                "--check" => options.check = true,
+                "--check-config" => options.check_config = true,
+                "--print-default-config" => options.print_default_config = true,
+                "--config" => options.config = Some(PathBuf::from(value()?)),
+                "--daemon" => options.daemon = true,
+                "--dbus" => options.dbus = true,
+                "--emit-json" => options.emit_json = true,
+                "--extra-client" => options.extra_client = true,
+                "--export-keymap" => {
+                    let value = value()?;
+                    let format = keymap::Format::from_name(&value)
+                        .ok_or_else(|| format!("unknown keymap format: {}", value))?;
+                    options.export_keymap = Some(format);
+                }
+                "--heat-map" => options.heat_map = true,
+                "--heat-map-csv" => options.heat_map_csv = Some(PathBuf::from(value()?)),
+                "--high-res-velocity" => options.high_res_velocity = true,
+                "--import-keymap" => options.import_keymap = Some(PathBuf::from(value()?)),
+                "--key-source" => {
+                    let value = value()?;
+                    options.key_source = KeySource::from_name(&value)
+                        .ok_or_else(|| format!("unknown key source: {}", value))?;
+                }
+                "--learn-keymap" => options.learn_keymap = true,
+                "--latency-offset" => {
+                    let value = value()?;
+                    let offset = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|offset| offset.is_finite())
+                        .ok_or_else(|| format!("invalid latency offset: {}", value))?;
+                    options.latency_offset = Some(offset);
+                }
+                "--log" => options.log = Some(PathBuf::from(value()?)),
+                "--monitor" => options.monitor = true,
+                "--mtc" => {
+                    let value = value()?;
+                    let rate = mtc::Rate::from_name(&value)
+                        .ok_or_else(|| format!("invalid MTC frame rate: {}", value))?;
+                    options.mtc = Some(rate);
+                }
+                "--rawmidi" => {
+                    let value = value()?;
+                    let path = rawmidi::device_path(&value)
+                        .ok_or_else(|| format!("invalid rawmidi device: {}", value))?;
+                    options.rawmidi = Some(path);
+                }
+                "--report-latency" => options.report_latency = true,
+                "--rtpmidi" => options.rtpmidi = Some(value()?),
+                "--running-status" => options.running_status = true,
+                "--script" => options.script = Some(PathBuf::from(value()?)),
+                "--single-instance" => options.single_instance = true,
+                "--stats" => options.stats = true,
+                "--stress" => {
+                    let value = value()?;
+                    let rate = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|rate| (1..=MAX_STRESS).contains(rate))
+                        .ok_or_else(|| format!("invalid stress rate: {}", value))?;
+                    options.stress = Some(rate);
+                    // What it is for is the numbers
+                    options.stats = true;
+                }
+                "--stdin" => options.stdin = true,
+                "--synth" => {
+                    let value = value()?;
+                    let waveform = Waveform::from_name(&value)
+                        .ok_or_else(|| format!("unknown waveform: {}", value))?;
+                    options.synth = Some(waveform);
+                }
+                "--timebase-master" => options.timebase_master = true,
+                "--ump" => {
+                    let value = value()?;
+                    let path = ump::device_path(&value)
+                        .ok_or_else(|| format!("invalid UMP device: {}", value))?;
+                    options.ump = Some(path);
+                }
+                "--websocket" => options.websocket = Some(value()?),
+                _ => return Err(format!("unknown option: {}", name)),
+            }
+        }
+
+        let backends: Vec<&str> = [
+            (options.rawmidi.is_some(), "--rawmidi"),
+            (options.rtpmidi.is_some(), "--rtpmidi"),
+            (options.ump.is_some(), "--ump"),
+        ]
+        .into_iter()
+        .filter_map(|(given, backend)| given.then_some(backend))
+        .collect();
+        if backends.len() > 1 {
+            return Err(format!("{} can't be used together", backends.join(" and ")));
+        }
+        let backend = backends.first().copied();
+        if let Some(backend) = backend {
+            // These only make sense with a JACK server
+            if options.synth.is_some() {
+                return Err(format!("--synth can't be used with {}", backend));
+            }
+            if options.audio_in.is_some() {
+                return Err(format!("--audio-in can't be used with {}", backend));
+            }
+            if options.latency_offset.is_some() {
+                return Err(format!("--latency-offset can't be used with {}", backend));
+            }
+            if options.timebase_master {
+                return Err(format!("--timebase-master can't be used with {}", backend));
+            }
+            if options.monitor {
+                return Err(format!("--monitor can't be used with {}", backend));
+            }
+            if options.mtc.is_some() {
+                return Err(format!("--mtc can't be used with {}", backend));
+            }
+            if options.report_latency {
+                return Err(format!("--report-latency can't be used with {}", backend));
+            }
+            if options.extra_client {
+                return Err(format!("--extra-client can't be used with {}", backend));
+            }
+        }
+        if options.daemon {
+            // These need the window
+            if options.monitor {
+                return Err("--monitor can't be used with --daemon".to_string());
+            }
+            if options.heat_map {
+                return Err("--heat-map can't be used with --daemon".to_string());
+            }
+            if options.heat_map_csv.is_some() {
+                return Err("--heat-map-csv can't be used with --daemon".to_string());
+            }
+            if options.script.is_some() {
+                return Err("--script can't be used with --daemon".to_string());
+            }
+            if options.learn_keymap {
+                return Err("--learn-keymap can't be used with --daemon".to_string());
+            }
+            if options.key_source != KeySource::Window {
+                return Err("--key-source can't be used with --daemon".to_string());
+            }
+        }
+        if options.autosave_thin.is_some() && options.autosave.is_none() {
+            return Err("--autosave-thin needs --autosave".to_string());
+        }
+        // The level of the audio input is the only velocity finer than 7 bits
+        if options.high_res_velocity && options.audio_in != Some(Target::Velocity) {
+            return Err("--high-res-velocity needs --audio-in velocity".to_string());
+        }
+        // The first message of every packet needs its status byte
+        if options.rtpmidi.is_some() && options.running_status {
+            return Err("--running-status can't be used with --rtpmidi".to_string());
+        }
+        // Packets are translated from whole messages
+        if options.ump.is_some() && options.running_status {
+            return Err("--running-status can't be used with --ump".to_string());
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,95 @@
+//! Where the events written to the MIDI output go besides it. Everything that wants to see
+//! them, like `--emit-json`, the WebSocket server or `--autosave`, is an [`OutputSink`]
+//! registered with [`Outputs`], and gets each event on a thread away from the process callback.
+//! Any number of sinks can be active at once, whatever the output is, and `[sinks]` in the
+//! config filters what each of them gets.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use crate::{json, midi::MidiMsg, thru::Kind, KeyboardMsg};
+
+pub trait OutputSink: Send {
+    /// What the sink is called in `[sinks]` in the config.
+    fn name(&self) -> &'static str;
+
+    /// Takes `midi`, written at JACK time `time`.
+    fn write(&mut self, midi: &MidiMsg, time: jack::Time);
+}
+
+/// Which events a sink gets, configured in `[sinks]` by the name of the sink.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// The channels passed on, zero-based, or empty for all of them.
+    pub channels: Vec<u8>,
+    /// The kinds of message passed on, or empty for all of them.
+    pub kinds: Vec<Kind>,
+}
+
+impl Filter {
+    fn passes(&self, midi: &MidiMsg) -> bool {
+        (self.channels.is_empty() || self.channels.contains(&midi.channel()))
+            && (self.kinds.is_empty() || self.kinds.contains(&Kind::of(midi)))
+    }
+}
+
+#[derive(Default)]
+pub struct Outputs {
+    sinks: Vec<(Box<dyn OutputSink>, Filter)>,
+}
+
+impl Outputs {
+    /// Adds `sink`, which gets the events its filter in `filters` passes, or all of them.
+    pub fn register(&mut self, sink: impl OutputSink + 'static, filters: &HashMap<String, Filter>) {
+        let filter = filters.get(sink.name()).cloned().unwrap_or_default();
+        self.sinks.push((Box::new(sink), filter));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// The names in `filters` that no sink registered has.
+    pub fn unknown<'a>(&self, filters: &'a HashMap<String, Filter>) -> Vec<&'a str> {
+        let names = filters.keys().map(String::as_str);
+        names
+            .filter(|&name| !self.sinks.iter().any(|(sink, _)| sink.name() == name))
+            .collect()
+    }
+
+    /// Hands every event sent to the returned channel to each sink its filter passes, on a
+    /// separate thread.
+    pub fn start(mut self) -> Sender<KeyboardMsg> {
+        let (tx, rx) = mpsc::channel::<KeyboardMsg>();
+
+        thread::spawn(move || {
+            for msg in rx {
+                for (sink, filter) in &mut self.sinks {
+                    if filter.passes(&msg.midi) {
+                        sink.write(&msg.midi, msg.time);
+                    }
+                }
+            }
+        });
+
+        tx
+    }
+}
+
+/// Every event printed as a line of JSON on stdout, with `--emit-json`.
+pub struct Json;
+
+impl OutputSink for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn write(&mut self, midi: &MidiMsg, time: jack::Time) {
+        // Nothing to be done if whoever was reading has gone away
+        let _ = writeln!(io::stdout(), "{}", json::event_line(midi, time));
+    }
+}
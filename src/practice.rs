@@ -0,0 +1,81 @@
+//! Practice mode, configured with `[practice]` and turned on and off with `practice`: the
+//! sequences are prompted one note at a time, on the keyboard of the fullscreen view and in the
+//! status, and every note played counts as right or wrong, with how long the right ones took.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Practice {
+    sequences: Vec<Vec<u8>>,
+    sequence: usize,
+    /// How many notes of the sequence have been played.
+    step: usize,
+    /// When the note prompted now was first prompted.
+    prompted: Instant,
+    right: u32,
+    wrong: u32,
+    /// How long the right notes took, all together.
+    took: Duration,
+}
+
+impl Practice {
+    pub fn new(sequences: Vec<Vec<u8>>, now: Instant) -> Self {
+        Practice {
+            sequences,
+            sequence: 0,
+            step: 0,
+            prompted: now,
+            right: 0,
+            wrong: 0,
+            took: Duration::ZERO,
+        }
+    }
+
+    /// The note to play next.
+    pub fn prompt(&self) -> u8 {
+        self.sequences[self.sequence][self.step]
+    }
+
+    /// Counts a note played, moving on to the next one if it was the note prompted.
+    pub fn note(&mut self, note: u8, now: Instant) {
+        if note != self.prompt() {
+            self.wrong += 1;
+            return;
+        }
+
+        self.right += 1;
+        self.took += now.saturating_duration_since(self.prompted);
+        self.prompted = now;
+        self.step += 1;
+        if self.step == self.sequences[self.sequence].len() {
+            self.step = 0;
+            self.sequence = (self.sequence + 1) % self.sequences.len();
+        }
+    }
+
+    /// The sequence with the note prompted in brackets, and the score, e.g.
+    /// `C4 [E4] G4   5/6 right 0.84 s`.
+    pub fn status(&self, name: impl Fn(u8) -> String) -> String {
+        let notes: Vec<_> = self.sequences[self.sequence]
+            .iter()
+            .enumerate()
+            .map(|(step, &note)| match step == self.step {
+                true => format!("[{}]", name(note)),
+                false => name(note),
+            })
+            .collect();
+        let played = self.right + self.wrong;
+        let average = match self.right {
+            0 => 0.0,
+            right => self.took.as_secs_f64() / right as f64,
+        };
+
+        format!(
+            "{}   {}/{} right {:.2} s",
+            notes.join(" "),
+            self.right,
+            played,
+            average
+        )
+    }
+}
@@ -0,0 +1,75 @@
+//! A pressure cluster, configured with `[pressure]`: a group of keys that works like a fader,
+//! sending more channel pressure (or mod wheel) the more of them are held.
+
+use winit::event::ScanCode;
+
+use crate::midi::{MidiMsg, CC_MOD_WHEEL};
+
+/// What the pressure cluster controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Channel pressure, aftertouch for the whole channel.
+    Aftertouch,
+    /// The mod wheel (CC1).
+    ModWheel,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "aftertouch" => Some(Target::Aftertouch),
+            "mod_wheel" => Some(Target::ModWheel),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pressure {
+    keys: Vec<ScanCode>,
+    target: Target,
+    channel: u8,
+    held: Vec<ScanCode>,
+}
+
+impl Pressure {
+    pub fn new(keys: Vec<ScanCode>, target: Target, channel: u8) -> Self {
+        Pressure {
+            keys,
+            target,
+            channel,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, scancode: ScanCode) -> bool {
+        self.keys.contains(&scancode)
+    }
+
+    /// Notes a key of the cluster being pressed or released, and returns the message for the
+    /// new pressure if it changed: none for no keys held, and all of it for all of them.
+    pub fn handle(&mut self, scancode: ScanCode, pressed: bool) -> Option<MidiMsg> {
+        let was_held = self.held.len();
+        if pressed {
+            if !self.held.contains(&scancode) {
+                self.held.push(scancode);
+            }
+        } else {
+            self.held.retain(|&key| key != scancode);
+        }
+        if self.held.len() == was_held {
+            return None;
+        }
+
+        let value = (self.held.len() * 127 / self.keys.len().max(1)) as u8;
+        let channel = self.channel;
+        Some(match self.target {
+            Target::Aftertouch => MidiMsg::ChannelPressure { channel, value },
+            Target::ModWheel => MidiMsg::ControlChange {
+                channel,
+                controller: CC_MOD_WHEEL,
+                value,
+            },
+        })
+    }
+}
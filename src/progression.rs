@@ -0,0 +1,222 @@
+//! Chord progressions, configured with `[progression]` and moved through with
+//! `progression_next`. The note keys can be locked to the current chord, playing the nearest
+//! note of its scale or of the chord itself, and a diatonic harmonizer and generative mode move
+//! along with it.
+
+use crate::{
+    midi::{MidiMsg, NoteNames},
+    scale::Scale,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Major,
+    Minor,
+    Dominant7,
+    Major7,
+    Minor7,
+    Diminished,
+}
+
+impl Quality {
+    const ALL: [Quality; 6] = [
+        Quality::Major,
+        Quality::Minor,
+        Quality::Dominant7,
+        Quality::Major7,
+        Quality::Minor7,
+        Quality::Diminished,
+    ];
+
+    /// What comes after the root in the name of a chord, e.g. `m7` in `Am7`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Quality::Major => "",
+            Quality::Minor => "m",
+            Quality::Dominant7 => "7",
+            Quality::Major7 => "maj7",
+            Quality::Minor7 => "m7",
+            Quality::Diminished => "dim",
+        }
+    }
+
+    /// Semitones above the root of each note of the chord.
+    fn tones(self) -> &'static [u8] {
+        match self {
+            Quality::Major => &[0, 4, 7],
+            Quality::Minor => &[0, 3, 7],
+            Quality::Dominant7 => &[0, 4, 7, 10],
+            Quality::Major7 => &[0, 4, 7, 11],
+            Quality::Minor7 => &[0, 3, 7, 10],
+            Quality::Diminished => &[0, 3, 6],
+        }
+    }
+
+    /// The scale played over the chord, from its root.
+    pub fn scale(self) -> Scale {
+        match self {
+            Quality::Major | Quality::Major7 => Scale::Major,
+            Quality::Minor => Scale::Minor,
+            Quality::Dominant7 => Scale::Mixolydian,
+            Quality::Minor7 => Scale::Dorian,
+            Quality::Diminished => Scale::Locrian,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    /// A pitch class, from 0 for C to 11.
+    pub root: u8,
+    pub quality: Quality,
+}
+
+impl Chord {
+    /// Parses a chord name like `C`, `F#m`, `Bb7`, `Ebmaj7`, `Dm7` or `Bdim`, with the root
+    /// named in English whatever `note_names` says.
+    pub fn parse(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        let letter = match chars.next()? {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let (root, suffix) = match rest.strip_prefix('#') {
+            Some(suffix) => (letter + 1, suffix),
+            None => match rest.strip_prefix('b') {
+                Some(suffix) => (letter + 11, suffix),
+                None => (letter, rest),
+            },
+        };
+        let quality = Quality::ALL
+            .into_iter()
+            .find(|quality| quality.suffix() == suffix)?;
+
+        Some(Chord {
+            root: root % 12,
+            quality,
+        })
+    }
+
+    pub fn name(self, names: NoteNames) -> String {
+        format!("{}{}", names.pitch_class(self.root), self.quality.suffix())
+    }
+}
+
+/// What the note keys are locked to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lock {
+    Off,
+    /// The nearest note of the chord's scale.
+    Scale,
+    /// The nearest note of the chord.
+    Chord,
+}
+
+impl Lock {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Lock::Off),
+            "scale" => Some(Lock::Scale),
+            "chord" => Some(Lock::Chord),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Progression {
+    chords: Vec<Chord>,
+    index: usize,
+    lock: Lock,
+    /// The notes held with the note they were played as, so each note off stops what was
+    /// played even if the chord has changed since.
+    held: Vec<(u8, u8)>,
+}
+
+impl Progression {
+    pub fn new(chords: Vec<Chord>, lock: Lock) -> Self {
+        Progression {
+            chords,
+            index: 0,
+            lock,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn chord(&self) -> Chord {
+        self.chords[self.index]
+    }
+
+    /// Moves on to the next chord, back to the first after the last, and returns it.
+    pub fn next(&mut self) -> Chord {
+        self.index = (self.index + 1) % self.chords.len();
+        self.chord()
+    }
+
+    /// Moves a note on or off to the nearest note the lock allows.
+    pub fn handle(&mut self, midi: MidiMsg) -> MidiMsg {
+        match midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => {
+                let played = self.lock_note(note);
+                self.held.push((note, played));
+                MidiMsg::NoteOn {
+                    channel,
+                    note: played,
+                    velocity,
+                }
+            }
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            }
+            | MidiMsg::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => match self.held.iter().position(|&(held, _)| held == note) {
+                Some(index) => {
+                    let (_, played) = self.held.remove(index);
+                    MidiMsg::NoteOff {
+                        channel,
+                        note: played,
+                        velocity,
+                    }
+                }
+                None => midi,
+            },
+            _ => midi,
+        }
+    }
+
+    fn lock_note(&self, note: u8) -> u8 {
+        let chord = self.chord();
+        match self.lock {
+            Lock::Off => note,
+            Lock::Scale => chord.quality.scale().snap(chord.root, note),
+            Lock::Chord => {
+                let in_chord = |note: i32| {
+                    let semitone = (note - chord.root as i32).rem_euclid(12) as u8;
+                    chord.quality.tones().contains(&semitone)
+                };
+                let note = note as i32;
+                let nearest = (0..=6)
+                    .flat_map(|distance| [note - distance, note + distance])
+                    .find(|&candidate| in_chord(candidate))
+                    .unwrap_or(note);
+                nearest.clamp(0, 127) as u8
+            }
+        }
+    }
+}
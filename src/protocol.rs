@@ -0,0 +1,165 @@
+//! The line protocol read by `--stdin`, the WebSocket server and from `--script`.
+//!
+//! A line is either a JSON event as written by `--emit-json`, or one of
+//!
+//! ```text
+//! on <note> [velocity] [channel]
+//! off <note> [velocity] [channel]
+//! cc <controller> <value> [channel]
+//! program <program> [channel]
+//! pressure <value> [channel]
+//! preset <number>
+//! octave <octaves>
+//! channel <channel>
+//! panic
+//! block <kind|all> [channel]
+//! unblock <kind|all> [channel]
+//! ```
+//!
+//! `octave` moves the note keys up or down from where they are configured, `channel` sets the
+//! channel they play on, and `panic` stops every note on every channel. `block` keeps a kind of
+//! message, named like in `[[thru]]`, from going out, on one channel or all of them, and
+//! `unblock` lets them out again, see [`block`](crate::block). Channels are one-based. Empty
+//! lines and lines starting with `#` are ignored.
+
+use crate::{block::Block, json, midi::MidiMsg, shift, velocity::FIXED_VELOCITY};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Send a MIDI message as is.
+    Midi(MidiMsg),
+    /// Select a preset by its zero-based index.
+    Preset(usize),
+    /// Move the note keys by this many octaves.
+    Octave(i8),
+    /// Play the note keys on this zero-based channel.
+    Channel(u8),
+    /// Stop every note on every channel.
+    Panic,
+    /// Keep messages from going out.
+    Block(Block),
+    /// Let messages out again.
+    Unblock(Block),
+}
+
+/// Parses one line, returning `Ok(None)` for blank lines and comments.
+pub fn parse_line(line: &str, default_channel: u8) -> Result<Option<Command>, String> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('{') {
+        return json::parse_event(line, default_channel).map(Some);
+    }
+
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+
+    if command == "panic" {
+        return match words.next() {
+            None => Ok(Some(Command::Panic)),
+            Some(_) => Err("'panic' takes no arguments".to_string()),
+        };
+    }
+    if command == "octave" {
+        let max = shift::MAX_OCTAVES;
+        return match (words.next().map(str::parse::<i8>), words.next()) {
+            (Some(Ok(octave)), None) if (-max..=max).contains(&octave) => {
+                Ok(Some(Command::Octave(octave)))
+            }
+            _ => Err(format!(
+                "'octave' takes a number of octaves from -{} to {}",
+                max, max
+            )),
+        };
+    }
+    if command == "channel" {
+        return match (words.next().map(str::parse::<u8>), words.next()) {
+            (Some(Ok(channel @ 1..=16)), None) => Ok(Some(Command::Channel(channel - 1))),
+            _ => Err("'channel' takes a MIDI channel (1-16)".to_string()),
+        };
+    }
+    if command == "block" || command == "unblock" {
+        return match Block::parse(words) {
+            Some(block) if command == "block" => Ok(Some(Command::Block(block))),
+            Some(block) => Ok(Some(Command::Unblock(block))),
+            None => Err(format!(
+                "'{}' takes a kind of message or 'all', and optionally a MIDI channel (1-16)",
+                command
+            )),
+        };
+    }
+    if command == "preset" {
+        return match (words.next().map(str::parse::<usize>), words.next()) {
+            (Some(Ok(number @ 1..)), None) => Ok(Some(Command::Preset(number - 1))),
+            _ => Err("'preset' takes a preset number starting at 1".to_string()),
+        };
+    }
+
+    let args = words
+        .map(|word| {
+            word.parse::<u8>()
+                .ok()
+                .filter(|&n| n <= 127)
+                .ok_or_else(|| format!("'{}' is not a number from 0 to 127", word))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let channel = |index: usize| match args.get(index) {
+        Some(&channel @ 1..=16) => Ok(channel - 1),
+        Some(channel) => Err(format!("{} is not a MIDI channel (1-16)", channel)),
+        None => Ok(default_channel),
+    };
+    let arity = |min: usize, max: usize| {
+        if (min..=max).contains(&args.len()) {
+            Ok(())
+        } else {
+            Err(format!("wrong number of arguments for '{}'", command))
+        }
+    };
+
+    let midi = match command {
+        "on" => {
+            arity(1, 3)?;
+            MidiMsg::NoteOn {
+                channel: channel(2)?,
+                note: args[0],
+                velocity: args.get(1).copied().unwrap_or(FIXED_VELOCITY),
+            }
+        }
+        "off" => {
+            arity(1, 3)?;
+            MidiMsg::NoteOff {
+                channel: channel(2)?,
+                note: args[0],
+                velocity: args.get(1).copied().unwrap_or(0),
+            }
+        }
+        "cc" => {
+            arity(2, 3)?;
+            MidiMsg::ControlChange {
+                channel: channel(2)?,
+                controller: args[0],
+                value: args[1],
+            }
+        }
+        "program" => {
+            arity(1, 2)?;
+            MidiMsg::ProgramChange {
+                channel: channel(1)?,
+                program: args[0],
+            }
+        }
+        "pressure" => {
+            arity(1, 2)?;
+            MidiMsg::ChannelPressure {
+                channel: channel(1)?,
+                value: args[0],
+            }
+        }
+        _ => return Err(format!("unknown command '{}'", command)),
+    };
+
+    Ok(Some(Command::Midi(midi)))
+}
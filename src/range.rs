@@ -0,0 +1,73 @@
+//! Keeping the notes sent within a range, for instruments that misbehave on notes outside of
+//! it, e.g. after transposing a keyboard a long way.
+
+use crate::midi::MidiMsg;
+
+/// What happens to notes outside the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Moved by octaves until they are inside, or to the nearest end if the range is less than
+    /// an octave wide.
+    Fold,
+    /// Moved to the nearest end.
+    Clamp,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fold" => Some(Mode::Fold),
+            "clamp" => Some(Mode::Clamp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteRange {
+    pub low: u8,
+    pub high: u8,
+    pub mode: Mode,
+}
+
+impl NoteRange {
+    /// Moves the note of a note on or off into the range. Note ons and offs for the same note
+    /// are moved the same way, so they still pair up.
+    pub fn apply(&self, midi: MidiMsg) -> MidiMsg {
+        match midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => MidiMsg::NoteOn {
+                channel,
+                note: self.note(note),
+                velocity,
+            },
+            MidiMsg::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => MidiMsg::NoteOff {
+                channel,
+                note: self.note(note),
+                velocity,
+            },
+            midi => midi,
+        }
+    }
+
+    fn note(&self, note: u8) -> u8 {
+        let mut note = note;
+        if self.mode == Mode::Fold {
+            while note < self.low && note + 12 <= self.high {
+                note += 12;
+            }
+            while note > self.high && note >= self.low + 12 {
+                note -= 12;
+            }
+        }
+
+        note.clamp(self.low, self.high)
+    }
+}
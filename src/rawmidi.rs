@@ -0,0 +1,88 @@
+//! Writing straight to an ALSA rawmidi device like a USB-MIDI interface, without JACK, for
+//! driving hardware synths on a machine that doesn't run a JACK server.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender},
+    thread,
+};
+
+use crate::{
+    config::Config,
+    engine::{Control, Engine, Outcome, REAL_TIME_RATE},
+    options::Options,
+    session_log::report,
+    KeyboardMsg,
+};
+
+/// The device file of an ALSA device name like `hw:1,0` (card 1, device 0) or `hw:1`, or a
+/// path to a device file as it is.
+pub fn device_path(name: &str) -> Option<PathBuf> {
+    device_file(name, "midi")
+}
+
+/// Like [`device_path`], for the kind of device whose files start with `kind`, e.g. `ump` for
+/// `/dev/snd/umpC1D0`.
+pub fn device_file(name: &str, kind: &str) -> Option<PathBuf> {
+    if name.starts_with('/') {
+        return Some(PathBuf::from(name));
+    }
+
+    let name = name.strip_prefix("hw:")?;
+    let (card, device) = name.split_once(',').unwrap_or((name, "0"));
+    let card = card.parse::<u32>().ok()?;
+    let device = device.parse::<u32>().ok()?;
+
+    Some(PathBuf::from(format!(
+        "/dev/snd/{}C{}D{}",
+        kind, card, device
+    )))
+}
+
+/// Opens the device at `path` and starts a thread that plays the [`Engine`] on it. If
+/// `written` is given, every message that was written is also sent there, stamped with the
+/// JACK time it was written at, and so is every beat of the clock to `beats`.
+pub fn start(
+    path: &Path,
+    rx: Receiver<KeyboardMsg>,
+    controls: Receiver<Control>,
+    written: Option<Sender<KeyboardMsg>>,
+    beats: Option<Sender<u64>>,
+    options: &Options,
+    config: &Config,
+) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    let engine = Engine::new(rx, controls, beats, options, config, REAL_TIME_RATE);
+    let path = path.to_owned();
+
+    thread::spawn(move || run(engine, file, &path, written));
+
+    Ok(())
+}
+
+fn run(engine: Engine, mut file: File, path: &Path, written: Option<Sender<KeyboardMsg>>) {
+    engine.run(|engine, n_frames| {
+        engine.cycle(
+            n_frames,
+            // Everything is written as soon as it comes in
+            |_| 0,
+            |_, bytes| match file.write_all(bytes) {
+                Ok(()) => Outcome::Done,
+                Err(err) => {
+                    report!("{}: {}", path.display(), err);
+                    Outcome::Failed
+                }
+            },
+            |_, &midi| {
+                if let Some(written) = &written {
+                    let _ = written.send(KeyboardMsg {
+                        midi,
+                        time: jack::get_time(),
+                    });
+                }
+            },
+        )
+    })
+}
@@ -0,0 +1,93 @@
+//! Release delay: note offs from the keys are held back for a while, like a damper that is
+//! slow to come down, without sending sustain. Configured with `release_delay`, and the
+//! `release_delay` of each `[split]` zone for the zone's channel.
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg, scheduler::Scheduler};
+
+#[derive(Debug, Clone)]
+pub struct ReleaseDelay {
+    /// Frames the note offs of each channel are held back by.
+    delays: [u64; 16],
+    /// Its own, so a note played again only cancels the note offs held back here and not those
+    /// of echo or macros.
+    scheduler: Scheduler,
+}
+
+impl ReleaseDelay {
+    /// `global` applies to every channel but those in `channels`, which have their own, all in
+    /// frames.
+    pub fn new(global: u64, channels: &[(u8, u64)]) -> Self {
+        let mut delays = [global; 16];
+        for &(channel, delay) in channels {
+            delays[channel as usize] = delay;
+        }
+        ReleaseDelay {
+            delays,
+            scheduler: Scheduler::new(),
+        }
+    }
+
+    /// How many note offs are being held back.
+    pub fn len(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Adds every note off held back to the start of this cycle.
+    pub fn flush(&mut self, events: &mut Vec<(Frames, MidiMsg)>) {
+        self.scheduler.flush(events, |_| true);
+    }
+
+    /// Holds back the note offs among the first `incoming` of `events`, to be played once the
+    /// delay has passed, and adds those held back earlier that are due in this cycle.
+    ///
+    /// A note played again before its note off was sent just plays again, and its pending note
+    /// off is dropped since the new note will get its own.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+        incoming: usize,
+    ) {
+        if self.delays.iter().all(|&delay| delay == 0) {
+            return;
+        }
+
+        let (delays, scheduler) = (&self.delays, &mut self.scheduler);
+        let mut index = 0;
+        events.retain(|&(time, midi)| {
+            index += 1;
+            if index > incoming {
+                return true;
+            }
+
+            if let Some((channel, _)) = note_off(&midi) {
+                let delay = delays[channel as usize & 0x0f];
+                if delay > 0 {
+                    scheduler.push(clock.frame() + time as u64 + delay, midi);
+                }
+                return delay == 0;
+            }
+            if let MidiMsg::NoteOn { channel, note, .. } = midi {
+                scheduler.cancel(|off| note_off(off) == Some((channel, note)));
+            }
+            true
+        });
+        scheduler.schedule(clock, n_frames, events);
+    }
+}
+
+/// The channel and note of a note off, including a note on with velocity 0.
+fn note_off(midi: &MidiMsg) -> Option<(u8, u8)> {
+    match *midi {
+        MidiMsg::NoteOff { channel, note, .. }
+        | MidiMsg::NoteOn {
+            channel,
+            note,
+            velocity: 0,
+        } => Some((channel, note)),
+        _ => None,
+    }
+}
@@ -0,0 +1,171 @@
+//! MPC-style note repeat: while it is on, held notes are played again on every step of a grid
+//! synced to the [`Clock`].
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rate {
+    Eighth,
+    Sixteenth,
+    SixteenthTriplet,
+    ThirtySecond,
+}
+
+impl Rate {
+    const ALL: [Rate; 4] = [
+        Rate::Eighth,
+        Rate::Sixteenth,
+        Rate::SixteenthTriplet,
+        Rate::ThirtySecond,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rate| rate.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rate::Eighth => "1/8",
+            Rate::Sixteenth => "1/16",
+            Rate::SixteenthTriplet => "1/16t",
+            Rate::ThirtySecond => "1/32",
+        }
+    }
+
+    /// The rate after this one, going back to the first after the last.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&rate| rate == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn per_beat(self) -> f64 {
+        match self {
+            Rate::Eighth => 2.0,
+            Rate::Sixteenth => 4.0,
+            Rate::SixteenthTriplet => 6.0,
+            Rate::ThirtySecond => 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    /// Pressed right on the current step, so it shouldn't be repeated there.
+    fresh: bool,
+}
+
+/// The note repeat state, which lives in the process callback.
+#[derive(Debug, Clone)]
+pub struct NoteRepeat {
+    pub rate: Option<Rate>,
+    /// Velocity in percent of the held note's, one for each step in turn. Steps at 0 are skipped.
+    accents: Vec<u8>,
+    held: Vec<Held>,
+}
+
+impl NoteRepeat {
+    pub fn new(accents: Vec<u8>) -> Self {
+        NoteRepeat {
+            rate: None,
+            accents,
+            // Enough that the process callback never has to allocate
+            held: Vec::with_capacity(16 * 128),
+        }
+    }
+
+    fn handle(&mut self, midi: &MidiMsg, fresh: bool) {
+        match *midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => {
+                self.held
+                    .retain(|held| held.channel != channel || held.note != note);
+                self.held.push(Held {
+                    channel,
+                    note,
+                    velocity,
+                    fresh,
+                });
+            }
+            MidiMsg::NoteOn { channel, note, .. } | MidiMsg::NoteOff { channel, note, .. } => {
+                self.held
+                    .retain(|held| held.channel != channel || held.note != note);
+            }
+            _ => (),
+        }
+    }
+
+    /// Keeps track of the held notes in `events` without repeating any, for cycles where the
+    /// clock doesn't run.
+    pub fn follow(&mut self, events: &[(Frames, MidiMsg)]) {
+        for (_, midi) in events {
+            self.handle(midi, false);
+        }
+    }
+
+    /// Adds the repeated notes for this cycle to `events`, which holds the cycle's other events
+    /// in order. The repeats are appended, so `events` has to be sorted by time afterwards.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+    ) {
+        let incoming = events.len();
+        let mut next = 0;
+
+        if let Some(rate) = self.rate {
+            for (step, time) in clock.steps(rate.per_beat(), n_frames) {
+                while next < incoming && events[next].0 <= time {
+                    let (event_time, midi) = events[next];
+                    self.handle(&midi, event_time == time);
+                    next += 1;
+                }
+
+                let accent = match self.accents.len() {
+                    0 => 100,
+                    len => self.accents[step as usize % len],
+                };
+
+                for held in &mut self.held {
+                    if held.fresh || accent == 0 {
+                        held.fresh = false;
+                        continue;
+                    }
+
+                    let velocity = (held.velocity as u32 * accent as u32 / 100).clamp(1, 127);
+                    events.push((
+                        time,
+                        MidiMsg::NoteOff {
+                            channel: held.channel,
+                            note: held.note,
+                            velocity: 0,
+                        },
+                    ));
+                    events.push((
+                        time,
+                        MidiMsg::NoteOn {
+                            channel: held.channel,
+                            note: held.note,
+                            velocity: velocity as u8,
+                        },
+                    ));
+                }
+            }
+        }
+
+        for &(_, midi) in &events[next..incoming] {
+            self.handle(&midi, false);
+        }
+        for held in &mut self.held {
+            held.fresh = false;
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers for generated patterns: a small random number generator and Euclidean rhythms.
+
+/// An xorshift64* generator. Not for anything that matters, but cheap enough for the process
+/// callback.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Rng(seed | 1)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    }
+
+    /// A number from 0 up to but not including `n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        ((self.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+}
+
+/// Whether `step` is a pulse of the Euclidean rhythm with `pulses` spread as evenly as possible
+/// over `steps`. The pattern starts with a pulse and repeats every `steps` steps.
+pub fn euclidean(step: u64, pulses: u32, steps: u32) -> bool {
+    if steps == 0 {
+        return false;
+    }
+
+    let step = step % steps as u64;
+    (step * pulses as u64) % (steps as u64) < pulses as u64
+}
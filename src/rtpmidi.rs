@@ -0,0 +1,320 @@
+//! Playing on a network MIDI session (RTP-MIDI, or AppleMIDI), like one set up in Audio MIDI
+//! Setup on a Mac, over the local network instead of through JACK.
+//!
+//! This joins the session as its initiator, like the "Connect" button there does. Messages go
+//! out without the recovery journal (RFC 6295), so a lost packet loses its messages, which is
+//! rare enough on a local network.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    process,
+    sync::mpsc::{Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    engine::{Control, Engine, Outcome, REAL_TIME_RATE},
+    mdns, midi,
+    options::Options,
+    session_log::report,
+    KeyboardMsg,
+};
+
+const SERVICE: &str = "_apple-midi._udp.local";
+/// The name other participants see.
+const NAME: &str = "jack_keyboard";
+
+const SIGNATURE: [u8; 2] = [0xff, 0xff];
+const PROTOCOL_VERSION: u32 = 2;
+const INVITATION: &[u8; 2] = b"IN";
+const ACCEPTED: &[u8; 2] = b"OK";
+const REJECTED: &[u8; 2] = b"NO";
+const SYNC: &[u8; 2] = b"CK";
+const END: &[u8; 2] = b"BY";
+
+/// RTP version 2, and the dynamic payload type that AppleMIDI uses.
+const RTP_HEADER: [u8; 2] = [0x80, 0x61];
+/// The most a MIDI command section can hold with a long length field.
+const MAX_COMMANDS: usize = 0x0fff;
+
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(3);
+const INVITATION_TIMEOUT: Duration = Duration::from_secs(1);
+const INVITATION_ATTEMPTS: usize = 5;
+/// How often the clocks are synchronised. Sessions that go without for long are ended.
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Joins `session`, which is either the name of a session found through mDNS or the
+/// `HOST:PORT` of its control port, and starts a thread that plays the [`Engine`] on it. If
+/// `written` is given, every message that was written is also sent there, stamped with the
+/// JACK time it was sent at, and so is every beat of the clock to `beats`.
+pub fn start(
+    session: &str,
+    rx: Receiver<KeyboardMsg>,
+    controls: Receiver<Control>,
+    written: Option<Sender<KeyboardMsg>>,
+    beats: Option<Sender<u64>>,
+    options: &Options,
+    config: &Config,
+) -> io::Result<()> {
+    let peer = match session.contains(':') {
+        true => session.to_socket_addrs()?.next(),
+        false => mdns::resolve(SERVICE, session, RESOLVE_TIMEOUT)?,
+    };
+    let peer = peer.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no network session called {:?} found", session),
+        )
+    })?;
+
+    let ssrc = jack::get_time() as u32 ^ process::id().rotate_left(16);
+    let (control, data) = bind_pair(peer)?;
+    invite(&control, peer, ssrc)?;
+    invite(&data, data_addr(peer), ssrc)?;
+    control.set_nonblocking(true)?;
+    data.set_nonblocking(true)?;
+    report!("joined the network session at {}", peer);
+
+    let engine = Engine::new(rx, controls, beats, options, config, REAL_TIME_RATE);
+    let session = Session {
+        control,
+        data,
+        peer,
+        ssrc,
+        start: Instant::now(),
+        sequence: 0,
+        last_sync: None,
+        ended: false,
+    };
+    thread::spawn(move || run(engine, session, written));
+
+    Ok(())
+}
+
+/// Binds sockets on two ports in a row, for control and data.
+fn bind_pair(peer: SocketAddr) -> io::Result<(UdpSocket, UdpSocket)> {
+    let ip: IpAddr = match peer {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    for _ in 0..16 {
+        let control = UdpSocket::bind((ip, 0))?;
+        let port = control.local_addr()?.port();
+        if port == u16::MAX {
+            continue;
+        }
+        if let Ok(data) = UdpSocket::bind((ip, port + 1)) {
+            return Ok((control, data));
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "no two free ports in a row",
+    ))
+}
+
+/// The data port goes right after the control port.
+fn data_addr(control: SocketAddr) -> SocketAddr {
+    SocketAddr::new(control.ip(), control.port().wrapping_add(1))
+}
+
+fn command(command: &[u8; 2], body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4 + body.len());
+    packet.extend_from_slice(&SIGNATURE);
+    packet.extend_from_slice(command);
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Invites `peer` on `socket` and waits for it to accept.
+fn invite(socket: &UdpSocket, peer: SocketAddr, ssrc: u32) -> io::Result<()> {
+    let token = ssrc.rotate_left(8) ^ peer.port() as u32;
+    let mut body = Vec::new();
+    body.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    body.extend_from_slice(&token.to_be_bytes());
+    body.extend_from_slice(&ssrc.to_be_bytes());
+    body.extend_from_slice(NAME.as_bytes());
+    body.push(0);
+    let invitation = command(INVITATION, &body);
+
+    socket.set_read_timeout(Some(INVITATION_TIMEOUT))?;
+    let mut buffer = [0; 512];
+    for _ in 0..INVITATION_ATTEMPTS {
+        socket.send_to(&invitation, peer)?;
+
+        let len = match socket.recv_from(&mut buffer) {
+            Ok((len, from)) if from.ip() == peer.ip() => len,
+            Ok(_) => continue,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+        let reply = &buffer[..len];
+        if len < 12 || reply[..2] != SIGNATURE || reply[8..12] != token.to_be_bytes() {
+            continue;
+        }
+
+        match &[reply[2], reply[3]] {
+            ACCEPTED => return Ok(()),
+            REJECTED => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "the session turned down the invitation",
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no answer from {}", peer),
+    ))
+}
+
+struct Session {
+    control: UdpSocket,
+    data: UdpSocket,
+    /// The control port of the session.
+    peer: SocketAddr,
+    ssrc: u32,
+    start: Instant,
+    sequence: u16,
+    last_sync: Option<Instant>,
+    ended: bool,
+}
+
+impl Session {
+    /// The session time, in the units of 100 µs that AppleMIDI uses.
+    fn now(&self) -> u64 {
+        (self.start.elapsed().as_micros() / 100) as u64
+    }
+
+    fn sync(&self, count: u8, timestamps: [u64; 3]) {
+        let mut body = Vec::with_capacity(32);
+        body.extend_from_slice(&self.ssrc.to_be_bytes());
+        body.extend_from_slice(&[count, 0, 0, 0]);
+        for timestamp in timestamps {
+            body.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        let _ = self
+            .data
+            .send_to(&command(SYNC, &body), data_addr(self.peer));
+    }
+
+    /// Answers clock synchronisation and notices the session ending, and starts a new
+    /// synchronisation once in a while.
+    fn poll(&mut self) {
+        let mut buffer = [0; 512];
+
+        for socket in [&self.control, &self.data] {
+            while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+                let packet = &buffer[..len];
+                if from.ip() != self.peer.ip() || len < 4 || packet[..2] != SIGNATURE {
+                    continue;
+                }
+
+                match &[packet[2], packet[3]] {
+                    END => {
+                        report!("the network session was ended");
+                        self.ended = true;
+                    }
+                    SYNC if len >= 36 => {
+                        let timestamp =
+                            |at: usize| u64::from_be_bytes(packet[at..at + 8].try_into().unwrap());
+                        let timestamps = [timestamp(12), timestamp(20), timestamp(28)];
+                        match packet[8] {
+                            // The other side started it
+                            0 => self.sync(1, [timestamps[0], self.now(), 0]),
+                            1 => self.sync(2, [timestamps[0], timestamps[1], self.now()]),
+                            _ => (),
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        if !self.ended
+            && self
+                .last_sync
+                .is_none_or(|last| last.elapsed() >= SYNC_INTERVAL)
+        {
+            self.sync(0, [self.now(), 0, 0]);
+            self.last_sync = Some(Instant::now());
+        }
+    }
+
+    /// Sends `commands`, a MIDI list without the delta time of the first command.
+    fn send(&mut self, commands: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(14 + commands.len());
+        packet.extend_from_slice(&RTP_HEADER);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&(self.now() as u32).to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        match commands.len() {
+            len @ 0..=15 => packet.push(len as u8),
+            len => packet.extend_from_slice(&[0x80 | (len >> 8) as u8, len as u8]),
+        }
+        packet.extend_from_slice(commands);
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.data.send_to(&packet, data_addr(self.peer)).map(|_| ())
+    }
+}
+
+fn run(engine: Engine, mut session: Session, written: Option<Sender<KeyboardMsg>>) {
+    let mut commands = Vec::with_capacity(MAX_COMMANDS);
+
+    engine.run(|engine, n_frames| {
+        session.poll();
+
+        commands.clear();
+        let mut last_time = None;
+        engine.cycle(
+            n_frames,
+            |_| 0,
+            |time, bytes| {
+                let delta = last_time.map(|last| (time - last) * 10);
+                if session.ended {
+                    return Outcome::Failed;
+                }
+                if commands.len() + 4 + bytes.len() > MAX_COMMANDS {
+                    return Outcome::Full;
+                }
+
+                if let Some(delta) = delta {
+                    midi::push_variable_length(&mut commands, delta);
+                }
+                commands.extend_from_slice(bytes);
+                last_time = Some(time);
+                Outcome::Done
+            },
+            |_, &midi| {
+                if let Some(written) = &written {
+                    let _ = written.send(KeyboardMsg {
+                        midi,
+                        time: jack::get_time(),
+                    });
+                }
+            },
+        );
+
+        if !commands.is_empty() {
+            if let Err(err) = session.send(&commands) {
+                report!("network session: {}", err);
+            }
+        }
+    })
+}
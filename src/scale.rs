@@ -0,0 +1,100 @@
+//! Musical scales.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    HarmonicMinor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    Chromatic,
+}
+
+impl Scale {
+    const ALL: [Scale; 12] = [
+        Scale::Major,
+        Scale::Minor,
+        Scale::Dorian,
+        Scale::Phrygian,
+        Scale::Lydian,
+        Scale::Mixolydian,
+        Scale::Locrian,
+        Scale::HarmonicMinor,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::Blues,
+        Scale::Chromatic,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scale| scale.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+            Scale::Dorian => "dorian",
+            Scale::Phrygian => "phrygian",
+            Scale::Lydian => "lydian",
+            Scale::Mixolydian => "mixolydian",
+            Scale::Locrian => "locrian",
+            Scale::HarmonicMinor => "harmonic_minor",
+            Scale::MajorPentatonic => "major_pentatonic",
+            Scale::MinorPentatonic => "minor_pentatonic",
+            Scale::Blues => "blues",
+            Scale::Chromatic => "chromatic",
+        }
+    }
+
+    /// Semitones above the root of each degree within an octave.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        }
+    }
+
+    /// The note of the scale from `root` (a pitch class) nearest to `note`, the lower one if two
+    /// are as near.
+    pub fn snap(self, root: u8, note: u8) -> u8 {
+        let in_scale = |note: i32| {
+            let semitone = (note - root as i32).rem_euclid(12) as u8;
+            self.intervals().contains(&semitone)
+        };
+        let note = note as i32;
+        let nearest = (0..=6)
+            .flat_map(|distance| [note - distance, note + distance])
+            .find(|&candidate| in_scale(candidate))
+            .unwrap_or(note);
+
+        nearest.clamp(0, 127) as u8
+    }
+
+    /// The note `degree` steps of the scale above `root` (below it if negative), clamped to the
+    /// MIDI note range.
+    pub fn note(self, root: u8, degree: i32) -> u8 {
+        let intervals = self.intervals();
+        let len = intervals.len() as i32;
+        let octave = degree.div_euclid(len);
+        let interval = intervals[degree.rem_euclid(len) as usize] as i32;
+
+        (root as i32 + 12 * octave + interval).clamp(0, 127) as u8
+    }
+}
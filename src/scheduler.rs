@@ -0,0 +1,76 @@
+//! Messages that are due in a later cycle than the one they were made in, for everything that
+//! plays something later than it happens, like release delay.
+
+use jack::Frames;
+
+use crate::{clock::Clock, midi::MidiMsg};
+
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    /// Messages with the frame they are due at, in the order they were added.
+    pending: Vec<(u64, MidiMsg)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler {
+            // Enough that the process callback never has to allocate
+            pending: Vec::with_capacity(4096),
+        }
+    }
+
+    /// Plays `midi` at `frame`, counted like [`Clock::frame`]. If that has already passed, it
+    /// is played at the start of the next cycle.
+    pub fn push(&mut self, frame: u64, midi: MidiMsg) {
+        self.pending.push((frame, midi));
+    }
+
+    /// How many messages are waiting.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drops the pending messages that `cancel` returns true for.
+    pub fn cancel(&mut self, mut cancel: impl FnMut(&MidiMsg) -> bool) {
+        self.pending.retain(|(_, midi)| !cancel(midi));
+    }
+
+    /// Adds the pending messages that `flush` returns true for to the start of this cycle,
+    /// however far off they are due.
+    pub fn flush(
+        &mut self,
+        events: &mut Vec<(Frames, MidiMsg)>,
+        mut flush: impl FnMut(&MidiMsg) -> bool,
+    ) {
+        self.pending.retain(|&(_, midi)| {
+            if flush(&midi) {
+                events.push((0, midi));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Adds the messages that are due in this cycle to `events`. They are appended, so `events`
+    /// has to be sorted by time afterwards; messages due at the same frame stay in the order
+    /// they were added.
+    pub fn schedule(
+        &mut self,
+        clock: &Clock,
+        n_frames: Frames,
+        events: &mut Vec<(Frames, MidiMsg)>,
+    ) {
+        let end = clock.frame() + n_frames as u64;
+
+        self.pending.retain(|&(due, midi)| {
+            if due < end {
+                let time = due.saturating_sub(clock.frame()) as Frames;
+                events.push((time, midi));
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
@@ -0,0 +1,191 @@
+//! Scripting with `--script <FILE>`: the note keys are handed to FILE, an executable in whatever
+//! language it likes, which plays what it wants in return. Each press and release is written to
+//! its stdin as a line of
+//!
+//! ```text
+//! key <name> <down|up> <note>
+//! ```
+//!
+//! with the key named like in the config and the note it would have played, and each line it
+//! prints is played like a line of `--stdin` (see [`protocol`]). Whenever FILE changes, the
+//! script is started again and the notes it left playing are stopped, so it can be edited while
+//! playing.
+//!
+//! A script that transposes everything up an octave:
+//!
+//! ```text
+//! #!/bin/sh
+//! while read -r _ key state note; do
+//!     if [ "$state" = down ]; then echo "on $((note + 12))"; else echo "off $((note + 12))"; fi
+//! done
+//! ```
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command as Process, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use winit::event::ScanCode;
+
+use crate::{
+    keys,
+    midi::{MidiMsg, DEFAULT_CHANNEL},
+    protocol::{self, Command},
+    session_log::report,
+    Proxy, UserEvent,
+};
+
+/// How often the file is checked for changes.
+const POLL: Duration = Duration::from_millis(500);
+
+/// The thread playing what the script prints, which returns the notes it left playing as
+/// `(channel, note)`.
+type Reader = JoinHandle<HashSet<(u8, u8)>>;
+
+/// The running script, which note keys are written to.
+#[derive(Debug, Clone)]
+pub struct Script {
+    /// The stdin of the script, or `None` while it isn't running.
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+}
+
+impl Script {
+    /// Starts the script at `path`, and a thread that starts it again whenever it changes.
+    /// Whatever it plays is sent to the event loop through `proxy`.
+    pub fn start(path: PathBuf, proxy: Proxy) -> Self {
+        let stdin = Arc::new(Mutex::new(None));
+        let script = Script {
+            stdin: stdin.clone(),
+        };
+
+        thread::spawn(move || {
+            let mut modified = None;
+            let mut running: Option<(Child, Reader)> = None;
+            loop {
+                let changed = mtime(&path);
+                if changed != modified {
+                    modified = changed;
+                    if let Some((child, reader)) = running.take() {
+                        if !stop(child, reader, &proxy) {
+                            break;
+                        }
+                        report!("{} changed, restarting it", path.display());
+                    }
+                    match spawn(&path) {
+                        Ok((mut child, stdout)) => {
+                            *stdin.lock().unwrap() = child.stdin.take();
+                            let proxy = proxy.clone();
+                            let reader = thread::spawn(move || read(stdout, &proxy));
+                            running = Some((child, reader));
+                        }
+                        Err(err) => report!("{}: {}", path.display(), err),
+                    }
+                }
+
+                if let Some((child, _)) = &mut running {
+                    if let Ok(Some(status)) = child.try_wait() {
+                        // Left for the next change to start again
+                        report!("{} exited with {}", path.display(), status);
+                        *stdin.lock().unwrap() = None;
+                        let (child, reader) = running.take().unwrap();
+                        if !stop(child, reader, &proxy) {
+                            break;
+                        }
+                    }
+                }
+                thread::sleep(POLL);
+            }
+        });
+
+        script
+    }
+
+    /// Hands the press or release of the key with `scancode`, which plays `note`, to the script.
+    pub fn key(&self, scancode: ScanCode, pressed: bool, note: u8) {
+        let mut stdin = self.stdin.lock().unwrap();
+        if let Some(input) = stdin.as_mut() {
+            let name = keys::name(scancode).unwrap_or("Unknown");
+            let state = if pressed { "down" } else { "up" };
+            if writeln!(input, "key {} {} {}", name, state, note).is_err() {
+                // It has exited, which the thread watching it reports
+                *stdin = None;
+            }
+        }
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn spawn(path: &Path) -> io::Result<(Child, ChildStdout)> {
+    let mut child = Process::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    Ok((child, stdout))
+}
+
+/// Plays the lines the script prints until it exits, returning the notes it left playing as
+/// `(channel, note)`.
+fn read(stdout: ChildStdout, proxy: &Proxy) -> HashSet<(u8, u8)> {
+    let mut sounding = HashSet::new();
+
+    for (number, line) in BufReader::new(stdout).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match protocol::parse_line(&line, DEFAULT_CHANNEL) {
+            Ok(Some(command)) => {
+                match command {
+                    Command::Midi(MidiMsg::NoteOn {
+                        channel,
+                        note,
+                        velocity: 1..,
+                    }) => sounding.insert((channel, note)),
+                    Command::Midi(
+                        MidiMsg::NoteOn { channel, note, .. }
+                        | MidiMsg::NoteOff { channel, note, .. },
+                    ) => sounding.remove(&(channel, note)),
+                    _ => false,
+                };
+                if proxy.send_event(UserEvent::Command(command)).is_err() {
+                    // The event loop has exited
+                    break;
+                }
+            }
+            Ok(None) => (),
+            Err(err) => report!("script:{}: {}", number + 1, err),
+        }
+    }
+
+    sounding
+}
+
+/// Stops the script and the notes it left playing. Returns false once the event loop has
+/// exited.
+fn stop(mut child: Child, reader: Reader, proxy: &Proxy) -> bool {
+    // It may have exited already
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let sounding = reader.join().unwrap_or_default();
+    sounding.into_iter().all(|(channel, note)| {
+        let midi = MidiMsg::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        };
+        proxy
+            .send_event(UserEvent::Command(Command::Midi(midi)))
+            .is_ok()
+    })
+}
@@ -0,0 +1,105 @@
+//! `--log <FILE>`: a log of the session, for working out afterwards what went wrong during a
+//! set. Every line is the UTC time, what happened and the details, e.g.
+//!
+//! ```text
+//! 2024-05-01 18:30:00.125 start jack_keyboard 0.1.0
+//! 2024-05-01 18:30:02.500 connected jack_keyboard:out -> fluidsynth:midi_00
+//! 2024-05-01 18:31:10.042 preset 2 Bright organ
+//! 2024-05-01 18:32:00.000 dropped 12 events
+//! 2024-05-01 18:32:05.311 notice xrun
+//! ```
+//!
+//! Besides start and stop, preset changes and ports of ours being connected or disconnected,
+//! everything printed with [`report!`] is logged as a notice, and the messages the output had to
+//! drop are counted and logged once a second.
+
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::autosave;
+
+/// How often the messages dropped are logged, if there were any.
+const DROPPED_INTERVAL: Duration = Duration::from_secs(1);
+
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// Messages dropped by the engine since they were last logged. Counted in the process callback,
+/// which can't write to the file itself.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Prints a line on stderr, starting with `jack_keyboard: ` like the rest, and logs it as a
+/// notice.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        $crate::session_log::notice(format_args!($($arg)*))
+    };
+}
+pub(crate) use report;
+
+/// Starts logging to the end of the file at `path`.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *LOG.lock().unwrap() = Some(file);
+
+    thread::spawn(|| loop {
+        thread::sleep(DROPPED_INTERVAL);
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            record("dropped", format_args!("{} events", dropped));
+        }
+    });
+    Ok(())
+}
+
+/// Logs that `what` happened, if there is a log.
+pub fn record(what: &str, details: impl Display) {
+    let mut log = LOG.lock().unwrap();
+    let Some(file) = log.as_mut() else {
+        return;
+    };
+
+    let now = SystemTime::now();
+    let (year, month, day, seconds) = autosave::utc(now);
+    let millis = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_millis();
+    let line = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} {} {}\n",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis,
+        what,
+        details
+    );
+    if file.write_all(line.as_bytes()).is_err() {
+        // Nowhere left to log it but stderr, once
+        eprintln!("jack_keyboard: can't write the session log anymore");
+        *log = None;
+    }
+}
+
+/// See [`report!`].
+pub fn notice(message: impl Display) {
+    eprintln!("jack_keyboard: {}", message);
+    record("notice", message);
+}
+
+/// Counts `count` messages dropped. Safe to call from the process callback.
+pub fn dropped(count: usize) {
+    DROPPED.fetch_add(count as u64, Ordering::Relaxed);
+}
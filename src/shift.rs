@@ -0,0 +1,65 @@
+//! The octave and channel the note keys play in, set from outside with the `octave` and
+//! `channel` commands of the [`protocol`](crate::protocol) or over D-Bus.
+
+use crate::midi::{MidiMsg, DEFAULT_CHANNEL};
+
+/// How many octaves the keys can be moved up or down.
+pub const MAX_OCTAVES: i8 = 4;
+
+#[derive(Debug, Clone)]
+pub struct Shift {
+    /// Octaves the notes are moved by.
+    pub octave: i8,
+    /// The zero-based channel notes are played on.
+    pub channel: u8,
+    /// The notes held with the channel and note they were played as, so each note off stops
+    /// what was played even if the octave or channel has changed since.
+    held: Vec<(u8, u8, u8)>,
+}
+
+impl Default for Shift {
+    fn default() -> Self {
+        Shift {
+            octave: 0,
+            channel: DEFAULT_CHANNEL,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl Shift {
+    /// Moves a note on or off to the octave and channel. Notes moved outside the MIDI range are
+    /// clamped to it.
+    pub fn handle(&mut self, midi: MidiMsg) -> MidiMsg {
+        match midi {
+            MidiMsg::NoteOn { note, velocity, .. } if velocity > 0 => {
+                let played = (note as i32 + self.octave as i32 * 12).clamp(0, 127) as u8;
+                self.held.push((note, self.channel, played));
+                MidiMsg::NoteOn {
+                    channel: self.channel,
+                    note: played,
+                    velocity,
+                }
+            }
+            MidiMsg::NoteOn { note, velocity, .. } | MidiMsg::NoteOff { note, velocity, .. } => {
+                match self.held.iter().position(|&(held, ..)| held == note) {
+                    Some(index) => {
+                        let (_, channel, played) = self.held.remove(index);
+                        MidiMsg::NoteOff {
+                            channel,
+                            note: played,
+                            velocity,
+                        }
+                    }
+                    None => midi,
+                }
+            }
+            _ => midi,
+        }
+    }
+
+    /// Forgets the notes held, once they have been stopped some other way.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
@@ -0,0 +1,80 @@
+//! Snapshots of the controller state, configured with `[snapshots]`: the last value written of
+//! every control change is tracked, and each snapshot key stores all of them to send again
+//! later, so a sound tweaked live can be brought back at once.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    midi::{MidiMsg, CC_BANK_SELECT_LSB, CC_BANK_SELECT_MSB, CC_SUSTAIN},
+    output::OutputSink,
+};
+
+/// Controllers left out of snapshots: the pedal, which would hold notes, bank select, which
+/// only means something with a program change, the (N)RPN controllers, which only mean
+/// something together, and the channel mode messages.
+fn skipped(controller: u8) -> bool {
+    matches!(controller, 6 | 38 | 96..=101 | 120..)
+        || [CC_SUSTAIN, CC_BANK_SELECT_MSB, CC_BANK_SELECT_LSB].contains(&controller)
+}
+
+/// The last value of each controller on each channel, if one was sent.
+#[derive(Debug, Clone)]
+pub struct Controllers([[Option<u8>; 128]; 16]);
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Controllers([[None; 128]; 16])
+    }
+}
+
+/// Tracked from the messages written, on another thread.
+pub type Shared = Arc<Mutex<Controllers>>;
+
+impl OutputSink for Shared {
+    fn name(&self) -> &'static str {
+        "snapshots"
+    }
+
+    fn write(&mut self, midi: &MidiMsg, _time: jack::Time) {
+        self.lock().unwrap().update(midi);
+    }
+}
+
+impl Controllers {
+    pub fn update(&mut self, midi: &MidiMsg) {
+        if let MidiMsg::ControlChange {
+            channel,
+            controller,
+            value,
+        } = *midi
+        {
+            if !skipped(controller) {
+                self.0[channel as usize & 0x0f][controller as usize & 0x7f] = Some(value);
+            }
+        }
+    }
+
+    /// The last value written of a controller, if it isn't one left out.
+    pub fn get(&self, channel: u8, controller: u8) -> Option<u8> {
+        self.0[channel as usize & 0x0f][controller as usize & 0x7f]
+    }
+
+    /// The control changes that bring the controllers back to this state.
+    pub fn messages(&self) -> Vec<MidiMsg> {
+        let mut messages = Vec::new();
+
+        for (channel, controllers) in self.0.iter().enumerate() {
+            for (controller, value) in controllers.iter().enumerate() {
+                if let Some(value) = *value {
+                    messages.push(MidiMsg::ControlChange {
+                        channel: channel as u8,
+                        controller: controller as u8,
+                        value,
+                    });
+                }
+            }
+        }
+
+        messages
+    }
+}
@@ -0,0 +1,71 @@
+//! Which notes each key held down turned on, as they were sent after everything between the key
+//! and the output. Releasing a key stops exactly those, whatever has changed since it was
+//! pressed: the chord on it, the octave, the split or anything else that moves notes.
+
+use std::collections::HashMap;
+
+use winit::event::ScanCode;
+
+use crate::midi::MidiMsg;
+
+#[derive(Debug, Clone, Default)]
+pub struct Sounding {
+    /// The channels and notes each key turned on that are still sounding.
+    keys: HashMap<ScanCode, Vec<(u8, u8)>>,
+}
+
+impl Sounding {
+    /// Records the notes `key` turned on among the messages `sent` for its press.
+    pub fn pressed(&mut self, key: ScanCode, sent: &[MidiMsg]) {
+        self.stopped(sent);
+        let notes = self.keys.entry(key).or_default();
+        for midi in sent {
+            if let MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity: 1..,
+            } = *midi
+            {
+                notes.push((channel, note));
+            }
+        }
+    }
+
+    /// Returns note offs for the notes `key` turned on that the messages `sent` for its
+    /// release didn't stop.
+    pub fn released(&mut self, key: ScanCode, sent: &[MidiMsg]) -> Vec<MidiMsg> {
+        self.stopped(sent);
+        let notes = self.keys.remove(&key).unwrap_or_default();
+        notes
+            .into_iter()
+            .map(|(channel, note)| MidiMsg::NoteOff {
+                channel,
+                note,
+                velocity: 0,
+            })
+            .collect()
+    }
+
+    /// Forgets every note, once they have been stopped some other way.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    /// Forgets the notes stopped by `sent`, whichever key turned them on.
+    fn stopped(&mut self, sent: &[MidiMsg]) {
+        for midi in sent {
+            let stopped = match *midi {
+                MidiMsg::NoteOff { channel, note, .. }
+                | MidiMsg::NoteOn {
+                    channel,
+                    note,
+                    velocity: 0,
+                } => (channel, note),
+                _ => continue,
+            };
+            for notes in self.keys.values_mut() {
+                notes.retain(|&held| held != stopped);
+            }
+        }
+    }
+}
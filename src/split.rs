@@ -0,0 +1,87 @@
+//! A keyboard split, configured with `[split]`: the notes below the split point play in the
+//! lower zone and the rest in the upper zone, each on its own channel and transposed by its
+//! own amount. The split point can be moved while playing with `set_split`.
+
+use crate::{gui::canvas::Color, midi::MidiMsg};
+
+/// Where the notes of one side of the split go.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zone {
+    pub channel: u8,
+    pub transpose: i8,
+    /// Milliseconds the zone's channel plays behind the beat, or ahead of it if negative, see
+    /// [`Feel`](crate::feel::Feel).
+    pub feel: f64,
+    /// Milliseconds the note offs of the zone's channel are held back by, instead of the
+    /// global `release_delay`, see [`ReleaseDelay`](crate::release::ReleaseDelay).
+    pub release_delay: Option<f64>,
+    /// The color of the zone's keys on the keyboard of the fullscreen view.
+    pub color: Option<Color>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Split {
+    /// The lowest note of the upper zone.
+    pub note: u8,
+    lower: Zone,
+    upper: Zone,
+    /// The notes held with the channel and note they were played as, so each note off stops
+    /// what was played even if the split has moved since.
+    held: Vec<(u8, u8, u8)>,
+}
+
+impl Split {
+    pub fn new(note: u8, lower: Zone, upper: Zone) -> Self {
+        Split {
+            note,
+            lower,
+            upper,
+            held: Vec::new(),
+        }
+    }
+
+    /// The zone `note` plays in.
+    pub fn zone(&self, note: u8) -> Zone {
+        if note < self.note {
+            self.lower
+        } else {
+            self.upper
+        }
+    }
+
+    /// Moves a note on or off into its zone. Notes transposed outside the MIDI range are
+    /// clamped to it.
+    pub fn handle(&mut self, midi: MidiMsg) -> MidiMsg {
+        match midi {
+            MidiMsg::NoteOn { note, velocity, .. } if velocity > 0 => {
+                let zone = self.zone(note);
+                let played = (note as i32 + zone.transpose as i32).clamp(0, 127) as u8;
+                self.held.push((note, zone.channel, played));
+                MidiMsg::NoteOn {
+                    channel: zone.channel,
+                    note: played,
+                    velocity,
+                }
+            }
+            MidiMsg::NoteOn { note, velocity, .. } | MidiMsg::NoteOff { note, velocity, .. } => {
+                match self.held.iter().position(|&(held, ..)| held == note) {
+                    Some(index) => {
+                        let (_, channel, played) = self.held.remove(index);
+                        MidiMsg::NoteOff {
+                            channel,
+                            note: played,
+                            velocity,
+                        }
+                    }
+                    None => midi,
+                }
+            }
+            _ => midi,
+        }
+    }
+
+    /// Forgets the notes held, once they have been stopped some other way.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
@@ -0,0 +1,41 @@
+//! Velocity stacking for the `[numpad]` pads: hitting the same key again soon after plays it
+//! louder than the hit before, so flams and accents come out of repetition the way they would
+//! from hitting a pad harder.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use winit::event::ScanCode;
+
+#[derive(Debug, Clone)]
+pub struct Stack {
+    window: Duration,
+    step: u8,
+    /// When each key was last hit, and how loud.
+    hits: HashMap<ScanCode, (Instant, u8)>,
+}
+
+impl Stack {
+    /// Hits within `window` of the last one on their key are `step` louder than it.
+    pub fn new(window: Duration, step: u8) -> Self {
+        Stack {
+            window,
+            step,
+            hits: HashMap::new(),
+        }
+    }
+
+    /// The velocity of a hit of `key` at `now` that would otherwise be `velocity`.
+    pub fn hit(&mut self, key: ScanCode, velocity: u8, now: Instant) -> u8 {
+        let velocity = match self.hits.get(&key) {
+            Some(&(at, last)) if now.duration_since(at) <= self.window => {
+                last.saturating_add(self.step).min(127)
+            }
+            _ => velocity,
+        };
+        self.hits.insert(key, (now, velocity));
+        velocity
+    }
+}
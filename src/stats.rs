@@ -0,0 +1,128 @@
+//! `--stats`: how long the engine takes and how much it has to do, printed on stderr once a
+//! second, to check that the real-time path keeps up.
+
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use jack::Frames;
+
+use crate::session_log::report;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happened since the last report.
+#[derive(Debug, Clone, Copy, Default)]
+struct Stats {
+    cycles: u32,
+    /// Frames played, to tell how much of the time the engine was busy.
+    frames: u64,
+    busy: Duration,
+    worst_cycle: Duration,
+    written: u32,
+    /// Messages the output had no room for or refused.
+    failed: u32,
+    received: u32,
+    /// How long all the messages took from being made to being given to the output, in µs.
+    total_latency: u64,
+    /// The longest a message took from being made to being given to the output, in µs.
+    worst_latency: u64,
+    most_events: usize,
+    most_scheduled: usize,
+    most_unsent: usize,
+}
+
+/// Collects stats in the engine and hands them to a thread that prints them, so the engine
+/// never waits on stderr.
+#[derive(Debug)]
+pub struct Recorder {
+    sample_rate: usize,
+    stats: Stats,
+    since: Instant,
+    reports: Sender<Stats>,
+}
+
+impl Recorder {
+    pub fn new(sample_rate: usize) -> Self {
+        let (reports, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stats in rx {
+                report!("{}", report(&stats, sample_rate));
+            }
+        });
+
+        Recorder {
+            sample_rate,
+            stats: Stats::default(),
+            since: Instant::now(),
+            reports,
+        }
+    }
+
+    /// Notes that a message made `age` µs ago was placed `offset` frames into the cycle.
+    pub fn received(&mut self, age: u64, offset: Frames) {
+        let latency = age + offset as u64 * 1_000_000 / self.sample_rate as u64;
+        self.stats.worst_latency = self.stats.worst_latency.max(latency);
+        self.stats.total_latency += latency;
+        self.stats.received += 1;
+    }
+
+    pub fn written(&mut self) {
+        self.stats.written += 1;
+    }
+
+    pub fn failed(&mut self) {
+        self.stats.failed += 1;
+    }
+
+    /// Notes the end of a cycle of `n_frames` that started at `started`, and how many messages
+    /// it had and left waiting.
+    pub fn cycle(
+        &mut self,
+        started: Instant,
+        n_frames: Frames,
+        events: usize,
+        scheduled: usize,
+        unsent: usize,
+    ) {
+        let took = started.elapsed();
+        let stats = &mut self.stats;
+        stats.cycles += 1;
+        stats.frames += n_frames as u64;
+        stats.busy += took;
+        stats.worst_cycle = stats.worst_cycle.max(took);
+        stats.most_events = stats.most_events.max(events);
+        stats.most_scheduled = stats.most_scheduled.max(scheduled);
+        stats.most_unsent = stats.most_unsent.max(unsent);
+
+        if self.since.elapsed() >= REPORT_INTERVAL {
+            let _ = self.reports.send(std::mem::take(&mut self.stats));
+            self.since = Instant::now();
+        }
+    }
+}
+
+fn report(stats: &Stats, sample_rate: usize) -> String {
+    let played = Duration::from_secs_f64(stats.frames as f64 / sample_rate as f64);
+    let cycles = stats.cycles.max(1);
+
+    format!(
+        "{} cycles, {} µs average and {} µs worst, {:.2}% busy; {} events/s, {} failed writes, \
+         {:.1} ms average and {:.1} ms worst latency; queues up to {} events, {} scheduled, {} \
+         unsent",
+        stats.cycles,
+        (stats.busy / cycles).as_micros(),
+        stats.worst_cycle.as_micros(),
+        stats.busy.as_secs_f64() / played.as_secs_f64().max(f64::MIN_POSITIVE) * 100.0,
+        (stats.written as f64 / played.as_secs_f64().max(f64::MIN_POSITIVE)).round(),
+        stats.failed,
+        stats.total_latency as f64 / stats.received.max(1) as f64 / 1000.0,
+        stats.worst_latency as f64 / 1000.0,
+        stats.most_events,
+        stats.most_scheduled,
+        stats.most_unsent,
+    )
+}
@@ -0,0 +1,61 @@
+//! `--stress`: plays random notes at a steady rate, alongside whatever the keys play, to check
+//! the whole pipeline keeps up before a performance. `--stats` comes with it, to show the
+//! cycle times, the failed writes and how late the messages were.
+
+use std::{
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    midi::{MidiMsg, DEFAULT_CHANNEL},
+    rhythm::Rng,
+    KeyboardMsg,
+};
+
+/// The most notes played at once, after which the next event always lets go of one.
+const MAX_HELD: usize = 32;
+/// The lowest note played, and how many notes above it can be.
+const LOWEST: u8 = 36;
+const NOTES: u32 = 61;
+/// How often the generator catches up on the events due.
+const TICK: Duration = Duration::from_millis(1);
+
+/// Starts sending `rate` note ons and offs a second to `tx`, until the engine has gone away.
+pub fn start(rate: u32, tx: Sender<KeyboardMsg>) {
+    thread::spawn(move || {
+        let mut rng = Rng::new(jack::get_time());
+        let mut held: Vec<u8> = Vec::new();
+        let started = Instant::now();
+        let mut sent = 0;
+
+        loop {
+            let due = (started.elapsed().as_secs_f64() * rate as f64) as u64;
+            while sent < due {
+                let midi = if held.len() == MAX_HELD || !held.is_empty() && rng.below(2) == 0 {
+                    let note = held.swap_remove(rng.below(held.len() as u32) as usize);
+                    MidiMsg::NoteOff {
+                        channel: DEFAULT_CHANNEL,
+                        note,
+                        velocity: 0,
+                    }
+                } else {
+                    let note = LOWEST + rng.below(NOTES) as u8;
+                    held.push(note);
+                    MidiMsg::NoteOn {
+                        channel: DEFAULT_CHANNEL,
+                        note,
+                        velocity: 1 + rng.below(127) as u8,
+                    }
+                };
+                let time = jack::get_time();
+                if tx.send(KeyboardMsg { midi, time }).is_err() {
+                    return;
+                }
+                sent += 1;
+            }
+            thread::sleep(TICK);
+        }
+    });
+}
@@ -0,0 +1,186 @@
+//! A tiny polyphonic synth for previewing without connecting anything, enabled with `--synth`.
+//!
+//! It plays whatever notes are sent to the MIDI output, on any channel.
+
+use std::f32::consts::TAU;
+
+use crate::midi::{self, MidiMsg};
+
+const VOICES: usize = 16;
+const GAIN: f32 = 0.2;
+
+const ATTACK: f32 = 0.005;
+const DECAY: f32 = 0.1;
+const SUSTAIN: f32 = 0.6;
+const RELEASE: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sine" => Some(Waveform::Sine),
+            "square" => Some(Waveform::Square),
+            _ => None,
+        }
+    }
+
+    /// The value at `phase`, which goes from 0 to 1 over one period.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (phase * TAU).sin(),
+            // Quieter than the sine so the two are about equally loud
+            Waveform::Square => {
+                if phase < 0.5 {
+                    0.5
+                } else {
+                    -0.5
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    channel: u8,
+    note: u8,
+    gain: f32,
+    phase: f32,
+    increment: f32,
+    stage: Stage,
+    level: f32,
+    /// Increases every note on, used to steal the oldest voice.
+    age: u64,
+}
+
+impl Voice {
+    const OFF: Voice = Voice {
+        channel: 0,
+        note: 0,
+        gain: 0.0,
+        phase: 0.0,
+        increment: 0.0,
+        stage: Stage::Off,
+        level: 0.0,
+        age: 0,
+    };
+}
+
+pub struct Synth {
+    waveform: Waveform,
+    sample_rate: f32,
+    voices: [Voice; VOICES],
+    notes_played: u64,
+}
+
+impl Synth {
+    pub fn new(waveform: Waveform, sample_rate: usize) -> Self {
+        Synth {
+            waveform,
+            sample_rate: sample_rate as f32,
+            voices: [Voice::OFF; VOICES],
+            notes_played: 0,
+        }
+    }
+
+    pub fn handle(&mut self, midi: &MidiMsg) {
+        match *midi {
+            MidiMsg::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity > 0 => self.note_on(channel, note, velocity),
+            MidiMsg::NoteOn { channel, note, .. } | MidiMsg::NoteOff { channel, note, .. } => {
+                for voice in &mut self.voices {
+                    if voice.stage != Stage::Off && voice.channel == channel && voice.note == note {
+                        voice.stage = Stage::Release;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        self.notes_played += 1;
+
+        // Use a free voice, or steal the one that has been playing the longest
+        let voice = match self.voices.iter_mut().find(|v| v.stage == Stage::Off) {
+            Some(voice) => voice,
+            None => self.voices.iter_mut().min_by_key(|v| v.age).unwrap(),
+        };
+
+        let frequency = midi::frequency(note);
+        *voice = Voice {
+            channel,
+            note,
+            gain: GAIN * velocity as f32 / 127.0,
+            phase: 0.0,
+            increment: frequency / self.sample_rate,
+            stage: Stage::Attack,
+            level: voice.level,
+            age: self.notes_played,
+        };
+    }
+
+    /// Mixes the sounding voices into `out`, overwriting what was there.
+    pub fn render(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+
+        let attack = 1.0 / (ATTACK * self.sample_rate);
+        let decay = (1.0 - SUSTAIN) / (DECAY * self.sample_rate);
+        let release = 1.0 / (RELEASE * self.sample_rate);
+
+        for voice in &mut self.voices {
+            if voice.stage == Stage::Off {
+                continue;
+            }
+
+            for sample in out.iter_mut() {
+                match voice.stage {
+                    Stage::Attack => {
+                        voice.level += attack;
+                        if voice.level >= 1.0 {
+                            voice.level = 1.0;
+                            voice.stage = Stage::Decay;
+                        }
+                    }
+                    Stage::Decay => {
+                        voice.level -= decay;
+                        if voice.level <= SUSTAIN {
+                            voice.level = SUSTAIN;
+                            voice.stage = Stage::Sustain;
+                        }
+                    }
+                    Stage::Sustain => (),
+                    Stage::Release => {
+                        voice.level -= release;
+                        if voice.level <= 0.0 {
+                            voice.level = 0.0;
+                            voice.stage = Stage::Off;
+                            break;
+                        }
+                    }
+                    Stage::Off => break,
+                }
+
+                *sample += self.waveform.sample(voice.phase) * voice.gain * voice.level;
+                voice.phase = (voice.phase + voice.increment).fract();
+            }
+        }
+    }
+}
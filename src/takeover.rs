@@ -0,0 +1,13 @@
+//! Soft takeover, turned on with `soft_takeover`: once a preset, a morph, a snapshot or anything
+//! else has moved a controller away from where the mod wheel slider or the portamento time keys
+//! left it, they only take it back when they reach or cross the value it is at, so the sound
+//! never jumps to wherever they happen to be.
+
+/// Whether a control moving from `from` to `to` takes over its controller, last written at
+/// `written` if it was written at all.
+pub fn takes_over(written: Option<u8>, from: u8, to: u8) -> bool {
+    match written {
+        None => true,
+        Some(written) => from == written || to == written || (from < written) != (to < written),
+    }
+}
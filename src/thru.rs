@@ -0,0 +1,123 @@
+//! MIDI thru, configured with `[[thru]]`: messages arriving on the input port are passed on to
+//! the output, filtered and changed by rules along the way.
+//!
+//! The first rule that matches a message decides what happens to it, and messages no rule
+//! matches are passed on as they are. Only the channel messages the keyboard sends itself can
+//! be passed on, so e.g. system exclusive never is.
+
+use crate::{midi::MidiMsg, scale::Scale};
+
+/// The kinds of message a rule can match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Note ons and note offs.
+    Note,
+    ControlChange,
+    Program,
+    Pressure,
+    PitchBend,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "note" => Some(Kind::Note),
+            "control_change" => Some(Kind::ControlChange),
+            "program" => Some(Kind::Program),
+            "pressure" => Some(Kind::Pressure),
+            "pitch_bend" => Some(Kind::PitchBend),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Note => "note",
+            Kind::ControlChange => "control_change",
+            Kind::Program => "program",
+            Kind::Pressure => "pressure",
+            Kind::PitchBend => "pitch_bend",
+        }
+    }
+
+    pub fn of(midi: &MidiMsg) -> Self {
+        match midi {
+            MidiMsg::NoteOn { .. } | MidiMsg::NoteOff { .. } => Kind::Note,
+            MidiMsg::ControlChange { .. } => Kind::ControlChange,
+            MidiMsg::ProgramChange { .. } => Kind::Program,
+            MidiMsg::ChannelPressure { .. } => Kind::Pressure,
+            MidiMsg::PitchBend { .. } => Kind::PitchBend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rule {
+    /// The channel matched, zero-based, or `None` for every channel.
+    pub channel: Option<u8>,
+    /// The kinds of message matched, or empty for all of them.
+    pub kinds: Vec<Kind>,
+    /// Whether matching messages are dropped rather than passed on.
+    pub drop: bool,
+    /// The channel matching messages are moved to, zero-based.
+    pub to_channel: Option<u8>,
+    /// Semitones notes are moved by. Notes moved outside the MIDI range are dropped.
+    pub transpose: i8,
+    /// The scale, with the pitch class of its root, notes are moved to the nearest note of
+    /// after transposing, so wrong notes from a controller come out right.
+    pub scale: Option<(Scale, u8)>,
+    /// The lowest and highest velocity note ons are scaled to, from 1 and 127.
+    pub velocity: Option<(u8, u8)>,
+}
+
+impl Rule {
+    fn matches(&self, midi: &MidiMsg) -> bool {
+        self.channel.is_none_or(|matched| matched == midi.channel())
+            && (self.kinds.is_empty() || self.kinds.contains(&Kind::of(midi)))
+    }
+
+    fn apply(&self, mut midi: MidiMsg) -> Option<MidiMsg> {
+        if self.drop {
+            return None;
+        }
+
+        // A note on with velocity 0 is a note off, and stays one
+        let note_on = matches!(midi, MidiMsg::NoteOn { velocity: 1.., .. });
+        match &mut midi {
+            MidiMsg::NoteOn { note, velocity, .. } | MidiMsg::NoteOff { note, velocity, .. } => {
+                let moved = *note as i32 + self.transpose as i32;
+                *note = u8::try_from(moved).ok().filter(|&note| note <= 127)?;
+                if let Some((scale, root)) = self.scale {
+                    // The same for a note on and its note off, so nothing is left hanging
+                    *note = scale.snap(root, *note);
+                }
+
+                if let (Some((low, high)), true) = (self.velocity, note_on) {
+                    let span = high as u32 - low as u32;
+                    *velocity = (low as u32 + (*velocity as u32 - 1) * span / 126) as u8;
+                }
+            }
+            _ => (),
+        }
+        if let Some(to_channel) = self.to_channel {
+            match &mut midi {
+                MidiMsg::NoteOn { channel, .. }
+                | MidiMsg::NoteOff { channel, .. }
+                | MidiMsg::ControlChange { channel, .. }
+                | MidiMsg::ProgramChange { channel, .. }
+                | MidiMsg::ChannelPressure { channel, .. }
+                | MidiMsg::PitchBend { channel, .. } => *channel = to_channel,
+            }
+        }
+
+        Some(midi)
+    }
+}
+
+/// What becomes of a message passed through `rules`, or `None` if it is dropped.
+pub fn route(rules: &[Rule], midi: MidiMsg) -> Option<MidiMsg> {
+    match rules.iter().find(|rule| rule.matches(&midi)) {
+        Some(rule) => rule.apply(midi),
+        None => Some(midi),
+    }
+}
@@ -0,0 +1,110 @@
+//! Acting as the JACK timebase master with `--timebase-master`, so that other clients can follow
+//! the tempo of the clock, tapped or not, in bars and beats.
+//!
+//! The `jack` crate doesn't wrap timebase callbacks, so this registers one with libjack itself.
+
+use std::{
+    os::raw::{c_int, c_uint, c_void},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use jack::{Client, Frames, TransportBBT, TransportPosition};
+
+const BEATS_PER_BAR: f64 = 4.0;
+const TICKS_PER_BEAT: f64 = 1920.0;
+
+/// The tempo to publish, stored as the bits of an `f64` by the process callback.
+pub type Tempo = Arc<AtomicU64>;
+
+type TimebaseCallback = unsafe extern "C" fn(
+    state: c_uint,
+    n_frames: Frames,
+    pos: *mut TransportPosition,
+    new_pos: c_int,
+    arg: *mut c_void,
+);
+
+#[link(name = "jack")]
+extern "C" {
+    fn jack_set_timebase_callback(
+        client: *mut c_void,
+        conditional: c_int,
+        callback: Option<TimebaseCallback>,
+        arg: *mut c_void,
+    ) -> c_int;
+}
+
+/// What the timebase callback keeps from one cycle to the next.
+struct State {
+    tempo: Tempo,
+    /// The beat at the transport frame of the last cycle, counted from the start.
+    beat: f64,
+    frame: Frames,
+}
+
+/// Makes `client` the timebase master, publishing the bars and beats of `tempo`. Fails if
+/// another client already is.
+pub fn start(client: &Client, tempo: Tempo) -> Result<(), String> {
+    // Stays around for as long as the client, which is as long as the process
+    let state = Box::into_raw(Box::new(State {
+        tempo,
+        beat: 0.0,
+        frame: 0,
+    }));
+
+    // SAFETY: `state` is never freed, and only ever used by the callback, which JACK calls from
+    // the process thread alone.
+    let result = unsafe {
+        jack_set_timebase_callback(
+            client.raw() as *mut c_void,
+            1,
+            Some(timebase),
+            state as *mut c_void,
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err("another JACK client is already the timebase master".to_string()),
+    }
+}
+
+/// Counts beats on from the last cycle at the current tempo, or from the start of the
+/// transport when it was moved.
+unsafe extern "C" fn timebase(
+    _state: c_uint,
+    _n_frames: Frames,
+    pos: *mut TransportPosition,
+    new_pos: c_int,
+    arg: *mut c_void,
+) {
+    let (state, pos) = (&mut *(arg as *mut State), &mut *pos);
+    let bpm = f64::from_bits(state.tempo.load(Ordering::Relaxed));
+    let frames_per_beat = pos.frame_rate().unwrap_or(48000) as f64 * 60.0 / bpm;
+
+    let frame = pos.frame();
+    state.beat = if new_pos != 0 {
+        frame as f64 / frames_per_beat
+    } else {
+        let elapsed = frame as i64 - state.frame as i64;
+        (state.beat + elapsed as f64 / frames_per_beat).max(0.0)
+    };
+    state.frame = frame;
+
+    let bar = (state.beat / BEATS_PER_BAR).floor();
+    let bbt = TransportBBT {
+        bar: bar as usize + 1,
+        beat: (state.beat % BEATS_PER_BAR) as usize + 1,
+        tick: (state.beat.fract() * TICKS_PER_BEAT) as usize,
+        sig_num: BEATS_PER_BAR as f32,
+        sig_denom: 4.0,
+        ticks_per_beat: TICKS_PER_BEAT,
+        bpm,
+        bar_start_tick: bar * BEATS_PER_BAR * TICKS_PER_BEAT,
+    };
+    // Only fails for values that can't come out of the above
+    let _ = pos.set_bbt(Some(bbt));
+}
@@ -0,0 +1,609 @@
+//! A small parser for the subset of TOML used by the config file.
+//!
+//! Supported are tables, arrays of tables, dotted keys, strings, integers, floats, booleans,
+//! arrays and inline tables. Dates aren't. Every key remembers where it was defined so the
+//! config loader can point at the offending line.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub pos: Pos,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.pos, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub pos: Pos,
+}
+
+/// A table that keeps its keys in definition order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entry(key).map(|entry| &entry.value)
+    }
+
+    pub fn entry(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn entry_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.key == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+}
+
+pub fn parse(source: &str) -> Result<Table, Error> {
+    Parser::new(source).parse()
+}
+
+/// Sets `key = value` in the `[section]` table of `source`, adding the section if it isn't
+/// there. Everything else, comments included, is kept as it is. `value` is written as given, so
+/// it has to be valid TOML on a single line.
+pub fn set_in_section(source: &str, section: &str, key: &str, value: &str) -> String {
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    let new_line = format!("{} = {}", key, value);
+    let header = format!("[{}]", section);
+    let without_comment = |line: &str| {
+        line.split('#')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+
+    match lines
+        .iter()
+        .position(|line| without_comment(line) == header)
+    {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(lines.len(), |i| start + 1 + i);
+
+            let existing = lines[start + 1..end].iter().position(|line| {
+                line.split_once('=')
+                    .is_some_and(|(k, _)| k.trim().trim_matches('"') == key)
+            });
+            match existing {
+                Some(i) => lines[start + 1 + i] = new_line,
+                None => {
+                    // After the section's last line that isn't blank
+                    let last = lines[start..end]
+                        .iter()
+                        .rposition(|line| !line.trim().is_empty())
+                        .map_or(start, |i| start + i);
+                    lines.insert(last + 1, new_line);
+                }
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(new_line);
+        }
+    }
+
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Takes `key` out of the `[section]` table of `source`, keeping everything else as it is.
+pub fn remove_in_section(source: &str, section: &str, key: &str) -> String {
+    let mut lines: Vec<&str> = source.lines().collect();
+    let header = format!("[{}]", section);
+
+    let start = lines
+        .iter()
+        .position(|line| line.split('#').next().unwrap_or_default().trim() == header);
+    if let Some(start) = start {
+        let end = lines[start + 1..]
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .map_or(lines.len(), |i| start + 1 + i);
+        let existing = lines[start + 1..end].iter().position(|line| {
+            line.split_once('=')
+                .is_some_and(|(k, _)| k.trim().trim_matches('"') == key)
+        });
+        if let Some(i) = existing {
+            lines.remove(start + 1 + i);
+        }
+    }
+
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    pos: Pos,
+}
+
+/// Which table `key = value` lines currently go into.
+enum Current {
+    Table(Vec<String>),
+    ArrayTable(Vec<String>),
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Parser {
+            chars: source.chars().peekable(),
+            pos: Pos { line: 1, column: 1 },
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error {
+            pos: self.pos,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            match self.peek() {
+                Some(c) => self.error(format!(
+                    "expected '{}', found '{}'",
+                    expected,
+                    c.escape_debug()
+                )),
+                None => self.error(format!("expected '{}', found end of file", expected)),
+            }
+        }
+    }
+
+    /// Skips spaces and tabs.
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skips whitespace, newlines and comments, as allowed inside arrays.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            if !self.eat('\n') && !self.eat('\r') {
+                break;
+            }
+        }
+    }
+
+    fn expect_line_end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        self.skip_comment();
+        self.eat('\r');
+
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.error(format!(
+                "expected the end of the line, found '{}'",
+                c.escape_debug()
+            )),
+        }
+    }
+
+    fn parse(mut self) -> Result<Table, Error> {
+        let mut root = Table::default();
+        let mut current = Current::Table(Vec::new());
+
+        loop {
+            self.skip_blank();
+
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    let pos = self.pos;
+                    self.bump();
+                    let array = self.eat('[');
+                    self.skip_whitespace();
+                    let path = self.key_path()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.expect_line_end()?;
+
+                    current = if array {
+                        let tables = Self::array_of_tables(&mut root, &path, pos)?;
+                        tables.push(Value::Table(Table::default()));
+                        Current::ArrayTable(path)
+                    } else {
+                        Self::table_at(&mut root, &path, pos)?;
+                        Current::Table(path)
+                    };
+                }
+                Some(_) => {
+                    let pos = self.pos;
+                    let path = self.key_path()?;
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let value = self.value()?;
+                    self.expect_line_end()?;
+
+                    let table = match &current {
+                        Current::Table(table_path) => Self::table_at(&mut root, table_path, pos)?,
+                        Current::ArrayTable(table_path) => {
+                            match Self::array_of_tables(&mut root, table_path, pos)?.last_mut() {
+                                Some(Value::Table(table)) => table,
+                                _ => unreachable!("array tables always end in a table"),
+                            }
+                        }
+                    };
+                    Self::insert_at(table, &path, value, pos)?;
+                }
+            }
+        }
+    }
+
+    /// Finds or creates the table at `path`, descending into the last element of arrays of
+    /// tables on the way.
+    fn table_at<'t>(
+        mut table: &'t mut Table,
+        path: &[String],
+        pos: Pos,
+    ) -> Result<&'t mut Table, Error> {
+        for key in path {
+            if table.get(key).is_none() {
+                table.entries.push(Entry {
+                    key: key.clone(),
+                    value: Value::Table(Table::default()),
+                    pos,
+                });
+            }
+
+            table = match &mut table.entry_mut(key).unwrap().value {
+                Value::Table(table) => table,
+                Value::Array(values) => match values.last_mut() {
+                    Some(Value::Table(table)) => table,
+                    _ => return Self::duplicate(key, pos),
+                },
+                _ => return Self::duplicate(key, pos),
+            };
+        }
+
+        Ok(table)
+    }
+
+    fn array_of_tables<'t>(
+        root: &'t mut Table,
+        path: &[String],
+        pos: Pos,
+    ) -> Result<&'t mut Vec<Value>, Error> {
+        let (last, parents) = path.split_last().unwrap();
+        let table = Self::table_at(root, parents, pos)?;
+
+        if table.get(last).is_none() {
+            table.entries.push(Entry {
+                key: last.clone(),
+                value: Value::Array(Vec::new()),
+                pos,
+            });
+        }
+
+        match &mut table.entry_mut(last).unwrap().value {
+            Value::Array(values) => Ok(values),
+            _ => Self::duplicate(last, pos),
+        }
+    }
+
+    fn insert_at(table: &mut Table, path: &[String], value: Value, pos: Pos) -> Result<(), Error> {
+        let (last, parents) = path.split_last().unwrap();
+        let table = Self::table_at(table, parents, pos)?;
+
+        if table.get(last).is_some() {
+            return Self::duplicate(last, pos);
+        }
+
+        table.entries.push(Entry {
+            key: last.clone(),
+            value,
+            pos,
+        });
+        Ok(())
+    }
+
+    fn duplicate<T>(key: &str, pos: Pos) -> Result<T, Error> {
+        Err(Error {
+            pos,
+            message: format!("'{}' is defined more than once", key),
+        })
+    }
+
+    /// Parses `a.b."c"`, followed by optional whitespace.
+    fn key_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = vec![self.key()?];
+
+        loop {
+            self.skip_whitespace();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+            self.skip_whitespace();
+            path.push(self.key()?);
+        }
+    }
+
+    fn key(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        key.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+
+                if key.is_empty() {
+                    match self.peek() {
+                        Some(c) => {
+                            self.error(format!("expected a key, found '{}'", c.escape_debug()))
+                        }
+                        None => self.error("expected a key, found end of file"),
+                    }
+                } else {
+                    Ok(key)
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t' | 'f') => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => self.error(format!("invalid value '{}'", word)),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | 'i' | 'n') => self.number(),
+            Some(c) => self.error(format!("expected a value, found '{}'", c.escape_debug())),
+            None => self.error("expected a value, found end of file"),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.') {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let pos = self.pos;
+        let word = self.word();
+        let digits = word.replace('_', "");
+        let unsigned = digits.trim_start_matches(['+', '-']);
+        let negative = digits.starts_with('-');
+
+        let radix = match unsigned.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+
+        let value = if let Some(radix) = radix {
+            i64::from_str_radix(&unsigned[2..], radix)
+                .ok()
+                .map(|n| Value::Integer(if negative { -n } else { n }))
+        } else if matches!(unsigned, "inf" | "nan") || unsigned.contains(['.', 'e', 'E']) {
+            digits.parse::<f64>().ok().map(Value::Float)
+        } else {
+            digits.parse::<i64>().ok().map(Value::Integer)
+        };
+
+        value.ok_or(Error {
+            pos,
+            message: format!("invalid number '{}'", word),
+        })
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        let mut string = String::new();
+
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.error("unterminated string"),
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c @ ('u' | 'U')) => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let hex: String = (0..len).filter_map(|_| self.bump()).collect();
+                            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => {
+                                    return self.error(format!("invalid escape \\{}{}", c, hex))
+                                }
+                            }
+                        }
+                        Some(c) => return self.error(format!("invalid escape \\{}", c)),
+                        None => return self.error("unterminated string"),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        self.expect('\'')?;
+        let mut string = String::new();
+
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.error("unterminated string"),
+                Some('\'') => return Ok(string),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_blank();
+            if self.eat(']') {
+                return Ok(Value::Array(values));
+            }
+
+            values.push(self.value()?);
+
+            self.skip_blank();
+            if !self.eat(',') {
+                self.skip_blank();
+                self.expect(']')?;
+                return Ok(Value::Array(values));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, Error> {
+        self.expect('{')?;
+        let mut table = Table::default();
+
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::Table(table));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let pos = self.pos;
+            let path = self.key_path()?;
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = self.value()?;
+            Self::insert_at(&mut table, &path, value, pos)?;
+
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Table(table));
+            }
+            self.expect(',')?;
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Writing MIDI 2.0 to an ALSA UMP device (Linux 6.5 and later), enabled with `--ump`.
+//!
+//! Everything is still played as MIDI 1.0 and translated on the way out, as the MIDI 2.0
+//! specification describes: each message becomes a Universal MIDI Packet with its values scaled
+//! up, so velocity has 16 bits and controllers 32. Pan sent right before a note, like note
+//! channels do, also goes out as a per-note controller of that note.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender},
+    thread,
+};
+
+use crate::{
+    config::Config,
+    engine::{Control, Engine, Outcome, REAL_TIME_RATE},
+    midi::{ACTIVE_SENSING, CC_PAN},
+    options::Options,
+    rawmidi,
+    session_log::report,
+    KeyboardMsg,
+};
+
+/// Message types of the first word of a packet, each in group 0.
+const SYSTEM: u32 = 0x1000_0000;
+const MIDI_2_CHANNEL_VOICE: u32 = 0x4000_0000;
+
+/// Statuses of MIDI 2.0 channel voice messages that MIDI 1.0 doesn't have.
+const REGISTERED_PER_NOTE_CONTROLLER: u8 = 0x00;
+
+/// The registered per-note controller for pan, numbered like the control change.
+const PER_NOTE_PAN: u8 = CC_PAN;
+
+/// The UMP device file of an ALSA device name like `hw:1,0`, see [`rawmidi::device_file`].
+pub fn device_path(name: &str) -> Option<PathBuf> {
+    rawmidi::device_file(name, "ump")
+}
+
+/// Scales a value of `from` bits up to `to` bits the way MIDI 2.0 translators do, so the
+/// lowest, the centre and the highest value stay the lowest, the centre and the highest.
+fn scale_up(value: u32, from: u32, to: u32) -> u32 {
+    let shift = to - from;
+    let shifted = value << shift;
+    if value <= 1 << (from - 1) {
+        return shifted;
+    }
+
+    // Above the centre, the bits below the top one are repeated to fill the new low bits
+    let repeat_bits = from - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    repeat = if shift > repeat_bits {
+        repeat << (shift - repeat_bits)
+    } else {
+        repeat >> (repeat_bits - shift)
+    };
+
+    let mut scaled = shifted;
+    while repeat != 0 {
+        scaled |= repeat;
+        repeat >>= repeat_bits;
+    }
+    scaled
+}
+
+/// Turns the MIDI 1.0 bytes the [`Engine`] writes into Universal MIDI Packets.
+#[derive(Debug, Default)]
+pub struct Translator {
+    /// Pan sent on each channel since its last note on, for the next note on to have it as a
+    /// per-note controller as well.
+    pan: [Option<u8>; 16],
+}
+
+impl Translator {
+    /// Translates a message, passing the words of each packet to `packet`. Running status
+    /// isn't understood, so every message needs its status byte.
+    pub fn translate(&mut self, bytes: &[u8], mut packet: impl FnMut(&[u32])) {
+        let (status, data) = match bytes {
+            &[ACTIVE_SENSING] => return packet(&[SYSTEM | (ACTIVE_SENSING as u32) << 16]),
+            [status, data @ ..] => (*status, data),
+            [] => return,
+        };
+        let channel = status & 0x0f;
+        let data = |index: usize| data.get(index).copied().unwrap_or(0) as u32;
+        let word = |status: u8, index: u32| {
+            MIDI_2_CHANNEL_VOICE | (status as u32) << 20 | (channel as u32) << 16 | index
+        };
+
+        let pan = std::mem::take(&mut self.pan[channel as usize]);
+        match status & 0xf0 {
+            // Velocity 0 is a note on in MIDI 2.0, so a MIDI 1.0 one is a note off
+            0x90 if data(1) > 0 => {
+                let note = data(0);
+                packet(&[word(0x9, note << 8), scale_up(data(1), 7, 16) << 16]);
+                if let Some(pan) = pan {
+                    let index = note << 8 | PER_NOTE_PAN as u32;
+                    let value = scale_up(pan as u32, 7, 32);
+                    packet(&[word(REGISTERED_PER_NOTE_CONTROLLER, index), value]);
+                }
+            }
+            0x80 | 0x90 => packet(&[word(0x8, data(0) << 8), scale_up(data(1), 7, 16) << 16]),
+            0xb0 => {
+                if data(0) == CC_PAN as u32 {
+                    self.pan[channel as usize] = Some(data(1) as u8);
+                }
+                packet(&[word(0xb, data(0) << 8), scale_up(data(1), 7, 32)]);
+            }
+            // Without a bank, which goes as its control changes
+            0xc0 => packet(&[word(0xc, 0), data(0) << 24]),
+            0xd0 => packet(&[word(0xd, 0), scale_up(data(0), 7, 32)]),
+            0xe0 => packet(&[word(0xe, 0), scale_up(data(1) << 7 | data(0), 14, 32)]),
+            _ => (),
+        }
+    }
+}
+
+/// Opens the UMP device at `path` and starts a thread that plays the [`Engine`] on it, like
+/// [`rawmidi::start`](crate::rawmidi::start).
+pub fn start(
+    path: &Path,
+    rx: Receiver<KeyboardMsg>,
+    controls: Receiver<Control>,
+    written: Option<Sender<KeyboardMsg>>,
+    beats: Option<Sender<u64>>,
+    options: &Options,
+    config: &Config,
+) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    let engine = Engine::new(rx, controls, beats, options, config, REAL_TIME_RATE);
+    let path = path.to_owned();
+
+    thread::spawn(move || run(engine, file, &path, written));
+
+    Ok(())
+}
+
+fn run(engine: Engine, mut file: File, path: &Path, written: Option<Sender<KeyboardMsg>>) {
+    let mut translator = Translator::default();
+
+    engine.run(|engine, n_frames| {
+        engine.cycle(
+            n_frames,
+            // Everything is written as soon as it comes in
+            |_| 0,
+            |_, bytes| {
+                let mut result = Ok(());
+                translator.translate(bytes, |words| {
+                    // The device takes whole packets in native byte order
+                    let mut buffer = [0; 8];
+                    for (bytes, word) in buffer.chunks_exact_mut(4).zip(words) {
+                        bytes.copy_from_slice(&word.to_ne_bytes());
+                    }
+                    if result.is_ok() {
+                        result = file.write_all(&buffer[..words.len() * 4]);
+                    }
+                });
+                match result {
+                    Ok(()) => Outcome::Done,
+                    Err(err) => {
+                        report!("{}: {}", path.display(), err);
+                        Outcome::Failed
+                    }
+                }
+            },
+            |_, &midi| {
+                if let Some(written) = &written {
+                    let _ = written.send(KeyboardMsg {
+                        midi,
+                        time: jack::get_time(),
+                    });
+                }
+            },
+        )
+    })
+}
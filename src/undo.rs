@@ -0,0 +1,40 @@
+//! Undoing with Ctrl+Z what stays around after a key is released: notes latched by a gesture
+//! and chords learned onto keys, so a mistake while playing doesn't mean starting over.
+
+use std::collections::VecDeque;
+
+use winit::event::ScanCode;
+
+/// How many changes can be undone.
+const DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The gesture on the key latched its notes.
+    Latched(ScanCode),
+    /// A chord was learned onto the key, which played `previous` before.
+    Chord {
+        key: ScanCode,
+        previous: Option<Vec<u8>>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Undo {
+    /// Oldest first.
+    changes: VecDeque<Change>,
+}
+
+impl Undo {
+    pub fn push(&mut self, change: Change) {
+        if self.changes.len() == DEPTH {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+
+    /// The latest change, which is forgotten once taken.
+    pub fn pop(&mut self) -> Option<Change> {
+        self.changes.pop_back()
+    }
+}
@@ -0,0 +1,93 @@
+/// The velocity sent for every note before it goes through the [`VelocityCurve`].
+pub const FIXED_VELOCITY: u8 = 0x70;
+
+/// A piecewise linear mapping from source velocity to sent velocity.
+///
+/// The breakpoints are kept sorted by input velocity. The first and the last breakpoint always
+/// sit at inputs 0 and 127 so that every velocity is covered.
+#[derive(Debug, Clone)]
+pub struct VelocityCurve {
+    points: Vec<(u8, u8)>,
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        VelocityCurve {
+            points: vec![(0, 0), (127, 127)],
+        }
+    }
+}
+
+impl VelocityCurve {
+    pub fn points(&self) -> &[(u8, u8)] {
+        &self.points
+    }
+
+    /// Maps `velocity` through the curve.
+    ///
+    /// The result is never 0, since a note on with velocity 0 would be read as a note off.
+    pub fn apply(&self, velocity: u8) -> u8 {
+        let velocity = velocity.min(127);
+        let upper = self
+            .points
+            .iter()
+            .position(|&(input, _)| input >= velocity)
+            .unwrap_or(self.points.len() - 1);
+
+        let (x1, y1) = self.points[upper];
+        if upper == 0 || x1 == velocity {
+            return y1.max(1);
+        }
+
+        let (x0, y0) = self.points[upper - 1];
+        let t = (velocity - x0) as f32 / (x1 - x0) as f32;
+        let output = y0 as f32 + t * (y1 as f32 - y0 as f32);
+
+        (output.round() as u8).clamp(1, 127)
+    }
+
+    /// Adds a breakpoint, replacing any breakpoint at the same input. Returns its index.
+    pub fn insert(&mut self, input: u8, output: u8) -> usize {
+        let (input, output) = (input.min(127), output.min(127));
+
+        match self.points.binary_search_by_key(&input, |&(x, _)| x) {
+            Ok(index) => {
+                self.points[index].1 = output;
+                index
+            }
+            Err(index) => {
+                self.points.insert(index, (input, output));
+                index
+            }
+        }
+    }
+
+    /// Moves the breakpoint at `index`, keeping it between its neighbours.
+    ///
+    /// The end points can only be moved vertically.
+    pub fn move_point(&mut self, index: usize, input: u8, output: u8) {
+        let last = self.points.len() - 1;
+        let input = if index == 0 {
+            0
+        } else if index == last {
+            127
+        } else {
+            let min = self.points[index - 1].0 + 1;
+            let max = self.points[index + 1].0 - 1;
+            input.clamp(min, max.max(min))
+        };
+
+        self.points[index] = (input, output.min(127));
+    }
+
+    /// Removes the breakpoint at `index`, and returns whether it did. The end points can't be
+    /// removed.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index != 0 && index != self.points.len() - 1 {
+            self.points.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! A minimal WebSocket server for remote control, enabled with `--websocket`.
+//!
+//! Every text message a client sends is a line of the [`protocol`](crate::protocol), and every
+//! event the keyboard sends out is broadcast back as a JSON line, together with state changes
+//! like the selected preset. Plain HTTP requests for `/` get a small web page that acts as a
+//! remote keyboard.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    json,
+    midi::{MidiMsg, DEFAULT_CHANNEL},
+    output::OutputSink,
+    protocol,
+    session_log::report,
+    Proxy, UserEvent,
+};
+
+const PAGE: &str = include_str!("remote.html");
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Messages from clients are single protocol lines, so anything bigger is a mistake.
+const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+/// The most a request line and its headers can take up, far more than any browser sends.
+const MAX_HEAD_LEN: u64 = 8 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+#[derive(Default)]
+struct Shared {
+    clients: Vec<TcpStream>,
+    /// The latest line for each kind of state, sent to clients when they connect.
+    state: BTreeMap<&'static str, String>,
+}
+
+/// A handle for sending messages to every connected client.
+#[derive(Clone)]
+pub struct Broadcaster {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl OutputSink for Broadcaster {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn write(&mut self, midi: &MidiMsg, time: jack::Time) {
+        self.broadcast(&json::event_line(midi, time));
+    }
+}
+
+impl Broadcaster {
+    pub fn broadcast(&self, text: &str) {
+        let mut shared = self.shared.lock().unwrap();
+        shared
+            .clients
+            .retain_mut(|client| write_frame(client, OP_TEXT, text.as_bytes()).is_ok());
+    }
+
+    /// Broadcasts `text` and remembers it as the current value of `kind` for new clients.
+    pub fn set_state(&self, kind: &'static str, text: String) {
+        self.broadcast(&text);
+        self.shared.lock().unwrap().state.insert(kind, text);
+    }
+}
+
+/// Starts listening on `addr`. Commands are forwarded to the event loop through `proxy`.
+pub fn start(addr: impl ToSocketAddrs, proxy: Proxy) -> io::Result<Broadcaster> {
+    let listener = TcpListener::bind(addr)?;
+    let broadcaster = Broadcaster {
+        shared: Default::default(),
+    };
+
+    let shared = broadcaster.shared.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    report!("websocket: {}", err);
+                    continue;
+                }
+            };
+
+            let shared = shared.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || {
+                if let Err(err) = serve(stream, &shared, &proxy) {
+                    report!("websocket: {}", err);
+                }
+            });
+        }
+    });
+
+    Ok(broadcaster)
+}
+
+fn serve(stream: TcpStream, shared: &Mutex<Shared>, proxy: &Proxy) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut head = reader.by_ref().take(MAX_HEAD_LEN);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    let (mut key, mut host, mut origin) = (None, None, None);
+    loop {
+        let mut header = String::new();
+        let read = head.read_line(&mut header)?;
+        if head.limit() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request headers too long",
+            ));
+        }
+        if read == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = Some(value.trim().to_string());
+            match name.trim().to_ascii_lowercase().as_str() {
+                "sec-websocket-key" => key = value,
+                "host" => host = value,
+                "origin" => origin = value,
+                _ => (),
+            }
+        }
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let (status, body) = match path {
+                "/" | "/index.html" => ("200 OK", PAGE),
+                _ => ("404 Not Found", "Not found\n"),
+            };
+            return write!(
+                writer,
+                "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    };
+
+    // Any page open in the browser could connect to a local port otherwise, and play or change
+    // presets behind the user's back. Clients that aren't browsers don't send an origin.
+    if let Some(origin) = origin {
+        let origin = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+            .unwrap_or(&origin);
+        if host.is_none_or(|host| !origin.eq_ignore_ascii_case(&host)) {
+            return write!(
+                writer,
+                "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+    }
+
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept(&key)
+    )?;
+
+    // A client that doesn't read shouldn't hold up everyone else's broadcasts
+    writer.set_write_timeout(Some(Duration::from_secs(1)))?;
+    {
+        let mut shared = shared.lock().unwrap();
+        for text in shared.state.values() {
+            write_frame(&mut writer, OP_TEXT, text.as_bytes())?;
+        }
+        shared.clients.push(writer.try_clone()?);
+    }
+
+    let mut message = Vec::new();
+    loop {
+        let (fin, opcode, payload) = read_frame(&mut reader)?;
+
+        match opcode {
+            OP_TEXT | OP_CONTINUATION => {
+                message.extend_from_slice(&payload);
+                if message.len() as u64 > MAX_MESSAGE_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "message too long",
+                    ));
+                }
+                if !fin {
+                    continue;
+                }
+
+                let text = String::from_utf8_lossy(&message);
+                for line in text.lines() {
+                    match protocol::parse_line(line, DEFAULT_CHANNEL) {
+                        Ok(Some(command)) => {
+                            if proxy.send_event(UserEvent::Command(command)).is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(err) => {
+                            let reply = json::error_line(&err);
+                            send(shared, &mut writer, OP_TEXT, reply.as_bytes())?;
+                        }
+                    }
+                }
+                message.clear();
+            }
+            OP_PING => send(shared, &mut writer, OP_PONG, &payload)?,
+            OP_CLOSE => {
+                let _ = send(shared, &mut writer, OP_CLOSE, &payload);
+                return Ok(());
+            }
+            // Pongs and binary messages
+            _ => (),
+        }
+    }
+}
+
+/// Writes a frame to a client that broadcasts are written to as well, holding their lock so
+/// the two can't end up interleaved.
+fn send(
+    shared: &Mutex<Shared>,
+    writer: &mut TcpStream,
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let _shared = shared.lock().unwrap();
+    write_frame(writer, opcode, payload)
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok((fin, opcode, payload))
+}
+
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    writer.write_all(&frame)
+}
+
+/// The `Sec-WebSocket-Accept` value that answers a client's `Sec-WebSocket-Key`.
+fn accept(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, h) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_rfc_6455_sample_key() {
+        assert_eq!(
+            accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! The setup run on the first start, when there is no config file yet: the window asks for the
+//! key to play each note on in turn, and the keys pressed are saved as the `[notes]` of a new
+//! config. That way the notes are laid out right whatever scancodes the keyboard sends.
+
+use winit::event::ScanCode;
+
+use crate::midi::NoteNames;
+
+/// The notes asked for, an octave from middle C like the default keys.
+const FIRST: u8 = 60;
+const LAST: u8 = 72;
+
+#[derive(Debug, Clone)]
+pub struct Wizard {
+    /// The keys given so far, with their notes from [`FIRST`] up.
+    notes: Vec<(ScanCode, u8)>,
+}
+
+impl Wizard {
+    pub fn new() -> Self {
+        Wizard { notes: Vec::new() }
+    }
+
+    /// Gives the note asked for to the key pressed, unless it already has one. Returns the
+    /// keys and their notes once every note has a key.
+    pub fn key(&mut self, scancode: ScanCode) -> Option<Vec<(ScanCode, u8)>> {
+        if !self.notes.iter().any(|&(key, _)| key == scancode) {
+            let note = FIRST + self.notes.len() as u8;
+            self.notes.push((scancode, note));
+        }
+
+        (self.notes.len() > (LAST - FIRST) as usize).then(|| std::mem::take(&mut self.notes))
+    }
+
+    /// What to show in the window.
+    pub fn status(&self, names: NoteNames) -> String {
+        let note = FIRST + self.notes.len() as u8;
+        format!(
+            "Setup: press the key for {} ({}/{}), Esc to skip for now",
+            names.name(note),
+            self.notes.len() + 1,
+            LAST - FIRST + 1
+        )
+    }
+}